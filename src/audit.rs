@@ -0,0 +1,297 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::cli_style::CliStyle;
+use crate::npm_client::NpmClient;
+use crate::package_info::PackageJson;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Moderate => "moderate",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    fn styled(&self) -> String {
+        let label = self.name().to_uppercase();
+        match self {
+            Severity::Critical => style(label).magenta().bold().to_string(),
+            Severity::High => style(label).red().bold().to_string(),
+            Severity::Moderate => style(label).yellow().bold().to_string(),
+            Severity::Low => style(label).cyan().to_string(),
+            Severity::Info => style(label).dim().to_string(),
+        }
+    }
+}
+
+/// A single entry from the registry's bulk advisories response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advisory {
+    pub id: u64,
+    pub title: String,
+    pub severity: Severity,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub vulnerable_versions: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Vulnerability {
+    package: String,
+    version: String,
+    #[serde(flatten)]
+    advisory: Advisory,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditReport {
+    vulnerabilities: Vec<Vulnerability>,
+    summary: BTreeMap<&'static str, usize>,
+    audited: usize,
+}
+
+pub struct Auditor {
+    npm_client: NpmClient,
+    node_modules_dir: PathBuf,
+}
+
+impl Auditor {
+    pub fn new(npm_client: NpmClient) -> Self {
+        Self {
+            npm_client,
+            node_modules_dir: PathBuf::from("node_modules"),
+        }
+    }
+
+    /// Audit installed packages; returns false if any advisory is at or above `level`
+    pub async fn audit(&self, json: bool, level: Severity) -> Result<bool> {
+        let installed = self.collect_installed_versions().await?;
+
+        if installed.is_empty() {
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&Self::build_report(Vec::new(), 0))?
+                );
+            } else {
+                println!("{}", CliStyle::info("No installed packages to audit"));
+            }
+            return Ok(true);
+        }
+
+        let spinner = (!json).then(|| {
+            CliStyle::create_spinner(&format!("Auditing {} packages...", installed.len()))
+        });
+        let advisories = self.npm_client.get_bulk_advisories(&installed).await;
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+        let advisories = advisories?;
+
+        let vulnerabilities = Self::match_installed(&installed, advisories);
+        let passed = vulnerabilities
+            .iter()
+            .all(|vulnerability| vulnerability.advisory.severity < level);
+        let report = Self::build_report(vulnerabilities, installed.len());
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            Self::print_report(&report, level);
+        }
+
+        Ok(passed)
+    }
+
+    /// Advisory counts by severity for everything installed, without printing anything
+    pub async fn summary(&self) -> Result<BTreeMap<&'static str, usize>> {
+        let installed = self.collect_installed_versions().await?;
+        let advisories = if installed.is_empty() {
+            HashMap::new()
+        } else {
+            self.npm_client.get_bulk_advisories(&installed).await?
+        };
+        let vulnerabilities = Self::match_installed(&installed, advisories);
+        Ok(Self::build_report(vulnerabilities, installed.len()).summary)
+    }
+
+    /// Name -> versions for everything in node_modules, including scoped packages
+    async fn collect_installed_versions(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut installed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        if !self.node_modules_dir.exists() {
+            return Ok(installed);
+        }
+
+        let mut package_dirs = Vec::new();
+        let mut entries = fs::read_dir(&self.node_modules_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || !entry.file_type().await?.is_dir() {
+                continue;
+            }
+
+            if name.starts_with('@') {
+                let mut scoped = fs::read_dir(entry.path()).await?;
+                while let Some(scoped_entry) = scoped.next_entry().await? {
+                    if scoped_entry.file_type().await?.is_dir() {
+                        package_dirs.push(scoped_entry.path());
+                    }
+                }
+            } else {
+                package_dirs.push(entry.path());
+            }
+        }
+
+        for package_dir in package_dirs {
+            if let Some((name, version)) = Self::read_name_and_version(&package_dir).await {
+                let versions = installed.entry(name).or_default();
+                if !versions.contains(&version) {
+                    versions.push(version);
+                }
+            }
+        }
+
+        Ok(installed)
+    }
+
+    async fn read_name_and_version(package_dir: &Path) -> Option<(String, String)> {
+        let content = fs::read_to_string(package_dir.join("package.json"))
+            .await
+            .ok()?;
+        let package_json: PackageJson = serde_json::from_str(&content).ok()?;
+        Some((package_json.name?, package_json.version?))
+    }
+
+    /// The bulk endpoint only returns advisories affecting the versions sent, so attach
+    /// each advisory to every installed version of the package
+    fn match_installed(
+        installed: &BTreeMap<String, Vec<String>>,
+        advisories: HashMap<String, Vec<Advisory>>,
+    ) -> Vec<Vulnerability> {
+        let mut vulnerabilities = Vec::new();
+
+        for (package, package_advisories) in advisories {
+            let Some(versions) = installed.get(&package) else {
+                continue;
+            };
+            for advisory in package_advisories {
+                for version in versions {
+                    vulnerabilities.push(Vulnerability {
+                        package: package.clone(),
+                        version: version.clone(),
+                        advisory: advisory.clone(),
+                    });
+                }
+            }
+        }
+
+        vulnerabilities.sort_by(|a, b| {
+            b.advisory
+                .severity
+                .cmp(&a.advisory.severity)
+                .then_with(|| a.package.cmp(&b.package))
+                .then_with(|| a.advisory.id.cmp(&b.advisory.id))
+        });
+        vulnerabilities
+    }
+
+    fn build_report(vulnerabilities: Vec<Vulnerability>, audited: usize) -> AuditReport {
+        let mut summary = BTreeMap::new();
+        for severity in Severity::value_variants() {
+            summary.insert(severity.name(), 0);
+        }
+        for vulnerability in &vulnerabilities {
+            *summary
+                .entry(vulnerability.advisory.severity.name())
+                .or_default() += 1;
+        }
+
+        AuditReport {
+            vulnerabilities,
+            summary,
+            audited,
+        }
+    }
+
+    fn print_report(report: &AuditReport, level: Severity) {
+        if report.vulnerabilities.is_empty() {
+            println!(
+                "{} No known vulnerabilities in {} packages",
+                CliStyle::success(""),
+                style(report.audited).white().bold()
+            );
+            return;
+        }
+
+        for severity in Severity::value_variants().iter().rev() {
+            let group: Vec<&Vulnerability> = report
+                .vulnerabilities
+                .iter()
+                .filter(|vulnerability| vulnerability.advisory.severity == *severity)
+                .collect();
+            if group.is_empty() {
+                continue;
+            }
+
+            println!("\n{} ({})", severity.styled(), group.len());
+            for vulnerability in group {
+                println!(
+                    "  {} {}@{} {}",
+                    style("•").red(),
+                    style(&vulnerability.package).white().bold(),
+                    style(&vulnerability.version).dim(),
+                    vulnerability.advisory.title
+                );
+                println!(
+                    "    {} {}",
+                    CliStyle::dim_text("vulnerable:"),
+                    CliStyle::dim_text(&vulnerability.advisory.vulnerable_versions)
+                );
+                if !vulnerability.advisory.url.is_empty() {
+                    println!("    {}", CliStyle::cyan_text(&vulnerability.advisory.url));
+                }
+            }
+        }
+
+        let counts: Vec<String> = Severity::value_variants()
+            .iter()
+            .rev()
+            .filter_map(|severity| {
+                let count = report.summary.get(severity.name()).copied().unwrap_or(0);
+                (count > 0).then(|| format!("{count} {}", severity.name()))
+            })
+            .collect();
+
+        println!(
+            "\n{} {} vulnerabilities in {} packages ({})",
+            CliStyle::warning(""),
+            style(report.vulnerabilities.len()).yellow().bold(),
+            report.audited,
+            counts.join(", ")
+        );
+        println!(
+            "{}",
+            CliStyle::dim_text(&format!("Failing at severity {} or above", level.name()))
+        );
+    }
+}
@@ -2,6 +2,8 @@ use anyhow::{Result, anyhow};
 use console::style;
 
 use crate::cli_style::CliStyle;
+use crate::config::BundleConfig;
+use crate::shutdown;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
@@ -11,6 +13,8 @@ use tokio::io::{AsyncWriteExt, BufWriter};
 
 pub struct Bundler {
     entry_points: Vec<PathBuf>,
+    /// `[bundle] entry`, used instead of discovering one
+    configured_entry: Option<PathBuf>,
     output_dir: PathBuf,
     resolve_cache: HashMap<String, PathBuf>,
     module_cache: HashMap<PathBuf, ModuleInfo>,
@@ -23,10 +27,11 @@ struct ModuleInfo {
 }
 
 impl Bundler {
-    pub fn new() -> Self {
+    pub fn new(config: &BundleConfig) -> Self {
         Self {
             entry_points: vec![PathBuf::from("src/index.js")],
-            output_dir: PathBuf::from("dist"),
+            configured_entry: config.entry.clone(),
+            output_dir: config.out_dir.clone(),
             resolve_cache: HashMap::new(),
             module_cache: HashMap::new(),
         }
@@ -108,7 +113,7 @@ impl Bundler {
 
     async fn bundle_with_watch(&mut self, output_path: &Path, minify: bool) -> Result<()> {
         use std::collections::HashSet;
-        use tokio::time::{Duration, sleep};
+        use tokio::time::Duration;
 
         println!("{}", CliStyle::info("Performing initial bundle..."));
         self.bundle_once(output_path, minify).await?;
@@ -122,20 +127,19 @@ impl Bundler {
             watched_files.len()
         );
 
-        loop {
-            sleep(Duration::from_millis(500)).await;
-
+        let watcher = shutdown::Watcher::new();
+        while watcher.tick(Duration::from_millis(500)).await {
             let mut has_changes = false;
             let mut new_watched_files = HashSet::new();
 
             for file_path in &watched_files {
-                if let Ok(metadata) = fs::metadata(file_path).await {
-                    if metadata.modified().is_ok() {
-                        // Simple change detection - in production, we'd use proper file watching
-                        if !self.module_cache.contains_key(file_path) {
-                            has_changes = true;
-                            break;
-                        }
+                if let Ok(metadata) = fs::metadata(file_path).await
+                    && metadata.modified().is_ok()
+                {
+                    // Simple change detection - in production, we'd use proper file watching
+                    if !self.module_cache.contains_key(file_path) {
+                        has_changes = true;
+                        break;
                     }
                 }
             }
@@ -157,19 +161,32 @@ impl Bundler {
                 }
             }
         }
+
+        println!("{}", CliStyle::info("Stopped watching"));
+        Ok(())
     }
 
     async fn discover_entry_points(&mut self) -> Result<()> {
+        if let Some(entry) = &self.configured_entry {
+            if !entry.exists() {
+                return Err(anyhow!(
+                    "The entry point {} ([bundle] entry) doesn't exist",
+                    entry.display()
+                ));
+            }
+            self.entry_points = vec![entry.clone()];
+            return Ok(());
+        }
+
         // Check package.json for main field
-        if let Ok(content) = fs::read_to_string("package.json").await {
-            if let Ok(package_json) = serde_json::from_str::<Value>(&content) {
-                if let Some(main) = package_json.get("main").and_then(|m| m.as_str()) {
-                    let main_path = PathBuf::from(main);
-                    if main_path.exists() {
-                        self.entry_points = vec![main_path];
-                        return Ok(());
-                    }
-                }
+        if let Ok(content) = fs::read_to_string("package.json").await
+            && let Ok(package_json) = serde_json::from_str::<Value>(&content)
+            && let Some(main) = package_json.get("main").and_then(|m| m.as_str())
+        {
+            let main_path = PathBuf::from(main);
+            if main_path.exists() {
+                self.entry_points = vec![main_path];
+                return Ok(());
             }
         }
 
@@ -415,16 +432,14 @@ impl Bundler {
             if node_modules.exists() {
                 // Check for package.json main field
                 let package_json_path = node_modules.join("package.json");
-                if package_json_path.exists() {
-                    if let Ok(content) = fs::read_to_string(&package_json_path).await {
-                        if let Ok(package_json) = serde_json::from_str::<Value>(&content) {
-                            if let Some(main) = package_json.get("main").and_then(|m| m.as_str()) {
-                                let main_path = node_modules.join(main);
-                                if main_path.exists() {
-                                    return Ok(main_path);
-                                }
-                            }
-                        }
+                if package_json_path.exists()
+                    && let Ok(content) = fs::read_to_string(&package_json_path).await
+                    && let Ok(package_json) = serde_json::from_str::<Value>(&content)
+                    && let Some(main) = package_json.get("main").and_then(|m| m.as_str())
+                {
+                    let main_path = node_modules.join(main);
+                    if main_path.exists() {
+                        return Ok(main_path);
                     }
                 }
 
@@ -551,9 +566,3 @@ impl Bundler {
         }
     }
 }
-
-impl Default for Bundler {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -0,0 +1,99 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Prefix of ranges that take their version from a catalog
+const PROTOCOL: &str = "catalog:";
+
+/// The catalog `catalog:` with no name refers to
+const DEFAULT_CATALOG: &str = "default";
+
+/// Canonical ranges a monorepo root defines for dependencies its workspaces share, pnpm
+/// style: package.json's `catalog` is the default catalog and `catalogs` holds named
+/// ones. A dependency on `catalog:` (or `catalog:<name>`) uses the range listed there,
+/// so bumping it is one edit at the root.
+#[derive(Debug, Clone, Default)]
+pub struct Catalogs {
+    catalogs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct RootManifest {
+    #[serde(default)]
+    catalog: BTreeMap<String, String>,
+    #[serde(default)]
+    catalogs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Catalogs {
+    /// The catalogs of the package.json in `root`; none when there is no package.json
+    pub fn read(root: &Path) -> Result<Self> {
+        let path = root.join("package.json");
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return Ok(Self::default());
+        };
+        let manifest: RootManifest = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse catalogs in {}: {e}", path.display()))?;
+
+        // `catalog` is shorthand for `catalogs.default`
+        let mut catalogs = manifest.catalogs;
+        if !manifest.catalog.is_empty() {
+            catalogs
+                .entry(DEFAULT_CATALOG.to_string())
+                .or_default()
+                .extend(manifest.catalog);
+        }
+        Ok(Self { catalogs })
+    }
+
+    /// Whether `range` takes its version from a catalog
+    pub fn is_reference(range: &str) -> bool {
+        range.starts_with(PROTOCOL)
+    }
+
+    /// The range a dependency on `name` with `range` asks for: the catalog's entry for
+    /// `name` when `range` is a `catalog:` reference, otherwise `range` itself
+    pub fn resolve(&self, name: &str, range: &str) -> Result<String> {
+        let Some(catalog) = range.strip_prefix(PROTOCOL) else {
+            return Ok(range.to_string());
+        };
+        let catalog = match catalog.trim() {
+            "" => DEFAULT_CATALOG,
+            catalog => catalog,
+        };
+        let entries = self.catalogs.get(catalog).ok_or_else(|| {
+            anyhow!("{name}@{range} refers to the {catalog} catalog, which the workspace root doesn't define")
+        })?;
+        entries
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("{name}@{range}: the {catalog} catalog has no entry for {name}"))
+    }
+
+    /// `specs` with their `catalog:` ranges resolved
+    pub fn resolve_all(&self, specs: Vec<(String, String)>) -> Result<Vec<(String, String)>> {
+        specs
+            .into_iter()
+            .map(|(name, range)| {
+                let range = self.resolve(&name, &range)?;
+                Ok((name, range))
+            })
+            .collect()
+    }
+
+    /// Fingerprint of every entry, for keys that change when a catalog does
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for (catalog, entries) in &self.catalogs {
+            for (name, range) in entries {
+                hasher.update(format!("catalog:{catalog}:{name}:{range}\n").as_bytes());
+            }
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.catalogs.values().all(BTreeMap::is_empty)
+    }
+}
@@ -1,10 +1,44 @@
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle, TermLike};
+use std::io::{self, IsTerminal};
+use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 
+use crate::progress;
+
+/// How often plain-line progress prints the current status, per second
+const PLAIN_PROGRESS_HZ: u8 = 1;
+
+/// When to color output (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// When writing to a terminal, unless NO_COLOR is set or FORCE_COLOR asks for it anyway
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
 pub struct CliStyle;
 
 impl CliStyle {
+    /// Turn colors on or off for everything styled through `console`, spinners included
+    pub fn set_color(choice: ColorChoice) {
+        let env = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => match (env("NO_COLOR"), env("FORCE_COLOR")) {
+                (Some(_), _) => false,
+                (None, Some(force)) => force != "0",
+                // console's own check: a terminal, and CLICOLOR/CLICOLOR_FORCE
+                (None, None) => return,
+            },
+        };
+        console::set_colors_enabled(enabled);
+        console::set_colors_enabled_stderr(enabled);
+    }
+
     pub fn success(text: &str) -> String {
         format!("{} {}", style("✓").green().bold(), style(text).white())
     }
@@ -25,10 +59,6 @@ impl CliStyle {
         format!("{} {}", style("→").cyan(), style(text).white())
     }
 
-    pub fn bullet(text: &str) -> String {
-        format!("{} {}", style("•").dim(), style(text).white())
-    }
-
     pub fn package_name(name: &str) -> String {
         style(name).white().bold().to_string()
     }
@@ -45,8 +75,35 @@ impl CliStyle {
         style(text).cyan().to_string()
     }
 
+    /// Whether output can be redrawn in place and questions asked: not in CI, and both
+    /// stdout and stderr are terminals
+    pub fn is_interactive() -> bool {
+        static INTERACTIVE: OnceLock<bool> = OnceLock::new();
+        *INTERACTIVE.get_or_init(|| {
+            std::env::var("CI").is_err() && io::stdout().is_terminal() && io::stderr().is_terminal()
+        })
+    }
+
+    /// `pb` printing its status as plain lines on stderr, at most PLAIN_PROGRESS_HZ times a
+    /// second, plus the message it finishes with
+    fn plain_progress(pb: ProgressBar, template: &str, message: &str) -> ProgressBar {
+        pb.set_draw_target(ProgressDrawTarget::term_like_with_hz(
+            Box::new(PlainLines::default()),
+            PLAIN_PROGRESS_HZ,
+        ));
+        pb.set_style(ProgressStyle::default_spinner().template(template).unwrap());
+        pb.set_message(message.to_string());
+        pb
+    }
+
     pub fn create_spinner(message: &str) -> ProgressBar {
+        if progress::is_json() {
+            return ProgressBar::hidden();
+        }
         let pb = ProgressBar::new_spinner();
+        if !Self::is_interactive() {
+            return Self::plain_progress(pb, "{msg}", message);
+        }
         pb.set_style(
             ProgressStyle::default_spinner()
                 .template("{spinner:.cyan} {msg}")
@@ -59,7 +116,13 @@ impl CliStyle {
     }
 
     pub fn create_progress_bar(total: u64) -> ProgressBar {
+        if progress::is_json() {
+            return ProgressBar::hidden();
+        }
         let pb = ProgressBar::new(total);
+        if !Self::is_interactive() {
+            return Self::plain_progress(pb, "{pos}/{len} {msg}", "");
+        }
         pb.set_style(
             ProgressStyle::default_bar()
                 .template("{spinner:.cyan} {bar:40.green/dim} {pos:>3}/{len:3} {msg}")
@@ -100,12 +163,58 @@ impl CliStyle {
     pub fn section_header(title: &str) -> String {
         style(title).blue().bold().to_string()
     }
+}
+
+/// A progress draw target for CI logs and pipes, where redrawing a line in place would
+/// come out as a jumble of escape codes: each new status is printed on its own line
+#[derive(Debug, Default)]
+struct PlainLines {
+    last: Mutex<String>,
+}
+
+impl TermLike for PlainLines {
+    fn width(&self) -> u16 {
+        u16::MAX
+    }
+
+    fn move_cursor_up(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_down(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_right(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_left(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let line = line.trim_end();
+        let Ok(mut last) = self.last.lock() else {
+            return Ok(());
+        };
+        // Redraws of an unchanged status, and clearing it, print nothing
+        if !line.is_empty() && *last != line {
+            eprintln!("{line}");
+            *last = line.to_string();
+        }
+        Ok(())
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.write_line(s)
+    }
 
-    pub fn command_suggestion(command: &str) -> String {
-        style(command).cyan().to_string()
+    fn clear_line(&self) -> io::Result<()> {
+        Ok(())
     }
 
-    pub fn highlight(text: &str) -> String {
-        style(text).white().bold().to_string()
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
     }
 }
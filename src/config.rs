@@ -0,0 +1,647 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audit::Severity;
+use crate::content_store::LinkMode;
+use crate::errors::{self, ErrorCode};
+use crate::exec;
+use crate::package_info::{LockFormat, LockMode};
+use crate::version_range::VersionRange;
+
+/// Project-level configuration file, read from the project's root directory
+pub const PROJECT_CONFIG_FILE: &str = "clay.toml";
+
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClayConfig {
+    pub network: NetworkConfig,
+    pub registry: RegistryConfig,
+    pub audit: AuditConfig,
+    pub install: InstallConfig,
+    pub store: StoreConfig,
+    pub policy: PolicyConfig,
+    pub bundle: BundleConfig,
+    pub dev: DevConfig,
+}
+
+/// `clay bundle`, and the bundle `clay dev` serves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BundleConfig {
+    /// Module bundling starts from; defaults to package.json `main`, then src/index.js
+    /// and the like
+    pub entry: Option<PathBuf>,
+    /// Where bundle.js is written unless `--output` says otherwise
+    pub out_dir: PathBuf,
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        Self {
+            entry: None,
+            out_dir: PathBuf::from("dist"),
+        }
+    }
+}
+
+/// `clay dev`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DevConfig {
+    pub port: u16,
+    pub host: String,
+    /// Directory of static files served alongside the bundle
+    pub public_dir: PathBuf,
+    /// Directories whose sources trigger a rebuild when they change
+    pub watch: Vec<String>,
+}
+
+impl Default for DevConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            host: "localhost".to_string(),
+            public_dir: PathBuf::from("public"),
+            watch: ["src", "lib", "components"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// Packages a team never wants installed, or the only ones it does. Rules are `name`,
+/// `name@range` or `@scope/*`, and apply to dependencies at any depth.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Packages that may not be installed
+    pub deny: Vec<String>,
+    /// When not empty, the only packages that may be installed
+    pub allow: Vec<String>,
+}
+
+impl PolicyConfig {
+    /// Fails with EPOLICY if `name@version` is denied, or missing from a nonempty allow
+    /// list. A rule with an invalid range blocks everything it names rather than nothing.
+    pub fn check(&self, name: &str, version: &str) -> Result<()> {
+        if let Some(rule) = self
+            .deny
+            .iter()
+            .find(|rule| policy_rule_matches(rule, name, version).unwrap_or(true))
+        {
+            return Err(errors::coded(
+                ErrorCode::Policy,
+                format!("{name}@{version} is blocked by the policy rule deny = \"{rule}\""),
+            ));
+        }
+        if !self.allow.is_empty()
+            && !self
+                .allow
+                .iter()
+                .any(|rule| policy_rule_matches(rule, name, version).unwrap_or(false))
+        {
+            return Err(errors::coded(
+                ErrorCode::Policy,
+                format!("{name}@{version} isn't in the policy's allow list"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `rule` covers `name@version`; None if its range doesn't parse but the name
+/// matches
+fn policy_rule_matches(rule: &str, name: &str, version: &str) -> Option<bool> {
+    let (pattern, range) = match rule.rfind('@') {
+        Some(at) if at > 0 => (&rule[..at], Some(&rule[at + 1..])),
+        _ => (rule, None),
+    };
+    let name_matches = match pattern.strip_suffix("/*") {
+        Some(scope) => name
+            .strip_prefix(scope)
+            .is_some_and(|rest| rest.starts_with('/')),
+        None => pattern == name,
+    };
+    if !name_matches {
+        return Some(false);
+    }
+    match range {
+        Some(range) => VersionRange::parse(range)
+            .ok()
+            .map(|range| range.matches_str(version)),
+        None => Some(true),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct StoreConfig {
+    /// Where the content store lives; defaults to ~/.clay/content-store
+    pub path: Option<PathBuf>,
+    /// Size `clay store prune` evicts least recently used packages down to (e.g. "10GB")
+    pub max_size: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct InstallConfig {
+    /// How resolutions are persisted; detected from CI and existing lockfiles when unset
+    pub lock_mode: Option<LockMode>,
+    /// Lockfile written by explicit installs; an existing clay-lock.json is kept when unset
+    pub lock_format: Option<LockFormat>,
+    /// Keep a binary copy of the lockfile in node_modules for faster reads
+    pub lock_cache: bool,
+    /// How files are placed in node_modules from the content store
+    pub link_mode: LinkMode,
+    /// Save exact versions to package.json instead of ranges
+    pub save_exact: bool,
+    /// Range operator saved before new versions: "^" (the default), "~" or ""
+    pub save_prefix: Option<String>,
+    /// Don't run the install scripts of dependencies
+    pub ignore_scripts: bool,
+    /// How packages are laid out in node_modules
+    pub node_linker: NodeLinker,
+    /// Also add @types packages for installed packages that ship no types
+    pub with_types: bool,
+    /// Install even when package.json's packageManager names another package manager or
+    /// its clay.requiredVersion excludes this clay
+    pub ignore_package_manager: bool,
+}
+
+/// Layout of node_modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeLinker {
+    /// Every package directly in node_modules, as npm lays it out
+    #[default]
+    Hoisted,
+    /// Packages under node_modules/.clay, each seeing only its own dependencies, and only
+    /// the project's declared dependencies at the top level
+    Isolated,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AuditConfig {
+    /// Lowest advisory severity that makes `clay audit` exit nonzero
+    pub level: Severity,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            level: Severity::Low,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RegistryConfig {
+    /// Registries tried in order; later entries are fallbacks for 404s, 5xx and timeouts
+    pub urls: Vec<String>,
+    /// Registry per package scope (`"@my-org" = "https://npm.pkg.github.com"`)
+    pub scopes: BTreeMap<String, String>,
+    /// Send credentials with every request, including tarballs hosted elsewhere
+    pub always_auth: bool,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            urls: vec![DEFAULT_REGISTRY.to_string()],
+            scopes: BTreeMap::new(),
+            always_auth: false,
+        }
+    }
+}
+
+impl RegistryConfig {
+    /// Configured registries without trailing slashes, never empty
+    pub fn urls(&self) -> Vec<String> {
+        let urls: Vec<String> = self
+            .urls
+            .iter()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+
+        if urls.is_empty() {
+            vec![DEFAULT_REGISTRY.to_string()]
+        } else {
+            urls
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NetworkConfig {
+    /// Total time allowed for a single registry request, in seconds
+    pub timeout: u64,
+    /// Time allowed to establish a connection, in seconds
+    pub connect_timeout: u64,
+    /// Maximum number of concurrent tarball downloads
+    pub concurrency: usize,
+    /// Maximum number of concurrent metadata fetches while resolving
+    pub resolve_concurrency: usize,
+    /// Times a package that fails to download or unpack is tried again before it counts
+    /// as failed
+    pub fetch_retries: u32,
+    /// User-Agent sent with every request; defaults to .npmrc `user-agent`, then clay/<version>
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request; `${VAR}` references are expanded
+    pub headers: BTreeMap<String, String>,
+    /// Never contact a registry: installs use cached metadata and the content store, and
+    /// anything they lack fails rather than being fetched
+    pub offline: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            connect_timeout: 10,
+            concurrency: 30,
+            resolve_concurrency: 50,
+            fetch_retries: 2,
+            user_agent: None,
+            headers: BTreeMap::new(),
+            offline: false,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout)
+    }
+
+    /// Concurrency clamped to at least one permit so a bad value can't deadlock installs
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.max(1)
+    }
+
+    pub fn resolve_concurrency(&self) -> usize {
+        self.resolve_concurrency.max(1)
+    }
+}
+
+impl ClayConfig {
+    /// The configuration in effect: defaults, then ~/.clay/config.toml, clay.toml and
+    /// CLAY_* environment variables, each overriding the last
+    pub fn load() -> Result<Self> {
+        ConfigLayers::load()?.config()
+    }
+}
+
+/// The clay.toml of the project the current directory is in, which needn't exist yet
+pub fn project_config_path() -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) => exec::project_root(&cwd).join(PROJECT_CONFIG_FILE),
+        Err(_) => PathBuf::from(PROJECT_CONFIG_FILE),
+    }
+}
+
+/// Per-user configuration, overridden by a project's clay.toml
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".clay").join("config.toml"))
+}
+
+/// Settings `clay config` reads and writes, as `<section>.<key>`
+pub const KEYS: &[&str] = &[
+    "network.timeout",
+    "network.connect-timeout",
+    "network.concurrency",
+    "network.resolve-concurrency",
+    "network.fetch-retries",
+    "network.user-agent",
+    "network.headers",
+    "network.offline",
+    "registry.urls",
+    "registry.scopes",
+    "registry.always-auth",
+    "audit.level",
+    "install.lock-mode",
+    "install.lock-format",
+    "install.lock-cache",
+    "install.link-mode",
+    "install.save-exact",
+    "install.save-prefix",
+    "install.ignore-scripts",
+    "install.node-linker",
+    "install.with-types",
+    "install.ignore-package-manager",
+    "store.path",
+    "store.max-size",
+    "policy.deny",
+    "policy.allow",
+    "bundle.entry",
+    "bundle.out-dir",
+    "dev.port",
+    "dev.host",
+    "dev.public-dir",
+    "dev.watch",
+];
+
+/// Shorter environment variables for common settings, as CI configures them; the full
+/// CLAY_<SECTION>_<KEY> name wins when both are set
+const ENV_ALIASES: &[(&str, &str)] = &[
+    ("CLAY_REGISTRY", "registry.urls"),
+    ("CLAY_CONCURRENCY", "network.concurrency"),
+    ("CLAY_TIMEOUT", "network.timeout"),
+    ("CLAY_OFFLINE", "network.offline"),
+    ("CLAY_IGNORE_SCRIPTS", "install.ignore-scripts"),
+    ("CLAY_LOCK_MODE", "install.lock-mode"),
+    ("CLAY_SAVE_EXACT", "install.save-exact"),
+    ("CLAY_NODE_LINKER", "install.node-linker"),
+    ("CLAY_STORE", "store.path"),
+];
+
+/// Keys holding a table, whose entries can be set one at a time
+/// (`registry.scopes.@my-org`)
+const TABLE_KEYS: &[&str] = &["network.headers", "registry.scopes"];
+
+/// Where a setting's value came from, lowest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Default,
+    User,
+    Project,
+    Env,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConfigSource::Default => "default",
+            ConfigSource::User => "user",
+            ConfigSource::Project => "project",
+            ConfigSource::Env => "env",
+        })
+    }
+}
+
+/// The configuration as each source sets it, before they are merged
+pub struct ConfigLayers {
+    /// Lowest precedence first; defaults aren't a layer
+    layers: Vec<(ConfigSource, toml::Table)>,
+}
+
+impl ConfigLayers {
+    pub fn load() -> Result<Self> {
+        let mut layers = Vec::new();
+        if let Some(path) = user_config_path()
+            && let Some(table) = read_config_file(&path)?
+        {
+            layers.push((ConfigSource::User, table));
+        }
+        if let Some(table) = read_config_file(&project_config_path())? {
+            layers.push((ConfigSource::Project, table));
+        }
+        let env = env_layer()?;
+        if !env.is_empty() {
+            layers.push((ConfigSource::Env, env));
+        }
+        Ok(Self { layers })
+    }
+
+    pub fn config(&self) -> Result<ClayConfig> {
+        let mut merged = toml::Table::new();
+        for (_, table) in &self.layers {
+            merge_tables(&mut merged, table);
+        }
+        ClayConfig::deserialize(merged).context("Invalid configuration")
+    }
+
+    /// A setting's value and the source it comes from; None for an optional setting
+    /// nobody set
+    pub fn get(&self, key: &str) -> Result<Option<(toml::Value, ConfigSource)>> {
+        let path = key_path(key)?;
+        for (source, table) in self.layers.iter().rev() {
+            if let Some(value) = lookup(table, &path) {
+                return Ok(Some((value.clone(), *source)));
+            }
+        }
+        let defaults = toml::Table::try_from(ClayConfig::default())?;
+        Ok(lookup(&defaults, &path).map(|value| (value.clone(), ConfigSource::Default)))
+    }
+}
+
+/// Environment variable that overrides `key`: `network.fetch-retries` is
+/// CLAY_NETWORK_FETCH_RETRIES
+pub fn env_var(key: &str) -> String {
+    format!("CLAY_{}", key.to_uppercase().replace(['.', '-'], "_"))
+}
+
+/// The environment variable overriding `key` in this shell and its value, if any: the
+/// full name, else the key's short alias
+pub fn env_override(key: &str) -> Option<(String, String)> {
+    std::iter::once(env_var(key))
+        .chain(
+            ENV_ALIASES
+                .iter()
+                .filter(|(_, aliased)| *aliased == key)
+                .map(|(alias, _)| alias.to_string()),
+        )
+        .find_map(|var| Some((var.clone(), std::env::var(&var).ok()?)))
+}
+
+/// Store `raw` as `key` in the config file at `path`, keeping its comments and layout
+pub fn set_value(path: &Path, key: &str, raw: &str) -> Result<()> {
+    let key_path = key_path(key)?;
+    let value = parse_value(key, raw)?;
+    edit_config_file(path, |document| {
+        let (last, parents) = key_path.split_last().expect("keys have a section");
+        let mut table = document.as_table_mut();
+        for part in parents {
+            table = table
+                .entry(part)
+                .or_insert_with(|| {
+                    let mut table = toml_edit::Table::new();
+                    table.set_implicit(true);
+                    toml_edit::Item::Table(table)
+                })
+                .as_table_mut()
+                .with_context(|| format!("{part} isn't a table in {}", path.display()))?;
+        }
+        table.insert(last, toml_edit::value(to_edit_value(&value)?));
+        Ok(())
+    })
+    .with_context(|| format!("Couldn't set {key}"))
+}
+
+/// Remove `key` from the config file at `path`; false if it wasn't set there
+pub fn unset_value(path: &Path, key: &str) -> Result<bool> {
+    let key_path = key_path(key)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut removed = false;
+    edit_config_file(path, |document| {
+        let (last, parents) = key_path.split_last().expect("keys have a section");
+        let mut table = Some(document.as_table_mut());
+        for part in parents {
+            table = table.and_then(|table| table.get_mut(part)?.as_table_mut());
+        }
+        removed = table.and_then(|table| table.remove(last)).is_some();
+        Ok(())
+    })?;
+    Ok(removed)
+}
+
+/// Apply `edit` to the config file at `path`, created if missing, and write it back only
+/// if the result is still a valid configuration
+fn edit_config_file(
+    path: &Path,
+    edit: impl FnOnce(&mut toml_edit::DocumentMut) -> Result<()>,
+) -> Result<()> {
+    let content = if path.exists() {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+    edit(&mut document)?;
+
+    let table: toml::Table = toml::from_str(&document.to_string())?;
+    ClayConfig::deserialize(table)?;
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, document.to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn read_config_file(path: &Path) -> Result<Option<toml::Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+    ClayConfig::deserialize(table.clone())
+        .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+    tracing::debug!("loaded {}", path.display());
+    Ok(Some(table))
+}
+
+/// Settings given as CLAY_* variables
+fn env_layer() -> Result<toml::Table> {
+    let mut layer = toml::Table::new();
+    for key in KEYS {
+        let Some((var, raw)) = env_override(key) else {
+            continue;
+        };
+        let (section, field) = key.split_once('.').expect("keys have a section");
+        let mut setting = toml::Table::new();
+        setting.insert(
+            section.to_string(),
+            toml::Value::Table(toml::Table::from_iter([(
+                field.to_string(),
+                parse_value(key, &raw)?,
+            )])),
+        );
+        ClayConfig::deserialize(setting.clone()).with_context(|| format!("Invalid {var}"))?;
+        merge_tables(&mut layer, &setting);
+    }
+    Ok(layer)
+}
+
+/// A key's path through the config tables, checked against KEYS and TABLE_KEYS
+fn key_path(key: &str) -> Result<Vec<String>> {
+    if KEYS.contains(&key) {
+        return Ok(key.split('.').map(str::to_string).collect());
+    }
+    for table in TABLE_KEYS {
+        // Entries (scopes, header names) are taken whole, dots and all
+        if let Some(entry) = key
+            .strip_prefix(table)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .filter(|entry| !entry.is_empty())
+        {
+            let mut path: Vec<String> = table.split('.').map(str::to_string).collect();
+            path.push(entry.to_string());
+            return Ok(path);
+        }
+    }
+    Err(anyhow!(
+        "Unknown config key '{key}'; `clay config list` shows them all"
+    ))
+}
+
+/// A value as written on the command line or in the environment: TOML (`30`, `true`,
+/// `["a", "b"]`) or else a plain string. Comma-separated strings become lists for keys
+/// that take one, and switches also take 1/0, yes/no and on/off.
+fn parse_value(key: &str, raw: &str) -> Result<toml::Value> {
+    let value = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()));
+
+    let defaults = toml::Table::try_from(ClayConfig::default())?;
+    let default = lookup(&defaults, &key_path(key)?);
+    let takes_list = default.is_some_and(|default| default.is_array());
+    let takes_bool = default.is_some_and(|default| default.is_bool());
+    Ok(match value {
+        toml::Value::Integer(flag @ (0 | 1)) if takes_bool => toml::Value::Boolean(flag == 1),
+        toml::Value::String(flag) if takes_bool => match flag.to_lowercase().as_str() {
+            "yes" | "on" => toml::Value::Boolean(true),
+            "no" | "off" => toml::Value::Boolean(false),
+            _ => toml::Value::String(flag),
+        },
+        toml::Value::String(list) if takes_list => toml::Value::Array(
+            list.split(',')
+                .map(|item| toml::Value::String(item.trim().to_string()))
+                .filter(|item| item.as_str() != Some(""))
+                .collect(),
+        ),
+        value => value,
+    })
+}
+
+fn lookup<'a>(table: &'a toml::Table, path: &[String]) -> Option<&'a toml::Value> {
+    let (first, rest) = path.split_first()?;
+    let value = table.get(first)?;
+    if rest.is_empty() {
+        Some(value)
+    } else {
+        lookup(value.as_table()?, rest)
+    }
+}
+
+/// Overlay `over` onto `base`, merging tables key by key and replacing everything else
+fn merge_tables(base: &mut toml::Table, over: &toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn to_edit_value(value: &toml::Value) -> Result<toml_edit::Value> {
+    let document: toml_edit::DocumentMut = format!("value = {value}").parse()?;
+    document["value"]
+        .as_value()
+        .cloned()
+        .ok_or_else(|| anyhow!("Can't write {value} to a config file"))
+}
@@ -1,18 +1,39 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
 use console::style;
 
 use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::links;
+use crate::long_path;
 use crate::package_info::DependencyTree;
+use crate::shutdown::CleanupGuard;
 use dashmap::DashMap;
 use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
-use sha1::{Digest, Sha1};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::Read;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use tar::Archive;
 use tokio::fs;
+use tokio::sync::Semaphore;
+use walkdir::WalkDir;
+
+/// Run CPU- and disk-bound install work (hashing, unpacking, linking) on the blocking
+/// pool, at most one job per CPU at a time so downloads keep flowing alongside it
+pub async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    static POOL: OnceLock<Semaphore> = OnceLock::new();
+    let pool = POOL.get_or_init(|| {
+        Semaphore::new(std::thread::available_parallelism().map_or(4, |n| n.get()))
+    });
+
+    let _permit = pool.acquire().await?;
+    tokio::task::spawn_blocking(work).await?
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentAddress {
@@ -28,23 +49,126 @@ pub struct PackageMetadata {
     pub content_address: ContentAddress,
     pub dependencies: Option<HashMap<String, String>>,
     pub files: Vec<String>,
+    /// Each file by its path inside the package. Empty for packages stored before files
+    /// were addressed individually, which keep their whole tarball instead.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_hashes: BTreeMap<String, StoredFile>,
+    /// Symlinks inside the package, by path, to where they point relative to themselves
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub symlinks: BTreeMap<String, String>,
+    /// When an install last stored or linked the package; unknown for older entries
+    #[serde(default)]
+    pub last_accessed: Option<DateTime<Utc>>,
+    /// Tarball URL it was downloaded from, for fetching it again if the store is damaged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+}
+
+/// A file stored once under `files/` by the hash of its contents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub hash: String,
+    pub size: u64,
+    /// Executables are stored separately, since hardlinks share one file mode
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub executable: bool,
+}
+
+/// What `store_files` wrote for one package
+#[derive(Default)]
+struct StoredEntries {
+    files: BTreeMap<String, StoredFile>,
+    symlinks: BTreeMap<String, String>,
 }
 
+/// How package files get from the store's unpacked copy into node_modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Reflink where the filesystem supports it, else hardlink, else copy
+    #[default]
+    Auto,
+    /// Copy-on-write clone (APFS, btrfs, XFS), copying elsewhere
+    Reflink,
+    /// Hardlink, copying across filesystems
+    Hardlink,
+    Copy,
+}
+
+/// A project whose node_modules was installed from the store, keyed in `projects.json`
+/// by its directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectReference {
+    /// Dependency fingerprint of its package.json; the cached tree's packages are kept too
+    pub tree_hash: Option<String>,
+    /// `name@version` of every package in its node_modules
+    pub packages: BTreeSet<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Name of the manifest inside a `clay store export` archive, written first
+const ARCHIVE_MANIFEST: &str = "clay-store.json";
+
+/// What a store archive holds; the stored objects follow at their store paths
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreArchiveManifest {
+    packages: BTreeMap<String, PackageMetadata>,
+    trees: Vec<DependencyTree>,
+}
+
+/// Outcome of `ContentStore::migrate_into`
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub migrated: usize,
+    /// Packages whose stored content no longer matches its hash; reinstalling refetches them
+    pub corrupted: Vec<String>,
+}
+
+/// Outcome of `ContentStore::dedupe_projects`
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    pub projects: usize,
+    /// Project files identical to a stored file but not linked to it
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Distinguishes concurrent unpacks of the same content within one process
+static UNPACK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 pub struct ContentStore {
     store_path: PathBuf,
+    link_mode: LinkMode,
     index: Arc<DashMap<String, ContentAddress>>,
     package_index: Arc<DashMap<String, PackageMetadata>>,
     tree_index: Arc<DashMap<String, DependencyTree>>,
+    /// Access times changed since the index was last written
+    access_dirty: AtomicBool,
 }
 
 impl ContentStore {
     pub fn new() -> Self {
-        let store_path = Self::get_store_path();
+        Self::at(Self::get_store_path(), LinkMode::default())
+    }
+
+    /// The store at `[store] path`, or the default location, linking per `[install]`
+    pub fn from_config(config: &ClayConfig) -> Self {
+        let store_path = config
+            .store
+            .path
+            .clone()
+            .unwrap_or_else(Self::get_store_path);
+        Self::at(store_path, config.install.link_mode)
+    }
+
+    pub fn at(store_path: PathBuf, link_mode: LinkMode) -> Self {
         Self {
             store_path,
+            link_mode,
             index: Arc::new(DashMap::new()),
             package_index: Arc::new(DashMap::new()),
             tree_index: Arc::new(DashMap::new()),
+            access_dirty: AtomicBool::new(false),
         }
     }
 
@@ -54,39 +178,40 @@ impl ContentStore {
         Ok(())
     }
 
+    /// Store a package's files, each under the hash of its contents, so files shared
+    /// between packages and versions are kept once. Damaged files are replaced.
+    #[tracing::instrument(name = "store", skip_all, fields(name = package_name, version = package_version))]
     pub async fn store_package(
         &self,
         package_name: &str,
         package_version: &str,
         tarball_data: &[u8],
         integrity_hash: &str,
+        tarball_url: &str,
     ) -> Result<ContentAddress> {
-        // Calculate content hash
-        let content_hash = self.calculate_content_hash(tarball_data);
+        let package_key = format!("{package_name}@{package_version}");
+        // Entries from older stores are stored again under BLAKE3; cleanup then drops
+        // the SHA-1 copies
+        if let Some(metadata) = self.package_index.get(&package_key)
+            && !metadata.file_hashes.is_empty()
+            && metadata
+                .file_hashes
+                .values()
+                .all(|file| Self::is_current_hash(&file.hash))
+            && self.has_stored_objects(&metadata)
+        {
+            return Ok(metadata.content_address.clone());
+        }
+
+        let content_hash = Self::content_hash(tarball_data);
         let content_address = ContentAddress {
             hash: content_hash.clone(),
             size: tarball_data.len() as u64,
             integrity: integrity_hash.to_string(),
         };
 
-        // Check if content already exists
-        if let Some(existing) = self.index.get(&content_hash) {
-            // Silent - package already in content store
-            return Ok(existing.clone());
-        }
-
-        // Store the content
-        let content_path = self.get_content_path(&content_hash);
-        if let Some(parent) = content_path.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-
-        // Compress and store the tarball
-        let compressed_data = self.compress_data(tarball_data)?;
-        fs::write(&content_path, &compressed_data).await?;
-
-        // Extract and analyze package contents
-        let package_metadata = self
+        let stored = self.store_files(tarball_data).await?;
+        let mut package_metadata = self
             .analyze_package_content(
                 package_name,
                 package_version,
@@ -94,49 +219,333 @@ impl ContentStore {
                 content_address.clone(),
             )
             .await?;
+        package_metadata.file_hashes = stored.files;
+        package_metadata.symlinks = stored.symlinks;
+        package_metadata.resolved = Some(tarball_url.to_string());
 
         // Update indices
-        self.index
-            .insert(content_hash.clone(), content_address.clone());
-        let package_key = format!("{package_name}@{package_version}");
+        self.index.insert(content_hash, content_address.clone());
         self.package_index.insert(package_key, package_metadata);
 
         // Persist index
         self.save_index().await?;
 
-        // Silent storage - no output needed for clean final summary
-
         Ok(content_address)
     }
 
+    /// Write every regular file of a tarball into `files/`, skipping contents already
+    /// stored, and map the package's paths to them. Hardlinks map to the file they link
+    /// to; symlinks are recorded when they point inside the package. Devices, FIFOs and
+    /// other special entries are dropped.
+    async fn store_files(&self, tarball_data: &[u8]) -> Result<StoredEntries> {
+        let files_dir = self.store_path.join("files");
+        let tarball = tarball_data.to_vec();
+
+        run_blocking(move || -> Result<StoredEntries> {
+            let mut stored = StoredEntries::default();
+            let mut symlinks = Vec::new();
+            let mut archive = Archive::new(GzDecoder::new(&tarball[..]));
+
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let Some(relative) = Self::package_path(&entry.path()?) else {
+                    continue;
+                };
+                let key = relative.to_string_lossy().replace('\\', "/");
+                let entry_type = entry.header().entry_type();
+
+                if entry_type.is_symlink() {
+                    if let Some(target) = entry.link_name()? {
+                        symlinks.push((relative, target.into_owned()));
+                    }
+                    continue;
+                }
+                if entry_type.is_hard_link() {
+                    if let Some(source) = entry.link_name()?.and_then(|source| {
+                        Self::package_path(&source)
+                            .map(|source| source.to_string_lossy().replace('\\', "/"))
+                    }) && let Some(file) = stored.files.get(&source).cloned()
+                    {
+                        stored.files.insert(key, file);
+                    }
+                    continue;
+                }
+                if !entry_type.is_file() {
+                    continue;
+                }
+
+                let executable = Self::is_executable(entry.header());
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+
+                let file = StoredFile {
+                    hash: Self::content_hash(&contents),
+                    size: contents.len() as u64,
+                    executable,
+                };
+                let file_path = Self::file_path_in(&files_dir, &file);
+                // Rewrites a damaged copy left by an earlier store
+                if std::fs::metadata(&file_path)
+                    .map_or(true, |metadata| metadata.len() != file.size)
+                {
+                    Self::write_stored_file(&file_path, &contents, executable)?;
+                }
+                stored.files.insert(key, file);
+            }
+
+            for (link, target) in Self::contained_symlinks(symlinks) {
+                stored.symlinks.insert(
+                    link.to_string_lossy().replace('\\', "/"),
+                    target.to_string_lossy().replace('\\', "/"),
+                );
+            }
+            Ok(stored)
+        })
+        .await
+    }
+
+    /// Write through a uniquely named temp file, since another install may be storing the
+    /// same contents
+    fn write_stored_file(path: &Path, contents: &[u8], executable: bool) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temp_path = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            UNPACK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp_path, contents)?;
+        Self::set_file_mode(&temp_path, executable)?;
+
+        if std::fs::rename(&temp_path, path).is_err() {
+            std::fs::remove_file(&temp_path).ok();
+        }
+        Ok(())
+    }
+
+    /// Put a package into `target_path` from the store, reflinking, hardlinking or copying
+    /// its files per the link mode. Returns false if the package isn't in the store.
     pub async fn link_package(
         &self,
         package_name: &str,
         package_version: &str,
         target_path: &Path,
+    ) -> Result<bool> {
+        self.place_package(package_name, package_version, target_path, self.link_mode)
+            .await
+    }
+
+    /// Put a copy of a stored package into `target_path` that shares nothing with the
+    /// store, safe to edit. Returns false if the package isn't in the store.
+    pub async fn copy_package(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        target_path: &Path,
+    ) -> Result<bool> {
+        self.place_package(package_name, package_version, target_path, LinkMode::Copy)
+            .await
+    }
+
+    async fn place_package(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        target_path: &Path,
+        link_mode: LinkMode,
     ) -> Result<bool> {
         let package_key = format!("{package_name}@{package_version}");
+        let Some(metadata) = self.package_index.get_mut(&package_key).map(|mut entry| {
+            entry.last_accessed = Some(Utc::now());
+            entry.clone()
+        }) else {
+            return Ok(false);
+        };
+        self.access_dirty.store(true, Ordering::Relaxed);
 
-        if let Some(metadata) = self.package_index.get(&package_key) {
-            let content_path = self.get_content_path(&metadata.content_address.hash);
+        // Missing or damaged content; the caller fetches the package again
+        if !self.has_stored_objects(&metadata) {
+            return Ok(false);
+        }
+
+        if metadata.file_hashes.is_empty() {
+            return self
+                .link_tarball_package(&metadata.content_address.hash, target_path, link_mode)
+                .await;
+        }
 
-            if content_path.exists() {
-                // Create target directory
-                if let Some(parent) = target_path.parent() {
-                    fs::create_dir_all(parent).await?;
+        let files: Vec<(String, PathBuf)> = metadata
+            .file_hashes
+            .iter()
+            .map(|(path, file)| (path.clone(), self.get_file_path(file)))
+            .collect();
+
+        let symlinks = metadata.symlinks;
+        Self::link_into(target_path, move |dest| {
+            let mut link_mode = link_mode;
+            std::fs::create_dir_all(long_path::extend(dest))?;
+            for (relative, source) in &files {
+                let target = dest.join(relative);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(long_path::extend(parent))?;
+                }
+                link_mode = Self::link_file(source, &target, link_mode)?;
+            }
+            // After the files, so Windows can tell what each link points at
+            for (relative, link_target) in &symlinks {
+                let link = dest.join(relative);
+                if let Some(parent) = link.parent() {
+                    std::fs::create_dir_all(long_path::extend(parent))?;
                 }
+                links::symlink(Path::new(link_target), &long_path::extend(&link))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Packages stored before per-file addressing keep their whole tarball: unpack it once
+    /// into the store and link from that copy
+    async fn link_tarball_package(
+        &self,
+        hash: &str,
+        target_path: &Path,
+        link_mode: LinkMode,
+    ) -> Result<bool> {
+        let content_path = self.get_content_path(hash);
+        if !content_path.exists() {
+            return Ok(false);
+        }
+
+        let unpacked_path = self.get_unpacked_path(hash);
+        if !unpacked_path.exists() {
+            self.unpack(&content_path, &unpacked_path).await?;
+        }
 
-                // Extract package to target location
-                self.extract_package_from_store(&content_path, target_path)
-                    .await?;
+        Self::link_into(target_path, move |dest| {
+            Self::link_tree(&unpacked_path, dest, link_mode)
+        })
+        .await
+    }
 
-                // Silent linking - clean final output
+    /// Build a package directory in a sibling of `target_path` and rename it into place,
+    /// so an interrupted link never looks installed
+    async fn link_into(
+        target_path: &Path,
+        build: impl FnOnce(&Path) -> Result<()> + Send + 'static,
+    ) -> Result<bool> {
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut temp_name = target_path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".clay-link");
+        let temp_dir = target_path.with_file_name(temp_name);
+        fs::remove_dir_all(long_path::extend(&temp_dir)).await.ok();
+        let _temp_guard = CleanupGuard::new(&temp_dir);
+
+        let dest = temp_dir.clone();
+        if let Err(e) = run_blocking(move || build(&dest)).await {
+            fs::remove_dir_all(long_path::extend(&temp_dir)).await.ok();
+            return Err(e);
+        }
+        fs::rename(&temp_dir, target_path).await?;
 
-                return Ok(true);
+        Ok(true)
+    }
+
+    /// Extract a stored tarball to its unpacked location. Another install may be doing the
+    /// same, so this extracts to a unique path and keeps whichever copy lands first.
+    async fn unpack(&self, content_path: &Path, unpacked_path: &Path) -> Result<()> {
+        if let Some(parent) = unpacked_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let partial_path = unpacked_path.with_extension(format!(
+            "{}-{}.partial",
+            std::process::id(),
+            UNPACK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _partial_guard = CleanupGuard::new(&partial_path);
+        self.extract_package_from_store(content_path, &partial_path)
+            .await?;
+
+        if fs::rename(&partial_path, unpacked_path).await.is_err() {
+            fs::remove_dir_all(&partial_path).await.ok();
+            if !unpacked_path.exists() {
+                return Err(anyhow::anyhow!(
+                    "Failed to unpack {} into the content store",
+                    content_path.display()
+                ));
             }
         }
+        Ok(())
+    }
 
-        Ok(false)
+    /// Recreate `source` at `dest`, linking each file per `link_mode`
+    fn link_tree(source: &Path, dest: &Path, link_mode: LinkMode) -> Result<()> {
+        let mut link_mode = link_mode;
+        // Made once the files are in place, so Windows can tell what each points at
+        let mut symlinks = Vec::new();
+
+        for entry in WalkDir::new(source) {
+            let entry = entry?;
+            let target = dest.join(entry.path().strip_prefix(source)?);
+            let file_type = entry.file_type();
+
+            if file_type.is_dir() {
+                std::fs::create_dir_all(long_path::extend(&target))?;
+            } else if file_type.is_symlink() {
+                symlinks.push((entry.into_path(), target));
+            } else {
+                link_mode = Self::link_file(entry.path(), &target, link_mode)?;
+            }
+        }
+
+        for (source, target) in symlinks {
+            links::copy_symlink(&source, &long_path::extend(&target))?;
+        }
+        Ok(())
+    }
+
+    /// Link one file, falling back from reflink to hardlink to copy. Returns the mode that
+    /// worked, so the rest of the tree doesn't retry a method the filesystem rejected.
+    fn link_file(source: &Path, target: &Path, link_mode: LinkMode) -> Result<LinkMode> {
+        let (source, target) = (&*long_path::extend(source), &*long_path::extend(target));
+        let mut link_mode = link_mode;
+        loop {
+            let linked = match link_mode {
+                // A clone gets default permissions, so carry the executable bit over
+                LinkMode::Auto | LinkMode::Reflink => reflink_copy::reflink(source, target)
+                    .and_then(|()| {
+                        std::fs::set_permissions(target, std::fs::metadata(source)?.permissions())
+                    }),
+                LinkMode::Hardlink => std::fs::hard_link(source, target),
+                LinkMode::Copy => {
+                    std::fs::copy(source, target)?;
+                    return Ok(LinkMode::Copy);
+                }
+            };
+            if linked.is_ok() {
+                return Ok(link_mode);
+            }
+
+            std::fs::remove_file(target).ok();
+            link_mode = match link_mode {
+                LinkMode::Auto => LinkMode::Hardlink,
+                _ => LinkMode::Copy,
+            };
+        }
+    }
+
+    /// Check whether a package's content is indexed and present on disk
+    pub fn has_package(&self, package_name: &str, package_version: &str) -> bool {
+        let package_key = format!("{package_name}@{package_version}");
+        self.package_index
+            .get(&package_key)
+            .is_some_and(|metadata| self.has_stored_objects(&metadata))
     }
 
     pub async fn get_package_info(
@@ -164,7 +573,7 @@ impl ContentStore {
         }
 
         let tree_json = serde_json::to_string_pretty(&tree)?;
-        fs::write(&tree_path, tree_json).await?;
+        Self::write_atomic(&tree_path, tree_json.as_bytes()).await?;
 
         // Silent storage - only log during development if needed
         // println!("Stored dependency tree ({})", &tree_hash[..8]);
@@ -181,13 +590,12 @@ impl ContentStore {
 
         // Try loading from disk
         let tree_path = self.get_tree_path(tree_hash);
-        if tree_path.exists() {
-            if let Ok(content) = fs::read_to_string(&tree_path).await {
-                if let Ok(tree) = serde_json::from_str::<DependencyTree>(&content) {
-                    self.tree_index.insert(tree_hash.to_string(), tree.clone());
-                    return Some(tree);
-                }
-            }
+        if tree_path.exists()
+            && let Ok(content) = fs::read_to_string(&tree_path).await
+            && let Ok(tree) = serde_json::from_str::<DependencyTree>(&content)
+        {
+            self.tree_index.insert(tree_hash.to_string(), tree.clone());
+            return Some(tree);
         }
 
         None
@@ -198,6 +606,96 @@ impl ContentStore {
         self.tree_index.contains_key(tree_hash) || self.get_tree_path(tree_hash).exists()
     }
 
+    /// Find files in recorded projects' node_modules identical to stored files but not
+    /// linked to them; with `apply`, replace them with hardlinks to the store
+    pub async fn dedupe_projects(&self, apply: bool) -> Result<DedupeReport> {
+        let dedupe_spinner = CliStyle::create_spinner("Scanning project node_modules...");
+
+        let node_modules_dirs: Vec<PathBuf> = self
+            .read_projects()
+            .await?
+            .into_keys()
+            .map(|path| Path::new(&path).join("node_modules"))
+            .filter(|node_modules| node_modules.is_dir())
+            .collect();
+        let files_dir = self.store_path.join("files");
+
+        let report = tokio::task::spawn_blocking(move || -> Result<DedupeReport> {
+            let mut report = DedupeReport {
+                projects: node_modules_dirs.len(),
+                ..DedupeReport::default()
+            };
+
+            for node_modules in &node_modules_dirs {
+                for entry in WalkDir::new(node_modules).into_iter().flatten() {
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+                    let Ok(metadata) = entry.metadata() else {
+                        continue;
+                    };
+                    let Ok(contents) = std::fs::read(entry.path()) else {
+                        continue;
+                    };
+
+                    #[cfg(unix)]
+                    let executable = {
+                        use std::os::unix::fs::PermissionsExt;
+                        metadata.permissions().mode() & 0o111 != 0
+                    };
+                    #[cfg(not(unix))]
+                    let executable = false;
+
+                    let stored = Self::file_path_in(
+                        &files_dir,
+                        &StoredFile {
+                            hash: Self::content_hash(&contents),
+                            size: contents.len() as u64,
+                            executable,
+                        },
+                    );
+                    let Ok(stored_metadata) = std::fs::metadata(&stored) else {
+                        continue;
+                    };
+
+                    // Already the store's file, or on another filesystem
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::MetadataExt;
+                        if stored_metadata.ino() == metadata.ino()
+                            || stored_metadata.dev() != metadata.dev()
+                        {
+                            continue;
+                        }
+                    }
+                    #[cfg(not(unix))]
+                    let _ = stored_metadata;
+
+                    if apply {
+                        let mut temp_name = entry.file_name().to_os_string();
+                        temp_name.push(".clay-dedupe");
+                        let temp_path = entry.path().with_file_name(temp_name);
+                        if std::fs::hard_link(&stored, &temp_path).is_err() {
+                            continue;
+                        }
+                        if std::fs::rename(&temp_path, entry.path()).is_err() {
+                            std::fs::remove_file(&temp_path).ok();
+                            continue;
+                        }
+                    }
+                    report.files += 1;
+                    report.bytes += metadata.len();
+                }
+            }
+
+            Ok(report)
+        })
+        .await??;
+
+        dedupe_spinner.finish_and_clear();
+        Ok(report)
+    }
+
     pub async fn deduplicate_store(&self) -> Result<u64> {
         let dedup_spinner =
             CliStyle::create_spinner("Analyzing content store for deduplication...");
@@ -250,121 +748,718 @@ impl ContentStore {
         Ok(saved_bytes)
     }
 
-    pub async fn cleanup_unused(&self, active_packages: &[String]) -> Result<u64> {
+    /// Remove packages no live project references, sparing any stored or used within
+    /// `grace` (an install may be midway, or a project not yet recorded). With `dry_run`,
+    /// only lists what would go.
+    pub async fn cleanup_unused(&self, dry_run: bool, grace: Duration) -> Result<u64> {
         let cleanup_spinner =
             CliStyle::create_spinner("Cleaning up unused packages from content store...");
 
-        let active_set: std::collections::HashSet<_> = active_packages.iter().collect();
-        let mut removed_bytes = 0u64;
-        let mut removed_count = 0u32;
+        let active_set = self.live_references().await?;
+        let cutoff = Utc::now() - grace;
 
         // Find packages to remove
-        let mut to_remove = Vec::new();
-        for entry in self.package_index.iter() {
-            if !active_set.contains(entry.key()) {
-                to_remove.push((entry.key().clone(), entry.value().clone()));
+        let mut to_remove: Vec<String> = self
+            .package_index
+            .iter()
+            .filter(|entry| {
+                !active_set.contains(entry.key())
+                    && entry.value().last_accessed.is_none_or(|at| at < cutoff)
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        to_remove.sort();
+
+        if dry_run {
+            cleanup_spinner.finish_and_clear();
+            let removing: HashSet<String> = to_remove.iter().cloned().collect();
+            let orphaned = self.unreferenced_files(&removing, grace).await?;
+            let blobs: u64 = self
+                .package_index
+                .iter()
+                .filter(|entry| {
+                    removing.contains(entry.key()) && entry.value().file_hashes.is_empty()
+                })
+                .map(|entry| entry.value().content_address.size)
+                .sum();
+
+            for key in &to_remove {
+                println!("{} {}", style("-").red(), key);
             }
+            println!(
+                "{}",
+                CliStyle::info(&format!(
+                    "Would remove {} packages and {} files ({})",
+                    to_remove.len(),
+                    orphaned.len(),
+                    Self::format_size(blobs + orphaned.iter().map(|(_, size)| size).sum::<u64>())
+                ))
+            );
+            return Ok(0);
         }
 
-        // Remove unused packages
-        for (package_key, metadata) in to_remove {
-            let content_path = self.get_content_path(&metadata.content_address.hash);
+        let removed_count = to_remove.len();
+        let removed_bytes = self.remove_packages(&to_remove, grace).await?;
 
-            if content_path.exists() {
-                fs::remove_file(&content_path).await?;
-                removed_bytes += metadata.content_address.size;
-                removed_count += 1;
+        if removed_count > 0 {
+            cleanup_spinner.finish_with_message(format!(
+                "Cleaned up {} packages ({} freed)",
+                removed_count,
+                Self::format_size(removed_bytes)
+            ));
+        } else {
+            cleanup_spinner.finish_with_message("No unused packages found");
+        }
+
+        Ok(removed_bytes)
+    }
+
+    /// Record the packages a project's node_modules uses, replacing what it used before
+    pub async fn record_project(
+        &self,
+        project: &Path,
+        tree_hash: Option<String>,
+        packages: BTreeSet<String>,
+    ) -> Result<()> {
+        let _lock = self.lock_index(true).await?;
+        let mut projects = self.read_projects().await?;
+        projects.insert(
+            project.to_string_lossy().into_owned(),
+            ProjectReference {
+                tree_hash,
+                packages,
+                updated_at: Utc::now(),
+            },
+        );
+        self.write_projects(&projects).await
+    }
+
+    /// Package keys some live project still needs. Projects whose node_modules is gone are
+    /// forgotten.
+    async fn live_references(&self) -> Result<HashSet<String>> {
+        let projects = {
+            let _lock = self.lock_index(true).await?;
+            let mut projects = self.read_projects().await?;
+            let before = projects.len();
+            projects.retain(|path, _| Path::new(path).join("node_modules").is_dir());
+            if projects.len() != before {
+                self.write_projects(&projects).await?;
             }
+            projects
+        };
 
-            self.package_index.remove(&package_key);
+        let mut live = HashSet::new();
+        for reference in projects.into_values() {
+            if let Some(ref tree_hash) = reference.tree_hash
+                && let Some(tree) = self.get_dependency_tree(tree_hash).await
+            {
+                live.extend(
+                    tree.packages
+                        .iter()
+                        .map(|(name, resolved)| format!("{name}@{}", resolved.version)),
+                );
+            }
+            live.extend(reference.packages);
         }
+        Ok(live)
+    }
 
-        // Clean up orphaned content
-        let mut content_refs: HashMap<String, u32> = HashMap::new();
-        for entry in self.package_index.iter() {
-            let hash = &entry.value().content_address.hash;
-            *content_refs.entry(hash.clone()).or_insert(0) += 1;
+    async fn read_projects(&self) -> Result<BTreeMap<String, ProjectReference>> {
+        let projects_path = self.store_path.join("index").join("projects.json");
+        if !projects_path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let content = fs::read_to_string(&projects_path).await?;
+        Ok(serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    async fn write_projects(&self, projects: &BTreeMap<String, ProjectReference>) -> Result<()> {
+        let projects_path = self.store_path.join("index").join("projects.json");
+        let projects_json = serde_json::to_string_pretty(projects)?;
+        Self::write_atomic(&projects_path, projects_json.as_bytes()).await
+    }
+
+    /// Evict packages not used within `older_than`, then the least recently used until
+    /// the store fits in `max_size`. Entries stored before access times were tracked are
+    /// never too old, but are evicted first for size. Packages and files stored or used
+    /// within `grace` are spared, as another install may be linking them. Returns the
+    /// bytes freed.
+    pub async fn prune(
+        &self,
+        older_than: Option<Duration>,
+        max_size: Option<u64>,
+        grace: Duration,
+    ) -> Result<u64> {
+        let prune_spinner = CliStyle::create_spinner("Pruning content store...");
+
+        let mut by_access: Vec<_> = self
+            .package_index
+            .iter()
+            .map(|entry| {
+                (
+                    entry.key().clone(),
+                    entry.value().last_accessed,
+                    self.stored_objects(entry.value()),
+                )
+            })
+            .collect();
+        by_access.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+        // How many packages use each stored object, to know what evicting one frees
+        let mut refs: HashMap<PathBuf, (u32, u64)> = HashMap::new();
+        for (_, _, objects) in &by_access {
+            for (path, size) in objects {
+                refs.entry(path.clone()).or_insert((0, *size)).0 += 1;
+            }
+        }
+        let mut store_size: u64 = refs.values().map(|(_, size)| size).sum();
+
+        let cutoff = older_than.map(|age| Utc::now() - age);
+        let grace_cutoff = Utc::now() - grace;
+        let mut to_remove = Vec::new();
+        for (key, last_accessed, objects) in by_access {
+            let too_old = cutoff.is_some_and(|cutoff| last_accessed.is_some_and(|at| at < cutoff));
+            let too_big = max_size.is_some_and(|max| store_size > max);
+            let recent = last_accessed.is_some_and(|at| at >= grace_cutoff);
+            if (!too_old && !too_big) || recent {
+                continue;
+            }
+
+            for (path, _) in objects {
+                if let Some((count, size)) = refs.get_mut(&path) {
+                    *count -= 1;
+                    if *count == 0 {
+                        store_size -= *size;
+                    }
+                }
+            }
+            to_remove.push(key);
+        }
+
+        let removed_bytes = self.remove_packages(&to_remove, grace).await?;
+
+        if to_remove.is_empty() {
+            prune_spinner.finish_with_message("Nothing to prune");
+        } else {
+            prune_spinner.finish_with_message(format!(
+                "Pruned {} packages ({} freed)",
+                to_remove.len(),
+                Self::format_size(removed_bytes)
+            ));
         }
 
+        Ok(removed_bytes)
+    }
+
+    /// Drop packages from the index and delete whatever content only they used; files
+    /// written within `grace` are kept
+    async fn remove_packages(&self, package_keys: &[String], grace: Duration) -> Result<u64> {
+        // Hold the lock throughout, so content is only judged unreferenced against every
+        // process's packages
+        let _lock = self.lock_index(true).await?;
+        self.merge_index_files().await?;
+
+        let mut removed_bytes = 0u64;
+        for package_key in package_keys {
+            self.package_index.remove(package_key);
+        }
+
+        let referenced: HashSet<String> = self
+            .package_index
+            .iter()
+            .map(|entry| entry.value().content_address.hash.clone())
+            .collect();
+
         for entry in self.index.iter() {
-            if !content_refs.contains_key(entry.key()) {
+            if !referenced.contains(entry.key()) {
                 let content_path = self.get_content_path(entry.key());
                 if content_path.exists() {
                     fs::remove_file(&content_path).await?;
                     removed_bytes += entry.value().size;
                 }
+                fs::remove_dir_all(self.get_unpacked_path(entry.key()))
+                    .await
+                    .ok();
+            }
+        }
+
+        self.index.retain(|hash, _| referenced.contains(hash));
+        self.write_index_files().await?;
+
+        removed_bytes += self.remove_unreferenced_files(grace).await?;
+        Ok(removed_bytes)
+    }
+
+    /// Write access times recorded by `link_package` since the index was last saved
+    pub async fn save_access_times(&self) -> Result<()> {
+        if self.access_dirty.swap(false, Ordering::Relaxed) {
+            self.save_index().await?;
+        }
+        Ok(())
+    }
+
+    /// Bundle packages (all of them when `package_keys` is None) and dependency trees into
+    /// a gzipped tar another machine's store can import. Returns the packages written and
+    /// the keys asked for that aren't in this store.
+    pub async fn export_archive(
+        &self,
+        archive_path: &Path,
+        package_keys: Option<&HashSet<String>>,
+        tree_hashes: &[String],
+    ) -> Result<(usize, Vec<String>)> {
+        let mut manifest = StoreArchiveManifest::default();
+        let mut missing = Vec::new();
+
+        for tree_hash in tree_hashes {
+            if let Some(tree) = self.get_dependency_tree(tree_hash).await {
+                manifest.trees.push(tree);
             }
         }
 
-        // Update index
-        self.index.retain(|hash, _| content_refs.contains_key(hash));
+        match package_keys {
+            Some(keys) => {
+                // Along with the packages the exported trees install
+                let tree_keys = manifest.trees.iter().flat_map(|tree| {
+                    tree.packages
+                        .iter()
+                        .map(|(name, resolved)| format!("{name}@{}", resolved.version))
+                });
+                let keys: BTreeSet<String> = keys.iter().cloned().chain(tree_keys).collect();
+                for key in keys {
+                    match self.package_index.get(&key) {
+                        Some(metadata) if self.has_stored_objects(&metadata) => {
+                            manifest.packages.insert(key, metadata.clone());
+                        }
+                        _ => missing.push(key),
+                    }
+                }
+            }
+            None => {
+                for entry in self.package_index.iter() {
+                    if self.has_stored_objects(entry.value()) {
+                        manifest
+                            .packages
+                            .insert(entry.key().clone(), entry.value().clone());
+                    }
+                }
+            }
+        }
+
+        let objects: BTreeMap<PathBuf, PathBuf> = manifest
+            .packages
+            .values()
+            .flat_map(|metadata| self.stored_objects(metadata))
+            .filter_map(|(path, _)| {
+                let name = path.strip_prefix(&self.store_path).ok()?.to_path_buf();
+                Some((name, path))
+            })
+            .collect();
+        let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+        let package_count = manifest.packages.len();
+        let archive_path = archive_path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            use flate2::Compression;
+            use flate2::write::GzEncoder;
+
+            let temp_path = archive_path.with_extension("partial");
+            let file = std::fs::File::create(&temp_path)?;
+            let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest_json.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, ARCHIVE_MANIFEST, &manifest_json[..])?;
+
+            for (name, path) in &objects {
+                builder.append_path_with_name(path, name)?;
+            }
+            builder.into_inner()?.finish()?;
+            std::fs::rename(&temp_path, &archive_path)?;
+            Ok(())
+        })
+        .await??;
+
+        Ok((package_count, missing))
+    }
+
+    /// Load a `clay store export` archive, keeping objects already stored. Returns the
+    /// number of packages imported.
+    pub async fn import_archive(&self, archive_path: &Path) -> Result<usize> {
+        let store_path = self.store_path.clone();
+        let archive_path = archive_path.to_path_buf();
+
+        let manifest = tokio::task::spawn_blocking(move || -> Result<StoreArchiveManifest> {
+            let file = std::fs::File::open(&archive_path)?;
+            let mut archive = Archive::new(GzDecoder::new(file));
+            let mut manifest = None;
+
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+                if path == Path::new(ARCHIVE_MANIFEST) {
+                    manifest = Some(serde_json::from_reader(&mut entry)?);
+                    continue;
+                }
+
+                // Only stored objects, and never outside the store
+                let in_store = matches!(
+                    path.components().next(),
+                    Some(Component::Normal(dir)) if dir == "files" || dir == "content"
+                ) && path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+                if !in_store || !entry.header().entry_type().is_file() {
+                    continue;
+                }
+
+                let target = store_path.join(&path);
+                if !target.exists() {
+                    let executable = entry.header().mode().is_ok_and(|mode| mode & 0o111 != 0);
+                    let mut contents = Vec::new();
+                    entry.read_to_end(&mut contents)?;
+                    Self::write_stored_file(&target, &contents, executable)?;
+                }
+            }
+
+            manifest.ok_or_else(|| anyhow!("not a clay store archive: {ARCHIVE_MANIFEST} missing"))
+        })
+        .await??;
+
+        let package_count = manifest.packages.len();
+        for (key, mut metadata) in manifest.packages {
+            metadata.last_accessed = Some(Utc::now());
+            self.index.insert(
+                metadata.content_address.hash.clone(),
+                metadata.content_address.clone(),
+            );
+            self.package_index.insert(key, metadata);
+        }
+        for tree in manifest.trees {
+            self.store_dependency_tree(tree).await?;
+        }
         self.save_index().await?;
 
-        if removed_count > 0 {
-            cleanup_spinner.finish_with_message(format!(
-                "Cleaned up {} packages ({} freed)",
-                removed_count,
-                Self::format_size(removed_bytes)
+        Ok(package_count)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.store_path
+    }
+
+    /// Copy every package into `target` in the current layout (per-file, BLAKE3), checking
+    /// each stored object against its hash on the way. `target` may be this same store
+    /// opened again, which upgrades it in place; the old objects are then cleaned up.
+    pub async fn migrate_into(&self, target: &ContentStore) -> Result<MigrationReport> {
+        let migrate_spinner = CliStyle::create_spinner("Migrating content store...");
+        let mut report = MigrationReport::default();
+
+        let mut packages: Vec<(String, PackageMetadata)> = self
+            .package_index
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        packages.sort_by(|a, b| a.0.cmp(&b.0));
+        let total = packages.len();
+
+        for (index, (package_key, metadata)) in packages.into_iter().enumerate() {
+            migrate_spinner.set_message(format!(
+                "Migrating {package_key} ({}/{total})...",
+                index + 1
             ));
+
+            let migrated = if metadata.file_hashes.is_empty() {
+                self.migrate_tarball_package(&metadata, target).await?
+            } else {
+                self.migrate_file_package(&metadata, target).await?
+            };
+            let Some(metadata) = migrated else {
+                report.corrupted.push(package_key);
+                continue;
+            };
+
+            target.index.insert(
+                metadata.content_address.hash.clone(),
+                metadata.content_address.clone(),
+            );
+            target.package_index.insert(package_key, metadata);
+            report.migrated += 1;
+        }
+
+        if target.store_path != self.store_path {
+            for entry in WalkDir::new(self.store_path.join("trees"))
+                .into_iter()
+                .flatten()
+                .filter(|entry| entry.file_type().is_file())
+            {
+                if let Ok(tree) =
+                    serde_json::from_slice::<DependencyTree>(&fs::read(entry.path()).await?)
+                    && !target.has_dependency_tree(&tree.tree_hash).await
+                {
+                    target.store_dependency_tree(tree).await?;
+                }
+            }
+
+            let _lock = target.lock_index(true).await?;
+            let mut projects = target.read_projects().await?;
+            for (path, reference) in self.read_projects().await? {
+                projects.entry(path).or_insert(reference);
+            }
+            target.write_projects(&projects).await?;
+        }
+
+        // Drops what the old layout left behind once nothing refers to it
+        target
+            .remove_packages(&report.corrupted, Duration::zero())
+            .await?;
+
+        migrate_spinner.finish_and_clear();
+        Ok(report)
+    }
+
+    /// A package kept as a whole tarball: check the tarball, then store its files
+    async fn migrate_tarball_package(
+        &self,
+        metadata: &PackageMetadata,
+        target: &ContentStore,
+    ) -> Result<Option<PackageMetadata>> {
+        let Ok(tarball) = fs::read(self.get_content_path(&metadata.content_address.hash)).await
+        else {
+            return Ok(None);
+        };
+        if !Self::hash_matches(&metadata.content_address.hash, &tarball) {
+            return Ok(None);
+        }
+
+        let mut migrated = metadata.clone();
+        migrated.content_address.hash = Self::content_hash(&tarball);
+        let stored = target.store_files(&tarball).await?;
+        migrated.file_hashes = stored.files;
+        migrated.symlinks = stored.symlinks;
+        Ok(Some(migrated))
+    }
+
+    /// A package stored file by file: check and rehash each file into `target`
+    async fn migrate_file_package(
+        &self,
+        metadata: &PackageMetadata,
+        target: &ContentStore,
+    ) -> Result<Option<PackageMetadata>> {
+        let sources: Vec<(String, StoredFile, PathBuf)> = metadata
+            .file_hashes
+            .iter()
+            .map(|(path, file)| (path.clone(), file.clone(), self.get_file_path(file)))
+            .collect();
+        let files_dir = target.store_path.join("files");
+
+        let file_hashes =
+            tokio::task::spawn_blocking(move || -> Result<Option<BTreeMap<String, StoredFile>>> {
+                let mut file_hashes = BTreeMap::new();
+                for (path, file, source) in sources {
+                    let Ok(contents) = std::fs::read(&source) else {
+                        return Ok(None);
+                    };
+                    if !Self::hash_matches(&file.hash, &contents) {
+                        return Ok(None);
+                    }
+
+                    let migrated = StoredFile {
+                        hash: Self::content_hash(&contents),
+                        size: contents.len() as u64,
+                        executable: file.executable,
+                    };
+                    let target_path = Self::file_path_in(&files_dir, &migrated);
+                    if !target_path.exists() {
+                        Self::write_stored_file(&target_path, &contents, migrated.executable)?;
+                    }
+                    file_hashes.insert(path, migrated);
+                }
+                Ok(Some(file_hashes))
+            })
+            .await??;
+
+        Ok(file_hashes.map(|file_hashes| PackageMetadata {
+            file_hashes,
+            ..metadata.clone()
+        }))
+    }
+
+    /// Check data against a hash from either this store version or an older SHA-1 one
+    fn hash_matches(hash: &str, data: &[u8]) -> bool {
+        if Self::is_current_hash(hash) {
+            return Self::content_hash(data) == hash;
+        }
+
+        use sha1::{Digest, Sha1};
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        format!("{:x}", hasher.finalize()) == hash
+    }
+
+    /// Whether every object a package uses is stored at its recorded size. A size check
+    /// catches truncated and partly overwritten files without rehashing on every install.
+    fn has_stored_objects(&self, metadata: &PackageMetadata) -> bool {
+        self.stored_objects(metadata).iter().all(|(path, size)| {
+            std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == *size)
+        })
+    }
+
+    /// Stored objects a package uses and their sizes: its files, or its whole tarball
+    fn stored_objects(&self, metadata: &PackageMetadata) -> Vec<(PathBuf, u64)> {
+        if metadata.file_hashes.is_empty() {
+            vec![(
+                self.get_content_path(&metadata.content_address.hash),
+                metadata.content_address.size,
+            )]
         } else {
-            cleanup_spinner.finish_with_message("No unused packages found");
+            metadata
+                .file_hashes
+                .values()
+                .map(|file| (self.get_file_path(file), file.size))
+                .collect()
         }
+    }
 
-        Ok(removed_bytes)
+    /// Delete stored files no indexed package refers to; returns the bytes freed
+    async fn remove_unreferenced_files(&self, grace: Duration) -> Result<u64> {
+        let mut freed = 0u64;
+        for (path, size) in self.unreferenced_files(&HashSet::new(), grace).await? {
+            if fs::remove_file(&path).await.is_ok() {
+                freed += size;
+            }
+        }
+        Ok(freed)
     }
 
-    pub async fn get_store_stats(&self) -> Result<StoreStats> {
-        let mut total_content_size = 0u64;
-        let mut duplicates = 0u32;
+    /// Files in `files/` used by no package outside `excluding`, older than `grace`
+    async fn unreferenced_files(
+        &self,
+        excluding: &HashSet<String>,
+        grace: Duration,
+    ) -> Result<Vec<(PathBuf, u64)>> {
+        let referenced: HashSet<PathBuf> = self
+            .package_index
+            .iter()
+            .filter(|entry| !excluding.contains(entry.key()))
+            .flat_map(|entry| {
+                entry
+                    .value()
+                    .file_hashes
+                    .values()
+                    .map(|file| self.get_file_path(file))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let files_dir = self.store_path.join("files");
+        let cutoff = std::time::SystemTime::now() - grace.to_std().unwrap_or_default();
+
+        tokio::task::spawn_blocking(move || {
+            let mut unreferenced = Vec::new();
+            for entry in WalkDir::new(&files_dir).into_iter().flatten() {
+                if !entry.file_type().is_file() || referenced.contains(entry.path()) {
+                    continue;
+                }
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.modified().is_ok_and(|modified| modified > cutoff) {
+                    continue;
+                }
+                unreferenced.push((entry.path().to_path_buf(), metadata.len()));
+            }
+            Ok(unreferenced)
+        })
+        .await?
+    }
 
-        // Count packages
+    pub async fn get_store_stats(&self) -> Result<StoreStats> {
         let total_packages = self.package_index.len() as u32;
 
-        // Count unique content
-        let unique_content_count = self.index.len() as u32;
-
-        // Calculate total size and duplicates
+        // Bytes on disk per stored object (a file, or a whole tarball for older entries),
+        // against what every package would take stored on its own
+        let mut stored: HashMap<PathBuf, u64> = HashMap::new();
+        let mut unshared_size = 0u64;
         let mut content_usage: HashMap<String, u32> = HashMap::new();
+
         for entry in self.package_index.iter() {
-            let hash = &entry.value().content_address.hash;
-            *content_usage.entry(hash.clone()).or_insert(0) += 1;
-        }
+            let metadata = entry.value();
+            *content_usage
+                .entry(metadata.content_address.hash.clone())
+                .or_insert(0) += 1;
 
-        for entry in self.index.iter() {
-            total_content_size += entry.value().size;
-            if let Some(usage) = content_usage.get(entry.key()) {
-                if *usage > 1 {
-                    duplicates += usage - 1;
-                }
+            for (path, size) in self.stored_objects(metadata) {
+                unshared_size += size;
+                stored.insert(path, size);
             }
         }
 
+        let duplicates = content_usage
+            .values()
+            .map(|usage| usage.saturating_sub(1))
+            .sum();
+        let total_content_size: u64 = stored.values().sum();
+
         Ok(StoreStats {
             total_packages,
-            unique_content_count,
+            unique_content_count: stored.len() as u32,
             total_content_size,
             duplicate_packages: duplicates,
-            space_saved: self.calculate_space_saved().await?,
+            space_saved: unshared_size.saturating_sub(total_content_size),
         })
     }
 
-    async fn calculate_space_saved(&self) -> Result<u64> {
-        let mut total_if_duplicated = 0u64;
-        let mut content_usage: HashMap<String, u32> = HashMap::new();
+    /// Per-package usage, largest first
+    pub async fn package_usage(&self) -> Result<Vec<PackageUsage>> {
+        let projects = self.read_projects().await?;
+        let mut by_name: BTreeMap<String, (PackageUsage, HashMap<PathBuf, u64>)> = BTreeMap::new();
 
         for entry in self.package_index.iter() {
-            let hash = &entry.value().content_address.hash;
-            *content_usage.entry(hash.clone()).or_insert(0) += 1;
-        }
-
-        for (hash, usage) in content_usage {
-            if let Some(content) = self.index.get(&hash) {
-                total_if_duplicated += content.size * usage as u64;
-            }
+            let metadata = entry.value();
+            let (usage, objects) = by_name.entry(metadata.name.clone()).or_insert_with(|| {
+                let prefix = format!("{}@", metadata.name);
+                let projects = projects
+                    .iter()
+                    .filter(|(path, reference)| {
+                        Path::new(path).join("node_modules").is_dir()
+                            && reference
+                                .packages
+                                .iter()
+                                .any(|key| key.starts_with(&prefix))
+                    })
+                    .count();
+                (
+                    PackageUsage {
+                        name: metadata.name.clone(),
+                        versions: Vec::new(),
+                        size: 0,
+                        last_used: None,
+                        projects,
+                    },
+                    HashMap::new(),
+                )
+            });
+
+            usage.versions.push(metadata.version.clone());
+            usage.last_used = usage.last_used.max(metadata.last_accessed);
+            objects.extend(self.stored_objects(metadata));
         }
 
-        let actual_size: u64 = self.index.iter().map(|entry| entry.value().size).sum();
-        Ok(total_if_duplicated.saturating_sub(actual_size))
+        let mut usage: Vec<PackageUsage> = by_name
+            .into_values()
+            .map(|(mut usage, objects)| {
+                usage.size = objects.values().sum();
+                usage.versions.sort_by(|a, b| {
+                    match (semver::Version::parse(a), semver::Version::parse(b)) {
+                        (Ok(a), Ok(b)) => a.cmp(&b),
+                        _ => a.cmp(b),
+                    }
+                });
+                usage
+            })
+            .collect();
+        usage.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        Ok(usage)
     }
 
     fn get_store_path() -> PathBuf {
@@ -380,6 +1475,8 @@ impl ContentStore {
         fs::create_dir_all(self.store_path.join("content")).await?;
         fs::create_dir_all(self.store_path.join("index")).await?;
         fs::create_dir_all(self.store_path.join("trees")).await?;
+        fs::create_dir_all(self.store_path.join("unpacked")).await?;
+        fs::create_dir_all(self.store_path.join("files")).await?;
         Ok(())
     }
 
@@ -393,6 +1490,27 @@ impl ContentStore {
             .join(format!("{file}.tar.gz"))
     }
 
+    fn get_file_path(&self, file: &StoredFile) -> PathBuf {
+        Self::file_path_in(&self.store_path.join("files"), file)
+    }
+
+    fn file_path_in(files_dir: &Path, file: &StoredFile) -> PathBuf {
+        let dir = &file.hash[..2];
+        let name = if file.executable {
+            format!("{}-exec", &file.hash[2..])
+        } else {
+            file.hash[2..].to_string()
+        };
+        files_dir.join(dir).join(name)
+    }
+
+    /// Extracted copy of a stored tarball that installs link files from
+    fn get_unpacked_path(&self, content_hash: &str) -> PathBuf {
+        let dir = &content_hash[..2];
+        let file = &content_hash[2..];
+        self.store_path.join("unpacked").join(dir).join(file)
+    }
+
     fn get_tree_path(&self, tree_hash: &str) -> PathBuf {
         // Use first 2 chars for directory sharding
         let dir = &tree_hash[..2];
@@ -403,20 +1521,14 @@ impl ContentStore {
             .join(format!("{file}.json"))
     }
 
-    fn calculate_content_hash(&self, data: &[u8]) -> String {
-        let mut hasher = Sha1::new();
-        hasher.update(data);
-        format!("{:x}", hasher.finalize())
+    /// BLAKE3, hex-encoded. npm's SHA-1/SHA-512 integrity is checked separately.
+    pub fn content_hash(data: &[u8]) -> String {
+        blake3::hash(data).to_hex().to_string()
     }
 
-    fn compress_data(&self, data: &[u8]) -> Result<Vec<u8>> {
-        use flate2::Compression;
-        use flate2::write::GzEncoder;
-        use std::io::Write;
-
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(data)?;
-        Ok(encoder.finish()?)
+    /// Whether a hash is this version's BLAKE3, not the SHA-1 earlier stores used
+    fn is_current_hash(hash: &str) -> bool {
+        hash.len() == blake3::OUT_LEN * 2
     }
 
     async fn analyze_package_content(
@@ -446,14 +1558,11 @@ impl ContentStore {
                     entry.read_to_end(&mut contents)?;
 
                     if let Ok(package_json) = serde_json::from_slice::<serde_json::Value>(&contents)
+                        && let Some(deps) = package_json.get("dependencies")
+                        && let Ok(deps_map) =
+                            serde_json::from_value::<HashMap<String, String>>(deps.clone())
                     {
-                        if let Some(deps) = package_json.get("dependencies") {
-                            if let Ok(deps_map) =
-                                serde_json::from_value::<HashMap<String, String>>(deps.clone())
-                            {
-                                dependencies = Some(deps_map);
-                            }
-                        }
+                        dependencies = Some(deps_map);
                     }
                 }
             }
@@ -465,9 +1574,162 @@ impl ContentStore {
             content_address,
             dependencies,
             files,
+            file_hashes: BTreeMap::new(),
+            symlinks: BTreeMap::new(),
+            last_accessed: Some(Utc::now()),
+            resolved: None,
         })
     }
 
+    /// Unpack an npm .tgz into `dest`, dropping the top-level directory (usually
+    /// package/) its contents sit in. Files keep their executable bit and are otherwise
+    /// made 0644; links are made once every file is in place, so nothing is ever written
+    /// through one. Entries that would land outside `dest`, symlinks pointing outside it,
+    /// and special files are skipped.
+    pub fn unpack_tarball(tarball: &[u8], dest: &Path) -> Result<()> {
+        std::fs::create_dir_all(dest)?;
+        let mut archive = Archive::new(GzDecoder::new(tarball));
+        archive.set_overwrite(true);
+        let mut symlinks = Vec::new();
+        let mut hardlinks = Vec::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let Some(relative) = Self::package_path(&entry.path()?) else {
+                continue;
+            };
+            let target = dest.join(&relative);
+            let entry_type = entry.header().entry_type();
+
+            if entry_type.is_dir() {
+                std::fs::create_dir_all(long_path::extend(&target))?;
+            } else if entry_type.is_file() {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(long_path::extend(parent))?;
+                }
+                let executable = Self::is_executable(entry.header());
+                let target = long_path::extend(&target);
+                entry.unpack(&target)?;
+                Self::set_file_mode(&target, executable)?;
+            } else if entry_type.is_symlink() {
+                if let Some(link_target) = entry.link_name()? {
+                    symlinks.push((relative, link_target.into_owned()));
+                }
+            } else if entry_type.is_hard_link()
+                && let Some(source) = entry
+                    .link_name()?
+                    .and_then(|source| Self::package_path(&source))
+            {
+                hardlinks.push((target, dest.join(source)));
+            }
+        }
+
+        for (target, source) in hardlinks {
+            if source.is_file() {
+                std::fs::copy(long_path::extend(&source), long_path::extend(&target))?;
+            }
+        }
+        for (link, link_target) in Self::contained_symlinks(symlinks) {
+            let link = dest.join(link);
+            if let Some(parent) = link.parent() {
+                std::fs::create_dir_all(long_path::extend(parent))?;
+            }
+            let link = long_path::extend(&link);
+            if link.symlink_metadata().is_ok() {
+                std::fs::remove_file(&link).ok();
+            }
+            links::symlink(&link_target, &link)?;
+        }
+
+        Ok(())
+    }
+
+    /// A tarball entry's path inside the package, without the top-level directory. None
+    /// for the directory itself and for paths that would climb out of it.
+    fn package_path(path: &Path) -> Option<PathBuf> {
+        let mut components = path.components();
+        components.next();
+        let relative = components.as_path();
+        let inside = !relative.as_os_str().is_empty()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        inside.then(|| relative.to_path_buf())
+    }
+
+    /// The symlinks of a package (package path, target) that stay inside it. Links placed
+    /// in or resolving through another of its symlinks are dropped too: their targets are
+    /// only checked lexically, and `y -> .` then `x -> y/..` looks inside but isn't.
+    fn contained_symlinks(symlinks: Vec<(PathBuf, PathBuf)>) -> Vec<(PathBuf, PathBuf)> {
+        let links: HashSet<PathBuf> = symlinks.iter().map(|(link, _)| link.clone()).collect();
+        let through_link = |link: &Path, target: &Path| {
+            if link.ancestors().skip(1).any(|dir| links.contains(dir)) {
+                return true;
+            }
+            let mut resolved = link.parent().map(Path::to_path_buf).unwrap_or_default();
+            let mut components = target.components().peekable();
+            while let Some(component) = components.next() {
+                match component {
+                    Component::Normal(name) => resolved.push(name),
+                    Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    _ => {}
+                }
+                // Landing on a link is fine: it was checked from where it sits
+                if components.peek().is_some() && links.contains(&resolved) {
+                    return true;
+                }
+            }
+            false
+        };
+
+        symlinks
+            .iter()
+            .filter(|(link, target)| {
+                Self::link_stays_inside(link, target) && !through_link(link, target)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a symlink at `link` (a package path) pointing at `target` stays inside the
+    /// package
+    fn link_stays_inside(link: &Path, target: &Path) -> bool {
+        let mut depth = link.components().count() as isize - 1;
+        for component in target.components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    depth -= 1;
+                    if depth < 0 {
+                        return false;
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return false,
+            }
+        }
+        true
+    }
+
+    fn is_executable(header: &tar::Header) -> bool {
+        header.mode().is_ok_and(|mode| mode & 0o111 != 0)
+    }
+
+    /// 0755 for executables and 0644 for everything else, whatever mode the archive had
+    fn set_file_mode(path: &Path, executable: bool) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = if executable { 0o755 } else { 0o644 };
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = (path, executable);
+        Ok(())
+    }
+
     async fn extract_package_from_store(
         &self,
         store_path: &Path,
@@ -480,63 +1742,67 @@ impl ContentStore {
         if let Some(parent) = target_path.parent() {
             fs::create_dir_all(parent).await?;
         }
-        
-        let temp_dir = target_path.with_extension("temp");
+
+        // Sibling temp dir named after the full file name, so that e.g. lodash.get and
+        // lodash.set linked concurrently don't share one
+        let mut temp_name = target_path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".clay-extract");
+        let temp_dir = target_path.with_file_name(temp_name);
+        fs::remove_dir_all(&temp_dir).await.ok();
+        let _temp_guard = CleanupGuard::new(&temp_dir);
+        let _partial_guard = CleanupGuard::new(target_path);
         fs::create_dir_all(&temp_dir).await?;
-        
+
         // Use blocking task for decompression and tar extraction
         let temp_dir_clone = temp_dir.clone();
-        tokio::task::spawn_blocking(move || -> Result<()> {
-            // Decompress
-            use flate2::read::GzDecoder;
+        run_blocking(move || -> Result<()> {
+            // Undo the store's own compression layer; what's left is the original .tgz
             let mut decoder = GzDecoder::new(&compressed_data[..]);
-            let mut decompressed = Vec::new();
-            decoder.read_to_end(&mut decompressed)?;
-
-            // Extract tarball - use same reliable method as regular installs
-            let mut archive = Archive::new(&decompressed[..]);
-            archive.set_overwrite(true);
-            archive.unpack(&temp_dir_clone)?;
-            
-            Ok(())
-        }).await??;
-        
-        // Move from package/ to target directory (npm tarballs have package/ prefix)
-        let package_dir = temp_dir.join("package");
-        if package_dir.exists() {
-            // Move contents of package/ to target_path
-            fs::rename(&package_dir, target_path).await?;
-        } else {
-            // No package/ prefix, move entire temp dir contents
-            fs::rename(&temp_dir, target_path).await?;
-        }
-        
-        // Clean up temp directory
-        fs::remove_dir_all(&temp_dir).await.ok();
+            let mut tarball = Vec::new();
+            decoder.read_to_end(&mut tarball)?;
+            Self::unpack_tarball(&tarball, &temp_dir_clone)
+        })
+        .await?;
+        fs::rename(&temp_dir, target_path).await?;
 
         Ok(())
     }
 
-    // Helper function for recursive directory copying
-    fn copy_dir_recursive(src: &Path, dest: &Path) -> std::io::Result<()> {
-        std::fs::create_dir_all(dest)?;
-        
-        for entry in std::fs::read_dir(src)? {
-            let entry = entry?;
-            let src_path = entry.path();
-            let dest_path = dest.join(entry.file_name());
-            
-            if src_path.is_dir() {
-                Self::copy_dir_recursive(&src_path, &dest_path)?;
+    async fn load_index(&self) -> Result<()> {
+        let _lock = self.lock_index(false).await?;
+        self.merge_index_files().await
+    }
+
+    /// Write the index, first taking in what other processes added since it was loaded
+    async fn save_index(&self) -> Result<()> {
+        let _lock = self.lock_index(true).await?;
+        self.merge_index_files().await?;
+        self.write_index_files().await
+    }
+
+    /// Held while the index files are read or written, so clay processes sharing a store
+    /// (parallel CI jobs, say) never lose each other's entries. Released on drop.
+    async fn lock_index(&self, exclusive: bool) -> Result<std::fs::File> {
+        let lock_path = self.store_path.join("index").join(".lock");
+        tokio::task::spawn_blocking(move || -> Result<std::fs::File> {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)?;
+            if exclusive {
+                file.lock()?;
             } else {
-                std::fs::copy(&src_path, &dest_path)?;
+                file.lock_shared()?;
             }
-        }
-        
-        Ok(())
+            Ok(file)
+        })
+        .await?
     }
 
-    async fn load_index(&self) -> Result<()> {
+    /// Add entries from the index files that aren't in memory. For packages in both, the
+    /// in-memory entry wins, keeping the later access time.
+    async fn merge_index_files(&self) -> Result<()> {
         let index_path = self.store_path.join("index").join("content.json");
         let package_index_path = self.store_path.join("index").join("packages.json");
 
@@ -547,7 +1813,7 @@ impl ContentStore {
                 serde_json::from_str::<HashMap<String, ContentAddress>>(&content)
             {
                 for (hash, address) in index_data {
-                    self.index.insert(hash, address);
+                    self.index.entry(hash).or_insert(address);
                 }
             }
         }
@@ -559,7 +1825,8 @@ impl ContentStore {
                 serde_json::from_str::<HashMap<String, PackageMetadata>>(&content)
             {
                 for (key, metadata) in package_data {
-                    self.package_index.insert(key, metadata);
+                    let mut entry = self.package_index.entry(key).or_insert(metadata.clone());
+                    entry.last_accessed = entry.last_accessed.max(metadata.last_accessed);
                 }
             }
         }
@@ -567,7 +1834,8 @@ impl ContentStore {
         Ok(())
     }
 
-    async fn save_index(&self) -> Result<()> {
+    /// Write both index files whole; callers hold the exclusive index lock
+    async fn write_index_files(&self) -> Result<()> {
         let index_path = self.store_path.join("index").join("content.json");
         let package_index_path = self.store_path.join("index").join("packages.json");
 
@@ -579,7 +1847,7 @@ impl ContentStore {
             .collect();
 
         let content_json = serde_json::to_string_pretty(&content_index)?;
-        fs::write(&index_path, content_json).await?;
+        Self::write_atomic(&index_path, content_json.as_bytes()).await?;
 
         // Save package index
         let package_index: HashMap<String, PackageMetadata> = self
@@ -589,11 +1857,27 @@ impl ContentStore {
             .collect();
 
         let package_json = serde_json::to_string_pretty(&package_index)?;
-        fs::write(&package_index_path, package_json).await?;
+        Self::write_atomic(&package_index_path, package_json.as_bytes()).await?;
 
         Ok(())
     }
 
+    /// Write through a uniquely named sibling so readers in other processes never see a
+    /// partial file
+    async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+        let temp_path = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            UNPACK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, contents).await?;
+        if let Err(e) = fs::rename(&temp_path, path).await {
+            fs::remove_file(&temp_path).await.ok();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
     pub fn format_size(bytes: u64) -> String {
         const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
         let mut size = bytes as f64;
@@ -610,9 +1894,52 @@ impl ContentStore {
             format!("{:.1} {}", size, UNITS[unit_index])
         }
     }
+
+    /// Parse a size such as `500MB` or `10 GB` (units as in `format_size`, 1024-based)
+    pub fn parse_size(value: &str) -> Result<u64> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("invalid size '{value}'"))?;
+        let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1u64,
+            "K" | "KB" => 1 << 10,
+            "M" | "MB" => 1 << 20,
+            "G" | "GB" => 1 << 30,
+            "T" | "TB" => 1 << 40,
+            _ => return Err(anyhow!("invalid size unit in '{value}'")),
+        };
+        Ok((number * multiplier as f64) as u64)
+    }
+
+    /// Parse an age such as `90d`, `12h` or `2w`
+    pub fn parse_age(value: &str) -> Result<Duration> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let number: i64 = number
+            .parse()
+            .map_err(|_| anyhow!("invalid age '{value}'"))?;
+        match unit {
+            "s" => Ok(Duration::seconds(number)),
+            "m" => Ok(Duration::minutes(number)),
+            "h" => Ok(Duration::hours(number)),
+            "d" => Ok(Duration::days(number)),
+            "w" => Ok(Duration::weeks(number)),
+            _ => Err(anyhow!(
+                "invalid age '{value}', expected e.g. 90d, 12h or 2w"
+            )),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct StoreStats {
     pub total_packages: u32,
     pub unique_content_count: u32,
@@ -621,8 +1948,158 @@ pub struct StoreStats {
     pub space_saved: u64,
 }
 
+/// Store usage of every version of one package
+#[derive(Debug, Serialize)]
+pub struct PackageUsage {
+    pub name: String,
+    pub versions: Vec<String>,
+    /// Bytes its versions take in the store, counting files they share once
+    pub size: u64,
+    pub last_used: Option<DateTime<Utc>>,
+    /// Installed projects with some version of it in node_modules
+    pub projects: usize,
+}
+
 impl Default for ContentStore {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Entry {
+        File(&'static str, &'static str, u32),
+        Symlink(&'static str, &'static str),
+    }
+
+    /// A gzipped npm-style tarball with `entries` under package/
+    fn tarball(entries: &[Entry]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            match entry {
+                Entry::File(path, contents, mode) => {
+                    header.set_size(contents.len() as u64);
+                    header.set_mode(*mode);
+                    header.set_cksum();
+                    builder
+                        .append_data(&mut header, format!("package/{path}"), contents.as_bytes())
+                        .unwrap();
+                }
+                Entry::Symlink(path, target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    header.set_mode(0o777);
+                    builder
+                        .append_link(&mut header, format!("package/{path}"), target)
+                        .unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn unpack(name: &str, entries: &[Entry]) -> PathBuf {
+        let dest = std::env::temp_dir().join(format!("clay-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dest);
+        ContentStore::unpack_tarball(&tarball(entries), &dest).unwrap();
+        dest
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpack_keeps_executable_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dest = unpack(
+            "unpack-modes",
+            &[
+                Entry::File("bin/cli.js", "#!/usr/bin/env node\n", 0o755),
+                Entry::File("index.js", "module.exports = 1\n", 0o664),
+            ],
+        );
+        let mode = |path: &str| {
+            std::fs::metadata(dest.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("bin/cli.js"), 0o755);
+        assert_eq!(mode("index.js"), 0o644);
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpack_recreates_symlinks() {
+        let dest = unpack(
+            "unpack-symlinks",
+            &[
+                Entry::File("lib/index.js", "module.exports = 1\n", 0o644),
+                Entry::Symlink("index.js", "lib/index.js"),
+            ],
+        );
+        let link = dest.join("index.js");
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            Path::new("lib/index.js")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&link).unwrap(),
+            "module.exports = 1\n"
+        );
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpack_drops_symlinks_escaping_the_package() {
+        let dest = unpack(
+            "unpack-escapes",
+            &[
+                Entry::File("index.js", "module.exports = 1\n", 0o644),
+                Entry::Symlink("up", "../outside"),
+                Entry::Symlink("y", "."),
+                Entry::Symlink("x", "y/.."),
+                Entry::Symlink("y/z", "../.."),
+            ],
+        );
+        assert!(dest.join("y").symlink_metadata().is_ok());
+        for escaping in ["up", "x"] {
+            assert!(
+                dest.join(escaping).symlink_metadata().is_err(),
+                "{escaping}"
+            );
+        }
+        assert!(!dest.join("z").exists());
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn contained_symlinks_keeps_chains_that_end_on_a_link() {
+        let links = |pairs: &[(&str, &str)]| -> Vec<(PathBuf, PathBuf)> {
+            pairs
+                .iter()
+                .map(|(link, target)| (PathBuf::from(link), PathBuf::from(target)))
+                .collect()
+        };
+        let kept = ContentStore::contained_symlinks(links(&[
+            ("a", "lib/a.js"),
+            ("b", "a"),
+            ("y", "."),
+            ("x", "y/.."),
+            ("w", "y/lib"),
+        ]));
+        let kept: Vec<&str> = kept
+            .iter()
+            .map(|(link, _)| link.to_str().unwrap())
+            .collect();
+        assert_eq!(kept, ["a", "b", "y"]);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::errors::{self, ErrorCode};
+use crate::exec;
+use crate::npm_client::NpmClient;
+use crate::package_manager::PackageManager;
+
+/// Where `clay create` gets a project from
+#[derive(Debug, PartialEq, Eq)]
+pub enum Template {
+    /// A create-* package whose command generates the project, as `npm init` runs it
+    Package(String),
+    /// A git repository whose files are the project
+    Git(String),
+}
+
+impl Template {
+    /// `vite` is the create-vite package, `@scope` is @scope/create and `@scope/app` is
+    /// @scope/create-app. URLs, `github:owner/repo` and `owner/repo` are git repositories.
+    pub fn parse(template: &str) -> Template {
+        if let Some(path) = template.strip_prefix("github:") {
+            return Template::Git(format!("https://github.com/{path}.git"));
+        }
+        if let Some(path) = template.strip_prefix("gitlab:") {
+            return Template::Git(format!("https://gitlab.com/{path}.git"));
+        }
+        if let Some(url) = template.strip_prefix("git+") {
+            return Template::Git(url.to_string());
+        }
+        if template.contains("://") || template.starts_with("git@") || template.ends_with(".git") {
+            return Template::Git(template.to_string());
+        }
+
+        let (name, version) = match template.rfind('@') {
+            Some(at) if at > 0 => (&template[..at], Some(&template[at + 1..])),
+            _ => (template, None),
+        };
+        let package = match name.split_once('/') {
+            Some((scope, name)) if scope.starts_with('@') => {
+                format!("{scope}/{}", create_name(name))
+            }
+            // Unscoped names can't contain a slash, so this is a GitHub repository
+            Some(_) => return Template::Git(format!("https://github.com/{template}.git")),
+            None if name.starts_with('@') => format!("{name}/create"),
+            None => create_name(name),
+        };
+        Template::Package(match version {
+            Some(version) => format!("{package}@{version}"),
+            None => package,
+        })
+    }
+}
+
+fn create_name(name: &str) -> String {
+    if name.starts_with("create-") {
+        name.to_string()
+    } else {
+        format!("create-{name}")
+    }
+}
+
+/// Make a new project from `template` and install its dependencies. A generator package
+/// is given `dir` and `args` and decides where the project goes; a git template is
+/// cloned into `dir`, defaulting to the repository's name. Returns the project directory
+/// if it could be told.
+pub async fn create(
+    config: ClayConfig,
+    npm_client: NpmClient,
+    template: &str,
+    dir: Option<PathBuf>,
+    args: &[String],
+    install: bool,
+) -> Result<Option<PathBuf>> {
+    let cwd = std::env::current_dir()?;
+    let project_dir = match Template::parse(template) {
+        Template::Package(package) => {
+            let before = project_dirs(&cwd);
+            let mut generator_args: Vec<String> = dir
+                .iter()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .collect();
+            generator_args.extend(args.iter().cloned());
+
+            let status = exec::dlx(
+                config.clone(),
+                npm_client.clone(),
+                &package,
+                &generator_args,
+            )
+            .await?;
+            if !status.success() {
+                return Err(errors::coded(
+                    ErrorCode::ScriptFailed,
+                    format!(
+                        "{package} failed with exit code {}",
+                        status.code().unwrap_or(-1)
+                    ),
+                ));
+            }
+
+            match dir {
+                Some(dir) => Some(cwd.join(dir)),
+                // Generators that ask for a name: look for the one project that appeared
+                None => {
+                    let mut created: Vec<PathBuf> =
+                        project_dirs(&cwd).difference(&before).cloned().collect();
+                    (created.len() == 1).then(|| created.remove(0))
+                }
+            }
+        }
+        Template::Git(url) => {
+            let dir = match dir {
+                Some(dir) => dir,
+                None => PathBuf::from(repository_name(&url)?),
+            };
+            clone_template(&url, &cwd.join(&dir))?;
+            Some(cwd.join(dir))
+        }
+    };
+
+    let Some(project_dir) = project_dir.filter(|dir| dir.join("package.json").is_file()) else {
+        return Ok(None);
+    };
+    if install {
+        let package_manager =
+            PackageManager::with_client(config, npm_client).with_project_dir(&project_dir);
+        package_manager.initialize().await?;
+        let specs = package_manager.get_package_json_dependencies(true).await?;
+        if !specs.is_empty() {
+            package_manager
+                .install_multiple_packages(specs, false, false)
+                .await?;
+        }
+    }
+    Ok(Some(project_dir))
+}
+
+/// `cwd` itself if it has a package.json, and its subdirectories that do
+fn project_dirs(cwd: &Path) -> HashSet<PathBuf> {
+    let mut dirs: HashSet<PathBuf> = std::fs::read_dir(cwd)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join("package.json").is_file())
+        .collect();
+    if cwd.join("package.json").is_file() {
+        dirs.insert(cwd.to_path_buf());
+    }
+    dirs
+}
+
+/// `app` for https://github.com/owner/app.git
+fn repository_name(url: &str) -> Result<&str> {
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| anyhow!("Can't tell a directory name from {url}; pass one"))
+}
+
+/// Copy the latest files of the repository at `url` into `dir`, without its history, and
+/// name the package after the directory
+fn clone_template(url: &str, dir: &Path) -> Result<()> {
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(anyhow!("{} already exists and isn't empty", dir.display()));
+    }
+
+    let spinner = CliStyle::create_spinner(&format!("Cloning {url}..."));
+    let output = Command::new("git")
+        .args(["clone", "--depth", "1", "--quiet", url])
+        .arg(dir)
+        .output()
+        .context("Failed to run git");
+    spinner.finish_and_clear();
+    let output = output?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Couldn't clone {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    std::fs::remove_dir_all(dir.join(".git"))?;
+
+    let package_json_path = dir.join("package.json");
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    if let (Ok(content), Some(name)) = (std::fs::read_to_string(&package_json_path), name)
+        && let Ok(Value::Object(mut package_json)) = serde_json::from_str(&content)
+    {
+        package_json.insert("name".to_string(), Value::String(name));
+        let mut content = serde_json::to_string_pretty(&package_json)?;
+        content.push('\n');
+        std::fs::write(&package_json_path, content)?;
+    }
+    Ok(())
+}
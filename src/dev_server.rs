@@ -7,15 +7,20 @@ use std::sync::Arc;
 use tokio::fs;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{RwLock, broadcast};
-use tokio::time::{Duration, Instant, sleep};
+use tokio::time::{Duration, Instant};
 
 use crate::bundler::Bundler;
 use crate::cli_style::CliStyle;
+use crate::config::{BundleConfig, DevConfig};
+use crate::shutdown;
 
 pub struct DevServer {
     port: u16,
     host: String,
     public_dir: PathBuf,
+    /// Directories watched for changes
+    watch_dirs: Vec<String>,
+    bundle: BundleConfig,
     bundle_cache: Arc<RwLock<Option<String>>>,
     file_watcher: Arc<RwLock<FileWatcher>>,
     ws_clients: Arc<RwLock<Vec<broadcast::Sender<String>>>>,
@@ -39,23 +44,23 @@ impl FileWatcher {
         let now = Instant::now();
 
         for path in watch_paths {
-            if let Ok(metadata) = fs::metadata(path).await {
-                if let Ok(modified) = metadata.modified() {
-                    let modified_instant = Instant::now()
-                        - Duration::from_secs(modified.elapsed().unwrap_or_default().as_secs());
-
-                    match self.watched_files.get(path) {
-                        Some(last_modified) => {
-                            if modified_instant > *last_modified {
-                                has_changes = true;
-                                self.watched_files.insert(path.clone(), modified_instant);
-                            }
-                        }
-                        None => {
+            if let Ok(metadata) = fs::metadata(path).await
+                && let Ok(modified) = metadata.modified()
+            {
+                let modified_instant = Instant::now()
+                    - Duration::from_secs(modified.elapsed().unwrap_or_default().as_secs());
+
+                match self.watched_files.get(path) {
+                    Some(last_modified) => {
+                        if modified_instant > *last_modified {
+                            has_changes = true;
                             self.watched_files.insert(path.clone(), modified_instant);
-                            if now.duration_since(self.last_check) > Duration::from_millis(100) {
-                                has_changes = true;
-                            }
+                        }
+                    }
+                    None => {
+                        self.watched_files.insert(path.clone(), modified_instant);
+                        if now.duration_since(self.last_check) > Duration::from_millis(100) {
+                            has_changes = true;
                         }
                     }
                 }
@@ -75,20 +80,27 @@ impl FileWatcher {
 }
 
 impl DevServer {
-    pub fn new() -> Self {
+    pub fn new(config: &DevConfig, bundle: &BundleConfig) -> Self {
         Self {
-            port: 3000,
-            host: "localhost".to_string(),
-            public_dir: PathBuf::from("public"),
+            port: config.port,
+            host: config.host.clone(),
+            public_dir: config.public_dir.clone(),
+            watch_dirs: config.watch.clone(),
+            bundle: bundle.clone(),
             bundle_cache: Arc::new(RwLock::new(None)),
             file_watcher: Arc::new(RwLock::new(FileWatcher::new())),
             ws_clients: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
-    pub async fn start(&mut self, host: &str, port: u16) -> Result<()> {
-        self.host = host.to_string();
-        self.port = port;
+    pub async fn start(&mut self, host: Option<&str>, port: Option<u16>) -> Result<()> {
+        if let Some(host) = host {
+            self.host = host.to_string();
+        }
+        if let Some(port) = port {
+            self.port = port;
+        }
+        let (host, port) = (self.host.clone(), self.port);
 
         let server_spinner =
             CliStyle::create_spinner(&format!("Starting development server on {host}:{port}..."));
@@ -102,9 +114,11 @@ impl DevServer {
         let file_watcher = Arc::clone(&self.file_watcher);
         let bundle_cache = Arc::clone(&self.bundle_cache);
         let ws_clients = Arc::clone(&self.ws_clients);
+        let watch_dirs = self.watch_dirs.clone();
+        let bundle = self.bundle.clone();
 
         tokio::spawn(async move {
-            Self::watch_files(file_watcher, bundle_cache, ws_clients).await;
+            Self::watch_files(file_watcher, bundle_cache, ws_clients, &watch_dirs, &bundle).await;
         });
 
         // Start HTTP server
@@ -116,7 +130,15 @@ impl DevServer {
             style(&format!("http://{host}:{port}")).cyan().underlined()
         ));
 
-        while let Ok((stream, addr)) = listener.accept().await {
+        let watcher = shutdown::Watcher::new();
+        loop {
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(_) => break,
+                },
+                _ = watcher.stopping() => break,
+            };
             println!("{} Connection from {}", style("→").dim(), addr);
 
             let bundle_cache = Arc::clone(&self.bundle_cache);
@@ -139,7 +161,7 @@ impl DevServer {
         let rebuild_spinner = CliStyle::create_spinner("Rebuilding bundle...");
         let start_time = Instant::now();
 
-        let mut bundler = Bundler::new();
+        let mut bundler = Bundler::new(&self.bundle);
         let bundle_output = std::env::temp_dir().join("clay_dev_bundle.js");
 
         bundler
@@ -206,17 +228,18 @@ impl DevServer {
         file_watcher: Arc<RwLock<FileWatcher>>,
         bundle_cache: Arc<RwLock<Option<String>>>,
         ws_clients: Arc<RwLock<Vec<broadcast::Sender<String>>>>,
+        watch_dirs: &[String],
+        bundle: &BundleConfig,
     ) {
-        let watch_paths = Self::get_watch_paths().await;
+        let watch_paths = Self::get_watch_paths(watch_dirs).await;
 
         {
             let mut watcher = file_watcher.write().await;
             watcher.add_watched_paths(watch_paths.clone());
         }
 
-        loop {
-            sleep(Duration::from_millis(500)).await;
-
+        let watcher = shutdown::Watcher::new();
+        while watcher.tick(Duration::from_millis(500)).await {
             let has_changes = {
                 let mut watcher = file_watcher.write().await;
                 watcher
@@ -231,7 +254,7 @@ impl DevServer {
                     CliStyle::info("File changes detected, rebuilding...")
                 );
 
-                match Self::rebuild_bundle_static(bundle_cache.clone()).await {
+                match Self::rebuild_bundle_static(bundle_cache.clone(), bundle).await {
                     Ok(()) => {
                         Self::notify_clients_static(ws_clients.clone(), "reload").await;
                     }
@@ -245,12 +268,9 @@ impl DevServer {
         }
     }
 
-    async fn get_watch_paths() -> Vec<PathBuf> {
+    async fn get_watch_paths(watch_dirs: &[String]) -> Vec<PathBuf> {
         let mut paths = Vec::new();
 
-        // Watch common source directories
-        let watch_dirs = vec!["src", "lib", "components"];
-
         for dir in watch_dirs {
             if let Ok(entries) = Self::collect_files_recursively(dir).await {
                 paths.extend(entries);
@@ -283,13 +303,13 @@ impl DevServer {
 
                 if entry_path.is_dir() {
                     stack.push(entry_path);
-                } else if let Some(ext) = entry_path.extension() {
-                    if matches!(
+                } else if let Some(ext) = entry_path.extension()
+                    && matches!(
                         ext.to_str(),
                         Some("js") | Some("ts") | Some("jsx") | Some("tsx")
-                    ) {
-                        files.push(entry_path);
-                    }
+                    )
+                {
+                    files.push(entry_path);
                 }
             }
         }
@@ -297,8 +317,11 @@ impl DevServer {
         Ok(files)
     }
 
-    async fn rebuild_bundle_static(bundle_cache: Arc<RwLock<Option<String>>>) -> Result<()> {
-        let mut bundler = Bundler::new();
+    async fn rebuild_bundle_static(
+        bundle_cache: Arc<RwLock<Option<String>>>,
+        bundle: &BundleConfig,
+    ) -> Result<()> {
+        let mut bundler = Bundler::new(bundle);
         let bundle_output = std::env::temp_dir().join("clay_dev_bundle.js");
 
         bundler
@@ -496,9 +519,3 @@ impl DevServer {
             .to_string()
     }
 }
-
-impl Default for DevServer {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+use console::style;
+use std::collections::BTreeSet;
+
+use crate::cli_style::CliStyle;
+use crate::npm_client::NpmClient;
+
+/// Environment checks for diagnosing installs that fail for reasons outside the project
+pub struct Doctor {
+    npm_client: NpmClient,
+    failures: usize,
+}
+
+impl Doctor {
+    pub fn new(npm_client: NpmClient) -> Self {
+        Self {
+            npm_client,
+            failures: 0,
+        }
+    }
+
+    /// Run every check; returns false if any failed
+    pub async fn run(&mut self) -> Result<bool> {
+        self.check_proxies();
+        self.check_registries().await;
+        self.report_scopes();
+
+        println!();
+        if self.failures == 0 {
+            println!("{}", CliStyle::success("All checks passed"));
+        } else {
+            println!(
+                "{}",
+                CliStyle::error(&format!("{} check(s) failed", self.failures))
+            );
+        }
+
+        Ok(self.failures == 0)
+    }
+
+    fn pass(&self, message: &str) {
+        println!("  {}", CliStyle::success(message));
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.failures += 1;
+        println!("  {}", CliStyle::error(message));
+    }
+
+    /// Proxies come from the environment; .npmrc proxy keys are reported because clay
+    /// does not read them, which is a common source of "works with npm" confusion
+    fn check_proxies(&mut self) {
+        println!("{}", CliStyle::section_header("Proxy"));
+
+        let mut any = false;
+        for name in [
+            "HTTPS_PROXY",
+            "https_proxy",
+            "HTTP_PROXY",
+            "http_proxy",
+            "ALL_PROXY",
+        ] {
+            let Ok(value) = std::env::var(name) else {
+                continue;
+            };
+            any = true;
+            match Self::validate_proxy(&value) {
+                Ok(()) => self.pass(&format!("{name}={value}")),
+                Err(reason) => self.fail(&format!("{name}={value} is not usable: {reason}")),
+            }
+        }
+
+        if let Ok(no_proxy) = std::env::var("NO_PROXY").or_else(|_| std::env::var("no_proxy")) {
+            println!("  {} NO_PROXY={}", style("•").dim(), no_proxy);
+        }
+
+        for key in ["proxy", "https-proxy"] {
+            if let Some(value) = self.npmrc_value(key) {
+                any = true;
+                println!(
+                    "  {}",
+                    CliStyle::warning(&format!(
+                        ".npmrc sets {key}={value}; clay uses HTTPS_PROXY/HTTP_PROXY instead"
+                    ))
+                );
+            }
+        }
+
+        if !any {
+            println!("  {} No proxy configured", style("•").dim());
+        }
+    }
+
+    /// Proxy URLs without a scheme are treated as http, as reqwest does
+    fn validate_proxy(value: &str) -> std::result::Result<(), String> {
+        let url = if value.contains("://") {
+            value.to_string()
+        } else {
+            format!("http://{value}")
+        };
+        let url = reqwest::Url::parse(&url).map_err(|e| e.to_string())?;
+
+        if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+            return Err(format!("unsupported scheme '{}'", url.scheme()));
+        }
+        if url.host_str().is_none_or(str::is_empty) {
+            return Err("missing host".to_string());
+        }
+        Ok(())
+    }
+
+    fn npmrc_value(&self, key: &str) -> Option<String> {
+        self.npm_client.npmrc().get(key).map(str::to_string)
+    }
+
+    /// Ping every configured and scoped registry and verify credentials where present
+    async fn check_registries(&mut self) {
+        println!("\n{}", CliStyle::section_header("Registries"));
+
+        let mut registries: BTreeSet<String> = BTreeSet::new();
+        let mut ordered = Vec::new();
+        for registry in self
+            .npm_client
+            .registries()
+            .iter()
+            .chain(self.npm_client.scope_registries().values())
+        {
+            if registries.insert(registry.clone()) {
+                ordered.push(registry.clone());
+            }
+        }
+
+        for registry in ordered {
+            match self.npm_client.ping(&registry).await {
+                Ok(latency) => self.pass(&format!(
+                    "{registry} reachable ({})",
+                    CliStyle::format_duration(latency)
+                )),
+                Err(e) => {
+                    self.fail(&format!("{registry} unreachable: {e}"));
+                    continue;
+                }
+            }
+
+            match self.npm_client.whoami(&registry).await {
+                Ok(Some(username)) => self.pass(&format!("authenticated as {username}")),
+                Ok(None) => println!("    {}", CliStyle::dim_text("no credentials configured")),
+                Err(e) => self.fail(&format!("{registry} auth: {e}")),
+            }
+        }
+    }
+
+    fn report_scopes(&self) {
+        println!("\n{}", CliStyle::section_header("Scopes"));
+
+        let scopes = self.npm_client.scope_registries();
+        if scopes.is_empty() {
+            println!(
+                "  {} All packages use {}",
+                style("•").dim(),
+                self.npm_client.registries().join(", ")
+            );
+            return;
+        }
+
+        for (scope, registry) in scopes {
+            println!(
+                "  {} {} {}",
+                style(scope).white().bold(),
+                style("→").cyan(),
+                registry
+            );
+        }
+        println!(
+            "  {} everything else {} {}",
+            style("•").dim(),
+            style("→").cyan(),
+            self.npm_client.registries().join(", ")
+        );
+    }
+}
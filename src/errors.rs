@@ -0,0 +1,102 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Failure classes scripts can branch on: each is printed with its code and exits the
+/// process with its own status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The registry has no such package, version or tarball
+    NotFound,
+    /// Downloaded or stored content doesn't match its recorded hash
+    Integrity,
+    /// A registry couldn't be reached or failed to answer
+    Network,
+    /// The lockfile disagrees with package.json or has unresolved conflicts
+    LockConflict,
+    /// A lifecycle or package.json script exited unsuccessfully
+    ScriptFailed,
+    /// A package is blocked by the `[policy]` in clay.toml
+    Policy,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "E404",
+            ErrorCode::Integrity => "EINTEGRITY",
+            ErrorCode::Network => "ENETWORK",
+            ErrorCode::LockConflict => "ELOCKCONFLICT",
+            ErrorCode::ScriptFailed => "ESCRIPTFAIL",
+            ErrorCode::Policy => "EPOLICY",
+        }
+    }
+
+    /// Process exit status. 1 is left for errors without a code and 2 for usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::Network => 3,
+            ErrorCode::NotFound => 4,
+            ErrorCode::Integrity => 5,
+            ErrorCode::LockConflict => 6,
+            ErrorCode::ScriptFailed => 7,
+            ErrorCode::Policy => 8,
+        }
+    }
+
+    /// The code of the outermost coded error in `error`, whether it was raised or added as
+    /// context. Transport failures from reqwest count as ENETWORK wherever they come from.
+    pub fn of(error: &anyhow::Error) -> Option<ErrorCode> {
+        error
+            .downcast_ref::<CodedError>()
+            .or_else(|| error.chain().find_map(|cause| cause.downcast_ref()))
+            .map(|coded| coded.code)
+            .or_else(|| {
+                error
+                    .chain()
+                    .any(|cause| cause.is::<reqwest::Error>())
+                    .then_some(ErrorCode::Network)
+            })
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error carrying an ErrorCode; context added on top keeps the code
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// A coded error, usable wherever an anyhow error is
+pub fn coded(code: ErrorCode, message: impl Into<String>) -> anyhow::Error {
+    CodedError {
+        code,
+        message: message.into(),
+    }
+    .into()
+}
+
+/// An error for a failed HTTP response: E404 for a missing resource, ENETWORK when the
+/// server failed or throttled, uncoded otherwise (e.g. rejected credentials)
+pub fn http(status: StatusCode, message: impl Into<String>) -> anyhow::Error {
+    if status == StatusCode::NOT_FOUND {
+        coded(ErrorCode::NotFound, message)
+    } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        coded(ErrorCode::Network, message)
+    } else {
+        anyhow::anyhow!(message.into())
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::node_runtime;
+use crate::npm_client::NpmClient;
+use crate::package_manager::{PackageManager, SaveTarget};
+use crate::shutdown::{ForwardSignals, ScratchDir};
+
+/// Run `bin` with every node_modules/.bin from `project_dir` up to the filesystem root,
+/// then the Node the project pins, ahead of the inherited PATH
+pub fn exec_bin(project_dir: &Path, bin: &str, args: &[String]) -> Result<ExitStatus> {
+    let mut dirs = bin_dirs(project_dir);
+    dirs.extend(node_runtime::runtime_bin_dir(&project_root(project_dir)));
+    run_bin(&dirs, bin, args)
+}
+
+/// Every existing node_modules/.bin from `dir` up to the filesystem root, nearest first
+pub fn bin_dirs(dir: &Path) -> Vec<PathBuf> {
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .map(|dir| dir.join("node_modules").join(".bin"))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// The nearest directory from `dir` up holding a package.json or node_modules, as npm
+/// finds a project's root; `dir` itself if there is none
+pub fn project_root(dir: &Path) -> PathBuf {
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .find(|dir| dir.join("package.json").is_file() || dir.join("node_modules").is_dir())
+        .unwrap_or(&dir)
+        .to_path_buf()
+}
+
+/// Where packages linked or installed for every project live: `node_modules` and `bin`
+/// under ~/.clay/global
+pub fn global_prefix() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow!("Could not find home directory"))?
+        .join(".clay")
+        .join("global"))
+}
+
+/// The inherited PATH with `bin_dirs` in front
+pub fn path_with(bin_dirs: &[PathBuf]) -> Result<OsString> {
+    let inherited = std::env::var_os("PATH").unwrap_or_default();
+    Ok(std::env::join_paths(
+        bin_dirs
+            .iter()
+            .cloned()
+            .chain(std::env::split_paths(&inherited)),
+    )?)
+}
+
+fn run_bin(bin_dirs: &[PathBuf], bin: &str, args: &[String]) -> Result<ExitStatus> {
+    let path = path_with(bin_dirs)?;
+
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(bin);
+        cmd
+    } else {
+        // Prefer the project's own copy even if PATH lookup would find another one first
+        let local = bin_dirs
+            .iter()
+            .map(|dir| dir.join(bin))
+            .find(|candidate| candidate.is_file());
+        Command::new(local.unwrap_or_else(|| PathBuf::from(bin)))
+    };
+    cmd.args(args).env("PATH", path);
+
+    cmd.status().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => anyhow!(
+            "'{bin}' not found in node_modules/.bin or on PATH; install the package that provides it"
+        ),
+        _ => anyhow!("Failed to run '{bin}': {e}"),
+    })
+}
+
+/// Run `cmd` to completion, passing the signals clay receives on to it. Outside a terminal
+/// it leads a process group of its own, so whatever it starts gets them as well; in one it
+/// stays in clay's group, where it can read the terminal and gets Ctrl+C from it directly.
+pub async fn status_forwarding_signals(
+    cmd: &mut tokio::process::Command,
+) -> std::io::Result<ExitStatus> {
+    let own_group = !std::io::stdin().is_terminal();
+    #[cfg(unix)]
+    if own_group {
+        cmd.process_group(0);
+    }
+    let mut child = cmd.spawn()?;
+    let _forward = child.id().map(|pid| ForwardSignals::new(pid, own_group));
+    child.wait().await
+}
+
+/// The status clay exits with after a child's: its exit code, or 128 plus the signal that
+/// killed it, as shells report it
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Fetch `spec` (`name` or `name@range`) into a throwaway prefix and run its bin there,
+/// leaving the current project untouched
+pub async fn dlx(
+    config: ClayConfig,
+    npm_client: NpmClient,
+    spec: &str,
+    args: &[String],
+) -> Result<ExitStatus> {
+    let (name, version) = match spec.rfind('@') {
+        Some(at_pos) if at_pos > 0 => (&spec[..at_pos], &spec[at_pos + 1..]),
+        _ => (spec, "latest"),
+    };
+
+    let prefix = ScratchDir::new("clay-dlx");
+    tokio::fs::create_dir_all(prefix.path()).await?;
+    tokio::fs::write(prefix.path().join("package.json"), "{}\n").await?;
+
+    let mut package_manager =
+        PackageManager::with_client(config, npm_client).with_project_dir(prefix.path());
+    package_manager.set_quiet(true);
+    package_manager.set_save_target(SaveTarget::None);
+    package_manager.initialize().await?;
+
+    let spinner = CliStyle::create_spinner(&format!("fetching {spec}..."));
+    let installed = package_manager
+        .install_multiple_packages(vec![(name.to_string(), version.to_string())], false, true)
+        .await;
+    spinner.finish_and_clear();
+    installed?;
+
+    let node_modules = prefix.path().join("node_modules");
+    let bin = package_bin(&node_modules.join(name), name)?;
+    // Runs in the current directory, like npx
+    run_bin(&[node_modules.join(".bin")], &bin, args)
+}
+
+/// The command a package provides: its only bin, or the one named after the package
+fn package_bin(package_dir: &Path, name: &str) -> Result<String> {
+    let content = std::fs::read_to_string(package_dir.join("package.json"))?;
+    let package_json: Value = serde_json::from_str(&content)?;
+    let unscoped = name.rsplit('/').next().unwrap_or(name);
+
+    match package_json.get("bin") {
+        Some(Value::String(_)) => Ok(unscoped.to_string()),
+        Some(Value::Object(bins)) if bins.len() == 1 => Ok(bins.keys().next().unwrap().clone()),
+        Some(Value::Object(bins)) if bins.contains_key(unscoped) => Ok(unscoped.to_string()),
+        Some(Value::Object(bins)) if !bins.is_empty() => Err(anyhow!(
+            "{name} provides several commands ({}); run one with `clay exec` after installing",
+            bins.keys().cloned().collect::<Vec<_>>().join(", ")
+        )),
+        _ => Err(anyhow!("{name} doesn't provide any commands")),
+    }
+}
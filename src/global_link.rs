@@ -0,0 +1,219 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::exec;
+use crate::links;
+use crate::npm_client::NpmClient;
+use crate::package_manager::{PackageManager, SaveTarget};
+
+/// package.json sections a linked package is reinstalled from by `clay unlink <name>`
+const DEPENDENCY_FIELDS: [&str; 3] = ["dependencies", "devDependencies", "optionalDependencies"];
+
+/// `clay link` in a package: make it linkable by name from other projects, through a
+/// symlink in the global node_modules, and put its commands in the global bin directory
+pub fn register(package_dir: &Path) -> Result<()> {
+    let package_dir = std::fs::canonicalize(package_dir)
+        .with_context(|| format!("{} doesn't exist", package_dir.display()))?;
+    let (name, package_json) = read_package(&package_dir)?;
+    let prefix = exec::global_prefix()?;
+    let link = prefix.join("node_modules").join(&name);
+    replace_with_link(&package_dir, &link)?;
+
+    let bin_dir = prefix.join("bin");
+    for (command, path) in package_bins(&package_json, &name) {
+        std::fs::create_dir_all(&bin_dir)?;
+        let source = package_dir.join(&path);
+        links::link_bin(&bin_dir, &command, &source, &source)
+            .with_context(|| format!("Couldn't link the {command} command"))?;
+    }
+
+    println!(
+        "{} {} → {}",
+        CliStyle::success("Registered"),
+        CliStyle::package_name(&name),
+        CliStyle::dim_text(&package_dir.display().to_string())
+    );
+    println!(
+        "{}",
+        CliStyle::info(&format!(
+            "Run clay link {name} in a project to use it there"
+        ))
+    );
+    Ok(())
+}
+
+/// `clay unlink` in a package: take back what `register` did, if the global link is
+/// still this package's
+pub fn unregister(package_dir: &Path) -> Result<()> {
+    let package_dir = std::path::absolute(package_dir)?;
+    let (name, package_json) = read_package(&package_dir)?;
+    let prefix = exec::global_prefix()?;
+    let link = prefix.join("node_modules").join(&name);
+    match std::fs::read_link(&link) {
+        Ok(target) if same_dir(&target, &package_dir) => std::fs::remove_file(&link)?,
+        Ok(target) => {
+            return Err(anyhow!(
+                "{name} is registered from {}, not this package",
+                target.display()
+            ));
+        }
+        Err(_) => return Err(anyhow!("{name} isn't registered; nothing to unlink")),
+    }
+
+    let bin_dir = prefix.join("bin");
+    for (command, _) in package_bins(&package_json, &name) {
+        links::unlink_bin(&bin_dir, &command);
+    }
+    println!(
+        "{} {}",
+        CliStyle::success("Unregistered"),
+        CliStyle::package_name(&name)
+    );
+    Ok(())
+}
+
+/// `clay link <name>`: symlink a registered package (or the package at a path, which is
+/// registered along the way) into the project's node_modules, with its commands in .bin
+pub fn link_into(project_dir: &Path, package: &str) -> Result<()> {
+    let is_path = package.starts_with('.') || Path::new(package).is_absolute();
+    let source = if is_path {
+        let dir = project_dir.join(package);
+        register(&dir)?;
+        dir
+    } else {
+        exec::global_prefix()?.join("node_modules").join(package)
+    };
+    // Point at the package itself, so the project doesn't depend on the global link
+    let source = std::fs::canonicalize(&source)
+        .map_err(|_| anyhow!("{package} isn't registered; run clay link in its directory first"))?;
+    let (name, package_json) = read_package(&source)?;
+
+    let node_modules = project_dir.join("node_modules");
+    let bin_dir = node_modules.join(".bin");
+    // The commands of the copy being replaced may not exist in the linked one
+    if let Ok((_, installed)) = read_package(&node_modules.join(&name)) {
+        for (command, _) in package_bins(&installed, &name) {
+            links::unlink_bin(&bin_dir, &command);
+        }
+    }
+    replace_with_link(&source, &node_modules.join(&name))?;
+    for (command, path) in package_bins(&package_json, &name) {
+        std::fs::create_dir_all(&bin_dir)?;
+        let target = source.join(&path);
+        links::link_bin(&bin_dir, &command, &target, &target)
+            .with_context(|| format!("Couldn't link the {command} command"))?;
+    }
+
+    println!(
+        "{} {} → {}",
+        CliStyle::success("Linked"),
+        CliStyle::package_name(&name),
+        CliStyle::dim_text(&source.display().to_string())
+    );
+    Ok(())
+}
+
+/// `clay unlink <name>`: remove the project's link to a package, and put back the registry
+/// version if package.json depends on it
+pub async fn unlink_from(
+    config: ClayConfig,
+    npm_client: NpmClient,
+    project_dir: &Path,
+    name: &str,
+) -> Result<()> {
+    let node_modules = project_dir.join("node_modules");
+    let link = node_modules.join(name);
+    if !link.is_symlink() {
+        return Err(anyhow!("{name} isn't linked into this project"));
+    }
+    if let Ok(source) = std::fs::canonicalize(&link)
+        && let Ok((_, package_json)) = read_package(&source)
+    {
+        for (command, _) in package_bins(&package_json, name) {
+            links::unlink_bin(&node_modules.join(".bin"), &command);
+        }
+    }
+    remove_link(&link)?;
+    println!(
+        "{} {}",
+        CliStyle::success("Unlinked"),
+        CliStyle::package_name(name)
+    );
+
+    let content = std::fs::read_to_string(project_dir.join("package.json")).unwrap_or_default();
+    let package_json: Value = serde_json::from_str(&content).unwrap_or_default();
+    let Some(range) = DEPENDENCY_FIELDS
+        .iter()
+        .find_map(|field| package_json[field][name].as_str())
+    else {
+        return Ok(());
+    };
+    let mut package_manager =
+        PackageManager::with_client(config, npm_client).with_project_dir(project_dir);
+    package_manager.set_save_target(SaveTarget::None);
+    package_manager.initialize().await?;
+    package_manager
+        .install_multiple_packages(vec![(name.to_string(), range.to_string())], false, true)
+        .await
+}
+
+/// The name and manifest of the package in `dir`
+fn read_package(dir: &Path) -> Result<(String, Value)> {
+    let content = std::fs::read_to_string(dir.join("package.json"))
+        .map_err(|_| anyhow!("No package.json in {}", dir.display()))?;
+    let package_json: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid JSON in {}/package.json", dir.display()))?;
+    let name = package_json["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("{}/package.json has no name", dir.display()))?
+        .to_string();
+    Ok((name, package_json))
+}
+
+/// Commands a package provides, as (command, path in the package)
+fn package_bins(package_json: &Value, name: &str) -> Vec<(String, String)> {
+    match &package_json["bin"] {
+        // Scoped packages get a command named without the scope
+        Value::String(path) => vec![(
+            name.rsplit('/').next().unwrap_or(name).to_string(),
+            path.clone(),
+        )],
+        Value::Object(bins) => bins
+            .iter()
+            .filter_map(|(command, path)| Some((command.clone(), path.as_str()?.to_string())))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Make `link` a symlink to `target`, replacing an installed copy or an older link
+fn replace_with_link(target: &Path, link: &Path) -> Result<()> {
+    if link.symlink_metadata().is_ok() {
+        if link.is_symlink() {
+            remove_link(link)?;
+        } else {
+            std::fs::remove_dir_all(link)?;
+        }
+    }
+    if let Some(parent) = link.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    links::symlink(target, link)
+        .with_context(|| format!("Couldn't link {} to {}", link.display(), target.display()))
+}
+
+/// Remove a directory symlink, which Windows treats as a directory and Unix as a file
+fn remove_link(link: &Path) -> Result<()> {
+    std::fs::remove_file(link)
+        .or_else(|_| std::fs::remove_dir(link))
+        .with_context(|| format!("Couldn't remove {}", link.display()))
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    canonical(a) == canonical(b)
+}
@@ -0,0 +1,315 @@
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local, Utc};
+use console::style;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::lock_import;
+use crate::long_path;
+use crate::npm_client::NpmClient;
+use crate::package_manager::{PackageManager, SaveTarget};
+
+/// Files whose contents make up a project's dependency state: what it asks for and what
+/// it locked. `clay revert` puts all of them back.
+const TRACKED_FILES: [&str; 3] = ["package.json", "clay-lock.toml", "clay-lock.json"];
+
+/// Where transactions are logged, under the project's .clay directory
+const HISTORY_DIR: &str = "history";
+const LOG_FILE: &str = "log.jsonl";
+/// Contents of tracked files by their sha256, so any logged state can be restored
+const OBJECTS_DIR: &str = "objects";
+
+/// One command that changed the project's dependencies
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    /// The command line, without `clay`
+    pub command: String,
+    pub changes: Vec<Change>,
+    /// sha256 of each tracked file before the command ran; None where it didn't exist
+    pub before: BTreeMap<String, Option<String>>,
+    pub after: BTreeMap<String, Option<String>>,
+}
+
+/// A package added (no `from`), removed (no `to`) or moved to another version
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// The project's dependency state when a command started, recorded once it succeeds
+pub struct Transaction {
+    project_dir: PathBuf,
+    command: String,
+    /// Contents of each tracked file; None where it doesn't exist
+    files: BTreeMap<String, Option<Vec<u8>>>,
+    installed: BTreeSet<String>,
+}
+
+impl Transaction {
+    pub fn begin(project_dir: &Path, command: String) -> Self {
+        Self {
+            files: read_files(project_dir),
+            installed: installed(project_dir),
+            project_dir: project_dir.to_path_buf(),
+            command,
+        }
+    }
+
+    /// Log the command if it changed anything; returns the entry's id
+    pub fn commit(self) -> Result<Option<u64>> {
+        let files = read_files(&self.project_dir);
+        let changes = diff(&self.installed, &installed(&self.project_dir));
+        if files == self.files && changes.is_empty() {
+            return Ok(None);
+        }
+
+        let entries = read_log(&self.project_dir)?;
+        let id = entries.last().map_or(1, |entry| entry.id + 1);
+        let entry = Entry {
+            id,
+            time: Utc::now(),
+            command: self.command,
+            changes,
+            before: save_files(&self.project_dir, &self.files)?,
+            after: save_files(&self.project_dir, &files)?,
+        };
+        let log = history_dir(&self.project_dir).join(LOG_FILE);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .with_context(|| format!("Couldn't write {}", log.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(Some(id))
+    }
+}
+
+/// The project's transactions, oldest first
+pub fn read_log(project_dir: &Path) -> Result<Vec<Entry>> {
+    let log = history_dir(project_dir).join(LOG_FILE);
+    let Ok(content) = std::fs::read_to_string(&log) else {
+        return Ok(Vec::new());
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid entry in {}", log.display()))
+        })
+        .collect()
+}
+
+pub fn print(entries: &[Entry]) {
+    if entries.is_empty() {
+        println!("{}", CliStyle::dim_text("No installs recorded yet"));
+        return;
+    }
+    for entry in entries.iter().rev() {
+        println!(
+            "{} {} {}",
+            style(format!("#{}", entry.id)).cyan().bold(),
+            CliStyle::dim_text(
+                &entry
+                    .time
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            ),
+            style(format!("clay {}", entry.command)).white().bold()
+        );
+        for change in &entry.changes {
+            let line = match (&change.from, &change.to) {
+                (None, Some(to)) => format!("{} {}@{}", style("+").green(), change.name, to),
+                (Some(from), None) => format!("{} {}@{}", style("-").red(), change.name, from),
+                (Some(from), Some(to)) => format!(
+                    "{} {} {} → {}",
+                    style("~").yellow(),
+                    change.name,
+                    from,
+                    CliStyle::version(to)
+                ),
+                (None, None) => continue,
+            };
+            println!("    {line}");
+        }
+        if let Some((file, before, after)) = changed_lockfile(entry) {
+            println!(
+                "    {}",
+                CliStyle::dim_text(&format!("{file} {before} → {after}"))
+            );
+        }
+    }
+}
+
+/// The lockfile an entry changed, with its short hashes before and after
+fn changed_lockfile(entry: &Entry) -> Option<(&str, String, String)> {
+    let short = |hash: Option<&Option<String>>| match hash {
+        Some(Some(hash)) => hash[..8].to_string(),
+        _ => "none".to_string(),
+    };
+    TRACKED_FILES[1..]
+        .iter()
+        .find(|file| entry.before.get(**file) != entry.after.get(**file))
+        .map(|file| {
+            (
+                *file,
+                short(entry.before.get(*file)),
+                short(entry.after.get(*file)),
+            )
+        })
+}
+
+/// `clay revert <id>`: put package.json and the lockfile back as they were before
+/// transaction `id`, then install that state into a fresh node_modules
+pub async fn revert(
+    config: ClayConfig,
+    npm_client: NpmClient,
+    project_dir: &Path,
+    id: u64,
+) -> Result<()> {
+    let entries = read_log(project_dir)?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| anyhow!("No install #{id} in the history; `clay history` lists them"))?;
+
+    // Read every object first, so a missing one leaves the project untouched
+    let objects = history_dir(project_dir).join(OBJECTS_DIR);
+    let mut restored = Vec::new();
+    for (file, hash) in &entry.before {
+        let content = match hash {
+            Some(hash) => Some(
+                std::fs::read(objects.join(hash))
+                    .with_context(|| format!("The saved {file} of install #{id} is missing"))?,
+            ),
+            None => None,
+        };
+        restored.push((project_dir.join(file), content));
+    }
+    for (path, content) in restored {
+        match content {
+            Some(content) => std::fs::write(&path, content)?,
+            None if path.exists() => std::fs::remove_file(&path)?,
+            None => {}
+        }
+    }
+
+    let node_modules = project_dir.join("node_modules");
+    if node_modules.exists() {
+        std::fs::remove_dir_all(long_path::extend(&node_modules))
+            .with_context(|| format!("Couldn't remove {}", node_modules.display()))?;
+    }
+    println!(
+        "{}",
+        CliStyle::info(&format!(
+            "Restored package.json and the lockfile from before #{id} (clay {})",
+            entry.command
+        ))
+    );
+
+    let mut package_manager =
+        PackageManager::with_client(config, npm_client).with_project_dir(project_dir);
+    package_manager.set_save_target(SaveTarget::None);
+    package_manager.initialize().await?;
+    let specs = package_manager.get_package_json_dependencies(true).await?;
+    if specs.is_empty() {
+        return Ok(());
+    }
+    package_manager
+        .install_multiple_packages(specs, true, false)
+        .await
+}
+
+fn history_dir(project_dir: &Path) -> PathBuf {
+    project_dir.join(".clay").join(HISTORY_DIR)
+}
+
+fn read_files(project_dir: &Path) -> BTreeMap<String, Option<Vec<u8>>> {
+    TRACKED_FILES
+        .iter()
+        .map(|file| (file.to_string(), std::fs::read(project_dir.join(file)).ok()))
+        .collect()
+}
+
+/// Save the contents of `files` under their sha256, returning the hashes
+fn save_files(
+    project_dir: &Path,
+    files: &BTreeMap<String, Option<Vec<u8>>>,
+) -> Result<BTreeMap<String, Option<String>>> {
+    let objects = history_dir(project_dir).join(OBJECTS_DIR);
+    std::fs::create_dir_all(&objects)
+        .with_context(|| format!("Couldn't create {}", objects.display()))?;
+    let mut hashes = BTreeMap::new();
+    for (file, content) in files {
+        let hash = content.as_ref().map(|content| {
+            let hash = format!("{:x}", Sha256::digest(content));
+            (hash, content)
+        });
+        if let Some((hash, content)) = &hash {
+            let object = objects.join(hash);
+            if !object.exists() {
+                std::fs::write(&object, content)?;
+            }
+        }
+        hashes.insert(file.clone(), hash.map(|(hash, _)| hash));
+    }
+    Ok(hashes)
+}
+
+fn installed(project_dir: &Path) -> BTreeSet<String> {
+    lock_import::installed_versions(&project_dir.join("node_modules")).unwrap_or_default()
+}
+
+/// What happened to each package between two sets of installed `name@version`s
+fn diff(before: &BTreeSet<String>, after: &BTreeSet<String>) -> Vec<Change> {
+    let versions = |keys: &BTreeSet<String>| {
+        let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for key in keys {
+            if let Some((name, version)) = key.rsplit_once('@') {
+                versions
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(version.to_string());
+            }
+        }
+        versions
+    };
+    let (before, after) = (versions(before), versions(after));
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    let mut changes = Vec::new();
+    for name in names {
+        let from = before.get(name).cloned().unwrap_or_default();
+        let to = after.get(name).cloned().unwrap_or_default();
+        if from == to {
+            continue;
+        }
+        let removed: Vec<&String> = from
+            .iter()
+            .filter(|version| !to.contains(version))
+            .collect();
+        let added: Vec<&String> = to
+            .iter()
+            .filter(|version| !from.contains(version))
+            .collect();
+        // Pair versions up as upgrades or downgrades, leaving the rest added or removed
+        for index in 0..removed.len().max(added.len()) {
+            changes.push(Change {
+                name: name.clone(),
+                from: removed.get(index).map(|version| version.to_string()),
+                to: added.get(index).map(|version| version.to_string()),
+            });
+        }
+    }
+    changes
+}
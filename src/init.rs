@@ -0,0 +1,203 @@
+use anyhow::{Result, anyhow};
+use console::style;
+use serde_json::{Map, Value, json};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::cli_style::CliStyle;
+
+const DEFAULT_TEST_SCRIPT: &str = "echo \"Error: no test specified\" && exit 1";
+const ENTRY_POINT: &str = "src/index.js";
+
+/// Scaffold package.json in `dir`, asking for each field unless `yes` takes the defaults
+pub fn init_project(dir: &Path, yes: bool) -> Result<()> {
+    let package_json_path = dir.join("package.json");
+    if package_json_path.exists() {
+        return Err(anyhow!("package.json already exists in {}", dir.display()));
+    }
+
+    let default_name = dir
+        .file_name()
+        .map(|name| sanitize_name(&name.to_string_lossy()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "my-project".to_string());
+    let repository = git_remote(dir);
+
+    let ask = |label: &str, default: &str| -> Result<String> {
+        if yes {
+            Ok(default.to_string())
+        } else {
+            prompt(label, default)
+        }
+    };
+
+    let name = loop {
+        let name = ask("package name", &default_name)?;
+        match validate_name(&name) {
+            Ok(()) => break name,
+            Err(e) if !yes => println!("{}", CliStyle::error(&e.to_string())),
+            Err(e) => return Err(e),
+        }
+    };
+    let version = loop {
+        let version = ask("version", "1.0.0")?;
+        if semver::Version::parse(&version).is_ok() {
+            break version;
+        }
+        if yes {
+            return Err(anyhow!("Invalid version '{version}'"));
+        }
+        println!(
+            "{}",
+            CliStyle::error(&format!("'{version}' is not a semver version"))
+        );
+    };
+    let description = ask("description", "")?;
+    let test_script = ask("test command", DEFAULT_TEST_SCRIPT)?;
+    let repository = ask("git repository", repository.as_deref().unwrap_or(""))?;
+    let license = ask("license", "ISC")?;
+    let create_entry = !dir.join(ENTRY_POINT).exists()
+        && (yes || ask(&format!("create {ENTRY_POINT}? (y/n)"), "y")?.starts_with(['y', 'Y']));
+
+    let mut scripts = Map::new();
+    if create_entry || dir.join(ENTRY_POINT).exists() {
+        scripts.insert("start".to_string(), json!(format!("node {ENTRY_POINT}")));
+    }
+    scripts.insert("test".to_string(), json!(test_script));
+
+    let mut package_json = Map::new();
+    package_json.insert("name".to_string(), json!(name));
+    package_json.insert("version".to_string(), json!(version));
+    package_json.insert("description".to_string(), json!(description));
+    package_json.insert("main".to_string(), json!(ENTRY_POINT));
+    package_json.insert("scripts".to_string(), Value::Object(scripts));
+    if !repository.is_empty() {
+        package_json.insert(
+            "repository".to_string(),
+            json!({ "type": "git", "url": repository_url(&repository) }),
+        );
+    }
+    package_json.insert("license".to_string(), json!(license));
+
+    let mut content = serde_json::to_string_pretty(&Value::Object(package_json))?;
+    content.push('\n');
+
+    if !yes {
+        println!("\n{content}");
+        if !prompt("Is this OK? (y/n)", "y")?.starts_with(['y', 'Y']) {
+            println!("{}", CliStyle::info("Aborted"));
+            return Ok(());
+        }
+    }
+
+    std::fs::write(&package_json_path, content)?;
+    if create_entry {
+        let entry = dir.join(ENTRY_POINT);
+        if let Some(parent) = entry.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&entry, "console.log(\"Hello from clay!\");\n")?;
+    }
+
+    println!(
+        "{} {}{}",
+        CliStyle::success("Created package.json for"),
+        style(&name).white().bold(),
+        if create_entry {
+            CliStyle::dim_text(&format!(" and {ENTRY_POINT}"))
+        } else {
+            String::new()
+        }
+    );
+    Ok(())
+}
+
+fn prompt(label: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{label}: ");
+    } else {
+        print!("{label}: {} ", CliStyle::dim_text(&format!("({default})")));
+    }
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    Ok(if input.is_empty() { default } else { input }.to_string())
+}
+
+/// A directory name made into a valid package name: lowercase, with spaces and anything
+/// npm would reject turned into dashes
+fn sanitize_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-._~".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches(['.', '_', '-'])
+        .to_string()
+}
+
+/// npm's package name rules: at most 214 URL-safe lowercase characters, not starting
+/// with a dot or underscore, optionally under an `@scope/`
+fn validate_name(name: &str) -> Result<()> {
+    let bare = match name.strip_prefix('@') {
+        Some(scoped) => scoped
+            .split_once('/')
+            .map(|(_, bare)| bare)
+            .ok_or_else(|| anyhow!("Scoped names look like @scope/name"))?,
+        None => name,
+    };
+
+    if name.len() > 214 {
+        return Err(anyhow!("Package names are at most 214 characters"));
+    }
+    if bare.is_empty() || bare.starts_with(['.', '_']) {
+        return Err(anyhow!("Package names can't be empty or start with . or _"));
+    }
+    if name != name.to_lowercase() {
+        return Err(anyhow!("Package names must be lowercase"));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-._~@/".contains(c))
+    {
+        return Err(anyhow!("'{name}' contains characters not allowed in a URL"));
+    }
+    Ok(())
+}
+
+/// The `origin` remote of the git repository `dir` is in, if any
+fn git_remote(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--get", "remote.origin.url"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !url.is_empty()).then_some(url)
+}
+
+/// A remote as npm writes it in `repository.url`: `git+https://...`, with scp-style
+/// `git@host:owner/repo` rewritten to https
+fn repository_url(remote: &str) -> String {
+    if remote.starts_with("git+") {
+        return remote.to_string();
+    }
+    if let Some((host, path)) = remote
+        .strip_prefix("git@")
+        .and_then(|rest| rest.split_once(':'))
+    {
+        return format!("git+https://{host}/{path}");
+    }
+    if remote.starts_with("https://") || remote.starts_with("http://") {
+        return format!("git+{remote}");
+    }
+    remote.to_string()
+}
@@ -0,0 +1,255 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use crate::errors::{self, ErrorCode};
+use crate::exec;
+
+/// Scripts a dependency runs once it's linked, in the order npm runs them
+const INSTALL_SCRIPTS: [&str; 3] = ["preinstall", "install", "postinstall"];
+
+/// The install scripts `package_dir` declares. A package with a binding.gyp and neither a
+/// preinstall nor an install script gets npm's implicit `node-gyp rebuild`.
+pub fn install_scripts(package_dir: &Path) -> Vec<(&'static str, String)> {
+    let scripts = std::fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|package_json| package_json.get("scripts").cloned())
+        .unwrap_or(Value::Null);
+
+    let mut found: Vec<(&'static str, String)> = INSTALL_SCRIPTS
+        .iter()
+        .filter_map(|&event| {
+            let command = scripts.get(event)?.as_str()?;
+            Some((event, command.to_string()))
+        })
+        .collect();
+    if found.iter().all(|(event, _)| *event == "postinstall")
+        && package_dir.join("binding.gyp").is_file()
+    {
+        found.insert(0, ("install", "node-gyp rebuild".to_string()));
+    }
+    found
+}
+
+/// Run one install script of the package in `package_dir`, with the node_modules/.bin
+/// directories above it on PATH. Output is only shown when the script fails.
+#[tracing::instrument(name = "install script", skip_all, fields(name = name, event = event))]
+pub async fn run_install_script(
+    package_dir: &Path,
+    name: &str,
+    version: &str,
+    event: &str,
+    command: &str,
+) -> Result<()> {
+    tracing::debug!("running {event} of {name}@{version}: {command}");
+    let mut cmd = script_command(package_dir, name, version, event, command)?;
+    cmd.stdin(std::process::Stdio::null());
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| anyhow!("Couldn't run the {event} script of {name}@{version}: {e}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let mut log = String::from_utf8_lossy(&output.stdout).into_owned();
+    log.push_str(&String::from_utf8_lossy(&output.stderr));
+    Err(errors::coded(
+        ErrorCode::ScriptFailed,
+        format!(
+            "{name}@{version} {event} script `{command}` failed with exit code {}\n{}",
+            output.status.code().unwrap_or(-1),
+            log.trim_end()
+        ),
+    ))
+}
+
+/// Run the project's own `event` script in `project_dir`, if package.json has one, with
+/// its output shown as it runs
+pub async fn run_project_script(project_dir: &Path, event: &str) -> Result<()> {
+    let package_json: Value =
+        serde_json::from_str(&std::fs::read_to_string(project_dir.join("package.json"))?)?;
+    let Some(command) = package_json
+        .get("scripts")
+        .and_then(|scripts| scripts.get(event))
+        .and_then(Value::as_str)
+    else {
+        return Ok(());
+    };
+    let name = package_json["name"].as_str().unwrap_or_default();
+    let version = package_json["version"].as_str().unwrap_or_default();
+
+    tracing::debug!("running {event} of {name}@{version}: {command}");
+    let status = script_command(project_dir, name, version, event, command)?
+        .status()
+        .await
+        .map_err(|e| anyhow!("Couldn't run the {event} script: {e}"))?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(errors::coded(
+        ErrorCode::ScriptFailed,
+        format!(
+            "{event} script `{command}` failed with exit code {}",
+            status.code().unwrap_or(-1)
+        ),
+    ))
+}
+
+/// `command` run by the platform shell in `package_dir` as npm runs scripts: with the
+/// node_modules/.bin directories above it on PATH and the npm_* variables set
+fn script_command(
+    package_dir: &Path,
+    name: &str,
+    version: &str,
+    event: &str,
+    command: &str,
+) -> Result<tokio::process::Command> {
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = tokio::process::Command::new("/bin/sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.current_dir(package_dir)
+        .env("PATH", exec::path_with(&exec::bin_dirs(package_dir))?)
+        .env("INIT_CWD", std::env::current_dir()?)
+        .env("npm_lifecycle_event", event)
+        .env("npm_lifecycle_script", command)
+        .env("npm_package_name", name)
+        .env("npm_package_version", version);
+    Ok(cmd)
+}
+
+/// Size and modification time of every file under a package, to tell what a build changed
+pub type Snapshot = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+/// Files install scripts added or changed, kept under ~/.clay/builds by package version,
+/// Node ABI and platform, so a clean install restores a native build instead of compiling
+/// it again. Without a working `node` there's no ABI to key on, and nothing is cached.
+pub struct BuildCache {
+    dir: PathBuf,
+    target: OnceLock<Option<String>>,
+}
+
+impl BuildCache {
+    pub fn new() -> Self {
+        let dir = dirs::home_dir()
+            .map(|home| home.join(".clay").join("builds"))
+            .unwrap_or_else(|| PathBuf::from(".clay-builds"));
+        Self {
+            dir,
+            target: OnceLock::new(),
+        }
+    }
+
+    /// e.g. `node-abi127-linux-x86_64`; asked of node once per run
+    fn target(&self) -> Option<&str> {
+        self.target
+            .get_or_init(|| {
+                let output = std::process::Command::new("node")
+                    .args(["-p", "process.versions.modules"])
+                    .output()
+                    .ok()?;
+                let abi = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                (output.status.success() && !abi.is_empty()).then(|| {
+                    format!(
+                        "node-abi{abi}-{}-{}",
+                        std::env::consts::OS,
+                        std::env::consts::ARCH
+                    )
+                })
+            })
+            .as_deref()
+    }
+
+    fn entry(&self, name: &str, version: &str) -> Option<PathBuf> {
+        Some(
+            self.dir
+                .join(format!("{name}@{version}"))
+                .join(self.target()?),
+        )
+    }
+
+    /// Copy a cached build of `name@version` into `package_dir`; false if there is none
+    pub fn restore(&self, name: &str, version: &str, package_dir: &Path) -> Result<bool> {
+        let Some(entry) = self.entry(name, version).filter(|entry| entry.is_dir()) else {
+            return Ok(false);
+        };
+
+        for file in WalkDir::new(&entry).into_iter().flatten() {
+            if !file.file_type().is_file() {
+                continue;
+            }
+            let target = package_dir.join(file.path().strip_prefix(&entry)?);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // The package's own files may be hardlinks into the content store; replace
+            // the link rather than writing through it
+            if target.symlink_metadata().is_ok() {
+                std::fs::remove_file(&target)?;
+            }
+            std::fs::copy(file.path(), &target)?;
+        }
+        Ok(true)
+    }
+
+    pub fn snapshot(package_dir: &Path) -> Snapshot {
+        WalkDir::new(package_dir)
+            .into_iter()
+            .flatten()
+            .filter(|file| file.file_type().is_file())
+            .filter_map(|file| {
+                let metadata = file.metadata().ok()?;
+                let relative = file.path().strip_prefix(package_dir).ok()?.to_path_buf();
+                Some((relative, (metadata.len(), metadata.modified().ok())))
+            })
+            .collect()
+    }
+
+    /// Keep what the scripts of `name@version` added or changed since `before`
+    pub fn save(
+        &self,
+        name: &str,
+        version: &str,
+        package_dir: &Path,
+        before: &Snapshot,
+    ) -> Result<()> {
+        let Some(entry) = self.entry(name, version) else {
+            return Ok(());
+        };
+
+        let partial = entry.with_extension(format!("{}.partial", std::process::id()));
+        std::fs::remove_dir_all(&partial).ok();
+        for (relative, state) in Self::snapshot(package_dir) {
+            if before.get(&relative) == Some(&state) {
+                continue;
+            }
+            let target = partial.join(&relative);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(package_dir.join(&relative), &target)?;
+        }
+        if !partial.exists() {
+            // The scripts built nothing; an empty entry still saves running them again
+            std::fs::create_dir_all(&partial)?;
+        }
+
+        // Another install may have cached the same build meanwhile; either copy will do
+        if std::fs::rename(&partial, &entry).is_err() {
+            std::fs::remove_dir_all(&partial).ok();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Shim extensions written next to the extensionless shim: cmd.exe and PowerShell
+const SHIM_EXTENSIONS: [&str; 2] = ["cmd", "ps1"];
+
+/// Expose `source` as `bin_dir/command_name`. `target` is how the link refers to
+/// `source`, relative to `bin_dir` where possible.
+///
+/// Unix gets a symlink to the (now executable) source, falling back to shims where the
+/// filesystem doesn't allow symlinks. Windows always gets shims, since symlinks there need
+/// elevated rights and wouldn't know which interpreter to use: a `.cmd` for cmd.exe, a
+/// `.ps1` for PowerShell and an extensionless sh script for Git Bash and Cygwin.
+pub fn link_bin(bin_dir: &Path, command_name: &str, source: &Path, target: &Path) -> Result<()> {
+    unlink_bin(bin_dir, command_name);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Ok(metadata) = std::fs::metadata(source) {
+            let mut perms = metadata.permissions();
+            perms.set_mode(perms.mode() | 0o755);
+            std::fs::set_permissions(source, perms).ok();
+        }
+        if std::os::unix::fs::symlink(target, bin_dir.join(command_name)).is_ok() {
+            return Ok(());
+        }
+    }
+
+    write_shims(bin_dir, command_name, source, target)
+}
+
+/// Remove every link or shim `link_bin` may have made for `command_name`. Returns whether
+/// there was one.
+pub fn unlink_bin(bin_dir: &Path, command_name: &str) -> bool {
+    let mut removed = false;
+    let candidates = std::iter::once(command_name.to_string()).chain(
+        SHIM_EXTENSIONS
+            .iter()
+            .map(|extension| format!("{command_name}.{extension}")),
+    );
+    for name in candidates {
+        let path = bin_dir.join(name);
+        // symlink_metadata, so dangling links are removed too
+        if path.symlink_metadata().is_ok() && std::fs::remove_file(&path).is_ok() {
+            removed = true;
+        }
+    }
+    removed
+}
+
+fn write_shims(bin_dir: &Path, command_name: &str, source: &Path, target: &Path) -> Result<()> {
+    // `"node" --flag ` ahead of the script, or nothing when it runs directly
+    let program = match interpreter(source) {
+        (Some(program), args) if args.is_empty() => format!("\"{program}\" "),
+        (Some(program), args) => format!("\"{program}\" {args} "),
+        (None, _) => String::new(),
+    };
+    let target = target.to_string_lossy().replace('\\', "/");
+    let shim_path = bin_dir.join(command_name);
+
+    std::fs::write(&shim_path, sh_shim(&program, &target))?;
+    std::fs::write(shim_path.with_extension("cmd"), cmd_shim(&program, &target))?;
+    std::fs::write(shim_path.with_extension("ps1"), ps1_shim(&program, &target))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&shim_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// The program (and its arguments) that should run `source`, from its shebang line. Files
+/// without one run under node when they are JavaScript, and directly otherwise.
+fn interpreter(source: &Path) -> (Option<String>, String) {
+    let first_line = std::fs::read(source)
+        .ok()
+        .and_then(|contents| {
+            let line = contents.split(|&b| b == b'\n').next()?.to_vec();
+            String::from_utf8(line).ok()
+        })
+        .unwrap_or_default();
+
+    if let Some(shebang) = first_line.strip_prefix("#!") {
+        let mut words = shebang.split_whitespace().peekable();
+        // `#!/usr/bin/env [-S] node` names the real program after env
+        if words.peek().is_some_and(|word| word.ends_with("/env")) {
+            words.next();
+            if words.peek() == Some(&"-S") {
+                words.next();
+            }
+        }
+        if let Some(program) = words.next() {
+            let program = program.rsplit('/').next().unwrap_or(program);
+            return (
+                Some(program.to_string()),
+                words.collect::<Vec<_>>().join(" "),
+            );
+        }
+    }
+
+    let is_javascript = source
+        .extension()
+        .is_some_and(|extension| ["js", "cjs", "mjs"].contains(&&*extension.to_string_lossy()));
+    (is_javascript.then(|| "node".to_string()), String::new())
+}
+
+fn sh_shim(program: &str, target: &str) -> String {
+    let run = format!("{program}\"$basedir/{target}\"");
+    format!(
+        "#!/bin/sh\n\
+         basedir=$(dirname \"$(echo \"$0\" | sed -e 's,\\\\,/,g')\")\n\
+         \n\
+         case `uname` in\n    \
+             *CYGWIN*|*MINGW*|*MSYS*) basedir=`cygpath -w \"$basedir\"`;;\n\
+         esac\n\
+         \n\
+         exec {run} \"$@\"\n"
+    )
+}
+
+fn cmd_shim(program: &str, target: &str) -> String {
+    let run = format!("{program}\"%dp0%\\{}\"", target.replace('/', "\\"));
+    format!("@ECHO off\r\nSETLOCAL\r\nSET \"dp0=%~dp0\"\r\n{run} %*\r\n")
+}
+
+fn ps1_shim(program: &str, target: &str) -> String {
+    let run = format!("& {program}\"$basedir/{target}\"");
+    format!(
+        "#!/usr/bin/env pwsh\n\
+         $basedir=Split-Path $MyInvocation.MyCommand.Definition -Parent\n\
+         if ($MyInvocation.ExpectingInput) {{\n  \
+             $input | {run} $args\n\
+         }} else {{\n  \
+             {run} $args\n\
+         }}\n\
+         exit $LASTEXITCODE\n"
+    )
+}
+
+/// Recreate the symlink at `source` as `dest`
+pub fn copy_symlink(source: &Path, dest: &Path) -> Result<()> {
+    symlink(&std::fs::read_link(source)?, dest)
+}
+
+/// Make `link` point at `target`, read relative to the link's directory. Windows needs to
+/// know whether the target is a directory, so it must exist already; where symlinks
+/// aren't permitted, directories get a junction and files a copy instead.
+pub fn symlink(target: &Path, link: &Path) -> Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+
+    #[cfg(windows)]
+    {
+        let resolved = link
+            .parent()
+            .map(|parent| parent.join(target))
+            .unwrap_or_else(|| target.to_path_buf());
+        if resolved.is_dir() {
+            if std::os::windows::fs::symlink_dir(target, link).is_err() {
+                let status = std::process::Command::new("cmd")
+                    .arg("/C")
+                    .arg("mklink")
+                    .arg("/J")
+                    .arg(link)
+                    .arg(&resolved)
+                    .output()?
+                    .status;
+                if !status.success() {
+                    return Err(anyhow::anyhow!(
+                        "Couldn't link {} to {}",
+                        link.display(),
+                        resolved.display()
+                    ));
+                }
+            }
+        } else if std::os::windows::fs::symlink_file(target, link).is_err() {
+            std::fs::copy(&resolved, link)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,705 @@
+use anyhow::{Context, Result, anyhow};
+use console::style;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli_style::CliStyle;
+use crate::errors::{self, ErrorCode};
+use crate::lock_export::{self, ExportFormat};
+use crate::lock_import::{self, ForeignLock};
+use crate::lock_merge;
+use crate::npm_client::NpmClient;
+use crate::package_info::{LOCKFILE_VERSION, LockFile, PackageJson};
+
+/// Why the lockfile at `path` couldn't be read: ELOCKCONFLICT when git left merge conflict
+/// markers in it, otherwise the parse error, both pointing at `clay lock merge`
+pub fn parse_error(path: &Path, content: &str, error: anyhow::Error) -> anyhow::Error {
+    if lock_merge::split_conflicts(content).is_some() {
+        return errors::coded(
+            ErrorCode::LockConflict,
+            format!(
+                "{} has merge conflicts; run `clay lock merge` to resolve them",
+                path.display()
+            ),
+        );
+    }
+    error.context(format!(
+        "Failed to parse {}; if it came from a merge, `clay lock merge` can rebuild it",
+        path.display()
+    ))
+}
+
+/// `clay lock` subcommands: operations on clay-lock.toml / clay-lock.json that don't install
+pub struct LockManager {
+    npm_client: NpmClient,
+    /// Parallel registry requests, for commands that look up many packages
+    concurrency: usize,
+}
+
+impl LockManager {
+    pub fn new(npm_client: NpmClient, concurrency: usize) -> Self {
+        Self {
+            npm_client,
+            concurrency,
+        }
+    }
+
+    /// The project's lockfile path: an existing clay-lock.json, else clay-lock.toml
+    pub fn lock_file_path() -> PathBuf {
+        let json = PathBuf::from("clay-lock.json");
+        if json.exists() && !Path::new("clay-lock.toml").exists() {
+            json
+        } else {
+            PathBuf::from("clay-lock.toml")
+        }
+    }
+
+    /// Read a lockfile, migrating older schema versions in memory
+    pub fn read(path: &Path) -> Result<LockFile> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        LockFile::parse(&content, Self::is_json(path)).map_err(|e| parse_error(path, &content, e))
+    }
+
+    /// Read the project's lockfile, failing with a hint when there is none
+    fn read_project() -> Result<(PathBuf, LockFile)> {
+        let path = Self::lock_file_path();
+        if !path.exists() {
+            return Err(anyhow!(
+                "No clay-lock.toml or clay-lock.json found; run `clay install` first"
+            ));
+        }
+        let lock_file = Self::read(&path)?;
+        Ok((path, lock_file))
+    }
+
+    fn read_package_json() -> Result<PackageJson> {
+        match std::fs::read_to_string("package.json") {
+            Ok(content) => serde_json::from_str(&content).context("Failed to parse package.json"),
+            Err(_) => Ok(PackageJson::new()),
+        }
+    }
+
+    /// Write through a sibling temp file so an interrupted write never truncates the lockfile
+    pub fn write(path: &Path, lock_file: &LockFile) -> Result<()> {
+        Self::write_as(path, lock_file, Self::is_json(path))
+    }
+
+    fn write_as(path: &Path, lock_file: &LockFile, json: bool) -> Result<()> {
+        let content = if json {
+            serde_json::to_string_pretty(lock_file)?
+        } else {
+            toml::to_string_pretty(lock_file)?
+        };
+
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, content)?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn is_json(path: &Path) -> bool {
+        path.extension().and_then(|ext| ext.to_str()) == Some("json")
+    }
+
+    /// Convert package-lock.json, yarn.lock or pnpm-lock.yaml into clay's lockfile
+    pub fn import(&self, source: Option<&Path>, force: bool) -> Result<()> {
+        let source = match source {
+            Some(path) => path.to_path_buf(),
+            None => ForeignLock::FILES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No lockfile to import; looked for {}",
+                        ForeignLock::FILES.join(", ")
+                    )
+                })?,
+        };
+
+        let target = Self::writable_target(force)?;
+        let package_json = Self::read_package_json()?;
+
+        let (format, lock_file) = lock_import::import(&source, &package_json, &self.npm_client)?;
+        if lock_file.packages.is_empty() {
+            return Err(anyhow!(
+                "{} has no registry packages to import",
+                source.display()
+            ));
+        }
+
+        Self::write(&target, &lock_file)?;
+        Self::report_import(format.name(), &target, &lock_file);
+        Ok(())
+    }
+
+    /// Generate the lockfile from the packages already installed in node_modules, for
+    /// projects adopting clay that have no lockfile to convert
+    pub async fn import_node_modules(&self, force: bool) -> Result<()> {
+        let target = Self::writable_target(force)?;
+        let package_json = Self::read_package_json()?;
+
+        let spinner = CliStyle::create_spinner("Looking up installed packages...");
+        let lock_file = lock_import::from_node_modules(
+            Path::new("node_modules"),
+            &package_json,
+            &self.npm_client,
+            self.concurrency,
+        )
+        .await;
+        spinner.finish_and_clear();
+        let lock_file = lock_file?;
+
+        Self::write(&target, &lock_file)?;
+        Self::report_import("node_modules", &target, &lock_file);
+        Ok(())
+    }
+
+    /// The lockfile path to import into, refusing to replace one without `force`
+    fn writable_target(force: bool) -> Result<PathBuf> {
+        let target = Self::lock_file_path();
+        if target.exists() && !force {
+            return Err(anyhow!(
+                "{} already exists; pass --force to overwrite it",
+                target.display()
+            ));
+        }
+        Ok(target)
+    }
+
+    fn report_import(source: &str, target: &Path, lock_file: &LockFile) {
+        let missing_integrity = lock_file
+            .packages
+            .values()
+            .filter(|package| package.integrity.is_empty())
+            .count();
+
+        println!(
+            "{}",
+            CliStyle::success(&format!(
+                "Imported {} packages from {} into {}",
+                style(lock_file.packages.len()).bold(),
+                source,
+                target.display()
+            ))
+        );
+        if missing_integrity > 0 {
+            println!(
+                "  {}",
+                CliStyle::dim_text(&format!(
+                    "{missing_integrity} package(s) had no registry checksum to carry over"
+                ))
+            );
+        }
+    }
+
+    /// Write clay's lock data in another tool's format, for tooling that only reads that
+    pub fn export(&self, format: ExportFormat, output: Option<&Path>) -> Result<()> {
+        let (source, lock_file) = Self::read_project()?;
+        let package_json = Self::read_package_json()?;
+
+        let document = match format {
+            ExportFormat::Npm => lock_export::to_npm(&lock_file, &package_json)?,
+        };
+
+        let output = output.unwrap_or_else(|| Path::new(format.default_file_name()));
+        std::fs::write(output, serde_json::to_string_pretty(&document)? + "\n")
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+
+        println!(
+            "{}",
+            CliStyle::success(&format!(
+                "Exported {} packages from {} to {}",
+                style(lock_file.packages.len()).bold(),
+                source.display(),
+                output.display()
+            ))
+        );
+
+        Ok(())
+    }
+
+    /// Rewrite the lockfile in the current schema. Installs migrate it too; this is for
+    /// committing the migration on its own.
+    pub fn migrate(&self) -> Result<()> {
+        let (path, lock_file) = Self::read_project()?;
+        let content = std::fs::read_to_string(&path)?;
+        let previous = Self::schema_version(&content, Self::is_json(&path));
+
+        if previous.as_deref() == Some(LOCKFILE_VERSION) {
+            println!(
+                "{}",
+                CliStyle::success(&format!(
+                    "{} is already at lockfile version {LOCKFILE_VERSION}",
+                    path.display()
+                ))
+            );
+            return Ok(());
+        }
+
+        Self::write(&path, &lock_file)?;
+        println!(
+            "{}",
+            CliStyle::success(&format!(
+                "Migrated {} from version {} to {LOCKFILE_VERSION} ({} packages)",
+                path.display(),
+                previous.as_deref().unwrap_or("unknown"),
+                lock_file.packages.len()
+            ))
+        );
+        Ok(())
+    }
+
+    fn schema_version(content: &str, json: bool) -> Option<String> {
+        if json {
+            let value: serde_json::Value = serde_json::from_str(content).ok()?;
+            Some(value.get("version")?.as_str()?.to_string())
+        } else {
+            let value: toml::Value = toml::from_str(content).ok()?;
+            Some(value.get("version")?.as_str()?.to_string())
+        }
+    }
+
+    /// Check the lockfile against package.json and the configured registries; fails with
+    /// ELOCKCONFLICT if anything is wrong
+    pub fn verify(&self) -> Result<()> {
+        let (path, lock_file) = Self::read_project()?;
+        let package_json = Self::read_package_json()?;
+        let mut problems: Vec<String> = Vec::new();
+
+        let mut requested: Vec<(&String, &String)> = [
+            &package_json.dependencies,
+            &package_json.dev_dependencies,
+            &package_json.optional_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|deps| deps.iter())
+        .collect();
+        requested.sort();
+
+        for (name, range) in requested {
+            match lock_file.root.get(name) {
+                None => problems.push(format!("{name} is in package.json but not locked")),
+                Some(edge) if &edge.requested_range != range => problems.push(format!(
+                    "{name} is locked for {} but package.json asks for {range}",
+                    edge.requested_range
+                )),
+                Some(edge)
+                    if lock_file.locked_key(name, edge).is_none()
+                        && lock_file.workspace_named(name).is_none() =>
+                {
+                    problems.push(format!("{name}@{range} has no locked version"))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for name in lock_file.root.keys() {
+            if package_json.requested_range(name).is_none() {
+                problems.push(format!("{name} is locked but no longer in package.json"));
+            }
+        }
+
+        for (path, workspace) in &lock_file.workspaces {
+            if !Path::new(path).join("package.json").exists() {
+                problems.push(format!(
+                    "workspace {} has no package.json at {path}",
+                    workspace.name
+                ));
+            }
+            for (dep, edge) in &workspace.dependencies {
+                if lock_file.locked_key(dep, edge).is_none()
+                    && lock_file.workspace_named(dep).is_none()
+                {
+                    problems.push(format!(
+                        "workspace {} depends on {dep}@{} which is not locked",
+                        workspace.name, edge.requested_range
+                    ));
+                }
+            }
+        }
+
+        let reachable = lock_file.reachable_keys();
+        for (key, package) in &lock_file.packages {
+            if package.integrity.is_empty() {
+                problems.push(format!("{key} has no integrity"));
+            }
+
+            for (dep, edge) in &package.dependencies {
+                if lock_file.locked_key(dep, edge).is_none() {
+                    problems.push(format!(
+                        "{key} depends on {dep}@{} which is not locked",
+                        edge.requested_range
+                    ));
+                }
+            }
+
+            if !reachable.contains(key) {
+                problems.push(format!("{key} is not required by anything (orphan)"));
+            }
+
+            let registries = self.npm_client.registries_for(&package.name);
+            if !registries
+                .iter()
+                .any(|registry| package.resolved.starts_with(&format!("{registry}/")))
+            {
+                problems.push(format!(
+                    "{key} resolves to {}, outside {}",
+                    package.resolved,
+                    registries.join(", ")
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            println!(
+                "{}",
+                CliStyle::success(&format!(
+                    "{} is valid ({} packages)",
+                    path.display(),
+                    lock_file.packages.len()
+                ))
+            );
+            return Ok(());
+        }
+
+        for problem in &problems {
+            println!("{}", CliStyle::error(problem));
+        }
+        Err(errors::coded(
+            ErrorCode::LockConflict,
+            format!("{} problem(s) in {}", problems.len(), path.display()),
+        ))
+    }
+
+    /// Drop root edges package.json no longer asks for and every package they alone kept
+    /// reachable, then list node_modules directories for packages that aren't locked
+    pub fn prune(&self, dry_run: bool) -> Result<()> {
+        let (path, mut lock_file) = Self::read_project()?;
+        let package_json = Self::read_package_json()?;
+
+        let stale_roots: Vec<String> = lock_file
+            .root
+            .keys()
+            .filter(|name| package_json.requested_range(name).is_none())
+            .cloned()
+            .collect();
+        for name in &stale_roots {
+            lock_file.root.remove(name);
+        }
+
+        let reachable = lock_file.reachable_keys();
+        let orphans: Vec<String> = lock_file
+            .packages
+            .keys()
+            .filter(|key| !reachable.contains(*key))
+            .cloned()
+            .collect();
+        for key in &orphans {
+            lock_file.packages.remove(key);
+        }
+
+        let strays = Self::unlocked_node_modules(&lock_file);
+
+        if stale_roots.is_empty() && orphans.is_empty() && strays.is_empty() {
+            println!(
+                "{}",
+                CliStyle::success(&format!("Nothing to prune in {}", path.display()))
+            );
+            return Ok(());
+        }
+
+        for name in &stale_roots {
+            println!(
+                "  {} {} {}",
+                style("-").red().bold(),
+                name,
+                style("(no longer in package.json)").dim()
+            );
+        }
+        for key in &orphans {
+            println!("  {} {}", style("-").red().bold(), key);
+        }
+        for name in &strays {
+            println!(
+                "  {} node_modules/{} {}",
+                style("?").yellow().bold(),
+                name,
+                style("is not in the lockfile").dim()
+            );
+        }
+
+        let pruned = !stale_roots.is_empty() || !orphans.is_empty();
+        if pruned && !dry_run {
+            Self::write(&path, &lock_file)?;
+        }
+
+        let action = if dry_run { "Would prune" } else { "Pruned" };
+        println!(
+            "\n{}",
+            CliStyle::success(&format!(
+                "{action} {} orphaned package(s) from {}",
+                orphans.len(),
+                path.display()
+            ))
+        );
+        if !strays.is_empty() {
+            println!(
+                "  {}",
+                CliStyle::dim_text(&format!(
+                    "{} director(ies) in node_modules left in place; delete them or reinstall",
+                    strays.len()
+                ))
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Top-level node_modules packages (including scoped ones) with no locked version
+    fn unlocked_node_modules(lock_file: &LockFile) -> Vec<String> {
+        let mut names = Vec::new();
+        let Ok(entries) = std::fs::read_dir("node_modules") else {
+            return names;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || !entry.path().is_dir() {
+                continue;
+            }
+            if name.starts_with('@') {
+                let Ok(scoped) = std::fs::read_dir(entry.path()) else {
+                    continue;
+                };
+                for scoped_entry in scoped.flatten() {
+                    names.push(format!(
+                        "{name}/{}",
+                        scoped_entry.file_name().to_string_lossy()
+                    ));
+                }
+            } else {
+                names.push(name);
+            }
+        }
+
+        names.retain(|name| lock_file.versions_of(name).next().is_none());
+        names.sort();
+        names
+    }
+
+    /// Resolve a conflicted lockfile. With no files, reads the conflict markers git left in
+    /// the project's lockfile; with `base ours theirs` it runs as a git merge driver and
+    /// writes the result over `ours`.
+    pub async fn merge(&self, files: &[PathBuf]) -> Result<()> {
+        let (target, json, base, ours, theirs) = match files {
+            [] => {
+                let path = Self::lock_file_path();
+                let content = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let Some(sides) = lock_merge::split_conflicts(&content) else {
+                    println!(
+                        "{}",
+                        CliStyle::success(&format!("{} has no conflicts", path.display()))
+                    );
+                    return Ok(());
+                };
+                let json = Self::is_json(&path);
+                let parse = |side: &str, name: &str| {
+                    LockFile::parse(side, json)
+                        .with_context(|| format!("Failed to parse {name} side of the conflict"))
+                };
+                let base = sides
+                    .base
+                    .as_deref()
+                    .map(|base| parse(base, "base"))
+                    .transpose()?;
+                let ours = parse(&sides.ours, "our")?;
+                let theirs = parse(&sides.theirs, "their")?;
+                (path, json, base, ours, theirs)
+            }
+            [base, ours, theirs] => {
+                let read = |path: &PathBuf| -> Result<(String, LockFile)> {
+                    let content = std::fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?;
+                    let json = content.trim_start().starts_with('{');
+                    let lock_file = LockFile::parse(&content, json)
+                        .with_context(|| format!("Failed to parse {}", path.display()))?;
+                    Ok((content, lock_file))
+                };
+                let (ours_content, ours_lock) = read(ours)?;
+                let (_, theirs_lock) = read(theirs)?;
+                let json = ours_content.trim_start().starts_with('{');
+                (
+                    ours.clone(),
+                    json,
+                    Some(read(base)?.1),
+                    ours_lock,
+                    theirs_lock,
+                )
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Pass no files, or <base> <ours> <theirs> when running as a merge driver"
+                ));
+            }
+        };
+
+        // A merge driver can run before git has merged package.json, so only a merge the
+        // user has finished resolving is checked against it
+        let package_json = if files.is_empty() {
+            Some(Self::read_package_json()?)
+        } else {
+            None
+        };
+
+        let (merged, report) = lock_merge::merge(
+            base.as_ref(),
+            ours,
+            theirs,
+            package_json.as_ref(),
+            &self.npm_client,
+        )
+        .await?;
+        Self::write_as(&target, &merged, json)?;
+
+        for name in &report.reresolved {
+            println!("  {} {}", style("↻").cyan().bold(), name);
+        }
+        for key in &report.fetched {
+            println!("  {} {}", style("+").green().bold(), key);
+        }
+        for key in &report.integrity_fixed {
+            println!(
+                "  {} {} {}",
+                style("!").yellow().bold(),
+                key,
+                style("(integrity taken from the registry)").dim()
+            );
+        }
+        println!(
+            "{}",
+            CliStyle::success(&format!(
+                "Merged {} ({} packages, {} re-resolved)",
+                target.display(),
+                merged.packages.len(),
+                report.reresolved.len()
+            ))
+        );
+
+        Ok(())
+    }
+
+    /// Compare the lockfile with its version at a git revision
+    pub fn diff(&self, git_ref: &str) -> Result<()> {
+        let (path, current) = Self::read_project()?;
+
+        let output = Command::new("git")
+            .args(["show", &format!("{git_ref}:./{}", path.display())])
+            .output()
+            .context("Failed to run git")?;
+        let previous = if output.status.success() {
+            LockFile::parse(
+                &String::from_utf8_lossy(&output.stdout),
+                Self::is_json(&path),
+            )
+            .with_context(|| format!("Failed to parse {} at {git_ref}", path.display()))?
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // A lockfile that didn't exist at that revision diffs as empty
+            if !stderr.contains("does not exist") && !stderr.contains("exists on disk, but not in")
+            {
+                return Err(anyhow!("git show failed: {}", stderr.trim()));
+            }
+            LockFile::new()
+        };
+
+        let versions = |lock_file: &LockFile| {
+            let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+            for package in lock_file.packages.values() {
+                versions
+                    .entry(package.name.clone())
+                    .or_default()
+                    .insert(package.version.clone());
+            }
+            versions
+        };
+        let before = versions(&previous);
+        let after = versions(&current);
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let empty = BTreeSet::new();
+
+        for name in names {
+            let old = before.get(name).unwrap_or(&empty);
+            let new = after.get(name).unwrap_or(&empty);
+            let gone: Vec<&String> = old.difference(new).collect();
+            let came: Vec<&String> = new.difference(old).collect();
+
+            match (gone.as_slice(), came.as_slice()) {
+                ([], []) => {}
+                ([old_version], [new_version]) => {
+                    changed.push((name, *old_version, *new_version));
+                }
+                _ => {
+                    removed.extend(gone.iter().map(|version| format!("{name}@{version}")));
+                    added.extend(came.iter().map(|version| format!("{name}@{version}")));
+                }
+            }
+        }
+
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
+            println!(
+                "{}",
+                CliStyle::success(&format!("No dependency changes since {git_ref}"))
+            );
+            return Ok(());
+        }
+
+        println!(
+            "{}",
+            CliStyle::section_header(&format!("Dependency changes since {git_ref}"))
+        );
+        for package in &added {
+            println!("  {} {}", style("+").green().bold(), package);
+        }
+        for package in &removed {
+            println!("  {} {}", style("-").red().bold(), package);
+        }
+        for (name, old_version, new_version) in &changed {
+            let direction = match (
+                semver::Version::parse(old_version),
+                semver::Version::parse(new_version),
+            ) {
+                (Ok(old), Ok(new)) if new < old => style("↓").yellow().bold(),
+                _ => style("↑").cyan().bold(),
+            };
+            println!(
+                "  {} {} {} {} {}",
+                direction,
+                name,
+                style(old_version).dim(),
+                style("→").dim(),
+                style(new_version).green()
+            );
+        }
+        println!(
+            "\n{}",
+            CliStyle::dim_text(&format!(
+                "{} added, {} removed, {} changed",
+                added.len(),
+                removed.len(),
+                changed.len()
+            ))
+        );
+
+        Ok(())
+    }
+}
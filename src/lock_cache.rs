@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::package_info::LockFile;
+
+/// Compact MessagePack copy of the lockfile, kept in node_modules like npm's hidden
+/// lockfile so it never shows up in version control
+const LOCK_CACHE_FILE: &str = ".clay-lock.bin";
+
+#[derive(Serialize, Deserialize)]
+struct LockCache {
+    /// sha256 of the clay-lock.toml / clay-lock.json text this was decoded from
+    source_hash: String,
+    lock_file: LockFile,
+}
+
+fn source_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn cache_path(node_modules_dir: &Path) -> PathBuf {
+    node_modules_dir.join(LOCK_CACHE_FILE)
+}
+
+/// Parse lockfile text, decoding the binary cache instead when it was built from the same
+/// text. A stale or missing cache is rebuilt, so edits to the lockfile always win.
+pub fn parse(content: &str, json: bool, node_modules_dir: &Path) -> Result<LockFile> {
+    let hash = source_hash(content);
+    if let Some(lock_file) = read(&cache_path(node_modules_dir), &hash) {
+        return Ok(lock_file);
+    }
+
+    let lock_file = LockFile::parse(content, json)?;
+    store(content, &lock_file, node_modules_dir);
+    Ok(lock_file)
+}
+
+/// Record the cache for lockfile text clay has just written. Failures are ignored; the
+/// next read falls back to the text.
+pub fn store(content: &str, lock_file: &LockFile, node_modules_dir: &Path) {
+    if !node_modules_dir.exists() {
+        return;
+    }
+    let path = cache_path(node_modules_dir);
+
+    let cache = LockCache {
+        source_hash: source_hash(content),
+        lock_file: lock_file.clone(),
+    };
+    if let Ok(bytes) = rmp_serde::to_vec_named(&cache) {
+        let temp_path = path.with_extension("tmp");
+        if std::fs::write(&temp_path, bytes).is_ok() {
+            std::fs::rename(&temp_path, &path).ok();
+        }
+    }
+}
+
+fn read(path: &Path, hash: &str) -> Option<LockFile> {
+    let bytes = std::fs::read(path).ok()?;
+    let cache: LockCache = rmp_serde::from_slice(&bytes).ok()?;
+    (cache.source_hash == hash).then_some(cache.lock_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_lives_in_the_projects_node_modules() {
+        let project =
+            std::env::temp_dir().join(format!("clay-test-lock-cache-{}", std::process::id()));
+        let node_modules = project.join("node_modules");
+        std::fs::create_dir_all(&node_modules).unwrap();
+        let content = serde_json::to_string(&LockFile::new()).unwrap();
+
+        parse(&content, true, &node_modules).unwrap();
+        assert!(node_modules.join(LOCK_CACHE_FILE).exists());
+        assert!(read(&cache_path(&node_modules), &source_hash(&content)).is_some());
+        assert!(read(&cache_path(&node_modules), &source_hash("{}")).is_none());
+
+        std::fs::remove_dir_all(&project).ok();
+    }
+
+    #[test]
+    fn nothing_is_cached_without_node_modules() {
+        let project =
+            std::env::temp_dir().join(format!("clay-test-no-lock-cache-{}", std::process::id()));
+        let node_modules = project.join("node_modules");
+        let content = serde_json::to_string(&LockFile::new()).unwrap();
+
+        parse(&content, true, &node_modules).unwrap();
+        assert!(!node_modules.exists());
+    }
+}
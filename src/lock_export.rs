@@ -0,0 +1,229 @@
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::package_info::{LockFile, LockedDependency, LockedPackage, PackageJson};
+
+/// Lockfile formats clay-lock can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// package-lock.json, lockfileVersion 3
+    Npm,
+}
+
+impl ExportFormat {
+    pub fn default_file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Npm => "package-lock.json",
+        }
+    }
+}
+
+/// Nesting deeper than this means a dependency cycle between differing versions
+const MAX_NESTING: usize = 32;
+
+/// Build a package-lock.json (v3) document. The version the project resolves to is
+/// hoisted to `node_modules/<name>`; other versions nest under the packages that need them.
+pub fn to_npm(lock_file: &LockFile, package_json: &PackageJson) -> Result<Value> {
+    let placements = layout(lock_file)?;
+    let dev_only = dev_only_packages(lock_file, package_json);
+
+    let mut root = Map::new();
+    if let Some(ref name) = package_json.name {
+        root.insert("name".to_string(), json!(name));
+    }
+    if let Some(ref version) = package_json.version {
+        root.insert("version".to_string(), json!(version));
+    }
+    for (key, deps) in [
+        ("dependencies", &package_json.dependencies),
+        ("devDependencies", &package_json.dev_dependencies),
+        ("optionalDependencies", &package_json.optional_dependencies),
+        ("peerDependencies", &package_json.peer_dependencies),
+    ] {
+        if let Some(deps) = deps.as_ref().filter(|deps| !deps.is_empty()) {
+            let sorted: BTreeMap<&String, &String> = deps.iter().collect();
+            root.insert(key.to_string(), json!(sorted));
+        }
+    }
+
+    let mut packages = Map::new();
+    packages.insert(String::new(), Value::Object(root));
+
+    for (path, key) in &placements {
+        let package = locked_package(lock_file, key)?;
+        let mut entry = Map::new();
+        entry.insert("version".to_string(), json!(package.version));
+        entry.insert("resolved".to_string(), json!(package.resolved));
+        if !package.integrity.is_empty() {
+            entry.insert("integrity".to_string(), json!(package.integrity));
+        }
+        if dev_only.contains(key.as_str()) {
+            entry.insert("dev".to_string(), json!(true));
+        }
+        if !package.dependencies.is_empty() {
+            let ranges: BTreeMap<&String, &String> = package
+                .dependencies
+                .iter()
+                .map(|(name, edge)| (name, &edge.requested_range))
+                .collect();
+            entry.insert("dependencies".to_string(), json!(ranges));
+        }
+        packages.insert(path.clone(), Value::Object(entry));
+    }
+
+    // Workspace packages get an entry at their own path and a link from node_modules
+    for (path, workspace) in &lock_file.workspaces {
+        let mut entry = Map::new();
+        entry.insert("name".to_string(), json!(workspace.name));
+        if let Some(ref version) = workspace.version {
+            entry.insert("version".to_string(), json!(version));
+        }
+        if !workspace.dependencies.is_empty() {
+            let ranges: BTreeMap<&String, &String> = workspace
+                .dependencies
+                .iter()
+                .map(|(name, edge)| (name, &edge.requested_range))
+                .collect();
+            entry.insert("dependencies".to_string(), json!(ranges));
+        }
+        packages.insert(path.clone(), Value::Object(entry));
+        packages.insert(
+            format!("node_modules/{}", workspace.name),
+            json!({ "resolved": path, "link": true }),
+        );
+    }
+
+    Ok(json!({
+        "name": package_json.name.clone().unwrap_or_default(),
+        "version": package_json.version.clone().unwrap_or_default(),
+        "lockfileVersion": 3,
+        "requires": true,
+        "packages": packages,
+    }))
+}
+
+/// The locked package under `key`, which a corrupt lockfile may not have
+fn locked_package<'a>(lock_file: &'a LockFile, key: &str) -> Result<&'a LockedPackage> {
+    lock_file
+        .packages
+        .get(key)
+        .ok_or_else(|| anyhow!("Lockfile refers to {key}, which it doesn't lock"))
+}
+
+/// `node_modules/...` path for every placed package key, laid out so Node's resolution
+/// from each dependent finds the version its edge is locked to
+fn layout(lock_file: &LockFile) -> Result<BTreeMap<String, String>> {
+    let mut placements: BTreeMap<String, String> = BTreeMap::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+
+    let mut names: Vec<&str> = lock_file
+        .packages
+        .values()
+        .map(|package| package.name.as_str())
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        if let Some(package) = lock_file.find(name) {
+            let path = format!("node_modules/{name}");
+            placements.insert(path.clone(), LockFile::key(name, &package.version));
+            queue.push_back(path);
+        }
+    }
+
+    while let Some(path) = queue.pop_front() {
+        if path.matches("node_modules/").count() > MAX_NESTING {
+            continue;
+        }
+        let key = placements
+            .get(&path)
+            .ok_or_else(|| anyhow!("No package placed at {path}"))?;
+        let package = locked_package(lock_file, key)?;
+
+        for (dep, edge) in &package.dependencies {
+            let Some(target) = lock_file.locked_key(dep, edge) else {
+                continue;
+            };
+            if resolve_from(&placements, &path, dep) == Some(&target) {
+                continue;
+            }
+
+            let nested = format!("{path}/node_modules/{dep}");
+            placements.insert(nested.clone(), target);
+            queue.push_back(nested);
+        }
+    }
+
+    Ok(placements)
+}
+
+/// What `require(dep)` from the package at `path` finds, walking up node_modules dirs
+fn resolve_from<'a>(
+    placements: &'a BTreeMap<String, String>,
+    path: &str,
+    dep: &str,
+) -> Option<&'a String> {
+    let mut base = path.to_string();
+    loop {
+        if let Some(key) = placements.get(&format!("{base}/node_modules/{dep}")) {
+            return Some(key);
+        }
+        match base.rfind("/node_modules/") {
+            Some(pos) => base.truncate(pos),
+            None => return placements.get(&format!("node_modules/{dep}")),
+        }
+    }
+}
+
+/// Package keys only reachable through devDependencies, which npm marks `"dev": true`
+fn dev_only_packages<'a>(lock_file: &'a LockFile, package_json: &PackageJson) -> HashSet<&'a str> {
+    let production = reachable(
+        lock_file,
+        [
+            &package_json.dependencies,
+            &package_json.optional_dependencies,
+        ],
+    );
+    let development = reachable(lock_file, [&package_json.dev_dependencies]);
+
+    development.difference(&production).copied().collect()
+}
+
+/// Locked package keys reachable from the root edges named in the given dependency maps
+fn reachable<'a, 'b>(
+    lock_file: &'a LockFile,
+    roots: impl IntoIterator<Item = &'b Option<HashMap<String, String>>>,
+) -> HashSet<&'a str> {
+    let key_of = |name: &String, edge: &LockedDependency| {
+        let key = lock_file.locked_key(name, edge)?;
+        lock_file
+            .packages
+            .get_key_value(&key)
+            .map(|(key, _)| key.as_str())
+    };
+
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut queue: VecDeque<&str> = roots
+        .into_iter()
+        .flatten()
+        .flat_map(|deps| deps.keys())
+        .filter_map(|name| key_of(name, lock_file.root.get(name)?))
+        .collect();
+
+    while let Some(key) = queue.pop_front() {
+        if !seen.insert(key) {
+            continue;
+        }
+        queue.extend(
+            lock_file
+                .packages
+                .get(key)
+                .into_iter()
+                .flat_map(|package| &package.dependencies)
+                .filter_map(|(name, edge)| key_of(name, edge)),
+        );
+    }
+
+    seen
+}
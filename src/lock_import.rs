@@ -0,0 +1,738 @@
+use anyhow::{Context, Result, anyhow};
+use futures::stream::{self, StreamExt};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::npm_client::NpmClient;
+use crate::package_info::{
+    LockFile, LockedDependency, LockedPackage, PackageJson, sri_from_shasum,
+};
+use crate::virtual_store;
+
+/// Lockfiles from other package managers that can be converted into clay-lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignLock {
+    Npm,
+    YarnClassic,
+    YarnBerry,
+    Pnpm,
+}
+
+impl ForeignLock {
+    /// Candidate files, in the order they are looked for
+    pub const FILES: [&'static str; 3] = ["package-lock.json", "yarn.lock", "pnpm-lock.yaml"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForeignLock::Npm => "package-lock.json",
+            ForeignLock::YarnClassic => "yarn.lock (v1)",
+            ForeignLock::YarnBerry => "yarn.lock (berry)",
+            ForeignLock::Pnpm => "pnpm-lock.yaml",
+        }
+    }
+
+    /// Detect the format from the file name and, for yarn.lock, its contents
+    pub fn detect(path: &Path, content: &str) -> Result<Self> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+
+        match file_name {
+            "package-lock.json" | "npm-shrinkwrap.json" => Ok(ForeignLock::Npm),
+            "pnpm-lock.yaml" => Ok(ForeignLock::Pnpm),
+            "yarn.lock" if content.contains("__metadata:") => Ok(ForeignLock::YarnBerry),
+            "yarn.lock" => Ok(ForeignLock::YarnClassic),
+            _ => Err(anyhow!(
+                "Unrecognized lockfile '{}'; expected one of {}",
+                path.display(),
+                Self::FILES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A package entry read from a foreign lockfile, before flattening
+#[derive(Debug, Clone, Default)]
+struct ImportedPackage {
+    name: String,
+    version: String,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    /// Dependency name to the range (yarn, npm) or exact version (pnpm) it asked for
+    dependencies: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+struct ImportedLock {
+    packages: Vec<ImportedPackage>,
+    /// Versions the project itself depends on, by name
+    root_versions: HashMap<String, String>,
+    /// `name@range` to the version it resolved to, where the format records it
+    descriptors: HashMap<String, String>,
+}
+
+impl ImportedLock {
+    /// Root versions from the `name@range` descriptors package.json's ranges map to
+    fn resolve_root(&mut self, package_json: &PackageJson) {
+        for (name, range) in project_dependencies(package_json) {
+            if let Some(version) = self.descriptors.get(&format!("{name}@{range}")) {
+                self.root_versions.insert(name, version.clone());
+            }
+        }
+    }
+}
+
+/// Convert a foreign lockfile into a clay LockFile. Formats that only record name
+/// and version get tarball URLs on the registry each package would be fetched from.
+pub fn import(
+    path: &Path,
+    package_json: &PackageJson,
+    npm_client: &NpmClient,
+) -> Result<(ForeignLock, LockFile)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = ForeignLock::detect(path, &content)?;
+
+    let imported = match format {
+        ForeignLock::Npm => parse_npm(&content),
+        ForeignLock::YarnClassic => parse_yarn_classic(&content, package_json),
+        ForeignLock::YarnBerry => parse_yarn_berry(&content, package_json),
+        ForeignLock::Pnpm => parse_pnpm(&content),
+    }
+    .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    Ok((format, build_lock_file(imported, package_json, npm_client)))
+}
+
+/// Build a lockfile from what is installed: every package.json under `node_modules`
+/// (nested ones included), with edges following Node's resolution and the tarball and
+/// integrity of each version looked up on its registry
+pub async fn from_node_modules(
+    node_modules: &Path,
+    package_json: &PackageJson,
+    npm_client: &NpmClient,
+    concurrency: usize,
+) -> Result<LockFile> {
+    let (installed, mut imported) = scan_installed(node_modules)?;
+
+    // The registry's tarball and integrity for each installed version, where it has them
+    let versions: BTreeSet<(String, String)> = installed
+        .values()
+        .map(|package| (package.name.clone(), package.version.clone()))
+        .collect();
+    let published: HashMap<(String, String), (String, String)> = stream::iter(versions)
+        .map(|(name, version)| async move {
+            let info = npm_client.get_version_info(&name, &version).await.ok()?;
+            let integrity = info
+                .dist
+                .integrity
+                .unwrap_or_else(|| sri_from_shasum(&info.dist.shasum));
+            Some(((name, version), (info.dist.tarball, integrity)))
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await;
+
+    imported.packages = installed
+        .into_values()
+        .map(|mut package| {
+            if let Some((resolved, integrity)) =
+                published.get(&(package.name.clone(), package.version.clone()))
+            {
+                package.resolved = Some(resolved.clone());
+                package.integrity = Some(integrity.clone());
+            }
+            package
+        })
+        .collect();
+
+    Ok(build_lock_file(imported, package_json, npm_client))
+}
+
+/// The dependency graph of what is installed, without registry lookups, so resolved
+/// URLs are the registry's defaults and integrity is empty
+pub fn graph_from_node_modules(
+    node_modules: &Path,
+    package_json: &PackageJson,
+    npm_client: &NpmClient,
+) -> Result<LockFile> {
+    let (installed, mut imported) = scan_installed(node_modules)?;
+    imported.packages = installed.into_values().collect();
+    Ok(build_lock_file(imported, package_json, npm_client))
+}
+
+/// Every installed package by its `node_modules/...` path, and the root versions and
+/// edges Node's resolution gives them
+fn scan_installed(
+    node_modules: &Path,
+) -> Result<(BTreeMap<String, ImportedPackage>, ImportedLock)> {
+    let mut installed: BTreeMap<String, ImportedPackage> = BTreeMap::new();
+    scan_node_modules(node_modules, "node_modules/", &mut installed)?;
+    if installed.is_empty() {
+        return Err(anyhow!("No packages found in {}", node_modules.display()));
+    }
+
+    let mut imported = ImportedLock::default();
+    for (path, package) in &installed {
+        if path.rfind("node_modules/") == Some(0) {
+            imported
+                .root_versions
+                .insert(package.name.clone(), package.version.clone());
+        }
+        for (dep, range) in &package.dependencies {
+            if let Some(target) = resolve_installed(&installed, path, dep) {
+                imported
+                    .descriptors
+                    .entry(format!("{dep}@{range}"))
+                    .or_insert_with(|| target.version.clone());
+            }
+        }
+    }
+
+    Ok((installed, imported))
+}
+
+/// `name@version` of every package installed under a node_modules directory, nested
+/// ones included
+pub fn installed_versions(node_modules: &Path) -> Result<BTreeSet<String>> {
+    let mut installed = BTreeMap::new();
+    scan_node_modules(node_modules, "node_modules/", &mut installed)?;
+    Ok(installed
+        .values()
+        .map(|package| LockFile::key(&package.name, &package.version))
+        .collect())
+}
+
+/// Where each `name@version` in `node_modules` is installed; nested layouts can hold the
+/// same version in several places
+pub fn installed_dirs(node_modules: &Path) -> Result<BTreeMap<String, Vec<PathBuf>>> {
+    let mut installed = BTreeMap::new();
+    scan_node_modules(node_modules, "node_modules/", &mut installed)?;
+    let project_dir = node_modules.parent().unwrap_or(Path::new(""));
+    let mut dirs: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (path, package) in installed {
+        dirs.entry(LockFile::key(&package.name, &package.version))
+            .or_default()
+            .push(project_dir.join(path));
+    }
+    Ok(dirs)
+}
+
+/// Read every package directory under `dir`, keyed by its `node_modules/...` path.
+/// Symlinked packages (workspace and `npm link` targets) are not registry packages,
+/// except links into the isolated layout's virtual store.
+fn scan_node_modules(
+    dir: &Path,
+    prefix: &str,
+    installed: &mut BTreeMap<String, ImportedPackage>,
+) -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = entry.file_type()?;
+        if name == virtual_store::DIR && file_type.is_dir() {
+            scan_virtual_store(&entry.path(), &format!("{prefix}{name}/"), installed)?;
+            continue;
+        }
+        // Links into the isolated layout's store stand for the package they point at
+        let is_store_link = file_type.is_symlink() && links_into_virtual_store(&entry.path());
+        if name.starts_with('.') || !(file_type.is_dir() || is_store_link) {
+            continue;
+        }
+        if name.starts_with('@') {
+            scan_node_modules(&entry.path(), &format!("{prefix}{name}/"), installed)?;
+            continue;
+        }
+
+        let package_dir = entry.path();
+        let Ok(content) = std::fs::read_to_string(package_dir.join("package.json")) else {
+            continue;
+        };
+        let Ok(manifest) = serde_json::from_str::<Value>(&content) else {
+            continue;
+        };
+        let (Some(package_name), Some(version)) = (
+            manifest.get("name").and_then(Value::as_str),
+            manifest.get("version").and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+
+        let mut dependencies = string_map(manifest.get("dependencies"));
+        dependencies.extend(string_map(manifest.get("optionalDependencies")));
+
+        let path = format!("{prefix}{name}");
+        installed.insert(
+            path.clone(),
+            ImportedPackage {
+                name: package_name.to_string(),
+                version: version.to_string(),
+                // Left by npm < 7; the registry lookup replaces them when it succeeds
+                resolved: manifest
+                    .get("_resolved")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                integrity: manifest
+                    .get("_integrity")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                dependencies,
+            },
+        );
+
+        if !is_store_link {
+            scan_node_modules(
+                &package_dir.join("node_modules"),
+                &format!("{path}/node_modules/"),
+                installed,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the isolated layout's `.clay/<name>@<version>/node_modules` directories
+fn scan_virtual_store(
+    dir: &Path,
+    prefix: &str,
+    installed: &mut BTreeMap<String, ImportedPackage>,
+) -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        scan_node_modules(
+            &entry.path().join("node_modules"),
+            &format!("{prefix}{name}/node_modules/"),
+            installed,
+        )?;
+    }
+    Ok(())
+}
+
+fn links_into_virtual_store(link: &Path) -> bool {
+    std::fs::canonicalize(link).is_ok_and(|target| {
+        target
+            .components()
+            .any(|component| component.as_os_str() == virtual_store::DIR)
+    })
+}
+
+/// The installed package `require(dep)` finds from the package at `path`
+fn resolve_installed<'a>(
+    installed: &'a BTreeMap<String, ImportedPackage>,
+    path: &str,
+    dep: &str,
+) -> Option<&'a ImportedPackage> {
+    let mut base = path.to_string();
+    loop {
+        if let Some(package) = installed.get(&format!("{base}/node_modules/{dep}")) {
+            return Some(package);
+        }
+        match base.rfind("/node_modules/") {
+            Some(pos) => base.truncate(pos),
+            None => return installed.get(&format!("node_modules/{dep}")),
+        }
+    }
+}
+
+/// Registry tarball URL for a package version, as npm lays them out
+fn tarball_url(registry: &str, name: &str, version: &str) -> String {
+    let basename = name.rsplit('/').next().unwrap_or(name);
+    format!("{registry}/{name}/-/{basename}-{version}.tgz")
+}
+
+/// Split `name@spec`, keeping the leading `@` of scoped names
+fn split_descriptor(descriptor: &str) -> Option<(&str, &str)> {
+    let at_pos = descriptor[1..].find('@')? + 1;
+    Some((&descriptor[..at_pos], &descriptor[at_pos + 1..]))
+}
+
+fn string_map(value: Option<&Value>) -> HashMap<String, String> {
+    value
+        .and_then(Value::as_object)
+        .map(|object| {
+            object
+                .iter()
+                .filter_map(|(name, spec)| Some((name.clone(), spec.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// package-lock.json v2/v3: `packages` keyed by `node_modules/...` paths
+fn parse_npm(content: &str) -> Result<ImportedLock> {
+    let lock: Value = serde_json::from_str(content)?;
+    let packages = lock
+        .get("packages")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("only lockfileVersion 2 and 3 are supported"))?;
+
+    let mut imported = ImportedLock::default();
+    for (path, entry) in packages {
+        let Some(name) = path
+            .rsplit("node_modules/")
+            .next()
+            .filter(|_| !path.is_empty())
+        else {
+            continue;
+        };
+        if entry.get("link").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+        let Some(version) = entry.get("version").and_then(Value::as_str) else {
+            continue;
+        };
+
+        let mut dependencies = string_map(entry.get("dependencies"));
+        dependencies.extend(string_map(entry.get("optionalDependencies")));
+
+        // Top-level node_modules entries are what the project resolves directly
+        if path.matches("node_modules/").count() == 1 {
+            imported
+                .root_versions
+                .insert(name.to_string(), version.to_string());
+        }
+
+        imported.packages.push(ImportedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            resolved: entry
+                .get("resolved")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            integrity: entry
+                .get("integrity")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            dependencies,
+        });
+    }
+
+    Ok(imported)
+}
+
+/// yarn v1's own indentation-based format
+fn parse_yarn_classic(content: &str, package_json: &PackageJson) -> Result<ImportedLock> {
+    let mut imported = ImportedLock::default();
+    let mut current: Option<(Vec<String>, ImportedPackage)> = None;
+    let mut in_dependencies = false;
+
+    let unquote = |value: &str| value.trim().trim_matches('"').to_string();
+
+    let finish = |entry: Option<(Vec<String>, ImportedPackage)>, imported: &mut ImportedLock| {
+        if let Some((keys, package)) = entry {
+            for key in keys {
+                imported.descriptors.insert(key, package.version.clone());
+            }
+            imported.packages.push(package);
+        }
+    };
+
+    for line in content.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+
+        if indent == 0 {
+            finish(current.take(), &mut imported);
+            let keys: Vec<String> = trimmed
+                .trim_end_matches(':')
+                .split(", ")
+                .map(unquote)
+                .collect();
+            let name = keys
+                .first()
+                .and_then(|key| split_descriptor(key))
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default();
+            current = Some((
+                keys,
+                ImportedPackage {
+                    name,
+                    ..Default::default()
+                },
+            ));
+            in_dependencies = false;
+            continue;
+        }
+
+        let Some((_, ref mut package)) = current else {
+            continue;
+        };
+
+        if indent == 2 {
+            in_dependencies = trimmed == "dependencies:" || trimmed == "optionalDependencies:";
+            let Some((key, value)) = trimmed.split_once(' ') else {
+                continue;
+            };
+            match key {
+                "version" => package.version = unquote(value),
+                "resolved" => package.resolved = Some(unquote(value)),
+                "integrity" => package.integrity = Some(unquote(value)),
+                _ => {}
+            }
+        } else if in_dependencies && let Some((name, range)) = trimmed.split_once(' ') {
+            package.dependencies.insert(unquote(name), unquote(range));
+        }
+    }
+    finish(current.take(), &mut imported);
+
+    imported.resolve_root(package_json);
+
+    // yarn v1 appends the sha1 to the tarball URL; older entries have no integrity field
+    for package in &mut imported.packages {
+        if let Some(ref resolved) = package.resolved
+            && let Some((url, sha1)) = resolved.split_once('#')
+        {
+            package.integrity.get_or_insert_with(|| sha1.to_string());
+            package.resolved = Some(url.to_string());
+        }
+    }
+
+    Ok(imported)
+}
+
+/// yarn 2+ (berry): YAML keyed by comma-separated `name@npm:range` descriptors
+fn parse_yarn_berry(content: &str, package_json: &PackageJson) -> Result<ImportedLock> {
+    let lock: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(content)?;
+    let mut imported = ImportedLock::default();
+
+    for (key, entry) in &lock {
+        if key == "__metadata" {
+            continue;
+        }
+        let Some(resolution) = entry.get("resolution").and_then(|r| r.as_str()) else {
+            continue;
+        };
+        // Workspaces and other non-registry protocols are not installable from a registry
+        let Some((name, reference)) = split_descriptor(resolution) else {
+            continue;
+        };
+        let Some(version) = reference.strip_prefix("npm:") else {
+            continue;
+        };
+
+        for descriptor in key.split(", ") {
+            if let Some((_, range)) = split_descriptor(descriptor.trim()) {
+                let range = range.strip_prefix("npm:").unwrap_or(range);
+                imported
+                    .descriptors
+                    .insert(format!("{name}@{range}"), version.to_string());
+            }
+        }
+
+        let dependencies = entry
+            .get("dependencies")
+            .and_then(|deps| deps.as_mapping())
+            .map(|deps| {
+                deps.iter()
+                    .filter_map(|(name, range)| {
+                        let range = range.as_str()?;
+                        Some((
+                            name.as_str()?.to_string(),
+                            range.strip_prefix("npm:").unwrap_or(range).to_string(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        imported.packages.push(ImportedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            resolved: None,
+            // Berry checksums are of its own zip cache, not the registry tarball
+            integrity: None,
+            dependencies,
+        });
+    }
+
+    imported.resolve_root(package_json);
+
+    Ok(imported)
+}
+
+/// pnpm-lock.yaml v5 (`/name/1.0.0`), v6 (`/name@1.0.0`) and v9 (`name@1.0.0` with
+/// dependency edges under `snapshots`)
+fn parse_pnpm(content: &str) -> Result<ImportedLock> {
+    let lock: serde_yaml::Value = serde_yaml::from_str(content)?;
+    let mut imported = ImportedLock::default();
+
+    // Peer-dependency suffixes: `1.0.0(react@18.0.0)` in v6+, `1.0.0_react@18.0.0` in v5
+    let clean_version = |version: &str| {
+        version
+            .split(['(', '_'])
+            .next()
+            .unwrap_or(version)
+            .to_string()
+    };
+
+    let legacy = lock
+        .get("lockfileVersion")
+        .and_then(|version| version.as_f64().or_else(|| version.as_str()?.parse().ok()))
+        .is_some_and(|version| version < 6.0);
+
+    let parse_key = |key: &str| -> Option<(String, String)> {
+        let key = key.strip_prefix('/').unwrap_or(key);
+        if legacy {
+            // v5: name/version, where scoped names contain a slash themselves
+            let name_segments = if key.starts_with('@') { 2 } else { 1 };
+            let mut segments = key.splitn(name_segments + 1, '/');
+            let name: Vec<&str> = segments.by_ref().take(name_segments).collect();
+            return Some((name.join("/"), clean_version(segments.next()?)));
+        }
+        let key = key.split('(').next().unwrap_or(key);
+        let (name, version) = split_descriptor(key)?;
+        Some((name.to_string(), clean_version(version)))
+    };
+
+    let yaml_map = |value: Option<&serde_yaml::Value>| -> HashMap<String, String> {
+        value
+            .and_then(|deps| deps.as_mapping())
+            .map(|deps| {
+                deps.iter()
+                    .filter_map(|(name, version)| {
+                        // v6+ importers nest `{ specifier, version }`
+                        let version = version
+                            .get("version")
+                            .unwrap_or(version)
+                            .as_str()
+                            .map(|version| version.to_string())
+                            .or_else(|| version.as_f64().map(|version| version.to_string()))?;
+                        Some((name.as_str()?.to_string(), clean_version(&version)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let snapshots = lock.get("snapshots");
+    if let Some(packages) = lock.get("packages").and_then(|p| p.as_mapping()) {
+        for (key, entry) in packages {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
+            let Some((name, version)) = parse_key(key) else {
+                continue;
+            };
+
+            let edges = snapshots
+                .and_then(|snapshots| snapshots.get(key))
+                .unwrap_or(entry);
+            let mut dependencies = yaml_map(edges.get("dependencies"));
+            dependencies.extend(yaml_map(edges.get("optionalDependencies")));
+
+            let resolution = entry.get("resolution");
+            imported.packages.push(ImportedPackage {
+                name,
+                version,
+                resolved: resolution
+                    .and_then(|r| r.get("tarball"))
+                    .and_then(|t| t.as_str())
+                    .map(str::to_string),
+                integrity: resolution
+                    .and_then(|r| r.get("integrity"))
+                    .and_then(|i| i.as_str())
+                    .map(str::to_string),
+                dependencies,
+            });
+        }
+    }
+
+    // v9 keeps the project's edges under importers["."]; older versions at the top level
+    let root = lock
+        .get("importers")
+        .and_then(|importers| importers.get("."))
+        .unwrap_or(&lock);
+    for section in ["dependencies", "devDependencies", "optionalDependencies"] {
+        imported.root_versions.extend(yaml_map(root.get(section)));
+    }
+
+    Ok(imported)
+}
+
+fn project_dependencies(package_json: &PackageJson) -> Vec<(String, String)> {
+    [
+        &package_json.dependencies,
+        &package_json.dev_dependencies,
+        &package_json.peer_dependencies,
+        &package_json.optional_dependencies,
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|deps| {
+        deps.iter()
+            .map(|(name, range)| (name.clone(), range.clone()))
+    })
+    .collect()
+}
+
+/// Lock every imported version, pointing edges at the versions the source tool resolved
+/// where it records them and at the best locked match otherwise
+fn build_lock_file(
+    imported: ImportedLock,
+    package_json: &PackageJson,
+    npm_client: &NpmClient,
+) -> LockFile {
+    let mut lock_file = LockFile::new();
+
+    for package in imported.packages {
+        if package.name.is_empty() || package.version.is_empty() {
+            continue;
+        }
+
+        let dependencies = package
+            .dependencies
+            .iter()
+            .map(|(name, range)| {
+                let mut edge = LockedDependency::new(range);
+                edge.version = imported
+                    .descriptors
+                    .get(&format!("{name}@{range}"))
+                    .cloned();
+                (name.clone(), edge)
+            })
+            .collect();
+
+        let key = LockFile::key(&package.name, &package.version);
+        lock_file.packages.entry(key).or_insert(LockedPackage {
+            resolved: package.resolved.unwrap_or_else(|| {
+                tarball_url(
+                    &npm_client.registry_for(&package.name),
+                    &package.name,
+                    &package.version,
+                )
+            }),
+            integrity: package
+                .integrity
+                .as_deref()
+                .map(sri_from_shasum)
+                .unwrap_or_default(),
+            dependencies,
+            registry: None,
+            name: package.name,
+            version: package.version,
+        });
+    }
+
+    for (name, range) in project_dependencies(package_json) {
+        let mut edge = LockedDependency::new(&range);
+        edge.version = imported.root_versions.get(&name).cloned();
+        lock_file.root.insert(name, edge);
+    }
+
+    lock_file.link();
+    lock_file
+}
@@ -0,0 +1,306 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::npm_client::NpmClient;
+use crate::package_info::{
+    DistInfo, LockFile, LockedDependency, LockedPackage, NpmRegistryResponse, PackageJson,
+    sri_from_shasum,
+};
+use crate::version_range::VersionRange;
+
+/// The versions of a file git wrote into one with conflict markers
+pub struct ConflictSides {
+    pub ours: String,
+    pub theirs: String,
+    /// Only present for `merge.conflictStyle = diff3` / `zdiff3`
+    pub base: Option<String>,
+}
+
+/// What a merge had to decide rather than take from one side
+#[derive(Default)]
+pub struct MergeReport {
+    /// Root edges both sides changed, re-resolved against package.json (or, as a merge
+    /// driver, settled in favour of the newer version)
+    pub reresolved: Vec<String>,
+    /// Packages neither side had locked at the version now needed, fetched from the registry
+    pub fetched: Vec<String>,
+    /// Packages whose integrity differed between the sides, taken from the registry
+    pub integrity_fixed: Vec<String>,
+}
+
+/// Rebuild each side of a file containing `<<<<<<<` conflict hunks; None if there are none
+pub fn split_conflicts(content: &str) -> Option<ConflictSides> {
+    enum Hunk {
+        None,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut base = String::new();
+    let mut has_conflicts = false;
+    let mut has_base = false;
+    let mut hunk = Hunk::None;
+
+    for line in content.split_inclusive('\n') {
+        if line.starts_with("<<<<<<<") {
+            has_conflicts = true;
+            hunk = Hunk::Ours;
+            continue;
+        }
+        if line.starts_with("|||||||") && matches!(hunk, Hunk::Ours) {
+            has_base = true;
+            hunk = Hunk::Base;
+            continue;
+        }
+        if line.starts_with("=======") && matches!(hunk, Hunk::Ours | Hunk::Base) {
+            hunk = Hunk::Theirs;
+            continue;
+        }
+        if line.starts_with(">>>>>>>") && matches!(hunk, Hunk::Theirs) {
+            hunk = Hunk::None;
+            continue;
+        }
+
+        match hunk {
+            Hunk::None => {
+                ours.push_str(line);
+                theirs.push_str(line);
+                base.push_str(line);
+            }
+            Hunk::Ours => ours.push_str(line),
+            Hunk::Base => base.push_str(line),
+            Hunk::Theirs => theirs.push_str(line),
+        }
+    }
+
+    has_conflicts.then_some(ConflictSides {
+        ours,
+        theirs,
+        base: has_base.then_some(base),
+    })
+}
+
+/// Merge two lockfiles. Root edges are merged three-way when `base` is known; with
+/// `package_json` (a merge the user has finished) its ranges decide every root edge, and
+/// conflicting edges are re-resolved against them. Without it (a merge driver, which git
+/// may run before package.json is merged) a real conflict keeps the newer version.
+/// Anything left unlocked is then resolved from the registry.
+pub async fn merge(
+    base: Option<&LockFile>,
+    ours: LockFile,
+    theirs: LockFile,
+    package_json: Option<&PackageJson>,
+    npm_client: &NpmClient,
+) -> Result<(LockFile, MergeReport)> {
+    let mut report = MergeReport::default();
+    let mut merged = LockFile::new();
+
+    let names: BTreeSet<&String> = ours.root.keys().chain(theirs.root.keys()).collect();
+    for name in names {
+        let ours_edge = ours.root.get(name);
+        let theirs_edge = theirs.root.get(name);
+
+        // The side to take when only one of them changed the edge
+        let three_way = if ours_edge == theirs_edge {
+            Some(ours_edge)
+        } else {
+            base.and_then(|base| {
+                let base_edge = base.root.get(name);
+                if ours_edge == base_edge {
+                    Some(theirs_edge)
+                } else if theirs_edge == base_edge {
+                    Some(ours_edge)
+                } else {
+                    None
+                }
+            })
+        };
+
+        let edge = match package_json {
+            Some(package_json) => package_json.requested_range(name).map(|range| {
+                let candidates: Vec<&LockedDependency> = match three_way {
+                    Some(side) => side.into_iter().collect(),
+                    None => [ours_edge, theirs_edge].into_iter().flatten().collect(),
+                };
+                let matching: Vec<&LockedDependency> = candidates
+                    .into_iter()
+                    .filter(|edge| edge.requested_range == range)
+                    .collect();
+                match matching.as_slice() {
+                    [edge] => (*edge).clone(),
+                    [a, b] if a == b => (*a).clone(),
+                    _ => {
+                        report.reresolved.push(format!("{name}@{range}"));
+                        LockedDependency::new(range)
+                    }
+                }
+            }),
+            None => match three_way {
+                Some(side) => side.cloned(),
+                None => {
+                    let newer = newer_edge(ours_edge, theirs_edge);
+                    if let Some(edge) = newer {
+                        report
+                            .reresolved
+                            .push(format!("{name}@{}", edge.requested_range));
+                    }
+                    newer.cloned()
+                }
+            },
+        };
+
+        if let Some(edge) = edge {
+            merged.root.insert(name.clone(), edge);
+        }
+    }
+
+    for (key, package) in theirs.packages.into_iter().chain(ours.packages) {
+        if let Some(existing) = merged.packages.get(&key)
+            && existing.integrity != package.integrity
+        {
+            let info = npm_client
+                .get_version_info(&package.name, &package.version)
+                .await?;
+            let mut package = package;
+            package.integrity = integrity_of(&info.dist);
+            report.integrity_fixed.push(key.clone());
+            merged.packages.insert(key, package);
+            continue;
+        }
+        merged.packages.insert(key, package);
+    }
+
+    merged.workspaces = theirs.workspaces;
+    merged.workspaces.extend(ours.workspaces);
+
+    resolve_missing(&mut merged, npm_client, &mut report).await?;
+    Ok((merged, report))
+}
+
+/// Lock a version for every edge that has none (or one outside its range), fetching
+/// metadata as needed, then drop whatever is no longer reachable
+async fn resolve_missing(
+    lock_file: &mut LockFile,
+    npm_client: &NpmClient,
+    report: &mut MergeReport,
+) -> Result<()> {
+    let mut metadata: HashMap<String, NpmRegistryResponse> = HashMap::new();
+
+    loop {
+        lock_file.link();
+        prune_unreachable(lock_file);
+
+        let missing = unresolved_edges(lock_file);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        for (name, range) in missing {
+            if !metadata.contains_key(&name) {
+                metadata.insert(name.clone(), npm_client.get_package_info(&name).await?);
+            }
+            let info = metadata[&name]
+                .max_satisfying(&range)
+                .ok_or_else(|| anyhow!("No version of {name} matches {range}"))?;
+
+            // Already locked: the edge was cleared above, so the next `link` picks it
+            let key = LockFile::key(&name, &info.version);
+            if lock_file.packages.contains_key(&key) {
+                continue;
+            }
+
+            lock_file.packages.insert(
+                key.clone(),
+                LockedPackage {
+                    name: info.name.clone(),
+                    version: info.version.clone(),
+                    resolved: info.dist.tarball.clone(),
+                    integrity: integrity_of(&info.dist),
+                    dependencies: info
+                        .dependencies
+                        .iter()
+                        .flatten()
+                        .map(|(dep, range)| (dep.clone(), LockedDependency::new(range)))
+                        .collect(),
+                    registry: info.registry.clone(),
+                },
+            );
+            report.fetched.push(key);
+        }
+    }
+}
+
+/// `(name, range)` of edges with no locked version in range. Out-of-range versions are
+/// cleared so `link` picks the newly locked one.
+fn unresolved_edges(lock_file: &mut LockFile) -> BTreeSet<(String, String)> {
+    let locked: BTreeSet<String> = lock_file.packages.keys().cloned().collect();
+    let local: BTreeSet<String> = lock_file
+        .workspaces
+        .values()
+        .map(|workspace| workspace.name.clone())
+        .collect();
+    let mut missing = BTreeSet::new();
+
+    let mut check = |name: &String, edge: &mut LockedDependency| {
+        if local.contains(name) {
+            return;
+        }
+        let in_range = edge.version.as_ref().is_some_and(|version| {
+            locked.contains(&LockFile::key(name, version))
+                && VersionRange::parse(&edge.requested_range)
+                    .map(|range| range.matches_str(version))
+                    .unwrap_or(true)
+        });
+        if !in_range {
+            edge.version = None;
+            missing.insert((name.clone(), edge.requested_range.clone()));
+        }
+    };
+
+    for (name, edge) in &mut lock_file.root {
+        check(name, edge);
+    }
+    for workspace in lock_file.workspaces.values_mut() {
+        for (name, edge) in &mut workspace.dependencies {
+            check(name, edge);
+        }
+    }
+    for package in lock_file.packages.values_mut() {
+        for (name, edge) in &mut package.dependencies {
+            check(name, edge);
+        }
+    }
+
+    missing
+}
+
+/// Of two conflicting edges, the one locked to the higher version
+fn newer_edge<'a>(
+    ours: Option<&'a LockedDependency>,
+    theirs: Option<&'a LockedDependency>,
+) -> Option<&'a LockedDependency> {
+    let version = |edge: &LockedDependency| {
+        edge.version
+            .as_deref()
+            .and_then(|version| semver::Version::parse(version).ok())
+    };
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) if version(theirs) > version(ours) => Some(theirs),
+        (Some(ours), _) => Some(ours),
+        (None, theirs) => theirs,
+    }
+}
+
+fn prune_unreachable(lock_file: &mut LockFile) {
+    let reachable = lock_file.reachable_keys();
+    lock_file.packages.retain(|key, _| reachable.contains(key));
+}
+
+fn integrity_of(dist: &DistInfo) -> String {
+    dist.integrity
+        .clone()
+        .unwrap_or_else(|| sri_from_shasum(&dist.shasum))
+}
@@ -0,0 +1,123 @@
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::Level;
+use tracing_chrome::FlushGuard;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+use crate::profiling;
+
+/// Debug logs kept in ~/.clay/logs; older ones are removed as new runs start
+const KEPT_LOGS: usize = 20;
+
+/// Where debug logs and timing reports go
+pub fn logs_dir() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow!("Could not find home directory"))?
+        .join(".clay")
+        .join("logs"))
+}
+
+/// `<prefix><timestamp>.<extension>` in the logs directory, which is created if needed
+pub fn log_path(prefix: &str, extension: &str) -> Result<PathBuf> {
+    let dir = logs_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!(
+        "{prefix}{}.{extension}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")
+    )))
+}
+
+/// Keeps the log outputs of a run alive; the Chrome trace is written when it's dropped
+pub struct Logging {
+    /// The debug log of this run, if one could be created
+    pub path: Option<PathBuf>,
+    _profile: Option<FlushGuard>,
+}
+
+/// Route clay's log events to a debug log under ~/.clay/logs, always written at debug
+/// level, and to stderr from `verbosity` 1 (info) up to 3 (trace). `profile` also
+/// records spans as a Chrome trace.
+pub fn init(verbosity: u8, profile: Option<&Path>) -> Result<Logging> {
+    let log_file = log_path("", "log").and_then(|path| {
+        let file = std::fs::File::create(&path)?;
+        prune_logs(&path);
+        Ok((path, file))
+    });
+    let (path, file) = match log_file {
+        Ok((path, file)) => (Some(path), Some(file)),
+        Err(_) => (None, None),
+    };
+
+    let file_layer = file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .with_filter(
+                Targets::new()
+                    .with_target("clay", Level::DEBUG)
+                    .with_default(Level::WARN),
+            )
+    });
+
+    let stderr_level = match verbosity {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let stderr_layer = (verbosity > 0).then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_ansi(false)
+            .without_time()
+            .with_target(false)
+            .with_filter(Targets::new().with_target("clay", stderr_level))
+    });
+
+    let (profile_layer, profile_guard) = match profile {
+        Some(path) => {
+            let (layer, guard) = profiling::layer(path)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(stderr_layer)
+        .with(profile_layer)
+        .try_init()?;
+
+    tracing::debug!(
+        args = ?std::env::args().collect::<Vec<_>>(),
+        version = env!("CARGO_PKG_VERSION"),
+        "clay started"
+    );
+    Ok(Logging {
+        path,
+        _profile: profile_guard,
+    })
+}
+
+/// Remove all but the newest KEPT_LOGS debug logs. Names sort by time, so the oldest
+/// come first.
+fn prune_logs(current: &Path) {
+    let Some(dir) = current.parent() else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut logs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    logs.sort();
+    let excess = logs.len().saturating_sub(KEPT_LOGS);
+    for old in &logs[..excess] {
+        std::fs::remove_file(old).ok();
+    }
+}
@@ -0,0 +1,43 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Windows rejects paths of MAX_PATH (260) characters or more unless they carry the
+/// `\\?\` prefix; directories already fail a little earlier, at 248
+#[cfg(windows)]
+const SHORT_PATH_LIMIT: usize = 248;
+
+/// `path` in a form every filesystem call accepts however deep it is. On Windows a path
+/// near MAX_PATH is made absolute and given the extended-length `\\?\` (or `\\?\UNC\`)
+/// prefix, which also turns off `/` and `..` handling, so it's only applied after
+/// `std::path::absolute` has normalized those away. Elsewhere `path` comes back as is.
+pub fn extend(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::path::PathBuf;
+
+        let Ok(absolute) = std::path::absolute(path) else {
+            return Cow::Borrowed(path);
+        };
+        if absolute.as_os_str().len() < SHORT_PATH_LIMIT {
+            return Cow::Owned(absolute);
+        }
+
+        let text = absolute.to_string_lossy().into_owned();
+        if text.starts_with(r"\\?\") || text.starts_with(r"\\.\") {
+            return Cow::Owned(absolute);
+        }
+        let extended = match text.strip_prefix(r"\\") {
+            Some(unc) => OsString::from(format!(r"\\?\UNC\{unc}")),
+            None => {
+                let mut extended = OsString::from(r"\\?\");
+                extended.push(absolute.as_os_str());
+                extended
+            }
+        };
+        Cow::Owned(PathBuf::from(extended))
+    }
+
+    #[cfg(not(windows))]
+    Cow::Borrowed(path)
+}
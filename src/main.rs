@@ -1,21 +1,69 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::process::Command;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::ffi::OsString;
 
+mod audit;
 mod bundler;
+mod catalog;
 mod cli_style;
+mod config;
 mod content_store;
+mod create;
 mod dev_server;
+mod doctor;
+mod errors;
+mod exec;
+mod global_link;
+mod history;
+mod init;
+mod lifecycle;
+mod links;
+mod lock;
+mod lock_cache;
+mod lock_export;
+mod lock_import;
+mod lock_merge;
+mod logging;
+mod long_path;
+mod manager_pin;
+mod node_runtime;
 mod npm_client;
+mod npmrc;
 mod package_info;
 mod package_manager;
+mod patches;
+mod phantom;
+mod plugins;
+mod profiling;
+mod progress;
+mod registry;
+mod search;
+mod self_update;
+mod shutdown;
+mod typings;
+mod upgrade_interactive;
+mod version_bump;
+mod version_range;
+mod virtual_store;
 mod workspace;
+mod workspace_filter;
+mod workspace_graph;
 
+use audit::{Auditor, Severity};
 use bundler::Bundler;
-use cli_style::CliStyle;
+use cli_style::{CliStyle, ColorChoice};
+use config::{ClayConfig, ConfigLayers};
 use content_store::ContentStore;
 use dev_server::DevServer;
-use package_manager::PackageManager;
+use doctor::Doctor;
+use errors::ErrorCode;
+use lock::LockManager;
+use lock_export::ExportFormat;
+use npm_client::NpmClient;
+use package_info::{LockFormat, LockMode};
+use package_manager::{PackageManager, SaveTarget};
+use progress::ProgressFormat;
+use registry::RegistryManager;
 use workspace::WorkspaceManager;
 
 #[derive(Parser)]
@@ -25,42 +73,208 @@ use workspace::WorkspaceManager;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(long, global = true, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    #[arg(long, global = true, value_name = "SECS")]
+    connect_timeout: Option<u64>,
+
+    #[arg(long, global = true, value_name = "N")]
+    concurrency: Option<usize>,
+
+    /// Times a failed package download is retried
+    #[arg(long, global = true, value_name = "N")]
+    fetch_retries: Option<u32>,
+
+    /// How installs persist resolutions
+    #[arg(long, global = true, value_enum, value_name = "MODE")]
+    lock_mode: Option<LockMode>,
+
+    /// Which lockfile explicit installs write
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    lock_format: Option<LockFormat>,
+
+    /// Print machine-readable JSON instead of text, for commands that support it
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Show clay's log on stderr: -v for info, -vv for debug, -vvv for trace
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only print results and errors, no progress or warnings
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// When to color output
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t)]
+    color: ColorChoice,
+
+    /// How to show progress: `json` streams one event per line on stderr instead of bars
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t
+    )]
+    progress: ProgressFormat,
+
+    /// Write a Chrome trace of this run to FILE (open it in ui.perfetto.dev)
+    #[arg(long, global = true, value_name = "FILE")]
+    profile: Option<std::path::PathBuf>,
+}
+
+impl Cli {
+    /// Apply command-line overrides on top of the loaded clay.toml
+    fn apply_overrides(&self, config: &mut ClayConfig) {
+        if let Some(timeout) = self.timeout {
+            config.network.timeout = timeout;
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            config.network.connect_timeout = connect_timeout;
+        }
+        if let Some(concurrency) = self.concurrency {
+            config.network.concurrency = concurrency;
+        }
+        if let Some(fetch_retries) = self.fetch_retries {
+            config.network.fetch_retries = fetch_retries;
+        }
+        if let Some(lock_mode) = self.lock_mode {
+            config.install.lock_mode = Some(lock_mode);
+        }
+        if let Some(lock_format) = self.lock_format {
+            config.install.lock_format = Some(lock_format);
+        }
+    }
 }
 
 #[derive(Subcommand)]
+#[command(allow_external_subcommands = true)]
 enum Commands {
     #[command(alias = "i", alias = "add")]
     Install {
         packages: Vec<String>,
 
-        #[arg(long)]
+        #[arg(long, short = 'D')]
         dev: bool,
 
+        /// Save to peerDependencies
+        #[arg(long, conflicts_with_all = ["dev", "save_optional", "no_save"])]
+        save_peer: bool,
+
+        /// Save to optionalDependencies
+        #[arg(long, short = 'O', conflicts_with_all = ["dev", "no_save"])]
+        save_optional: bool,
+
+        /// Install without changing package.json
+        #[arg(long, conflicts_with = "dev")]
+        no_save: bool,
+
+        /// Save the exact resolved version to package.json
+        #[arg(long, short = 'E')]
+        save_exact: bool,
+
+        /// Range operator to save new versions with
+        #[arg(long, value_name = "PREFIX", value_parser = ["^", "~", ""], conflicts_with = "save_exact")]
+        save_prefix: Option<String>,
+
+        /// Don't run the install scripts of dependencies
+        #[arg(long)]
+        ignore_scripts: bool,
+
+        /// Also add the matching @types package as a devDependency for packages without
+        /// bundled types
+        #[arg(long)]
+        with_types: bool,
+
+        /// Report how long each phase and package took, and save it under ~/.clay/logs
         #[arg(long)]
-        json: bool,
+        timing: bool,
 
         #[arg(long)]
         fix_peers: bool,
 
         #[arg(long)]
         skip_peers: bool,
+
+        /// Add the packages to this workspace of the monorepo, from anywhere in it
+        #[arg(long, short = 'w', value_name = "NAME", conflicts_with = "no_save")]
+        workspace: Option<String>,
     },
 
     Uninstall {
         packages: Vec<String>,
     },
 
-    List,
+    /// Show the dependency tree from the lockfile (or node_modules)
+    #[command(alias = "ls")]
+    List {
+        /// How many levels below the project's own dependencies to show
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+
+        /// Only dependencies, not devDependencies
+        #[arg(long, conflicts_with = "dev")]
+        prod: bool,
 
+        /// Only devDependencies
+        #[arg(long)]
+        dev: bool,
+
+        /// The old flat listing of node_modules
+        #[arg(long, conflicts_with_all = ["depth", "prod", "dev"])]
+        flat: bool,
+    },
+
+    /// Replace this clay with the latest release's binary, checked against its checksum
     Upgrade {
         #[arg(long, short)]
         yes: bool,
     },
 
+    /// Pick outdated dependencies to upgrade, to the newest version their range allows or
+    /// the latest, and install them
+    UpgradeInteractive,
+
     Run {
         script: Option<String>,
     },
 
+    /// Run a command from node_modules/.bin
+    Exec {
+        bin: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Fetch a package into a temporary prefix and run its command without installing it
+    Dlx {
+        /// Package to run, optionally with a version (`name@range`)
+        package: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Install and pin Node versions for `clay run` and `clay exec`
+    #[command(subcommand)]
+    Node(NodeCommands),
+
+    /// Copy an installed package somewhere to edit, then save the edits with patch-commit
+    Patch {
+        package: String,
+
+        /// Directory to copy the package into, instead of a temporary one
+        #[arg(long, value_name = "DIR")]
+        edit_dir: Option<std::path::PathBuf>,
+    },
+
+    /// Save the edits made in a `clay patch` directory as a patch applied on every install
+    PatchCommit {
+        dir: std::path::PathBuf,
+    },
+
     #[command(subcommand)]
     Cache(CacheCommands),
 
@@ -82,8 +296,9 @@ enum Commands {
     },
 
     Dev {
-        #[arg(short, long, default_value = "3000")]
-        port: u16,
+        /// Port to listen on; defaults to `[dev] port`, 3000 unless set
+        #[arg(short, long)]
+        port: Option<u16>,
 
         #[arg(long)]
         host: Option<String>,
@@ -98,17 +313,291 @@ enum Commands {
 
         #[arg(long)]
         all: bool,
+
+        /// Find imported packages package.json doesn't declare
+        #[arg(long)]
+        missing: bool,
+
+        /// With --missing, add them to package.json
+        #[arg(long, requires = "missing")]
+        fix: bool,
+    },
+
+    /// Show how a package's version was chosen
+    Explain {
+        package: String,
+    },
+
+    /// List the installs, uninstalls and updates recorded for this project
+    History,
+
+    /// Restore package.json and the lockfile to how they were before history entry <id>
+    Revert {
+        id: u64,
     },
 
     Info {
         package: Option<String>,
+
+        /// Only look in the local content store, never the registry
+        #[arg(long)]
+        local_only: bool,
     },
 
-    Link {
+    /// Check installed packages against the registry's security advisories
+    Audit {
+        /// Exit nonzero for advisories at or above this severity
+        #[arg(long, value_enum, value_name = "SEVERITY")]
+        audit_level: Option<Severity>,
+    },
+
+    /// Find packages on the registry
+    Search {
+        #[arg(required_unless_present = "interactive")]
+        query: Vec<String>,
+
+        /// Search as you type and install the package picked with enter
+        #[arg(short, long)]
+        interactive: bool,
+    },
+
+    /// Show what installing a package would download and take up, without installing it
+    Size {
+        /// `name` or `name@version`
         package: String,
-        version: String,
+    },
+
+    /// Without arguments, register this package for linking into other projects. With a
+    /// name (or path), symlink that package into this project's node_modules.
+    Link {
+        package: Option<String>,
+
+        /// With --target, the stored version to extract
+        #[arg(requires = "target")]
+        version: Option<String>,
+
+        /// Extract a package from the content store into this directory instead
+        #[arg(short, long, requires_all = ["package", "version"])]
+        target: Option<String>,
+    },
+
+    /// Without arguments, take back this package's registration. With a name, remove its
+    /// link from this project and reinstall the registry version package.json asks for.
+    Unlink {
+        package: Option<String>,
+    },
+
+    #[command(subcommand)]
+    DistTag(DistTagCommands),
+
+    #[command(subcommand)]
+    Lock(LockCommands),
+
+    /// Create clay-lock from another package manager's lockfile or from node_modules;
+    /// the same as `clay lock import`
+    Import(ImportArgs),
+
+    /// Print the node_modules/.bin directory commands are run from
+    Bin {
+        /// The global bin directory instead
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Print the node_modules directory packages are installed to
+    Root {
+        /// The global node_modules directory instead
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Bump the version in package.json, commit and tag it
+    Version {
+        /// major, minor, patch or an exact version
+        bump: version_bump::Bump,
+
+        /// Commit message; %s is replaced with the new version
+        #[arg(short, long, default_value = "%s")]
+        message: String,
+
+        /// Only change package.json; no commit or tag
+        #[arg(long)]
+        no_git_tag_version: bool,
+
+        /// Don't run the preversion, version and postversion scripts
+        #[arg(long)]
+        ignore_scripts: bool,
+    },
+
+    /// Show and change settings in clay.toml or ~/.clay/config.toml
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Diagnose registry connectivity, credentials and proxy settings
+    Doctor,
+
+    /// Start a project from a create-* package or a git repository, then install it
+    Create {
+        /// `vite` (runs create-vite), `@scope/app`, `owner/repo` or a git URL
+        template: String,
+
+        /// Directory for the project
+        dir: Option<std::path::PathBuf>,
+
+        /// Passed on to the template's generator, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+
+        /// Skip the initial install
+        #[arg(long)]
+        no_install: bool,
+    },
+
+    /// Create a package.json for a new project
+    Init {
+        /// Accept every default instead of prompting
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Log in to a registry and save the token to ~/.npmrc
+    Login {
+        #[arg(long, value_name = "URL")]
+        registry: Option<String>,
+
+        /// Also route this scope's packages to the registry
+        #[arg(long)]
+        scope: Option<String>,
+    },
+
+    /// Mark published versions as deprecated on the registry
+    Deprecate {
+        /// <package>[@<range>]
+        spec: String,
+
+        #[arg(required_unless_present = "undo")]
+        message: Option<String>,
+
+        /// Clear the deprecation message instead
+        #[arg(long, conflicts_with = "message")]
+        undo: bool,
+    },
+
+    /// `clay <name> [args]` for any other name runs clay-<name> from PATH
+    #[command(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+impl Commands {
+    /// Whether the command installs into or locks the project, and so has to respect the
+    /// package manager package.json pins
+    fn installs(&self) -> bool {
+        matches!(
+            self,
+            Commands::Install { .. }
+                | Commands::Uninstall { .. }
+                | Commands::UpgradeInteractive
+                | Commands::Workspace(_)
+                | Commands::Lock(_)
+                | Commands::Import(_)
+                | Commands::Peer(_)
+                | Commands::Check { fix: true, .. }
+                | Commands::Unlink { package: Some(_) }
+                | Commands::Revert { .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum DistTagCommands {
+    /// Point a tag at a published version
+    Add {
+        /// <package>@<version>
+        spec: String,
+        #[arg(default_value = "latest")]
+        tag: String,
+    },
+
+    /// Remove a tag
+    Rm { package: String, tag: String },
+
+    /// List tags, defaulting to the current package
+    Ls { package: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum LockCommands {
+    /// Convert package-lock.json, yarn.lock or pnpm-lock.yaml into clay-lock
+    Import(ImportArgs),
+
+    /// Write the lockfile in another package manager's format
+    Export {
+        #[arg(long, value_enum, default_value = "npm")]
+        format: ExportFormat,
+
+        /// Defaults to the format's usual file name
         #[arg(short, long)]
-        target: String,
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Rewrite an older lockfile in the current schema
+    Migrate,
+
+    /// Check the lockfile covers package.json, has integrity for every package and no
+    /// orphans, and resolves only from configured registries
+    Verify,
+
+    /// Remove lock entries nothing in package.json still depends on
+    Prune {
+        /// Report what would be removed without rewriting the lockfile
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Resolve a conflicted lockfile against package.json. As a git merge driver:
+    /// `clay lock merge %O %A %B`
+    Merge {
+        /// Base, ours and theirs versions, as git passes them to a merge driver
+        #[arg(value_name = "FILE", num_args = 3)]
+        files: Vec<std::path::PathBuf>,
+    },
+
+    /// Show packages added, removed or changed since a git revision
+    Diff {
+        /// Revision to compare against
+        #[arg(value_name = "GIT_REF", default_value = "HEAD")]
+        git_ref: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Every setting with its value and where that comes from
+    List,
+
+    /// Print a setting's value
+    Get { key: String },
+
+    /// Save a setting to clay.toml
+    Set {
+        key: String,
+
+        /// TOML (`30`, `true`, `["a", "b"]`) or a plain string
+        value: String,
+
+        /// Save to ~/.clay/config.toml instead, for every project
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Remove a setting from clay.toml
+    #[command(alias = "rm")]
+    Delete {
+        key: String,
+
+        /// Remove it from ~/.clay/config.toml instead
+        #[arg(short, long)]
+        global: bool,
     },
 }
 
@@ -125,11 +614,87 @@ enum CacheCommands {
 enum StoreCommands {
     Stats,
 
-    Dedupe,
+    /// Find node_modules files identical to store content in recorded projects
+    Dedupe {
+        /// Replace them with hardlinks to the store
+        #[arg(long)]
+        apply: bool,
+    },
+
+    Cleanup(GcArgs),
+
+    Gc(GcArgs),
+
+    /// Evict packages by last use
+    Prune {
+        /// Remove packages no install has used within this age (e.g. 90d, 12h, 2w)
+        #[arg(long, value_name = "AGE", value_parser = ContentStore::parse_age)]
+        older_than: Option<chrono::Duration>,
+
+        /// Evict least recently used packages until the store fits (e.g. 10GB);
+        /// defaults to [store] max-size
+        #[arg(long, value_name = "SIZE", value_parser = ContentStore::parse_size)]
+        max_size: Option<u64>,
+
+        /// Keep packages and files stored or used within this age (e.g. 7d; 0s for none)
+        #[arg(long, value_name = "AGE", default_value = "7d", value_parser = ContentStore::parse_age)]
+        grace_period: chrono::Duration,
+    },
+
+    /// Bundle the packages this project needs into an archive for offline machines
+    Export {
+        archive: std::path::PathBuf,
+
+        /// Export every package in the store
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Load packages from a `clay store export` archive
+    Import {
+        archive: std::path::PathBuf,
+    },
+    /// Upgrade the store to the current layout, or copy it to a new location
+    Migrate {
+        /// Write the migrated store here instead of upgrading it in place
+        #[arg(long, value_name = "DIR")]
+        to: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct ImportArgs {
+    /// Lockfile to import; detected in the current directory when omitted
+    path: Option<std::path::PathBuf>,
+
+    /// Build the lockfile from the packages installed in node_modules instead
+    #[arg(long, conflicts_with = "path")]
+    from_node_modules: bool,
+
+    /// Overwrite an existing clay lockfile
+    #[arg(long)]
+    force: bool,
+}
+
+impl ImportArgs {
+    async fn run(self, lock_manager: &LockManager) -> Result<()> {
+        if self.from_node_modules {
+            lock_manager.import_node_modules(self.force).await
+        } else {
+            lock_manager.import(self.path.as_deref(), self.force)
+        }
+    }
+}
 
-    Cleanup,
+#[derive(clap::Args)]
+struct GcArgs {
+    /// List what would be removed without deleting anything
+    #[arg(long)]
+    dry_run: bool,
 
-    Gc,
+    /// Keep packages and files stored or used within this age (e.g. 7d; 0s for none)
+    #[arg(long, value_name = "AGE", default_value = "7d", value_parser = ContentStore::parse_age)]
+    grace_period: chrono::Duration,
 }
 
 #[derive(Subcommand)]
@@ -141,10 +706,38 @@ enum PeerCommands {
     List,
 }
 
+#[derive(Subcommand)]
+enum NodeCommands {
+    /// Download an official Node build into ~/.clay/node
+    Install {
+        /// A version or range (`20`, `20.11.0`), `lts`, `lts/<codename>` or `latest`
+        version: String,
+    },
+
+    /// Pin this project to a Node version in .node-version, installing it if needed;
+    /// without one, install the version the project already pins
+    Use { version: Option<String> },
+
+    /// List installed Node versions
+    #[command(alias = "ls")]
+    List,
+}
+
 #[derive(Subcommand)]
 enum WorkspaceCommands {
     List,
 
+    /// Which workspaces depend on which, as a tree (or DOT, or JSON with --json), with
+    /// dependency cycles marked
+    Graph {
+        /// Graphviz source instead of a tree
+        #[arg(long)]
+        dot: bool,
+        /// Highlight the workspaces depending on this one, directly or not
+        #[arg(long, value_name = "NAME")]
+        dependents_of: Option<String>,
+    },
+
     Add {
         name: String,
         #[arg(long)]
@@ -159,60 +752,240 @@ enum WorkspaceCommands {
         script: String,
         #[arg(long)]
         workspace: Option<String>,
+        /// Only in matching workspaces: a name glob, ./path, `pkg...` with its dependents,
+        /// `...pkg` with its dependencies, or `!` to leave out; repeatable
+        #[arg(long = "filter", short = 'F')]
+        filters: Vec<String>,
+        /// Only in workspaces with files changed since this git ref, and their dependents
+        #[arg(long, value_name = "REF")]
+        since: Option<String>,
+        /// Run scripts side by side, each once those of the workspaces it depends on finish
         #[arg(long)]
         parallel: bool,
+        /// Most scripts to run at once with --parallel; defaults to the number of CPUs.
+        /// (The global --concurrency bounds registry requests.)
+        #[arg(long, value_name = "N", requires = "parallel")]
+        workspace_concurrency: Option<usize>,
+        /// Print --parallel output line by line as it comes, or each workspace's together
+        /// once its script finishes
+        #[arg(long, value_enum, default_value_t, requires = "parallel")]
+        output_style: workspace::OutputStyle,
     },
 
     Install {
         #[arg(long)]
         all: bool,
+        /// Only the matching workspaces, selected as for `workspace run --filter`
+        #[arg(long = "filter", short = 'F')]
+        filters: Vec<String>,
+    },
+
+    /// Bump the version of workspaces and the ranges other workspaces depend on them with,
+    /// in one release commit
+    Version {
+        /// major, minor, patch or an exact version
+        bump: version_bump::Bump,
+
+        /// Only the matching workspaces, selected as for `workspace run --filter`
+        #[arg(long = "filter", short = 'F')]
+        filters: Vec<String>,
+
+        /// Commit message; %s is replaced with the released name@version list
+        #[arg(short, long, default_value = "%s")]
+        message: String,
+
+        /// Only change package.json files; no commit or tags
+        #[arg(long)]
+        no_git_tag_version: bool,
+
+        /// Don't run the preversion, version and postversion scripts
+        #[arg(long)]
+        ignore_scripts: bool,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    CliStyle::set_color(cli.color);
+    progress::set_format(cli.progress);
+
+    let logging = logging::init(cli.verbose, cli.profile.as_deref())?;
+    let mut config = ClayConfig::load()?;
+    cli.apply_overrides(&mut config);
+
+    // Keep the command future alive (pinned, not dropped) while cleanup runs, so the
+    // paths it registered are still known when a signal arrives
+    let command = run(cli, config);
+    tokio::pin!(command);
+
+    let result = loop {
+        let signal = tokio::select! {
+            result = &mut command => break result,
+            signal = shutdown::wait_for_signal() => signal,
+        };
+        // A script or binary being run gets the signal instead, and its exit ends the run
+        if shutdown::forward(signal) {
+            continue;
+        }
+        eprintln!(
+            "\n{}",
+            CliStyle::warning(&format!("Received {}, cleaning up...", signal.name()))
+        );
+        // Watch loops finish the rebuild under way; a second signal doesn't wait for them
+        if shutdown::stop_watchers() {
+            let stopped = async {
+                let _ = (&mut command).await;
+                shutdown::watchers_stopped().await;
+            };
+            tokio::select! {
+                _ = tokio::time::timeout(shutdown::WATCHER_GRACE, stopped) => {}
+                _ = shutdown::wait_for_signal() => {}
+            }
+        }
+        shutdown::run_cleanup();
+        // Still write the trace: an install slow enough to interrupt is worth seeing
+        drop(logging);
+        std::process::exit(signal.exit_code());
+    };
+
+    if let Err(e) = result {
+        let code = ErrorCode::of(&e);
+        tracing::error!(code = code.map(ErrorCode::as_str), "{e:?}");
+        match code {
+            Some(code) => eprintln!("Error [{code}]: {e:?}"),
+            None => eprintln!("Error: {e:?}"),
+        }
+        if let Some(path) = &logging.path {
+            eprintln!(
+                "{}",
+                CliStyle::dim_text(&format!("Debug log: {}", path.display()))
+            );
+        }
+        drop(logging);
+        std::process::exit(code.map_or(1, ErrorCode::exit_code));
+    }
+    if let Ok(Some(code)) = result {
+        drop(logging);
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Run the command, returning the status to exit with when it ran a child that failed
+async fn run(cli: Cli, config: ClayConfig) -> Result<Option<i32>> {
+    // One client for every command so resolution, downloads and audits share connections
+    let npm_client = NpmClient::with_config(&config);
+    let new_package_manager = || PackageManager::with_client(config.clone(), npm_client.clone());
+    let json = cli.json;
+    let mut exit_code = None;
+    let quiet = cli.quiet;
+
+    let cwd = std::env::current_dir()?;
+
+    if cli.command.installs() && !config.install.ignore_package_manager {
+        manager_pin::enforce(&exec::project_root(&cwd))?;
+    }
+    let transaction = cli.command.installs().then(|| {
+        let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+        history::Transaction::begin(&exec::project_root(&cwd), command)
+    });
 
     match cli.command {
         Commands::Install {
             packages,
             dev,
-            json,
+            save_peer,
+            save_optional,
+            no_save,
+            save_exact,
+            save_prefix,
+            ignore_scripts,
+            with_types,
+            timing,
             fix_peers,
             skip_peers,
+            workspace,
         } => {
-            let package_manager = PackageManager::with_toml_lock(!json);
+            let start_time = std::time::Instant::now();
+            let mut install_config = config.clone();
+            if save_exact {
+                install_config.install.save_exact = true;
+            }
+            if let Some(save_prefix) = save_prefix {
+                install_config.install.save_exact = false;
+                install_config.install.save_prefix = Some(save_prefix);
+            }
+            if ignore_scripts {
+                install_config.install.ignore_scripts = true;
+            }
+            let with_types = with_types || install_config.install.with_types;
+            let save_target = if no_save {
+                SaveTarget::None
+            } else if save_peer {
+                SaveTarget::Peer
+            } else if save_optional {
+                SaveTarget::Optional
+            } else {
+                SaveTarget::Dependencies
+            };
+
+            if let Some(workspace) = workspace {
+                let root = WorkspaceManager::find_root(&cwd).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "--workspace needs a package.json with workspaces here or above"
+                    )
+                })?;
+                let workspaces = WorkspaceManager::with_root(&root, install_config);
+                let specs = packages
+                    .iter()
+                    .map(|spec| parse_package_spec(spec))
+                    .collect();
+                let field = save_target.field(dev).unwrap_or("dependencies");
+                workspaces
+                    .add_dependencies(&workspace, specs, field)
+                    .await?;
+                if let Some(transaction) = transaction {
+                    transaction.commit()?;
+                }
+                return Ok(None);
+            }
+
+            let mut package_manager =
+                PackageManager::with_client(install_config, npm_client.clone());
+            package_manager.set_quiet(json || quiet);
+            package_manager.set_save_target(save_target);
+            if let Some(root) = WorkspaceManager::find_root(&cwd) {
+                let workspaces = WorkspaceManager::with_root(&root, config.clone());
+                package_manager.set_workspace_packages(workspaces.local_packages().await?);
+                package_manager.set_catalogs(workspaces.catalogs()?);
+            }
             package_manager.initialize().await?;
+            let before = package_manager.installed_snapshot();
 
             let package_specs = if packages.is_empty() {
                 package_manager.get_package_json_dependencies(dev).await?
             } else {
-                let mut specs = Vec::new();
-                for package_spec in &packages {
-                    let (package_name, version) = if let Some(at_pos) = package_spec.rfind('@') {
-                        if at_pos > 0 {
-                            let name = &package_spec[..at_pos];
-                            let version = &package_spec[at_pos + 1..];
-                            (name.to_string(), version.to_string())
-                        } else {
-                            (package_spec.clone(), "latest".to_string())
-                        }
-                    } else {
-                        (package_spec.clone(), "latest".to_string())
-                    };
-                    specs.push((package_name, version));
-                }
-                specs
+                packages
+                    .iter()
+                    .map(|spec| parse_package_spec(spec))
+                    .collect()
             };
 
             let is_specific_install = !packages.is_empty();
             package_manager
-                .install_multiple_packages(package_specs, dev, is_specific_install)
+                .install_multiple_packages(package_specs.clone(), dev, is_specific_install)
                 .await?;
+            if with_types && is_specific_install {
+                let names: Vec<String> = package_specs.into_iter().map(|(name, _)| name).collect();
+                package_manager.install_missing_types(&names).await?;
+            }
 
             // Handle peer dependencies if requested
             if fix_peers && !skip_peers {
-                println!("{}", CliStyle::info("Auto-installing peer dependencies..."));
+                if !json {
+                    println!("{}", CliStyle::info("Auto-installing peer dependencies..."));
+                }
 
                 // Get all installed packages and check their peer dependencies
                 let installed_packages = package_manager
@@ -224,54 +997,164 @@ async fn main() -> Result<()> {
                         .npm_client
                         .get_package_info(package_name)
                         .await
+                        && let Some(latest_info) = package_info.get_latest_version()
                     {
-                        if let Some(latest_info) = package_info.get_latest_version() {
-                            package_manager
-                                .auto_install_peer_dependencies(latest_info)
-                                .await?;
-                        }
+                        package_manager
+                            .auto_install_peer_dependencies(latest_info)
+                            .await?;
                     }
                 }
 
-                // Report any remaining conflicts
-                package_manager.report_peer_conflicts().await?;
+                // Report any remaining conflicts (the JSON report lists them as warnings)
+                if !json {
+                    package_manager.report_peer_conflicts(false).await?;
+                }
             } else if !skip_peers {
                 // Only check peers if explicitly requested, keep output clean like Bun by default
             }
+
+            if json {
+                let mut report = package_manager
+                    .install_report(&before, start_time.elapsed())
+                    .await;
+                match Auditor::new(npm_client.clone()).summary().await {
+                    Ok(summary) => report.audit = Some(summary),
+                    Err(e) => report.warnings.push(format!("Audit skipped: {e}")),
+                }
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+
+            if timing {
+                let report = package_manager.timing_report(start_time.elapsed());
+                let path = save_timing_report(&report)?;
+                if !json {
+                    report.print();
+                    println!(
+                        "\n{}",
+                        CliStyle::info(&format!("Timing saved to {}", path.display()))
+                    );
+                }
+            }
         }
         Commands::Uninstall { packages } => {
-            let package_manager = PackageManager::new();
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
             for package_name in packages {
                 package_manager.uninstall_package(&package_name).await?;
             }
         }
-        Commands::List => {
-            let package_manager = PackageManager::new();
+        Commands::List {
+            depth,
+            prod,
+            dev,
+            flat,
+        } => {
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
-            package_manager.list_installed_packages().await?;
+            if flat {
+                package_manager.list_installed_packages(json).await?;
+            } else {
+                package_manager
+                    .list_dependency_tree(depth, prod, dev, json)
+                    .await?;
+            }
         }
         Commands::Upgrade { yes } => {
-            upgrade_clay(yes).await?;
+            upgrade_clay(&npm_client, yes).await?;
+        }
+        Commands::UpgradeInteractive => {
+            upgrade_interactive::upgrade_interactive(config.clone(), npm_client.clone()).await?;
         }
         Commands::Run { script } => {
-            let package_manager = PackageManager::new();
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
             match script {
                 Some(script_name) => {
-                    package_manager.run_script(&script_name).await?;
+                    let status = package_manager.run_script(&script_name).await?;
+                    if !status.success() {
+                        exit_code = Some(exec::exit_code(&status));
+                    }
                 }
                 None => {
                     package_manager.list_scripts().await?;
                 }
             }
         }
+        Commands::External(args) => {
+            let (name, args) = args.split_first().expect("clap passes the subcommand name");
+            let name = name.to_string_lossy();
+            let Some(program) = plugins::find(&name) else {
+                Cli::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidSubcommand,
+                        format!("unrecognized subcommand '{name}', and no clay-{name} on PATH"),
+                    )
+                    .exit();
+            };
+            let status = plugins::run(&config, &program, args).await?;
+            if !status.success() {
+                exit_code = Some(exec::exit_code(&status));
+            }
+        }
+        Commands::Exec { bin, args } => {
+            let status = exec::exec_bin(&std::env::current_dir()?, &bin, &args)?;
+            if !status.success() {
+                exit_code = Some(exec::exit_code(&status));
+            }
+        }
+        Commands::Dlx { package, args } => {
+            let status = exec::dlx(config.clone(), npm_client.clone(), &package, &args).await?;
+            if !status.success() {
+                exit_code = Some(exec::exit_code(&status));
+            }
+        }
+        Commands::Node(command) => {
+            let project_dir = exec::project_root(&std::env::current_dir()?);
+            match command {
+                NodeCommands::Install { version } => {
+                    node_runtime::install(&npm_client, &version).await?;
+                }
+                NodeCommands::Use { version } => {
+                    node_runtime::use_version(&npm_client, &project_dir, version.as_deref())
+                        .await?;
+                }
+                NodeCommands::List => node_runtime::list(&project_dir),
+            }
+        }
+        Commands::Patch { package, edit_dir } => {
+            let package_manager = new_package_manager();
+            package_manager.initialize().await?;
+            let edit_dir = package_manager.start_patch(&package, edit_dir).await?;
+            println!(
+                "{} Edit {} in {}",
+                CliStyle::info(""),
+                console::style(&package).white().bold(),
+                console::style(edit_dir.display()).cyan()
+            );
+            println!(
+                "{}",
+                CliStyle::dim_text(&format!(
+                    "Then run: clay patch-commit {}",
+                    edit_dir.display()
+                ))
+            );
+        }
+        Commands::PatchCommit { dir } => {
+            let package_manager = new_package_manager();
+            package_manager.initialize().await?;
+            let patch_path = package_manager.commit_patch(&dir).await?;
+            println!(
+                "{} Saved {}, applied on every install",
+                CliStyle::success(""),
+                console::style(patch_path).white().bold()
+            );
+        }
         Commands::Cache(cache_cmd) => {
-            let package_manager = PackageManager::new();
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
             match cache_cmd {
                 CacheCommands::Info => {
-                    package_manager.cache_info().await?;
+                    package_manager.cache_info(json).await?;
                 }
                 CacheCommands::Clear => {
                     package_manager.cache_clear().await?;
@@ -282,12 +1165,24 @@ async fn main() -> Result<()> {
             }
         }
         Commands::Store(store_cmd) => {
-            let content_store = ContentStore::new();
+            let content_store = ContentStore::from_config(&config);
             content_store.initialize().await?;
 
             match store_cmd {
                 StoreCommands::Stats => {
                     let stats = content_store.get_store_stats().await?;
+                    let packages = content_store.package_usage().await?;
+                    if json {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&serde_json::json!({
+                                "stats": stats,
+                                "packages": packages,
+                            }))?
+                        );
+                        return Ok(None);
+                    }
+
                     println!("{}", CliStyle::section_header("Content Store Statistics"));
                     println!(
                         "Total packages: {}",
@@ -309,45 +1204,194 @@ async fn main() -> Result<()> {
                         "Space saved by deduplication: {}",
                         console::style(ContentStore::format_size(stats.space_saved)).green()
                     );
+
+                    if !packages.is_empty() {
+                        let name_width = packages
+                            .iter()
+                            .map(|usage| usage.name.len())
+                            .max()
+                            .unwrap_or(0)
+                            .max("Package".len());
+                        println!();
+                        println!(
+                            "{}",
+                            console::style(format!(
+                                "{:<name_width$}  {:>10}  {:>8}  {:<10}  {:>8}",
+                                "Package", "Size", "Versions", "Last used", "Projects"
+                            ))
+                            .dim()
+                        );
+                        for usage in &packages {
+                            let last_used = usage
+                                .last_used
+                                .map(|at| at.format("%Y-%m-%d").to_string())
+                                .unwrap_or_else(|| "-".to_string());
+                            println!(
+                                "{:<name_width$}  {:>10}  {:>8}  {:<10}  {:>8}",
+                                usage.name,
+                                ContentStore::format_size(usage.size),
+                                usage.versions.len(),
+                                last_used,
+                                usage.projects
+                            );
+                        }
+                    }
+                }
+                StoreCommands::Dedupe { apply } => {
+                    let report = content_store.dedupe_projects(apply).await?;
+                    let size = ContentStore::format_size(report.bytes);
+                    if report.files == 0 {
+                        println!(
+                            "{}",
+                            CliStyle::success(&format!(
+                                "No duplicate files in {} projects",
+                                report.projects
+                            ))
+                        );
+                    } else if apply {
+                        println!(
+                            "{}",
+                            CliStyle::success(&format!(
+                                "Hardlinked {} files across {} projects, reclaiming {}",
+                                report.files, report.projects, size
+                            ))
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            CliStyle::info(&format!(
+                                "{} files ({}) across {} projects duplicate store content; run with --apply to hardlink them",
+                                report.files, size, report.projects
+                            ))
+                        );
+                    }
                 }
-                StoreCommands::Dedupe => {
-                    content_store.deduplicate_store().await?;
+                StoreCommands::Cleanup(gc) => {
+                    // Projects installed before references were recorded still count
+                    // if cleanup runs from them
+                    let package_manager = new_package_manager();
+                    package_manager.record_project_references().await?;
+                    content_store
+                        .cleanup_unused(gc.dry_run, gc.grace_period)
+                        .await?;
                 }
-                StoreCommands::Cleanup => {
-                    // Get list of currently installed packages
-                    let package_manager = PackageManager::new();
-                    package_manager.initialize().await?;
-                    let active_packages = package_manager
-                        .get_installed_packages()
-                        .await
-                        .unwrap_or_default();
-                    let active_package_specs: Vec<String> = active_packages
-                        .into_iter()
-                        .map(|name| format!("{name}@latest"))
-                        .collect();
-                    content_store.cleanup_unused(&active_package_specs).await?;
-                }
-                StoreCommands::Gc => {
-                    content_store.deduplicate_store().await?;
-                    let package_manager = PackageManager::new();
-                    package_manager.initialize().await?;
-                    let active_packages = package_manager
-                        .get_installed_packages()
-                        .await
-                        .unwrap_or_default();
-                    let active_package_specs: Vec<String> = active_packages
-                        .into_iter()
-                        .map(|name| format!("{name}@latest"))
-                        .collect();
-                    content_store.cleanup_unused(&active_package_specs).await?;
+                StoreCommands::Gc(gc) => {
+                    if !gc.dry_run {
+                        content_store.deduplicate_store().await?;
+                    }
+                    let package_manager = new_package_manager();
+                    package_manager.record_project_references().await?;
+                    content_store
+                        .cleanup_unused(gc.dry_run, gc.grace_period)
+                        .await?;
+                }
+                StoreCommands::Prune {
+                    older_than,
+                    max_size,
+                    grace_period,
+                } => {
+                    let max_size = match max_size {
+                        Some(max_size) => Some(max_size),
+                        None => config
+                            .store
+                            .max_size
+                            .as_deref()
+                            .map(ContentStore::parse_size)
+                            .transpose()
+                            .map_err(|e| {
+                                anyhow::anyhow!("Invalid [store] max-size in clay.toml: {e}")
+                            })?,
+                    };
+                    if older_than.is_none() && max_size.is_none() {
+                        anyhow::bail!(
+                            "Nothing to prune by: pass --older-than or --max-size, or set [store] max-size"
+                        );
+                    }
+                    content_store
+                        .prune(older_than, max_size, grace_period)
+                        .await?;
+                }
+                StoreCommands::Export { archive, all } => {
+                    let (package_keys, tree_hashes) = if all {
+                        (None, Vec::new())
+                    } else {
+                        let (keys, trees) = project_store_selection()?;
+                        (Some(keys), trees)
+                    };
+
+                    let (exported, missing) = content_store
+                        .export_archive(&archive, package_keys.as_ref(), &tree_hashes)
+                        .await?;
+                    for key in &missing {
+                        println!(
+                            "{}",
+                            CliStyle::warning(&format!("{key} is not in the content store"))
+                        );
+                    }
+                    println!(
+                        "{}",
+                        CliStyle::success(&format!(
+                            "Exported {} packages to {}",
+                            exported,
+                            archive.display()
+                        ))
+                    );
+                }
+                StoreCommands::Migrate { to } => {
+                    let target = ContentStore::at(
+                        to.clone()
+                            .unwrap_or_else(|| content_store.path().to_path_buf()),
+                        config.install.link_mode,
+                    );
+                    target.initialize().await?;
+
+                    let report = content_store.migrate_into(&target).await?;
+                    for key in &report.corrupted {
+                        println!(
+                            "{}",
+                            CliStyle::warning(&format!(
+                                "{key} failed verification and was dropped; reinstall to fetch it again"
+                            ))
+                        );
+                    }
+                    println!(
+                        "{}",
+                        CliStyle::success(&format!(
+                            "Migrated {} packages to {}",
+                            report.migrated,
+                            target.path().display()
+                        ))
+                    );
+                    if let Some(to) = to {
+                        println!(
+                            "Set `path = \"{}\"` under [store] in clay.toml to use it",
+                            to.display()
+                        );
+                    }
+                }
+                StoreCommands::Import { archive } => {
+                    let imported = content_store.import_archive(&archive).await?;
+                    println!(
+                        "{}",
+                        CliStyle::success(&format!(
+                            "Imported {} packages from {}",
+                            imported,
+                            archive.display()
+                        ))
+                    );
                 }
             }
         }
         Commands::Workspace(workspace_cmd) => {
-            let workspace_manager = WorkspaceManager::new();
+            let workspace_manager = WorkspaceManager::new(config.clone());
             match workspace_cmd {
                 WorkspaceCommands::List => {
-                    workspace_manager.list_workspaces().await?;
+                    workspace_manager.list_workspaces(json).await?;
+                }
+                WorkspaceCommands::Graph { dot, dependents_of } => {
+                    workspace_manager
+                        .print_graph(dot, json, dependents_of.as_deref())
+                        .await?;
                 }
                 WorkspaceCommands::Add { name, path } => {
                     let workspace_path = path.unwrap_or_else(|| format!("packages/{name}"));
@@ -361,14 +1405,56 @@ async fn main() -> Result<()> {
                 WorkspaceCommands::Run {
                     script,
                     workspace,
+                    mut filters,
+                    since,
                     parallel,
+                    workspace_concurrency,
+                    output_style,
                 } => {
+                    // --workspace <name> is a filter matching just that name
+                    filters.extend(workspace);
+                    let parallel = parallel.then(|| workspace::Parallelism {
+                        concurrency: workspace_concurrency.unwrap_or_else(|| {
+                            std::thread::available_parallelism().map_or(4, |n| n.get())
+                        }),
+                        output_style,
+                    });
                     workspace_manager
-                        .run_script(&script, workspace.as_deref(), parallel)
+                        .run_script(&script, &filters, since.as_deref(), parallel)
                         .await?;
                 }
-                WorkspaceCommands::Install { all: _ } => {
-                    workspace_manager.install_workspace_dependencies().await?;
+                WorkspaceCommands::Install { all: _, filters } => {
+                    workspace_manager
+                        .install_workspace_dependencies(&filters)
+                        .await?;
+                }
+                WorkspaceCommands::Version {
+                    bump,
+                    filters,
+                    message,
+                    no_git_tag_version,
+                    ignore_scripts,
+                } => {
+                    let workspaces = workspace_manager.select_workspaces(&filters, None).await?;
+                    if workspaces.is_empty() {
+                        return Err(anyhow::anyhow!("No workspaces to version"));
+                    }
+                    let options = version_bump::VersionOptions {
+                        git: !no_git_tag_version,
+                        message,
+                        scripts: !ignore_scripts,
+                    };
+                    let bumped = version_bump::bump_workspaces(
+                        &config,
+                        std::path::Path::new("."),
+                        &workspaces,
+                        &bump,
+                        &options,
+                    )
+                    .await?;
+                    for (name, version) in bumped {
+                        println!("{name} v{version}");
+                    }
                 }
             }
         }
@@ -377,20 +1463,19 @@ async fn main() -> Result<()> {
             minify,
             watch,
         } => {
-            let mut bundler = Bundler::new();
+            let mut bundler = Bundler::new(&config.bundle);
             bundler.bundle(output.as_deref(), minify, watch).await?;
         }
         Commands::Dev { port, host } => {
-            let mut dev_server = DevServer::new();
-            let host = host.unwrap_or_else(|| "localhost".to_string());
-            dev_server.start(&host, port).await?;
+            let mut dev_server = DevServer::new(&config.dev, &config.bundle);
+            dev_server.start(host.as_deref(), port).await?;
         }
         Commands::Peer(peer_cmd) => {
-            let package_manager = PackageManager::new();
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
             match peer_cmd {
                 PeerCommands::Check => {
-                    package_manager.report_peer_conflicts().await?;
+                    package_manager.report_peer_conflicts(json).await?;
                 }
                 PeerCommands::Install => {
                     let conflicts = package_manager.check_peer_dependency_conflicts().await?;
@@ -405,12 +1490,21 @@ async fn main() -> Result<()> {
                             CliStyle::info("Installing missing peer dependencies...")
                         );
                         // Auto-install missing peers would be implemented here
-                        package_manager.report_peer_conflicts().await?;
+                        package_manager.report_peer_conflicts(false).await?;
                     }
                 }
                 PeerCommands::List => {
-                    println!("{}", CliStyle::info("Listing peer dependencies..."));
                     let conflicts = package_manager.check_peer_dependency_conflicts().await?;
+                    if json {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(
+                                &serde_json::json!({ "peers": conflicts })
+                            )?
+                        );
+                        return Ok(None);
+                    }
+                    println!("{}", CliStyle::info("Listing peer dependencies..."));
                     if conflicts.is_empty() {
                         println!("{}", CliStyle::warning("No peer dependencies found"));
                     } else {
@@ -432,35 +1526,110 @@ async fn main() -> Result<()> {
                 }
             }
         }
-        Commands::Check { peers, all } => {
-            let package_manager = PackageManager::new();
+        Commands::Check {
+            peers,
+            all,
+            missing,
+            fix,
+        } => {
+            let package_manager = new_package_manager();
             package_manager.initialize().await?;
 
-            if peers || all {
-                println!("{}", CliStyle::info("Checking peer dependencies..."));
-                package_manager.report_peer_conflicts().await?;
+            if missing {
+                let found = phantom::find_missing(&std::env::current_dir()?)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&found)?);
+                } else {
+                    println!("{}", CliStyle::info("Checking for undeclared imports..."));
+                    phantom::print(&found);
+                }
+                if fix {
+                    phantom::fix(&package_manager, &found).await?;
+                } else if !found.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "{} imported package{} missing from package.json; run clay check --missing --fix to add them",
+                        found.len(),
+                        if found.len() == 1 { " is" } else { "s are" }
+                    ));
+                }
+            }
+
+            if all && json {
+                let conflicts = package_manager.check_peer_dependency_conflicts().await?;
+                let report = package_manager.verify_installed().await?;
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(
+                        &serde_json::json!({ "conflicts": conflicts, "integrity": report })
+                    )?
+                );
+                integrity_result(&report)?;
+            } else if peers || all {
+                if !json {
+                    println!("{}", CliStyle::info("Checking peer dependencies..."));
+                }
+                package_manager.report_peer_conflicts(json).await?;
             }
 
-            if all {
+            if all && !json {
                 println!("{}", CliStyle::info("Checking package integrity..."));
-                // Could add integrity checks here
-                println!("{}", CliStyle::success("Package integrity check completed"));
+                let report = package_manager.verify_installed().await?;
+                report.print();
+                integrity_result(&report)?;
             }
 
-            if !peers && !all {
+            if !peers && !all && !missing {
                 println!(
                     "{}",
-                    CliStyle::info("Use --peers or --all to specify what to check")
+                    CliStyle::info("Use --peers, --missing or --all to specify what to check")
                 );
             }
         }
-        Commands::Info { package } => {
-            let content_store = ContentStore::new();
+        Commands::Explain { package } => {
+            let mut package_manager = new_package_manager();
+            package_manager.set_quiet(json);
+            let explanation = package_manager.explain(&package).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&explanation)?);
+            } else {
+                explanation.print();
+            }
+        }
+        Commands::History => {
+            let entries = history::read_log(&exec::project_root(&cwd))?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else {
+                history::print(&entries);
+            }
+        }
+        Commands::Revert { id } => {
+            history::revert(config, npm_client, &exec::project_root(&cwd), id).await?;
+        }
+        Commands::Info {
+            package,
+            local_only,
+        } => {
+            let content_store = ContentStore::from_config(&config);
             content_store.initialize().await?;
 
             if let Some(pkg_name) = package {
                 // Show package info from content store
                 if let Some(metadata) = content_store.get_package_info(&pkg_name, "latest").await {
+                    if json {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&serde_json::json!({
+                                "name": metadata.name,
+                                "version": metadata.version,
+                                "hash": metadata.content_address.hash,
+                                "size": metadata.content_address.size,
+                                "dependencies": metadata.dependencies,
+                                "files": metadata.files.len(),
+                            }))?
+                        );
+                        return Ok(None);
+                    }
                     println!(
                         "{} Package: {}",
                         CliStyle::info(""),
@@ -480,6 +1649,11 @@ async fn main() -> Result<()> {
                         println!("Dependencies: {}", deps.len());
                     }
                     println!("Files: {}", metadata.files.len());
+                } else if !local_only {
+                    let registry_manager = RegistryManager::new(npm_client.clone());
+                    registry_manager.show_info(&pkg_name, json).await?;
+                } else if json {
+                    anyhow::bail!("Package '{pkg_name}' not found in content store");
                 } else {
                     println!(
                         "{} Package '{}' not found in content store",
@@ -490,9 +1664,20 @@ async fn main() -> Result<()> {
             } else {
                 // Show general package manager info
                 let stats = content_store.get_store_stats().await?;
-                let package_manager = PackageManager::new();
+                let package_manager = new_package_manager();
                 package_manager.initialize().await?;
-                package_manager.cache_info().await?;
+                if json {
+                    let (cached_packages, cache_size) = package_manager.cache_stats().await?;
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&serde_json::json!({
+                            "cache": { "packages": cached_packages, "size": cache_size },
+                            "store": stats,
+                        }))?
+                    );
+                    return Ok(None);
+                }
+                package_manager.cache_info(false).await?;
                 println!("\n{}", CliStyle::section_header("Content Store:"));
                 println!(
                     "Total packages: {}",
@@ -508,12 +1693,215 @@ async fn main() -> Result<()> {
                 );
             }
         }
+        Commands::Audit { audit_level } => {
+            let auditor = Auditor::new(npm_client.clone());
+            let level = audit_level.unwrap_or(config.audit.level);
+            if !auditor.audit(json, level).await? {
+                exit_code = Some(1);
+            }
+        }
+        Commands::Search { query, interactive } => {
+            let query = query.join(" ");
+            if interactive {
+                search::interactive(config.clone(), npm_client.clone(), query).await?;
+            } else {
+                search::search(&npm_client, &query, json).await?;
+            }
+        }
+        Commands::Size { package } => {
+            let (name, version) = match package.rfind('@') {
+                Some(at_pos) if at_pos > 0 => (&package[..at_pos], &package[at_pos + 1..]),
+                _ => (package.as_str(), "latest"),
+            };
+            let mut package_manager = new_package_manager();
+            package_manager.set_quiet(quiet || json);
+            let report = package_manager.size_report(name, version).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print(std::path::Path::new("package.json").is_file());
+            }
+        }
+        Commands::DistTag(dist_tag_cmd) => {
+            let registry_manager = RegistryManager::new(npm_client.clone());
+            match dist_tag_cmd {
+                DistTagCommands::Add { spec, tag } => {
+                    registry_manager.dist_tag_add(&spec, &tag).await?;
+                }
+                DistTagCommands::Rm { package, tag } => {
+                    registry_manager.dist_tag_rm(&package, &tag).await?;
+                }
+                DistTagCommands::Ls { package } => {
+                    registry_manager
+                        .dist_tag_ls(package.as_deref(), json)
+                        .await?;
+                }
+            }
+        }
+        Commands::Import(import) => {
+            let lock_manager =
+                LockManager::new(npm_client.clone(), config.network.resolve_concurrency());
+            import.run(&lock_manager).await?;
+        }
+        Commands::Lock(lock_cmd) => {
+            let lock_manager =
+                LockManager::new(npm_client.clone(), config.network.resolve_concurrency());
+            match lock_cmd {
+                LockCommands::Import(import) => {
+                    import.run(&lock_manager).await?;
+                }
+                LockCommands::Export { format, output } => {
+                    lock_manager.export(format, output.as_deref())?;
+                }
+                LockCommands::Migrate => {
+                    lock_manager.migrate()?;
+                }
+                LockCommands::Prune { dry_run } => {
+                    lock_manager.prune(dry_run)?;
+                }
+                LockCommands::Merge { files } => {
+                    lock_manager.merge(&files).await?;
+                }
+                LockCommands::Diff { git_ref } => {
+                    lock_manager.diff(&git_ref)?;
+                }
+                LockCommands::Verify => {
+                    lock_manager.verify()?;
+                }
+            }
+        }
+        Commands::Bin { global } => {
+            let bin_dir = if global {
+                exec::global_prefix()?.join("bin")
+            } else {
+                exec::project_root(&std::env::current_dir()?)
+                    .join("node_modules")
+                    .join(".bin")
+            };
+            println!("{}", bin_dir.display());
+        }
+        Commands::Root { global } => {
+            let root = if global {
+                exec::global_prefix()?
+            } else {
+                exec::project_root(&std::env::current_dir()?)
+            };
+            println!("{}", root.join("node_modules").display());
+        }
+        Commands::Version {
+            bump,
+            message,
+            no_git_tag_version,
+            ignore_scripts,
+        } => {
+            let options = version_bump::VersionOptions {
+                git: !no_git_tag_version,
+                message,
+                scripts: !ignore_scripts,
+            };
+            let version =
+                version_bump::bump(&config, &std::env::current_dir()?, &bump, &options).await?;
+            println!("v{version}");
+        }
+        Commands::Create {
+            template,
+            dir,
+            args,
+            no_install,
+        } => {
+            match create::create(
+                config.clone(),
+                npm_client.clone(),
+                &template,
+                dir,
+                &args,
+                !no_install,
+            )
+            .await?
+            {
+                Some(project_dir) => {
+                    let cwd = std::env::current_dir()?;
+                    let shown = project_dir.strip_prefix(&cwd).unwrap_or(&project_dir);
+                    println!(
+                        "{}",
+                        CliStyle::success(&format!("Created {}", project_dir.display()))
+                    );
+                    if !shown.as_os_str().is_empty() {
+                        println!("  cd {}", shown.display());
+                    }
+                }
+                None if !no_install => println!(
+                    "{}",
+                    CliStyle::info("Run `clay install` in the new project to install it")
+                ),
+                None => {}
+            }
+        }
+        Commands::Config(config_cmd) => config_command(config_cmd, json)?,
+        Commands::Doctor => {
+            let mut doctor = Doctor::new(npm_client.clone());
+            if !doctor.run().await? {
+                exit_code = Some(1);
+            }
+        }
+        Commands::Init { yes } => {
+            // Without a terminal to answer the questions, take the defaults
+            init::init_project(
+                &std::env::current_dir()?,
+                yes || !CliStyle::is_interactive(),
+            )?;
+        }
+        Commands::Login { registry, scope } => {
+            let registry_manager = RegistryManager::new(npm_client.clone());
+            registry_manager
+                .login(registry.as_deref(), scope.as_deref())
+                .await?;
+        }
+        Commands::Deprecate {
+            spec,
+            message,
+            undo: _,
+        } => {
+            let registry_manager = RegistryManager::new(npm_client.clone());
+            registry_manager
+                .deprecate(&spec, message.as_deref().unwrap_or_default())
+                .await?;
+        }
+        Commands::Link {
+            package,
+            target: None,
+            ..
+        } => {
+            let cwd = std::env::current_dir()?;
+            match package {
+                Some(package) => global_link::link_into(&exec::project_root(&cwd), &package)?,
+                None => global_link::register(&cwd)?,
+            }
+        }
+        Commands::Unlink { package } => {
+            let cwd = std::env::current_dir()?;
+            match package {
+                Some(package) => {
+                    global_link::unlink_from(
+                        config,
+                        npm_client,
+                        &exec::project_root(&cwd),
+                        &package,
+                    )
+                    .await?
+                }
+                None => global_link::unregister(&cwd)?,
+            }
+        }
         Commands::Link {
             package,
             version,
-            target,
+            target: Some(target),
         } => {
-            let content_store = ContentStore::new();
+            let (Some(package), Some(version)) = (package, version) else {
+                unreachable!("--target requires a package and version");
+            };
+            let content_store = ContentStore::from_config(&config);
             content_store.initialize().await?;
 
             let target_path = std::path::PathBuf::from(&target);
@@ -539,17 +1927,46 @@ async fn main() -> Result<()> {
         }
     }
 
-    Ok(())
+    if let Some(transaction) = transaction {
+        transaction.commit()?;
+    }
+    Ok(exit_code)
+}
+
+/// `name@version` from the command line as (name, version), `latest` when no version is
+/// given; a leading `@` is a scope
+fn parse_package_spec(spec: &str) -> (String, String) {
+    match spec.rfind('@') {
+        Some(at_pos) if at_pos > 0 => (spec[..at_pos].to_string(), spec[at_pos + 1..].to_string()),
+        _ => (spec.to_string(), "latest".to_string()),
+    }
 }
 
-async fn upgrade_clay(skip_confirmation: bool) -> Result<()> {
-    use console::style;
+async fn upgrade_clay(npm_client: &NpmClient, skip_confirmation: bool) -> Result<()> {
     use std::io::{self, Write};
 
     println!("{}", CliStyle::section_header("Clay Upgrade"));
-    println!("This will download and run the latest Clay installer.");
+
+    let release = self_update::latest_release(npm_client).await?;
+    let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    let latest = release.version()?;
+    if latest <= current {
+        println!(
+            "{}",
+            CliStyle::success(&format!("clay {current} is the latest version"))
+        );
+        return Ok(());
+    }
+    println!(
+        "clay {} → {}",
+        current,
+        CliStyle::version(&latest.to_string())
+    );
     println!();
 
+    if !skip_confirmation && !CliStyle::is_interactive() {
+        anyhow::bail!("Not asking for confirmation without a terminal; pass --yes to upgrade");
+    }
     if !skip_confirmation {
         print!("Do you want to continue? [y/N]: ");
         io::stdout().flush()?;
@@ -564,49 +1981,151 @@ async fn upgrade_clay(skip_confirmation: bool) -> Result<()> {
         }
     }
 
-    println!("{}", style("Downloading installer...").cyan());
+    let path = self_update::install(npm_client, &release).await?;
+    println!(
+        "{}",
+        CliStyle::success(&format!("Upgraded {} to clay {latest}", path.display()))
+    );
+    Ok(())
+}
 
-    let install_script_url =
-        "https://raw.githubusercontent.com/lassejlv/clay/main/scripts/install.sh";
-    let response = reqwest::get(install_script_url).await?;
+/// Store entries the current project installs from: its lockfile's packages and the
+/// dependency trees cached for its package.json, with and without devDependencies
+fn project_store_selection() -> Result<(std::collections::HashSet<String>, Vec<String>)> {
+    let mut package_keys = std::collections::HashSet::new();
+    let mut tree_hashes = Vec::new();
 
-    if !response.status().is_success() {
-        anyhow::bail!("Failed to download installer: HTTP {}", response.status());
+    let lock_path = LockManager::lock_file_path();
+    if lock_path.exists() {
+        let lock_file = LockManager::read(&lock_path)?;
+        package_keys.extend(lock_file.packages.keys().cloned());
     }
 
-    let script_content = response.text().await?;
-
-    let temp_dir = std::env::temp_dir();
-    let script_path = temp_dir.join("clay_install.sh");
-    std::fs::write(&script_path, script_content)?;
-
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = std::fs::metadata(&script_path)?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&script_path, perms)?;
+    if let Ok(content) = std::fs::read_to_string("package.json") {
+        let package_json: package_info::PackageJson = serde_json::from_str(&content)?;
+        for include_dev in [false, true] {
+            tree_hashes.push(package_json.calculate_dependency_fingerprint(include_dev));
+        }
     }
 
-    println!("{}", style("Running installer...").cyan());
-    println!();
-
-    let status = Command::new("bash").arg(&script_path).status()?;
-
-    let _ = std::fs::remove_file(&script_path);
-
-    if status.success() {
-        println!();
-        println!("{}", CliStyle::success("Upgrade completed successfully!"));
-        println!(
-            "Please restart your shell or run 'source ~/.bashrc' to ensure the new version is loaded."
-        );
-    } else {
+    if package_keys.is_empty() && tree_hashes.is_empty() {
         anyhow::bail!(
-            "Installer failed with exit code: {}",
-            status.code().unwrap_or(-1)
+            "Nothing to export: no lockfile or package.json here (pass --all for the whole store)"
         );
     }
+    Ok((package_keys, tree_hashes))
+}
+
+/// Write an install's timing report to ~/.clay/logs, named by when it ran
+fn save_timing_report(report: &package_manager::TimingReport) -> Result<std::path::PathBuf> {
+    let path = logging::log_path("timing-", "json")?;
+    std::fs::write(&path, serde_json::to_string_pretty(report)?)?;
+    Ok(path)
+}
+
+/// Fail `clay check --all` with EINTEGRITY when node_modules doesn't match
+fn integrity_result(report: &package_manager::IntegrityReport) -> Result<()> {
+    if report.problems.is_empty() {
+        return Ok(());
+    }
+    Err(errors::coded(
+        ErrorCode::Integrity,
+        format!(
+            "node_modules doesn't match what was installed ({} found); remove the affected \
+             packages and run clay install",
+            match report.problems.len() {
+                1 => "1 problem".to_string(),
+                n => format!("{n} problems"),
+            }
+        ),
+    ))
+}
 
+fn config_command(command: ConfigCommands, json: bool) -> Result<()> {
+    let file = |global: bool| -> Result<std::path::PathBuf> {
+        if global {
+            config::user_config_path()
+                .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))
+        } else {
+            Ok(config::project_config_path())
+        }
+    };
+
+    match command {
+        ConfigCommands::List => {
+            let layers = ConfigLayers::load()?;
+            let mut settings = serde_json::Map::new();
+            for key in config::KEYS {
+                let setting = layers.get(key)?;
+                if json {
+                    settings.insert(
+                        key.to_string(),
+                        match setting {
+                            Some((value, source)) => {
+                                serde_json::json!({ "value": value, "source": source })
+                            }
+                            None => serde_json::json!({ "value": null, "source": null }),
+                        },
+                    );
+                    continue;
+                }
+                match setting {
+                    Some((value, source)) => println!(
+                        "{key} = {value} {}",
+                        CliStyle::dim_text(&format!("({source})"))
+                    ),
+                    None => println!("{key} {}", CliStyle::dim_text("(unset)")),
+                }
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&settings)?);
+            } else {
+                println!(
+                    "\n{}",
+                    CliStyle::dim_text(&format!(
+                        "Sources: user is {}, project is {}, env is {}",
+                        file(true)?.display(),
+                        file(false)?.display(),
+                        config::env_var("<key>")
+                    ))
+                );
+            }
+        }
+        ConfigCommands::Get { key } => match ConfigLayers::load()?.get(&key)? {
+            Some((value, _)) if json => println!("{}", serde_json::to_string_pretty(&value)?),
+            Some((toml::Value::String(value), _)) => println!("{value}"),
+            Some((value, _)) => println!("{value}"),
+            None if json => println!("null"),
+            None => {}
+        },
+        ConfigCommands::Set { key, value, global } => {
+            let path = file(global)?;
+            config::set_value(&path, &key, &value)?;
+            println!(
+                "{}",
+                CliStyle::success(&format!("Set {key} in {}", path.display()))
+            );
+            if let Some((var, env_value)) = config::env_override(&key) {
+                println!(
+                    "{}",
+                    CliStyle::warning(&format!("{var}={env_value} overrides it in this shell"))
+                );
+            }
+        }
+        ConfigCommands::Delete { key, global } => {
+            let path = file(global)?;
+            if config::unset_value(&path, &key)? {
+                println!(
+                    "{}",
+                    CliStyle::success(&format!("Removed {key} from {}", path.display()))
+                );
+            } else {
+                println!(
+                    "{}",
+                    CliStyle::warning(&format!("{key} isn't set in {}", path.display()))
+                );
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,57 @@
+use anyhow::{Result, anyhow};
+use semver::Version;
+use serde_json::Value;
+use std::path::Path;
+
+use crate::cli_style::CliStyle;
+use crate::version_range::VersionRange;
+
+/// Check the project in `project_dir` may be installed with this clay. A `packageManager`
+/// naming another package manager, or a `clay.requiredVersion` range this version is
+/// outside of, refuses: installing anyway would leave the team's lockfile behind. A
+/// `packageManager` pinning another clay version only warns.
+pub fn enforce(project_dir: &Path) -> Result<()> {
+    let Ok(content) = std::fs::read_to_string(project_dir.join("package.json")) else {
+        return Ok(());
+    };
+    let Ok(package_json) = serde_json::from_str::<Value>(&content) else {
+        return Ok(());
+    };
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))?;
+
+    if let Some(pinned) = package_json["packageManager"].as_str() {
+        let (name, version) = match pinned.rfind('@') {
+            Some(at) if at > 0 => (&pinned[..at], &pinned[at + 1..]),
+            _ => (pinned, ""),
+        };
+        // Corepack pins can carry a hash: pnpm@9.1.0+sha512.abc
+        let version = version.split('+').next().unwrap_or(version);
+        if name != "clay" {
+            return Err(anyhow!(
+                "This project is managed with {pinned} (packageManager in package.json); use {name}, or set install.ignore-package-manager to install with clay anyway"
+            ));
+        }
+        if let Ok(version) = Version::parse(version)
+            && version != current
+        {
+            eprintln!(
+                "{}",
+                CliStyle::warning(&format!(
+                    "package.json pins clay {version} but this is clay {current}; the lockfile may come out differently"
+                ))
+            );
+        }
+    }
+
+    if let Some(required) = package_json["clay"]["requiredVersion"].as_str() {
+        let range = VersionRange::parse(required).map_err(|_| {
+            anyhow!("clay.requiredVersion in package.json isn't a valid range: {required}")
+        })?;
+        if !range.matches(&current) {
+            return Err(anyhow!(
+                "This project requires clay {required} (clay.requiredVersion in package.json), but this is clay {current}"
+            ));
+        }
+    }
+    Ok(())
+}
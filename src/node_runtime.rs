@@ -0,0 +1,366 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::cli_style::CliStyle;
+use crate::errors::{self, ErrorCode};
+use crate::npm_client::NpmClient;
+use crate::shutdown::CleanupGuard;
+use crate::version_range::VersionRange;
+
+/// Official Node builds; CLAY_NODE_MIRROR points downloads at a mirror instead
+const NODE_DIST: &str = "https://nodejs.org/dist";
+
+/// Files pinning a project's Node version, nearest directory first, tried in order
+const VERSION_FILES: [&str; 2] = [".node-version", ".nvmrc"];
+
+/// Copy of the release index kept next to the installed versions, so `lts/<name>` pins
+/// resolve without the network
+const INDEX_CACHE: &str = "index.json";
+
+/// A release as `index.json` lists it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NodeRelease {
+    /// `v20.11.0`
+    version: String,
+    /// The LTS codename, or false
+    #[serde(default)]
+    lts: Value,
+}
+
+impl NodeRelease {
+    fn version(&self) -> Option<Version> {
+        Version::parse(self.version.trim_start_matches('v')).ok()
+    }
+
+    fn lts(&self) -> Option<&str> {
+        self.lts.as_str()
+    }
+}
+
+/// Where the project's Node version is pinned, and to what
+pub struct Pin {
+    pub spec: String,
+    /// `.node-version`, `.nvmrc` or `engines.node`
+    pub source: String,
+}
+
+/// Where Node versions are installed: ~/.clay/node/<version>
+pub fn node_home() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow!("Could not find home directory"))?
+        .join(".clay")
+        .join("node"))
+}
+
+/// Installed Node versions, newest first
+pub fn installed() -> Vec<Version> {
+    let Ok(home) = node_home() else {
+        return Vec::new();
+    };
+    let mut versions: Vec<Version> = std::fs::read_dir(home)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().join("bin").is_dir())
+        .filter_map(|entry| Version::parse(&entry.file_name().to_string_lossy()).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+}
+
+/// The Node version pinned for the project in `project_dir`: a `.node-version` or `.nvmrc`
+/// in it or a directory above, else the `engines.node` range of its package.json
+pub fn pinned(project_dir: &Path) -> Option<Pin> {
+    for dir in project_dir.ancestors() {
+        for file in VERSION_FILES {
+            let Ok(content) = std::fs::read_to_string(dir.join(file)) else {
+                continue;
+            };
+            if let Some(spec) = content
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'))
+            {
+                return Some(Pin {
+                    spec: spec.to_string(),
+                    source: file.to_string(),
+                });
+            }
+        }
+    }
+
+    let content = std::fs::read_to_string(project_dir.join("package.json")).ok()?;
+    let package_json: Value = serde_json::from_str(&content).ok()?;
+    Some(Pin {
+        spec: package_json["engines"]["node"].as_str()?.to_string(),
+        source: "engines.node".to_string(),
+    })
+}
+
+/// The bin directory of the installed Node the project pins, for `clay run` and `clay
+/// exec` to put ahead of PATH. None leaves whichever node is on PATH: when nothing is
+/// pinned, or no installed version matches (which a version file, unlike a loose
+/// `engines` range, warns about).
+pub fn runtime_bin_dir(project_dir: &Path) -> Option<PathBuf> {
+    let pin = pinned(project_dir)?;
+    let lts = cached_releases();
+    let candidates: Vec<(Version, Option<String>)> = installed()
+        .into_iter()
+        .map(|version| {
+            let codename = lts
+                .iter()
+                .find(|release| release.version().as_ref() == Some(&version))
+                .and_then(|release| release.lts().map(str::to_lowercase));
+            (version, codename)
+        })
+        .collect();
+
+    match select(&pin.spec, &candidates) {
+        Ok(Some(version)) => Some(node_home().ok()?.join(version.to_string()).join("bin")),
+        _ => {
+            if pin.source != "engines.node" {
+                eprintln!(
+                    "{}",
+                    CliStyle::warning(&format!(
+                        "{} pins Node {} but no installed version matches; run clay node use",
+                        pin.source, pin.spec
+                    ))
+                );
+            }
+            None
+        }
+    }
+}
+
+/// The newest of `candidates` (version, LTS codename) matching `spec`: a version or range
+/// (`20`, `^20.11`, `v20.11.0`), `lts`, `lts/<codename>` or `latest`
+fn select(spec: &str, candidates: &[(Version, Option<String>)]) -> Result<Option<Version>> {
+    let spec = spec.trim().to_lowercase();
+    let range = match spec.as_str() {
+        "latest" | "current" | "node" | "lts" | "lts/*" => None,
+        alias if alias.starts_with("lts/") => None,
+        range => Some(
+            VersionRange::parse(range.trim_start_matches('v'))
+                .map_err(|_| anyhow!("'{spec}' isn't a Node version, range, lts or latest"))?,
+        ),
+    };
+    Ok(candidates
+        .iter()
+        .filter(|(version, lts)| match spec.as_str() {
+            "latest" | "current" | "node" => true,
+            "lts" | "lts/*" => lts.is_some(),
+            alias if alias.starts_with("lts/") => lts.as_deref() == Some(&alias["lts/".len()..]),
+            _ => range.as_ref().is_some_and(|range| range.matches(version)),
+        })
+        .map(|(version, _)| version)
+        .max()
+        .cloned())
+}
+
+fn dist_url() -> String {
+    std::env::var("CLAY_NODE_MIRROR")
+        .unwrap_or_else(|_| NODE_DIST.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Every published release, saving a copy for offline lookups
+async fn releases(npm_client: &NpmClient) -> Result<Vec<NodeRelease>> {
+    let bytes = download(npm_client, &format!("{}/index.json", dist_url())).await?;
+    let releases: Vec<NodeRelease> =
+        serde_json::from_slice(&bytes).context("Invalid Node release index")?;
+    let home = node_home()?;
+    std::fs::create_dir_all(&home)?;
+    std::fs::write(home.join(INDEX_CACHE), &bytes)?;
+    Ok(releases)
+}
+
+fn cached_releases() -> Vec<NodeRelease> {
+    node_home()
+        .ok()
+        .and_then(|home| std::fs::read(home.join(INDEX_CACHE)).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// `linux-x64` and the like, as Node names its builds
+fn platform() -> Result<String> {
+    let os = match std::env::consts::OS {
+        "linux" => "linux",
+        "macos" => "darwin",
+        "windows" => {
+            return Err(anyhow!(
+                "clay node can't install Node on Windows yet; install it from nodejs.org"
+            ));
+        }
+        other => return Err(anyhow!("Node publishes no builds for {other}")),
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        "arm" => "armv7l",
+        "powerpc64" => "ppc64le",
+        "s390x" => "s390x",
+        other => return Err(anyhow!("Node publishes no builds for {other}")),
+    };
+    Ok(format!("{os}-{arch}"))
+}
+
+/// Install the newest Node release matching `spec` into ~/.clay/node, unless it's there
+/// already. Returns the version.
+pub async fn install(npm_client: &NpmClient, spec: &str) -> Result<Version> {
+    let spinner = CliStyle::create_spinner("Looking up Node releases...");
+    let releases = releases(npm_client).await;
+    spinner.finish_and_clear();
+    let candidates: Vec<(Version, Option<String>)> = releases?
+        .iter()
+        .filter_map(|release| Some((release.version()?, release.lts().map(str::to_lowercase))))
+        .collect();
+    let version = select(spec, &candidates)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::NotFound,
+            format!("No Node release matches {spec}"),
+        )
+    })?;
+
+    let dir = node_home()?.join(version.to_string());
+    if dir.join("bin").is_dir() {
+        println!(
+            "{} Node {} is already installed",
+            CliStyle::info(""),
+            CliStyle::version(&version.to_string())
+        );
+        return Ok(version);
+    }
+
+    let archive = format!("node-v{version}-{}.tar.gz", platform()?);
+    let base = format!("{}/v{version}", dist_url());
+    let spinner = CliStyle::create_spinner(&format!("Downloading {archive}..."));
+    let downloaded = async {
+        let sums = download(npm_client, &format!("{base}/SHASUMS256.txt")).await?;
+        let tarball = download(npm_client, &format!("{base}/{archive}")).await?;
+        anyhow::Ok((sums, tarball))
+    }
+    .await;
+    spinner.finish_and_clear();
+    let (sums, tarball) = downloaded?;
+
+    let expected = String::from_utf8_lossy(&sums)
+        .lines()
+        .find_map(|line| {
+            let (hash, file) = line.split_once(char::is_whitespace)?;
+            (file.trim() == archive).then(|| hash.to_lowercase())
+        })
+        .ok_or_else(|| anyhow!("SHASUMS256.txt has no checksum for {archive}"))?;
+    let actual = format!("{:x}", Sha256::digest(&tarball));
+    if actual != expected {
+        return Err(errors::coded(
+            ErrorCode::Integrity,
+            format!(
+                "{archive} doesn't match its published checksum (expected {expected}, got {actual})"
+            ),
+        ));
+    }
+
+    // Unpack next to the final directory and move it in whole, so no half-extracted
+    // version ever looks installed
+    let staging = node_home()?.join(format!(".staging-{}", std::process::id()));
+    let _guard = CleanupGuard::new(&staging);
+    let unpacked = unpack(&tarball, &staging, &dir);
+    std::fs::remove_dir_all(&staging).ok();
+    unpacked?;
+
+    println!(
+        "{} Installed Node {}",
+        CliStyle::success(""),
+        CliStyle::version(&version.to_string())
+    );
+    Ok(version)
+}
+
+fn unpack(tarball: &[u8], staging: &Path, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(staging)?;
+    tar::Archive::new(flate2::read::GzDecoder::new(tarball))
+        .unpack(staging)
+        .context("Couldn't unpack the Node archive")?;
+    // The archive holds a single node-v<version>-<platform> directory
+    let root = std::fs::read_dir(staging)?
+        .flatten()
+        .find(|entry| entry.path().is_dir())
+        .ok_or_else(|| anyhow!("The Node archive is empty"))?
+        .path();
+    std::fs::rename(root, dir)?;
+    Ok(())
+}
+
+async fn download(npm_client: &NpmClient, url: &str) -> Result<Vec<u8>> {
+    let response = npm_client.client.get(url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(errors::http(
+            status,
+            format!("Couldn't download {url}: HTTP {status}"),
+        ));
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// `clay node use [version]`: install the given version and pin the project to it in
+/// `.node-version`, or without one, install whatever the project already pins
+pub async fn use_version(
+    npm_client: &NpmClient,
+    project_dir: &Path,
+    spec: Option<&str>,
+) -> Result<()> {
+    let Some(spec) = spec
+        .map(str::to_string)
+        .or_else(|| pinned(project_dir).map(|pin| pin.spec))
+    else {
+        return Err(anyhow!(
+            "This project pins no Node version; run clay node use <version>"
+        ));
+    };
+    let pin = !spec.is_empty() && pinned(project_dir).is_none_or(|pin| pin.spec != spec);
+    let version = install(npm_client, &spec).await?;
+
+    if pin {
+        std::fs::write(project_dir.join(".node-version"), format!("{version}\n"))?;
+        println!(
+            "{} This project now uses Node {} (.node-version)",
+            CliStyle::success(""),
+            CliStyle::version(&version.to_string())
+        );
+    }
+    Ok(())
+}
+
+/// `clay node list`: installed versions, marking the one the project runs with
+pub fn list(project_dir: &Path) {
+    let versions = installed();
+    if versions.is_empty() {
+        println!(
+            "{}",
+            CliStyle::info("No Node versions installed; run clay node install <version>")
+        );
+        return;
+    }
+    let current = runtime_bin_dir(project_dir)
+        .and_then(|bin| Some(bin.parent()?.file_name()?.to_string_lossy().to_string()));
+    for version in versions {
+        let version = version.to_string();
+        if current.as_deref() == Some(version.as_str()) {
+            println!(
+                "{} {} {}",
+                console::style("❯").cyan(),
+                CliStyle::version(&version),
+                CliStyle::dim_text("(this project)")
+            );
+        } else {
+            println!("  {version}");
+        }
+    }
+}
@@ -1,109 +1,962 @@
 use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use console::style;
-use reqwest::Client;
+use indicatif::ProgressBar;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue, RANGE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
+use sha2::Sha512;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
 
-use crate::package_info::{NpmRegistryResponse, PackageInfo};
+use crate::audit::Advisory;
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::errors::{self, ErrorCode};
+use crate::npmrc::Npmrc;
+use crate::package_info::{
+    DistInfo, NpmRegistryResponse, PackageDocument, PackageInfo, SearchResults,
+};
+use crate::progress::{self, Event};
+
+/// How many times a rate-limited request is retried before giving up
+const MAX_THROTTLE_RETRIES: u32 = 5;
+
+/// Upper bound on a single Retry-After pause, so a bogus header can't hang an install
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// How many times a tarball download cut off mid-stream is resumed within one run
+const MAX_RESUME_ATTEMPTS: u32 = 3;
 
 #[derive(Clone)]
 pub struct NpmClient {
     pub client: Client,
-    registry_url: String,
+    registries: Vec<String>,
+    scopes: BTreeMap<String, String>,
+    always_auth: bool,
+    npmrc: Arc<Npmrc>,
+    /// Shared across clones: once the registry throttles one request, all requests wait
+    throttled_until: Arc<Mutex<Option<Instant>>>,
+    progress: Arc<Mutex<Option<ProgressBar>>>,
+    transfer: Arc<TransferStats>,
+    metadata_cache_dir: PathBuf,
+    /// `network.offline`: every request fails before it is sent
+    offline: bool,
+    /// How long each metadata request took, by package, for `clay install --timing`
+    metadata_timings: Arc<Mutex<Vec<(String, Duration)>>>,
+}
+
+/// On-disk form of a per-version manifest, keeping the registry that served it
+#[derive(Serialize, Deserialize)]
+struct CachedVersion {
+    registry: String,
+    info: PackageInfo,
+}
+
+/// Aggregate tarball transfer since the progress bar was attached
+#[derive(Default)]
+struct TransferStats {
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+    started: Mutex<Option<Instant>>,
+}
+
+impl TransferStats {
+    fn reset(&self) {
+        self.bytes_done.store(0, Ordering::Relaxed);
+        self.bytes_total.store(0, Ordering::Relaxed);
+        if let Ok(mut started) = self.started.lock() {
+            *started = Some(Instant::now());
+        }
+    }
+
+    fn throughput(&self) -> f64 {
+        let elapsed = self
+            .started
+            .lock()
+            .ok()
+            .and_then(|started| *started)
+            .map_or(0.0, |started| started.elapsed().as_secs_f64());
+        if elapsed > 0.0 {
+            self.bytes_done.load(Ordering::Relaxed) as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
 }
 
 impl NpmClient {
     pub fn new() -> Self {
+        Self::with_config(&ClayConfig::default())
+    }
+
+    pub fn with_config(config: &ClayConfig) -> Self {
+        let network = &config.network;
+        let npmrc = Npmrc::load();
+
+        let user_agent = network
+            .user_agent
+            .clone()
+            .or_else(|| npmrc.get("user-agent").map(str::to_string))
+            .unwrap_or_else(|| format!("clay/{}", env!("CARGO_PKG_VERSION")));
+
+        // Tuned for many small metadata requests: HTTP/2 is negotiated via ALPN so
+        // HTTP/1.1-only registries (Verdaccio, some proxies) still work, and idle
+        // connections are kept for every concurrent request slot
         let client = Client::builder()
-            .pool_max_idle_per_host(50)
+            .user_agent(user_agent)
+            .default_headers(Self::extra_headers(&network.headers))
+            .pool_max_idle_per_host(network.concurrency().max(network.resolve_concurrency()))
             .pool_idle_timeout(Duration::from_secs(90))
-            .timeout(Duration::from_secs(30))
-            .connect_timeout(Duration::from_secs(10))
+            .timeout(network.timeout())
+            .connect_timeout(network.connect_timeout())
             .tcp_keepalive(Duration::from_secs(60))
-            .http2_prior_knowledge()
+            .tcp_nodelay(true)
             .http2_adaptive_window(true)
             .http2_max_frame_size(Some(32 * 1024))
+            .http2_keep_alive_interval(Duration::from_secs(30))
+            .http2_keep_alive_while_idle(true)
+            .gzip(true)
+            .brotli(true)
             .build()
-            .unwrap_or_else(|_| Client::new());
+            .expect("Failed to initialize the HTTP client");
 
         Self {
             client,
-            registry_url: "https://registry.npmjs.org".to_string(),
+            registries: config.registry.urls(),
+            scopes: config
+                .registry
+                .scopes
+                .iter()
+                .map(|(scope, url)| (scope.clone(), url.trim_end_matches('/').to_string()))
+                .collect(),
+            always_auth: config.registry.always_auth,
+            npmrc: Arc::new(npmrc),
+            throttled_until: Arc::new(Mutex::new(None)),
+            progress: Arc::new(Mutex::new(None)),
+            transfer: Arc::new(TransferStats::default()),
+            metadata_cache_dir: dirs::home_dir()
+                .map(|home| home.join(".clay").join("cache"))
+                .unwrap_or_else(|| PathBuf::from(".clay-cache"))
+                .join("metadata"),
+            offline: network.offline,
+            metadata_timings: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Configured extra headers; invalid ones are reported and skipped rather than
+    /// failing every request
+    fn extra_headers(headers: &BTreeMap<String, String>) -> HeaderMap {
+        let mut header_map = HeaderMap::new();
+
+        for (name, value) in headers {
+            let value = Npmrc::expand_env(value);
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    header_map.insert(name, value);
+                }
+                _ => eprintln!(
+                    "{} Ignoring invalid header '{}' in clay.toml",
+                    style("!").yellow().bold(),
+                    name
+                ),
+            }
+        }
+
+        header_map
+    }
+
+    /// Route throttling notices and download progress to the given progress bar
+    pub fn attach_progress(&self, progress_bar: &ProgressBar) {
+        if let Ok(mut progress) = self.progress.lock() {
+            *progress = Some(progress_bar.clone());
+        }
+        self.transfer.reset();
+    }
+
+    fn attached_progress(&self) -> Option<ProgressBar> {
+        self.progress
+            .lock()
+            .ok()
+            .and_then(|progress| progress.clone())
+    }
+
+    /// pnpm-style line: this package's bytes, then all bytes and throughput so far
+    fn report_download(
+        &self,
+        progress_bar: &ProgressBar,
+        name: &str,
+        done: u64,
+        total: Option<u64>,
+    ) {
+        let package_bytes = match total {
+            Some(total) => format!(
+                "{}/{}",
+                CliStyle::format_size(done),
+                CliStyle::format_size(total)
+            ),
+            None => CliStyle::format_size(done),
+        };
+
+        progress_bar.set_message(format!(
+            "downloading {name} {package_bytes} {}",
+            CliStyle::dim_text(&format!(
+                "(total {}/{}, {}/s)",
+                CliStyle::format_size(self.transfer.bytes_done.load(Ordering::Relaxed)),
+                CliStyle::format_size(self.transfer.bytes_total.load(Ordering::Relaxed)),
+                CliStyle::format_size(self.transfer.throughput() as u64)
+            ))
+        ));
+    }
+
+    /// Metadata requests made so far and how long each took
+    pub fn metadata_timings(&self) -> Vec<(String, Duration)> {
+        self.metadata_timings
+            .lock()
+            .map(|timings| timings.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn detach_progress(&self) {
+        if let Ok(mut progress) = self.progress.lock() {
+            *progress = None;
+        }
+    }
+
+    /// Registries to query for a package. Scoped packages go only to their scope's
+    /// registry when one is configured (clay.toml first, then .npmrc), as npm does.
+    pub fn registries_for(&self, package_name: &str) -> Vec<String> {
+        if let Some((scope, _)) = package_name.split_once('/')
+            && scope.starts_with('@')
+        {
+            if let Some(registry) = self.scopes.get(scope) {
+                return vec![registry.clone()];
+            }
+            if let Some(registry) = self.npmrc.scope_registry(scope) {
+                return vec![registry.trim_end_matches('/').to_string()];
+            }
+        }
+        self.registries.clone()
+    }
+
+    /// The registry a package is fetched from first
+    pub fn registry_for(&self, package_name: &str) -> String {
+        self.registries_for(package_name).swap_remove(0)
+    }
+
+    /// Attach .npmrc credentials matching the request URL. With always-auth, requests to
+    /// other hosts (e.g. tarball CDNs) reuse the credentials of `registry`.
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        url: &str,
+        registry: Option<&str>,
+    ) -> RequestBuilder {
+        let credentials = self.npmrc.credentials(url).or_else(|| {
+            registry
+                .filter(|registry| self.always_auth || self.npmrc.always_auth(registry))
+                .and_then(|registry| self.npmrc.credentials(registry))
+        });
+
+        match credentials {
+            Some(credentials) => request.header(AUTHORIZATION, credentials.header_value()),
+            None => request,
         }
     }
 
-    /// Fetch package information from NPM registry
+    /// Send a request, pausing and retrying when the registry answers 429 Too Many Requests
+    async fn send_with_throttle<F>(&self, build_request: F) -> Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        if self.offline {
+            return Err(errors::coded(
+                ErrorCode::Network,
+                "clay is offline (network.offline), and this needs the registry",
+            ));
+        }
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_throttle().await;
+
+            let started = Instant::now();
+            let response = build_request()
+                .send()
+                .await
+                .inspect_err(|e| tracing::debug!("request failed: {e}"))?;
+            tracing::debug!(
+                status = %response.status(),
+                elapsed = ?started.elapsed(),
+                "{}",
+                response.url()
+            );
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_THROTTLE_RETRIES
+            {
+                return Ok(response);
+            }
+
+            let delay = Self::parse_retry_after(&response)
+                .unwrap_or_else(|| Duration::from_secs(1 << attempt))
+                .min(MAX_RETRY_AFTER);
+            tracing::info!("{} is rate limited; waiting {delay:?}", response.url());
+            self.throttle_for(delay);
+            attempt += 1;
+        }
+    }
+
+    async fn wait_for_throttle(&self) {
+        let until = self.throttled_until.lock().ok().and_then(|until| *until);
+        if let Some(until) = until
+            && until > Instant::now()
+        {
+            tokio::time::sleep_until(until).await;
+        }
+    }
+
+    fn throttle_for(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        if let Ok(mut throttled_until) = self.throttled_until.lock() {
+            // Never shorten a pause another request already scheduled
+            if throttled_until.is_none_or(|current| current < until) {
+                *throttled_until = Some(until);
+            }
+        }
+
+        let notice = format!(
+            "throttled by registry, resuming in {}s...",
+            delay.as_secs().max(1)
+        );
+        match self.attached_progress() {
+            Some(progress_bar) => progress_bar.set_message(notice),
+            None => eprintln!("{} {}", style("!").yellow().bold(), notice),
+        }
+    }
+
+    /// Retry-After is either a number of seconds or an HTTP date
+    fn parse_retry_after(response: &Response) -> Option<Duration> {
+        let value = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)?
+            .to_str()
+            .ok()?
+            .trim();
+
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+
+        let retry_at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        let wait = retry_at.with_timezone(&chrono::Utc) - chrono::Utc::now();
+        Some(wait.to_std().unwrap_or_default())
+    }
+
+    /// Fetch package information, trying each configured registry in order
     pub async fn get_package_info(&self, package_name: &str) -> Result<NpmRegistryResponse> {
-        let url = format!("{}/{}", self.registry_url, package_name);
+        let (registry, mut package_info) = self
+            .fetch_from_registries::<NpmRegistryResponse>(
+                package_name,
+                "",
+                "application/vnd.npm.install-v1+json",
+            )
+            .await?;
+        package_info.set_registry(&registry);
+        Ok(package_info)
+    }
+
+    /// Fetch the manifest of one exact version from `/<pkg>/<version>` instead of the
+    /// whole packument. Published versions are immutable, so they are cached on disk.
+    pub async fn get_version_info(&self, package_name: &str, version: &str) -> Result<PackageInfo> {
+        let cache_path = self
+            .metadata_cache_dir
+            .join(Self::encode_package_name(package_name))
+            .join(format!("{version}.json"));
+
+        if let Ok(content) = fs::read_to_string(&cache_path).await
+            && let Ok(cached) = serde_json::from_str::<CachedVersion>(&content)
+        {
+            let mut package_info = cached.info;
+            package_info.registry = Some(cached.registry);
+            return Ok(package_info);
+        }
+
+        let (registry, mut package_info) = self
+            .fetch_from_registries::<PackageInfo>(
+                package_name,
+                &format!("/{version}"),
+                "application/json",
+            )
+            .await?;
+
+        if let Some(parent) = cache_path.parent()
+            && fs::create_dir_all(parent).await.is_ok()
+        {
+            let cached = CachedVersion {
+                registry: registry.clone(),
+                info: package_info.clone(),
+            };
+            if let Ok(content) = serde_json::to_string(&cached) {
+                fs::write(&cache_path, content).await.ok();
+            }
+        }
+
+        package_info.registry = Some(registry);
+        Ok(package_info)
+    }
+
+    /// Fetch the full registry document (maintainers, homepage, publish times, ...)
+    pub async fn get_package_document(&self, package_name: &str) -> Result<PackageDocument> {
+        let (_, document) = self
+            .fetch_from_registries(package_name, "", "application/json")
+            .await?;
+        Ok(document)
+    }
+
+    /// Try each configured registry in order, returning the first document and its registry.
+    /// `path` is appended after the package name (e.g. `/1.2.3`).
+    #[tracing::instrument(name = "metadata", skip_all, fields(name = package_name))]
+    async fn fetch_from_registries<T: DeserializeOwned>(
+        &self,
+        package_name: &str,
+        path: &str,
+        accept: &str,
+    ) -> Result<(String, T)> {
+        let started = Instant::now();
+        let result = self
+            .fetch_from_any_registry(package_name, path, accept)
+            .await;
+        if let Ok(mut timings) = self.metadata_timings.lock() {
+            timings.push((package_name.to_string(), started.elapsed()));
+        }
+        result
+    }
+
+    async fn fetch_from_any_registry<T: DeserializeOwned>(
+        &self,
+        package_name: &str,
+        path: &str,
+        accept: &str,
+    ) -> Result<(String, T)> {
+        let mut last_error = None;
+
+        for registry in &self.registries_for(package_name) {
+            match self
+                .fetch_package_info(registry, package_name, path, accept)
+                .await
+            {
+                Ok(document) => return Ok((registry.clone(), document)),
+                Err(FetchError::Fallback(e)) => {
+                    tracing::info!("{registry} couldn't serve {package_name}: {e:#}");
+                    last_error = Some(e);
+                }
+                Err(FetchError::Fatal(e)) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No registries configured")))
+    }
+
+    async fn fetch_package_info<T: DeserializeOwned>(
+        &self,
+        registry: &str,
+        package_name: &str,
+        path: &str,
+        accept: &str,
+    ) -> std::result::Result<T, FetchError> {
+        let url = format!(
+            "{registry}/{}{path}",
+            Self::encode_package_name(package_name)
+        );
+
+        let response = self
+            .send_with_throttle(|| {
+                self.authorize(self.client.get(&url), &url, None)
+                    .header("Accept", accept)
+            })
+            .await
+            .map_err(FetchError::from_send)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(FetchError::from_status(
+                status,
+                errors::http(
+                    status,
+                    format!("Failed to fetch package info from {registry}: HTTP {status}"),
+                ),
+            ));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| FetchError::Fatal(e.into()))
+    }
+
+    /// Query the bulk advisories endpoint with every installed version of each package
+    pub async fn get_bulk_advisories(
+        &self,
+        packages: &BTreeMap<String, Vec<String>>,
+    ) -> Result<HashMap<String, Vec<Advisory>>> {
+        let mut last_error = None;
+
+        for registry in &self.registries {
+            let url = format!("{registry}/-/npm/v1/security/advisories/bulk");
+
+            let response = match self
+                .send_with_throttle(|| {
+                    self.authorize(self.client.post(&url), &url, None)
+                        .json(packages)
+                })
+                .await
+                .map_err(FetchError::from_send)
+            {
+                Ok(response) => response,
+                Err(FetchError::Fallback(e)) => {
+                    last_error = Some(e);
+                    continue;
+                }
+                Err(FetchError::Fatal(e)) => return Err(e),
+            };
+
+            let status = response.status();
+            if !status.is_success() {
+                match FetchError::from_status(
+                    status,
+                    errors::http(
+                        status,
+                        format!("Failed to fetch advisories from {registry}: HTTP {status}"),
+                    ),
+                ) {
+                    FetchError::Fallback(e) => {
+                        last_error = Some(e);
+                        continue;
+                    }
+                    FetchError::Fatal(e) => return Err(e),
+                }
+            }
+
+            return Ok(response.json().await?);
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No registries configured")))
+    }
+
+    /// Registry for commands not tied to a package, like `clay login`
+    pub fn default_registry(&self) -> &str {
+        &self.registries[0]
+    }
+
+    /// Configured fallback registries, in order
+    pub fn registries(&self) -> &[String] {
+        &self.registries
+    }
+
+    /// Scope to registry mappings from clay.toml and .npmrc, clay.toml winning
+    pub fn scope_registries(&self) -> BTreeMap<String, String> {
+        let mut scopes: BTreeMap<String, String> = self
+            .npmrc
+            .scope_registries()
+            .into_iter()
+            .map(|(scope, url)| (scope, url.trim_end_matches('/').to_string()))
+            .collect();
+        scopes.extend(self.scopes.clone());
+        scopes
+    }
+
+    pub fn npmrc(&self) -> &Npmrc {
+        &self.npmrc
+    }
+
+    /// Hit the registry's ping endpoint, returning the round-trip time
+    pub async fn ping(&self, registry: &str) -> Result<Duration> {
+        let url = format!("{registry}/-/ping");
+        let started = Instant::now();
 
         let response = self
-            .client
-            .get(&url)
-            .header("Accept", "application/vnd.npm.install-v1+json")
+            .authorize(self.client.get(&url), &url, None)
             .send()
             .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("HTTP {}", response.status()));
+        }
+
+        Ok(started.elapsed())
+    }
 
+    /// Packages matching `text` on the default registry, best matches first
+    pub async fn search(&self, text: &str, size: usize) -> Result<SearchResults> {
+        let registry = self.default_registry();
+        let url = format!("{registry}/-/v1/search");
+        let response = self
+            .send_with_throttle(|| {
+                self.authorize(self.client.get(&url), &url, None)
+                    .query(&[("text", text), ("size", &size.to_string())])
+            })
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(errors::http(
+                status,
+                format!("Search on {registry} failed: HTTP {status}"),
+            ));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Username the registry associates with our credentials, or None without credentials
+    pub async fn whoami(&self, registry: &str) -> Result<Option<String>> {
+        if self.npmrc.credentials(registry).is_none() {
+            return Ok(None);
+        }
+
+        let url = format!("{registry}/-/whoami");
+        let response = self
+            .authorize(self.client.get(&url), &url, None)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("credentials rejected (HTTP {})", response.status()));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        Ok(Some(
+            body.get("username")
+                .and_then(|username| username.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+        ))
+    }
+
+    /// Registry that receives writes for a package: its scope's registry, else the first
+    fn write_registry(&self, package_name: &str) -> String {
+        self.registries_for(package_name).swap_remove(0)
+    }
+
+    /// Scoped names keep the `@` but encode the slash, which every npm-compatible
+    /// registry accepts and GitHub Packages requires
+    fn encode_package_name(package_name: &str) -> String {
+        package_name.replace('/', "%2f")
+    }
+
+    fn write_credentials(&self, registry: &str) -> Result<String> {
+        self.npmrc.credentials(registry).map(|credentials| credentials.header_value()).ok_or_else(|| {
+            anyhow!(
+                "Not logged in to {registry}. Run `clay login` or add //{}/:_authToken to your .npmrc",
+                registry
+                    .split_once("://")
+                    .map_or(registry, |(_, rest)| rest)
+            )
+        })
+    }
+
+    /// Fetch the dist-tags of a package from its write registry
+    pub async fn get_dist_tags(&self, package_name: &str) -> Result<BTreeMap<String, String>> {
+        let registry = self.write_registry(package_name);
+        let url = format!(
+            "{}/-/package/{}/dist-tags",
+            registry,
+            Self::encode_package_name(package_name)
+        );
+
+        let response = self
+            .send_with_throttle(|| self.authorize(self.client.get(&url), &url, None))
+            .await?;
         if !response.status().is_success() {
             return Err(anyhow!(
-                "Failed to fetch package info: HTTP {}",
+                "Failed to fetch dist-tags for {package_name}: HTTP {}",
                 response.status()
             ));
         }
 
-        let package_info: NpmRegistryResponse = response.json().await?;
-        Ok(package_info)
+        Ok(response.json().await?)
     }
 
-    /// Download package tarball to specified path
-    pub async fn download_package(
+    /// Point a dist-tag at a version on the write registry
+    pub async fn set_dist_tag(&self, package_name: &str, tag: &str, version: &str) -> Result<()> {
+        let registry = self.write_registry(package_name);
+        let authorization = self.write_credentials(&registry)?;
+        let url = format!(
+            "{}/-/package/{}/dist-tags/{tag}",
+            registry,
+            Self::encode_package_name(package_name)
+        );
+
+        let response = self
+            .send_with_throttle(|| {
+                self.client
+                    .put(&url)
+                    .header(AUTHORIZATION, &authorization)
+                    .json(version)
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to set dist-tag {tag} on {package_name}: HTTP {}",
+                response.status()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Remove a dist-tag from the write registry
+    pub async fn remove_dist_tag(&self, package_name: &str, tag: &str) -> Result<()> {
+        let registry = self.write_registry(package_name);
+        let authorization = self.write_credentials(&registry)?;
+        let url = format!(
+            "{}/-/package/{}/dist-tags/{tag}",
+            registry,
+            Self::encode_package_name(package_name)
+        );
+
+        let response = self
+            .send_with_throttle(|| {
+                self.client
+                    .delete(&url)
+                    .header(AUTHORIZATION, &authorization)
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to remove dist-tag {tag} from {package_name}: HTTP {}",
+                response.status()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the full, writable packument from the write registry
+    pub async fn get_packument_for_write(&self, package_name: &str) -> Result<serde_json::Value> {
+        let registry = self.write_registry(package_name);
+        let authorization = self.write_credentials(&registry)?;
+        let url = format!(
+            "{}/{}?write=true",
+            registry,
+            Self::encode_package_name(package_name)
+        );
+
+        let response = self
+            .send_with_throttle(|| self.client.get(&url).header(AUTHORIZATION, &authorization))
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to fetch {package_name} for update: HTTP {}",
+                response.status()
+            ));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Replace a packument on the write registry; the document must carry its `_rev`
+    pub async fn put_packument(
         &self,
-        package_info: &PackageInfo,
-        dest_path: &Path,
+        package_name: &str,
+        packument: &serde_json::Value,
     ) -> Result<()> {
-        // Ensure we have an absolute URL for the tarball
-        let tarball_url = if package_info.dist.tarball.starts_with("http") {
+        let registry = self.write_registry(package_name);
+        let authorization = self.write_credentials(&registry)?;
+        let url = format!("{}/{}", registry, Self::encode_package_name(package_name));
+
+        let response = self
+            .send_with_throttle(|| {
+                self.client
+                    .put(&url)
+                    .header(AUTHORIZATION, &authorization)
+                    .json(packument)
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to update {package_name}: HTTP {}",
+                response.status()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Create or log in a registry user with the couchdb-style PUT Verdaccio and npm
+    /// registries accept, returning the issued token
+    pub async fn login(
+        &self,
+        registry: &str,
+        username: &str,
+        password: &str,
+        email: &str,
+    ) -> Result<String> {
+        let url = format!("{registry}/-/user/org.couchdb.user:{username}");
+        let body = serde_json::json!({
+            "_id": format!("org.couchdb.user:{username}"),
+            "name": username,
+            "password": password,
+            "email": email,
+            "type": "user",
+            "roles": [],
+            "date": chrono::Utc::now().to_rfc3339(),
+        });
+
+        let response = self
+            .send_with_throttle(|| {
+                self.client
+                    .put(&url)
+                    .basic_auth(username, Some(password))
+                    .json(&body)
+            })
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response
+                .json::<serde_json::Value>()
+                .await
+                .ok()
+                .and_then(|body| {
+                    body.get("error")
+                        .or_else(|| body.get("reason"))
+                        .and_then(|error| error.as_str())
+                        .map(str::to_string)
+                })
+                .unwrap_or_else(|| format!("HTTP {status}"));
+            return Err(anyhow!("Login to {registry} failed: {detail}"));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        body.get("token")
+            .and_then(|token| token.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{registry} accepted the login but returned no token"))
+    }
+
+    /// Absolute URL of a package's tarball; relative ones are on the registry that served it
+    fn tarball_url(&self, package_info: &PackageInfo) -> String {
+        if package_info.dist.tarball.starts_with("http") {
             package_info.dist.tarball.clone()
         } else {
-            // If it's a relative URL, construct it with the npm registry base
             format!(
-                "https://registry.npmjs.org{}",
+                "{}{}",
+                package_info
+                    .registry
+                    .as_deref()
+                    .unwrap_or(&self.registries[0]),
                 if package_info.dist.tarball.starts_with('/') {
                     package_info.dist.tarball.clone()
                 } else {
                     format!("/{}", package_info.dist.tarball)
                 }
             )
-        };
-
-        let response = self.client.get(&tarball_url).send().await?;
+        }
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "Failed to download package: HTTP {}",
-                response.status()
+    /// Size in bytes of a package's tarball, from a HEAD request, without downloading it
+    pub async fn tarball_size(&self, package_info: &PackageInfo) -> Result<Option<u64>> {
+        let tarball_url = self.tarball_url(package_info);
+        let response = self
+            .send_with_throttle(|| {
+                self.authorize(
+                    self.client.head(&tarball_url),
+                    &tarball_url,
+                    package_info.registry.as_deref(),
+                )
+            })
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(errors::http(
+                status,
+                format!("Failed to size package: HTTP {status}"),
             ));
         }
+        // Response::content_length is the body's, which a HEAD response doesn't have
+        Ok(response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse().ok()))
+    }
+
+    /// Download package tarball to specified path. Bytes are appended to `partial_path` as
+    /// they arrive, so a download that breaks off resumes from there (with an HTTP Range
+    /// request) instead of starting over, both within this run and on the next one.
+    pub async fn download_package(
+        &self,
+        package_info: &PackageInfo,
+        dest_path: &Path,
+        partial_path: &Path,
+    ) -> Result<()> {
+        let tarball_url = self.tarball_url(package_info);
 
-        // Ensure the parent directory exists
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent).await?;
+        // Ensure the parent directories exist
+        for dir in [dest_path.parent(), partial_path.parent()]
+            .into_iter()
+            .flatten()
+        {
+            fs::create_dir_all(dir).await?;
         }
 
-        // Download and save the tarball
-        let bytes = response.bytes().await?;
+        let had_partial = fs::metadata(partial_path)
+            .await
+            .is_ok_and(|metadata| metadata.len() > 0);
+        let mut attempt = 0;
+        let mut bytes = loop {
+            match self
+                .download_to_partial(package_info, &tarball_url, partial_path)
+                .await
+            {
+                Ok(()) => break fs::read(partial_path).await?,
+                Err(e) if attempt < MAX_RESUME_ATTEMPTS && Self::is_interrupted_transfer(&e) => {
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        fs::remove_file(partial_path).await.ok();
+
+        // Resumed bytes may not belong to the tarball the registry serves now; fetch it
+        // whole once before treating it as corrupted
+        if (had_partial || attempt > 0)
+            && !self.verify_package_integrity(&bytes, &package_info.dist)?
+        {
+            self.download_to_partial(package_info, &tarball_url, partial_path)
+                .await?;
+            bytes = fs::read(partial_path).await?;
+            fs::remove_file(partial_path).await.ok();
+        }
 
         // Verify integrity
-        if !self.verify_package_integrity(&bytes, &package_info.dist.shasum)? {
+        if !self.verify_package_integrity(&bytes, &package_info.dist)? {
             // Skip verification for circular dependency stubs
             if package_info.name == "circular" {
                 // Don't save circular dependency files
                 return Ok(());
             }
 
+            // Nobody to ask whether to keep a corrupted download
+            if !CliStyle::is_interactive() {
+                return Err(errors::coded(
+                    ErrorCode::Integrity,
+                    format!(
+                        "Package integrity verification failed for {}@{} (expected {})",
+                        package_info.name, package_info.version, package_info.dist.shasum
+                    ),
+                ));
+            }
+
             println!(
                 "\n{} Package integrity verification failed for {}",
                 style("⚠").yellow(),
@@ -119,9 +972,12 @@ impl NpmClient {
 
             let response = input.trim().to_lowercase();
             if response != "y" && response != "yes" {
-                return Err(anyhow!(
-                    "Package integrity verification failed for {}. Installation aborted by user.",
-                    package_info.name
+                return Err(errors::coded(
+                    ErrorCode::Integrity,
+                    format!(
+                        "Package integrity verification failed for {}. Installation aborted by user.",
+                        package_info.name
+                    ),
                 ));
             }
 
@@ -137,12 +993,116 @@ impl NpmClient {
         Ok(())
     }
 
-    /// Verify package integrity using shasum
-    pub fn verify_package_integrity(
+    /// Fetch the rest of a tarball into `partial_path`, asking only for the bytes it
+    /// doesn't already hold. Servers that ignore the Range header send the whole file,
+    /// which replaces the partial.
+    async fn download_to_partial(
         &self,
-        file_data: &[u8],
-        expected_shasum: &str,
-    ) -> Result<bool> {
+        package_info: &PackageInfo,
+        tarball_url: &str,
+        partial_path: &Path,
+    ) -> Result<()> {
+        let offset = fs::metadata(partial_path)
+            .await
+            .map_or(0, |metadata| metadata.len());
+
+        let mut response = self
+            .send_with_throttle(|| {
+                let request = self.authorize(
+                    self.client.get(tarball_url),
+                    tarball_url,
+                    package_info.registry.as_deref(),
+                );
+                if offset > 0 {
+                    request.header(RANGE, format!("bytes={offset}-"))
+                } else {
+                    request
+                }
+            })
+            .await?;
+
+        // The partial already holds everything (or more than) the server has
+        if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            fs::remove_file(partial_path).await.ok();
+            return Box::pin(self.download_to_partial(package_info, tarball_url, partial_path))
+                .await;
+        }
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(errors::http(
+                status,
+                format!("Failed to download package: HTTP {status}"),
+            ));
+        }
+
+        let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut file = if resumed {
+            fs::OpenOptions::new()
+                .append(true)
+                .open(partial_path)
+                .await?
+        } else {
+            fs::File::create(partial_path).await?
+        };
+        let mut done = if resumed { offset } else { 0 };
+
+        // Stream the tarball, reporting per-package and aggregate progress
+        let content_length = response.content_length();
+        if let Some(length) = content_length {
+            self.transfer
+                .bytes_total
+                .fetch_add(length, Ordering::Relaxed);
+        }
+        let total = content_length.map(|length| done + length);
+
+        let progress_bar = self.attached_progress();
+        let mut last_event: Option<Instant> = None;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            done += chunk.len() as u64;
+            self.transfer
+                .bytes_done
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            if let Some(ref progress_bar) = progress_bar {
+                self.report_download(progress_bar, &package_info.name, done, total);
+            }
+            if last_event.is_none_or(|last| last.elapsed() >= progress::DOWNLOAD_PROGRESS_INTERVAL)
+                || Some(done) == total
+            {
+                progress::emit(Event::DownloadProgress {
+                    name: &package_info.name,
+                    version: &package_info.version,
+                    bytes: done,
+                    total,
+                });
+                last_event = Some(Instant::now());
+            }
+        }
+
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Whether a download failed because the connection dropped mid-transfer, which
+    /// resuming can get past
+    fn is_interrupted_transfer(error: &anyhow::Error) -> bool {
+        error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|err| err.is_body() || err.is_decode() || err.is_timeout())
+    }
+
+    /// Verify a tarball against the registry's sha512 integrity, or its sha1 shasum when
+    /// the registry doesn't publish one
+    pub fn verify_package_integrity(&self, file_data: &[u8], dist: &DistInfo) -> Result<bool> {
+        if let Some(expected) = dist
+            .integrity
+            .as_deref()
+            .filter(|integrity| integrity.starts_with("sha512-"))
+        {
+            return Ok(Self::integrity_of(file_data) == expected);
+        }
+
         // Compute SHA1 hash of the downloaded data
         let mut hasher = Sha1::new();
         hasher.update(file_data);
@@ -150,10 +1110,38 @@ impl NpmClient {
         let computed_hash_hex = format!("{computed_hash:x}");
 
         // Compare with expected hash
-        let matches = computed_hash_hex == expected_shasum;
+        let matches = computed_hash_hex == dist.shasum;
 
         Ok(matches)
     }
+
+    /// sha512 subresource-integrity string for tarball data, as recorded in clay-lock
+    pub fn integrity_of(file_data: &[u8]) -> String {
+        format!("sha512-{}", BASE64.encode(Sha512::digest(file_data)))
+    }
+}
+
+/// Whether a failed registry request should move on to the next configured registry
+enum FetchError {
+    Fallback(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl FetchError {
+    fn from_send(error: anyhow::Error) -> Self {
+        match error.downcast_ref::<reqwest::Error>() {
+            Some(err) if err.is_timeout() || err.is_connect() => FetchError::Fallback(error),
+            _ => FetchError::Fatal(error),
+        }
+    }
+
+    fn from_status(status: StatusCode, error: anyhow::Error) -> Self {
+        if status == StatusCode::NOT_FOUND || status.is_server_error() {
+            FetchError::Fallback(error)
+        } else {
+            FetchError::Fatal(error)
+        }
+    }
 }
 
 impl Default for NpmClient {
@@ -161,3 +1149,100 @@ impl Default for NpmClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(config: ClayConfig, npmrc: &str) -> NpmClient {
+        let mut client = NpmClient::with_config(&config);
+        client.npmrc = Arc::new(Npmrc::parse(npmrc));
+        client
+    }
+
+    fn authorization(client: &NpmClient, url: &str, registry: Option<&str>) -> Option<String> {
+        client
+            .authorize(client.client.get(url), url, registry)
+            .build()
+            .unwrap()
+            .headers()
+            .get(AUTHORIZATION)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn scoped_packages_use_their_registry() {
+        let mut config = ClayConfig::default();
+        config.registry.scopes.insert(
+            "@acme".to_string(),
+            "https://npm.pkg.github.com/".to_string(),
+        );
+        let client = client(
+            config,
+            "@tools:registry=https://nexus.acme.com/repository/npm/\n",
+        );
+
+        assert_eq!(
+            client.registries_for("@acme/widgets"),
+            ["https://npm.pkg.github.com"]
+        );
+        assert_eq!(
+            client.registries_for("@tools/cli"),
+            ["https://nexus.acme.com/repository/npm"]
+        );
+        assert_eq!(
+            client.registries_for("left-pad"),
+            ClayConfig::default().registry.urls()
+        );
+    }
+
+    #[test]
+    fn scoped_names_encode_the_slash() {
+        assert_eq!(
+            NpmClient::encode_package_name("@acme/widgets"),
+            "@acme%2fwidgets"
+        );
+        assert_eq!(NpmClient::encode_package_name("left-pad"), "left-pad");
+    }
+
+    #[test]
+    fn metadata_requests_carry_registry_credentials() {
+        let client = client(
+            ClayConfig::default(),
+            "//npm.pkg.github.com/:_authToken=ghp_secret\n",
+        );
+        assert_eq!(
+            authorization(&client, "https://npm.pkg.github.com/@acme%2fwidgets", None),
+            Some("Bearer ghp_secret".to_string())
+        );
+        assert_eq!(
+            authorization(&client, "https://registry.npmjs.org/left-pad", None),
+            None
+        );
+    }
+
+    #[test]
+    fn always_auth_sends_credentials_to_tarball_hosts() {
+        let npmrc = "//npm.pkg.github.com/:_authToken=ghp_secret\n";
+        let tarball = "https://pkg-containers.githubusercontent.com/npm/widgets-1.0.0.tgz";
+        let registry = Some("https://npm.pkg.github.com");
+
+        assert_eq!(
+            authorization(&client(ClayConfig::default(), npmrc), tarball, registry),
+            None
+        );
+
+        let mut config = ClayConfig::default();
+        config.registry.always_auth = true;
+        assert_eq!(
+            authorization(&client(config, npmrc), tarball, registry),
+            Some("Bearer ghp_secret".to_string())
+        );
+
+        let npmrc = format!("{npmrc}//npm.pkg.github.com/:always-auth=true\n");
+        assert_eq!(
+            authorization(&client(ClayConfig::default(), &npmrc), tarball, registry),
+            Some("Bearer ghp_secret".to_string())
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Registry credentials, as an Authorization header
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    Token(String),
+    /// Base64 of `username:password`
+    Basic(String),
+}
+
+impl Credentials {
+    pub fn header_value(&self) -> String {
+        match self {
+            Credentials::Token(token) => format!("Bearer {token}"),
+            Credentials::Basic(auth) => format!("Basic {auth}"),
+        }
+    }
+}
+
+/// Settings read from the user's and project's .npmrc files, project values winning
+#[derive(Debug, Clone, Default)]
+pub struct Npmrc {
+    entries: HashMap<String, String>,
+}
+
+impl Npmrc {
+    /// Load ~/.npmrc, then ./.npmrc on top of it
+    pub fn load() -> Self {
+        let mut npmrc = Self::default();
+        for path in Self::search_paths() {
+            if let Ok(content) = std::fs::read_to_string(&path) {
+                npmrc.merge(&content);
+            }
+        }
+        npmrc
+    }
+
+    fn search_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(path) = Self::user_path() {
+            paths.push(path);
+        }
+        paths.push(Path::new(".npmrc").to_path_buf());
+        paths
+    }
+
+    fn user_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".npmrc"))
+    }
+
+    /// `//host/path/` key prefix for a registry URL
+    pub fn nerf_dart(registry: &str) -> String {
+        let without_scheme = registry
+            .split_once("://")
+            .map_or(registry, |(_, rest)| rest)
+            .trim_end_matches('/');
+        format!("//{without_scheme}/")
+    }
+
+    /// Settings from one .npmrc's `content` alone
+    #[cfg(test)]
+    pub fn parse(content: &str) -> Self {
+        let mut npmrc = Self::default();
+        npmrc.merge(content);
+        npmrc
+    }
+
+    fn merge(&mut self, content: &str) {
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().trim_matches('"');
+                self.entries
+                    .insert(key.trim().to_string(), Self::expand_env(value));
+            }
+        }
+    }
+
+    /// Replace `${VAR}` references with environment values, as npm does
+    pub fn expand_env(value: &str) -> String {
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${") {
+            expanded.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let name = &rest[start + 2..start + end];
+            expanded.push_str(&std::env::var(name).unwrap_or_default());
+            rest = &rest[start + end + 1..];
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Look up a per-registry setting (`//host/path/:key`), trying the registry path and
+    /// each of its parents
+    pub fn registry_setting(&self, registry: &str, key: &str) -> Option<&str> {
+        let mut scope = Self::nerf_dart(registry);
+
+        loop {
+            if let Some(value) = self.get(&format!("{scope}:{key}")) {
+                return Some(value);
+            }
+
+            let trimmed = scope.trim_end_matches('/');
+            match trimmed.rfind('/') {
+                Some(pos) if pos > 1 => scope = trimmed[..=pos].to_string(),
+                _ => return None,
+            }
+        }
+    }
+
+    pub fn auth_token(&self, registry: &str) -> Option<&str> {
+        self.registry_setting(registry, "_authToken")
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Credentials for a URL: `_authToken`, then `_auth`, then `username` + `_password`
+    /// (the password base64-encoded, as npm stores it)
+    pub fn credentials(&self, url: &str) -> Option<Credentials> {
+        if let Some(token) = self.auth_token(url) {
+            return Some(Credentials::Token(token.to_string()));
+        }
+
+        if let Some(auth) = self
+            .registry_setting(url, "_auth")
+            .filter(|auth| !auth.is_empty())
+        {
+            return Some(Credentials::Basic(auth.to_string()));
+        }
+
+        let username = self.registry_setting(url, "username")?;
+        let password = BASE64
+            .decode(self.registry_setting(url, "_password")?)
+            .ok()
+            .and_then(|password| String::from_utf8(password).ok())?;
+        Some(Credentials::Basic(
+            BASE64.encode(format!("{username}:{password}")),
+        ))
+    }
+
+    /// Store a token for `registry` in ~/.npmrc, replacing any existing one
+    pub fn save_auth_token(registry: &str, token: &str) -> Result<PathBuf> {
+        let key = format!("{}:_authToken", Self::nerf_dart(registry));
+        Self::save_user_setting(&key, token)
+    }
+
+    /// Set `key=value` in ~/.npmrc, keeping every other line as written
+    pub fn save_user_setting(key: &str, value: &str) -> Result<PathBuf> {
+        let path = Self::user_path().ok_or_else(|| anyhow!("Could not find home directory"))?;
+        let content = std::fs::read_to_string(&path).unwrap_or_default();
+
+        let mut replaced = false;
+        let mut lines: Vec<String> = content
+            .lines()
+            .map(|line| match line.split_once('=') {
+                Some((existing, _)) if existing.trim() == key => {
+                    replaced = true;
+                    format!("{key}={value}")
+                }
+                _ => line.to_string(),
+            })
+            .collect();
+        if !replaced {
+            lines.push(format!("{key}={value}"));
+        }
+
+        std::fs::write(&path, lines.join("\n") + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Every `@scope:registry=...` entry
+    pub fn scope_registries(&self) -> Vec<(String, String)> {
+        let mut scopes: Vec<(String, String)> = self
+            .entries
+            .iter()
+            .filter_map(|(key, value)| {
+                let scope = key.strip_suffix(":registry")?;
+                scope
+                    .starts_with('@')
+                    .then(|| (scope.to_string(), value.clone()))
+            })
+            .collect();
+        scopes.sort();
+        scopes
+    }
+
+    /// Registry assigned to a scope with `@scope:registry=...`
+    pub fn scope_registry(&self, scope: &str) -> Option<&str> {
+        self.get(&format!("{scope}:registry"))
+    }
+
+    /// `always-auth`, globally or for the given registry
+    pub fn always_auth(&self, registry: &str) -> bool {
+        self.registry_setting(registry, "always-auth")
+            .or_else(|| self.get("always-auth"))
+            == Some("true")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(user_pass: &str) -> String {
+        format!("Basic {}", BASE64.encode(user_pass))
+    }
+
+    #[test]
+    fn nerf_dart_drops_scheme_and_keeps_path() {
+        assert_eq!(
+            Npmrc::nerf_dart("https://npm.pkg.github.com"),
+            "//npm.pkg.github.com/"
+        );
+        assert_eq!(
+            Npmrc::nerf_dart("https://acme.jfrog.io/artifactory/api/npm/npm-virtual/"),
+            "//acme.jfrog.io/artifactory/api/npm/npm-virtual/"
+        );
+    }
+
+    #[test]
+    fn github_packages_scope_and_token() {
+        let npmrc = Npmrc::parse(
+            "@acme:registry=https://npm.pkg.github.com\n\
+             //npm.pkg.github.com/:_authToken=ghp_secret\n",
+        );
+        assert_eq!(
+            npmrc.scope_registry("@acme"),
+            Some("https://npm.pkg.github.com")
+        );
+        assert_eq!(
+            npmrc.scope_registries(),
+            [(
+                "@acme".to_string(),
+                "https://npm.pkg.github.com".to_string()
+            )]
+        );
+        let credentials = npmrc
+            .credentials("https://npm.pkg.github.com/@acme%2fwidgets")
+            .unwrap();
+        assert_eq!(credentials.header_value(), "Bearer ghp_secret");
+        assert!(
+            npmrc
+                .credentials("https://registry.npmjs.org/left-pad")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn artifactory_auth_applies_to_paths_below_it() {
+        let auth = BASE64.encode("deployer:s3cret");
+        let npmrc = Npmrc::parse(&format!(
+            "; Artifactory\n//acme.jfrog.io/artifactory/api/npm/:_auth=\"{auth}\"\n"
+        ));
+        let tarball =
+            "https://acme.jfrog.io/artifactory/api/npm/npm-virtual/left-pad/-/left-pad-1.3.0.tgz";
+        assert_eq!(
+            npmrc.credentials(tarball).unwrap().header_value(),
+            basic("deployer:s3cret")
+        );
+        assert!(
+            npmrc
+                .credentials("https://acme.jfrog.io/other/left-pad")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn nexus_username_and_encoded_password() {
+        let password = BASE64.encode("hunter2");
+        let npmrc = Npmrc::parse(&format!(
+            "//nexus.acme.com/repository/npm-group/:username=ci\n\
+             //nexus.acme.com/repository/npm-group/:_password={password}\n"
+        ));
+        let credentials = npmrc
+            .credentials("https://nexus.acme.com/repository/npm-group/left-pad")
+            .unwrap();
+        assert_eq!(credentials.header_value(), basic("ci:hunter2"));
+    }
+
+    #[test]
+    fn token_wins_over_basic_auth() {
+        let npmrc = Npmrc::parse(
+            "//registry.acme.com/:_auth=dXNlcjpwYXNz\n//registry.acme.com/:_authToken=tok\n",
+        );
+        assert_eq!(
+            npmrc
+                .credentials("https://registry.acme.com/pkg")
+                .unwrap()
+                .header_value(),
+            "Bearer tok"
+        );
+    }
+
+    #[test]
+    fn tokens_expand_environment_variables() {
+        // SAFETY: no other test reads or writes this variable
+        unsafe { std::env::set_var("CLAY_TEST_NPMRC_TOKEN", "from-env") };
+        let npmrc = Npmrc::parse("//npm.pkg.github.com/:_authToken=${CLAY_TEST_NPMRC_TOKEN}\n");
+        assert_eq!(
+            npmrc.auth_token("https://npm.pkg.github.com"),
+            Some("from-env")
+        );
+    }
+
+    #[test]
+    fn always_auth_globally_or_per_registry() {
+        let global = Npmrc::parse("always-auth=true\n");
+        assert!(global.always_auth("https://npm.pkg.github.com"));
+
+        let per_registry = Npmrc::parse("//nexus.acme.com/repository/npm/:always-auth=true\n");
+        assert!(per_registry.always_auth("https://nexus.acme.com/repository/npm/"));
+        assert!(!per_registry.always_auth("https://registry.npmjs.org"));
+        assert!(!Npmrc::default().always_auth("https://registry.npmjs.org"));
+    }
+}
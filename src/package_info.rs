@@ -1,7 +1,12 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::version_range::VersionRange;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PackageInfo {
@@ -16,12 +21,25 @@ pub struct PackageInfo {
     #[serde(rename = "optionalDependencies")]
     pub optional_dependencies: Option<HashMap<String, String>>,
     pub dist: DistInfo,
+    /// Registry that served this version's metadata
+    #[serde(skip)]
+    pub registry: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DistInfo {
     pub tarball: String,
     pub shasum: String,
+    /// sha512 SRI string, when the registry provides one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// Bytes the package takes once extracted, when the registry publishes it
+    #[serde(
+        default,
+        rename = "unpackedSize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub unpacked_size: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -31,7 +49,79 @@ pub struct NpmRegistryResponse {
     pub dist_tags: HashMap<String, String>,
 }
 
+/// Full (non-abbreviated) registry document, used for display rather than installs
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackageDocument {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
+    #[serde(default)]
+    pub versions: HashMap<String, DocumentVersion>,
+    #[serde(default)]
+    pub maintainers: Vec<Maintainer>,
+    pub homepage: Option<String>,
+    pub license: Option<Value>,
+    /// Publish time per version, plus `created` and `modified`
+    #[serde(default)]
+    pub time: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentVersion {
+    pub version: String,
+    pub dependencies: Option<HashMap<String, String>>,
+    pub deprecated: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Maintainer {
+    pub name: String,
+    pub email: Option<String>,
+}
+
+/// Results of the registry's `/-/v1/search` endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResults {
+    pub objects: Vec<SearchResult>,
+    #[serde(default)]
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub package: SearchPackage,
+    /// Only some registries (npmjs.org among them) report downloads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloads: Option<SearchDownloads>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchPackage {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchDownloads {
+    #[serde(default)]
+    pub weekly: u64,
+    #[serde(default)]
+    pub monthly: u64,
+}
+
 impl NpmRegistryResponse {
+    /// Remember which registry served this document on every version it contains
+    pub fn set_registry(&mut self, registry: &str) {
+        for package_info in self.versions.values_mut() {
+            package_info.registry = Some(registry.to_string());
+        }
+    }
+
     pub fn get_version(&self, version: &str) -> Option<&PackageInfo> {
         if version == "latest" {
             let latest_version = self.dist_tags.get("latest")?;
@@ -45,6 +135,21 @@ impl NpmRegistryResponse {
         let latest_version = self.dist_tags.get("latest")?;
         self.versions.get(latest_version)
     }
+
+    /// Highest version matching an npm range, or the version a dist-tag points at
+    pub fn max_satisfying(&self, range: &str) -> Option<&PackageInfo> {
+        if let Some(version) = self.dist_tags.get(range) {
+            return self.versions.get(version);
+        }
+
+        let range = VersionRange::parse(range).ok()?;
+        self.versions
+            .values()
+            .filter_map(|info| Some((semver::Version::parse(&info.version).ok()?, info)))
+            .filter(|(version, _)| range.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, info)| info)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -65,7 +170,10 @@ pub struct PackageJson {
     pub dev_dependencies: Option<HashMap<String, String>>,
     #[serde(rename = "peerDependencies", skip_serializing_if = "Option::is_none")]
     pub peer_dependencies: Option<HashMap<String, String>>,
-    #[serde(rename = "optionalDependencies", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "optionalDependencies",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub optional_dependencies: Option<HashMap<String, String>>,
 }
 
@@ -84,37 +192,38 @@ impl PackageJson {
         }
     }
 
-    pub fn add_dependency(&mut self, name: &str, version: &str) {
-        match &mut self.dependencies {
-            Some(deps) => {
-                deps.insert(name.to_string(), version.to_string());
-            }
-            None => {
-                let mut deps = HashMap::new();
-                deps.insert(name.to_string(), version.to_string());
-                self.dependencies = Some(deps);
-            }
-        }
+    /// Range the project asks for, from dependencies, devDependencies or optionalDependencies
+    pub fn requested_range(&self, name: &str) -> Option<&str> {
+        [
+            &self.dependencies,
+            &self.dev_dependencies,
+            &self.optional_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|deps| deps.get(name))
+        .map(String::as_str)
     }
 
-    pub fn add_dev_dependency(&mut self, name: &str, version: &str) {
-        match &mut self.dev_dependencies {
-            Some(deps) => {
-                deps.insert(name.to_string(), version.to_string());
-            }
-            None => {
-                let mut deps = HashMap::new();
-                deps.insert(name.to_string(), version.to_string());
-                self.dev_dependencies = Some(deps);
-            }
-        }
+    /// What an install of the project asks for, as (name, range): dependencies, then
+    /// devDependencies when `include_dev`, then optionalDependencies
+    pub fn dependency_specs(&self, include_dev: bool) -> Vec<(String, String)> {
+        let dev = if include_dev {
+            &self.dev_dependencies
+        } else {
+            &None
+        };
+        [&self.dependencies, dev, &self.optional_dependencies]
+            .into_iter()
+            .flatten()
+            .flat_map(|deps| deps.iter())
+            .map(|(name, range)| (name.clone(), range.clone()))
+            .collect()
     }
 
     /// Calculate a deterministic fingerprint from dependencies
     pub fn calculate_dependency_fingerprint(&self, include_dev: bool) -> String {
-        use sha1::{Digest, Sha1};
-
-        let mut hasher = Sha1::new();
+        let mut hasher = blake3::Hasher::new();
 
         // Hash regular dependencies
         if let Some(ref deps) = self.dependencies {
@@ -126,13 +235,20 @@ impl PackageJson {
         }
 
         // Hash dev dependencies if requested
-        if include_dev {
-            if let Some(ref deps) = self.dev_dependencies {
-                let mut sorted: Vec<_> = deps.iter().collect();
-                sorted.sort_by_key(|(name, _)| *name);
-                for (name, version) in sorted {
-                    hasher.update(format!("dev:{name}:{version}").as_bytes());
-                }
+        if include_dev && let Some(ref deps) = self.dev_dependencies {
+            let mut sorted: Vec<_> = deps.iter().collect();
+            sorted.sort_by_key(|(name, _)| *name);
+            for (name, version) in sorted {
+                hasher.update(format!("dev:{name}:{version}").as_bytes());
+            }
+        }
+
+        // Hash optional dependencies, which installs include too
+        if let Some(ref deps) = self.optional_dependencies {
+            let mut sorted: Vec<_> = deps.iter().collect();
+            sorted.sort_by_key(|(name, _)| *name);
+            for (name, version) in sorted {
+                hasher.update(format!("opt:{name}:{version}").as_bytes());
             }
         }
 
@@ -145,17 +261,68 @@ impl PackageJson {
             }
         }
 
-        format!("{:x}", hasher.finalize())
+        hasher.finalize().to_hex().to_string()
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// How an install persists what it resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum LockMode {
+    /// Dependency trees in the content store only; no lockfile
     #[default]
-    Implicit, // Content store only (default)
-    Explicit, // Traditional lockfiles
-    Hybrid,   // Both (CI gets lockfiles, dev doesn't)
-    Memory,   // Pure in-memory (no persistence)
+    Implicit,
+    /// A clay-lock file, always written
+    Explicit,
+    /// Content store trees, plus a lockfile when running in CI
+    Hybrid,
+    /// Nothing persisted between installs
+    Memory,
+}
+
+impl LockMode {
+    pub fn writes_lock_file(&self) -> bool {
+        match self {
+            LockMode::Explicit => true,
+            LockMode::Hybrid => std::env::var("CI").is_ok(),
+            LockMode::Implicit | LockMode::Memory => false,
+        }
+    }
+
+    /// Whether resolved trees are cached in (and reused from) the content store
+    pub fn caches_trees(&self) -> bool {
+        matches!(self, LockMode::Implicit | LockMode::Hybrid)
+    }
+}
+
+/// Which file an explicit lockfile is written as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LockFormat {
+    /// clay-lock.toml
+    #[default]
+    Toml,
+    /// clay-lock.json
+    Json,
+}
+
+impl LockFormat {
+    /// The format of the project's existing lockfile, preferring clay-lock.json as
+    /// `clay lock` does, else TOML
+    pub fn detect() -> Self {
+        if Path::new("clay-lock.json").exists() {
+            LockFormat::Json
+        } else {
+            LockFormat::Toml
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            LockFormat::Toml => "clay-lock.toml",
+            LockFormat::Json => "clay-lock.json",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -183,9 +350,7 @@ impl DependencyTree {
     }
 
     pub fn calculate_tree_hash(&mut self) {
-        use sha1::{Digest, Sha1};
-
-        let mut hasher = Sha1::new();
+        let mut hasher = blake3::Hasher::new();
 
         // Create a sorted representation for consistent hashing
         let mut sorted_packages: Vec<_> = self.packages.iter().collect();
@@ -198,7 +363,7 @@ impl DependencyTree {
             hasher.update(package.integrity.as_bytes());
         }
 
-        self.tree_hash = format!("{:x}", hasher.finalize());
+        self.tree_hash = hasher.finalize().to_hex().to_string();
     }
 
     pub fn add_package(
@@ -222,80 +387,383 @@ impl DependencyTree {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Current clay-lock schema. Version 1 lockfiles are migrated when read.
+pub const LOCKFILE_VERSION: &str = "2";
+
+/// clay-lock v2: one entry per `name@version`, so several versions of a package can be
+/// locked, and every dependency edge records the range that was asked for
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockFile {
     pub version: String,
-    pub packages: HashMap<String, LockedPackage>,
+    /// The project's own dependencies
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub root: BTreeMap<String, LockedDependency>,
+    /// Keyed by `name@version`
+    pub packages: BTreeMap<String, LockedPackage>,
+    /// Local workspace packages, keyed by their path relative to the project root
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub workspaces: BTreeMap<String, LockedWorkspace>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockedPackage {
+    pub name: String,
     pub version: String,
     pub resolved: String,
+    /// Subresource-integrity string, sha512 for anything clay downloaded itself
     pub integrity: String,
-    pub dependencies: Option<HashMap<String, String>>,
-    pub required_by: Vec<String>, // Which packages depend on this one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedDependency>,
+    /// Registry the package metadata was resolved from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+}
+
+/// A workspace package: linked from its directory rather than downloaded, with its own
+/// dependency edges locked like the root's
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedWorkspace {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedDependency>,
+}
+
+/// One dependency edge: what the dependent asked for and which locked version satisfies it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedDependency {
+    #[serde(rename = "requestedRange")]
+    pub requested_range: String,
+    /// None until a matching version has been locked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl LockedDependency {
+    pub fn new(requested_range: &str) -> Self {
+        Self {
+            requested_range: requested_range.to_string(),
+            version: None,
+        }
+    }
+}
+
+/// clay-lock v1, read only for migration
+#[derive(Deserialize)]
+struct LockFileV1 {
+    packages: HashMap<String, LockedPackageV1>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackageV1 {
+    version: String,
+    resolved: String,
+    integrity: String,
+    dependencies: Option<HashMap<String, String>>,
+    required_by: Vec<String>,
+    #[serde(default)]
+    registry: Option<String>,
 }
 
 impl LockFile {
     pub fn new() -> Self {
         Self {
-            version: "1.0.0".to_string(),
-            packages: HashMap::new(),
+            version: LOCKFILE_VERSION.to_string(),
+            root: BTreeMap::new(),
+            packages: BTreeMap::new(),
+            workspaces: BTreeMap::new(),
         }
     }
 
-    pub fn add_package(
+    pub fn key(name: &str, version: &str) -> String {
+        format!("{name}@{version}")
+    }
+
+    /// Parse a lockfile of any schema version, migrating older ones to the current schema
+    pub fn parse(content: &str, json: bool) -> anyhow::Result<Self> {
+        if content.trim().is_empty() {
+            return Ok(Self::new());
+        }
+
+        let value: Value = if json {
+            serde_json::from_str(content)?
+        } else {
+            serde_json::to_value(toml::from_str::<toml::Value>(content)?)?
+        };
+
+        let is_v1 = value.get("version").and_then(Value::as_str) == Some("1.0.0");
+        if is_v1 {
+            Ok(Self::from_v1(serde_json::from_value(value)?))
+        } else {
+            Ok(serde_json::from_value(value)?)
+        }
+    }
+
+    /// v1 kept one entry per name and a `required_by` list; its root dependencies were
+    /// always written to package.json as exact versions, so that is their requested range
+    fn from_v1(v1: LockFileV1) -> Self {
+        let mut lock_file = Self::new();
+
+        for (name, package) in v1.packages {
+            if package
+                .required_by
+                .iter()
+                .any(|dependent| dependent == "root")
+            {
+                lock_file
+                    .root
+                    .insert(name.clone(), LockedDependency::new(&package.version));
+            }
+
+            lock_file.packages.insert(
+                Self::key(&name, &package.version),
+                LockedPackage {
+                    integrity: sri_from_shasum(&package.integrity),
+                    dependencies: package
+                        .dependencies
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|(dep, range)| (dep.clone(), LockedDependency::new(range)))
+                        .collect(),
+                    registry: package.registry,
+                    resolved: package.resolved,
+                    version: package.version,
+                    name,
+                },
+            );
+        }
+
+        lock_file.link();
+        lock_file
+    }
+
+    /// Add a package resolved from the registry, with the integrity of the downloaded tarball
+    pub fn add_resolved_package(&mut self, package_info: &PackageInfo, integrity: &str) {
+        let dependencies = package_info
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|(name, range)| (name.clone(), LockedDependency::new(range)))
+            .collect();
+
+        self.add_package(LockedPackage {
+            name: package_info.name.clone(),
+            version: package_info.version.clone(),
+            resolved: package_info.dist.tarball.clone(),
+            integrity: integrity.to_string(),
+            dependencies,
+            registry: package_info.registry.clone(),
+        });
+    }
+
+    /// Lock a package; its edges and those pointing at it resolve once the lock is linked
+    pub fn add_package(&mut self, package: LockedPackage) {
+        self.packages
+            .insert(Self::key(&package.name, &package.version), package);
+    }
+
+    /// Record a dependency of the project itself
+    pub fn add_root_dependency(&mut self, name: &str, requested_range: &str) {
+        self.root
+            .insert(name.to_string(), LockedDependency::new(requested_range));
+    }
+
+    /// Record the project's workspace packages from their package.json files, replacing
+    /// what was recorded before. Paths are relative to the project root.
+    pub fn set_workspaces<'a>(
         &mut self,
-        name: &str,
-        version: &str,
-        resolved: &str,
-        integrity: &str,
-        dependencies: Option<HashMap<String, String>>,
-        required_by: &str,
+        workspaces: impl IntoIterator<Item = (&'a str, &'a PackageJson)>,
     ) {
-        let package = self
-            .packages
-            .entry(name.to_string())
-            .or_insert(LockedPackage {
-                version: version.to_string(),
-                resolved: resolved.to_string(),
-                integrity: integrity.to_string(),
-                dependencies,
-                required_by: Vec::new(),
-            });
+        self.workspaces = workspaces
+            .into_iter()
+            .filter_map(|(path, package_json)| {
+                let dependencies = [
+                    &package_json.dependencies,
+                    &package_json.dev_dependencies,
+                    &package_json.optional_dependencies,
+                ]
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|(name, range)| (name.clone(), LockedDependency::new(range)))
+                .collect();
+
+                let workspace = LockedWorkspace {
+                    name: package_json.name.clone()?,
+                    version: package_json.version.clone(),
+                    dependencies,
+                };
+                Some((path.trim_start_matches("./").to_string(), workspace))
+            })
+            .collect();
+    }
+
+    /// Path and entry of the workspace package with this name
+    pub fn workspace_named(&self, name: &str) -> Option<(&String, &LockedWorkspace)> {
+        self.workspaces
+            .iter()
+            .find(|(_, workspace)| workspace.name == name)
+    }
+
+    /// Point every edge at the highest locked version satisfying its range, keeping
+    /// edges that are already satisfied as they are. Edges to workspace packages point
+    /// at the workspace's own version. Run once the lock is built, not after each change.
+    pub fn link(&mut self) {
+        let mut locked: HashMap<String, Vec<semver::Version>> = HashMap::new();
+        for package in self.packages.values() {
+            if let Ok(version) = semver::Version::parse(&package.version) {
+                locked
+                    .entry(package.name.clone())
+                    .or_default()
+                    .push(version);
+            }
+        }
+
+        let local: HashMap<String, Option<String>> = self
+            .workspaces
+            .values()
+            .map(|workspace| (workspace.name.clone(), workspace.version.clone()))
+            .collect();
+
+        let resolve = |name: &str, edge: &mut LockedDependency| {
+            if let Some(version) = local.get(name) {
+                edge.version = version.clone();
+                return;
+            }
+            let Some(candidates) = locked.get(name) else {
+                edge.version = None;
+                return;
+            };
+            if let Some(ref current) = edge.version
+                && candidates
+                    .iter()
+                    .any(|version| &version.to_string() == current)
+            {
+                return;
+            }
+
+            let range = VersionRange::parse(&edge.requested_range).ok();
+            edge.version = candidates
+                .iter()
+                .filter(|version| range.as_ref().is_none_or(|range| range.matches(version)))
+                .max()
+                .or_else(|| (candidates.len() == 1).then(|| &candidates[0]))
+                .map(ToString::to_string);
+        };
+
+        for (name, edge) in &mut self.root {
+            resolve(name, edge);
+        }
+        for package in self.packages.values_mut() {
+            for (name, edge) in &mut package.dependencies {
+                resolve(name, edge);
+            }
+        }
+        for workspace in self.workspaces.values_mut() {
+            for (name, edge) in &mut workspace.dependencies {
+                resolve(name, edge);
+            }
+        }
+    }
+
+    /// Every locked version of a package
+    pub fn versions_of<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a LockedPackage> {
+        self.packages
+            .values()
+            .filter(move |package| package.name == name)
+    }
 
-        // Add to required_by if not already present
-        if !package.required_by.contains(&required_by.to_string()) {
-            package.required_by.push(required_by.to_string());
+    /// The version a name resolves to from the project: the root edge's, else the highest
+    pub fn find<'a>(&'a self, name: &'a str) -> Option<&'a LockedPackage> {
+        if let Some(version) = self.root.get(name).and_then(|edge| edge.version.as_ref())
+            && let Some(package) = self.packages.get(&Self::key(name, version))
+        {
+            return Some(package);
         }
+
+        self.versions_of(name).max_by(|a, b| {
+            match (
+                semver::Version::parse(&a.version),
+                semver::Version::parse(&b.version),
+            ) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.version.cmp(&b.version),
+            }
+        })
     }
 
-    pub fn remove_package(&mut self, name: &str, required_by: &str) -> bool {
-        if let Some(package) = self.packages.get_mut(name) {
-            package.required_by.retain(|dep| dep != required_by);
+    /// Key of the locked package an edge points at, if it is locked
+    pub fn locked_key(&self, name: &str, edge: &LockedDependency) -> Option<String> {
+        let key = Self::key(name, edge.version.as_ref()?);
+        self.packages.contains_key(&key).then_some(key)
+    }
 
-            // If no packages depend on it, remove it completely
-            if package.required_by.is_empty() {
-                self.packages.remove(name);
-                return true;
+    /// Keys of every package reachable from the project's own dependencies and those of
+    /// its workspace packages
+    pub fn reachable_keys(&self) -> HashSet<String> {
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut queue: Vec<String> = self
+            .root
+            .iter()
+            .chain(
+                self.workspaces
+                    .values()
+                    .flat_map(|workspace| &workspace.dependencies),
+            )
+            .filter_map(|(name, edge)| self.locked_key(name, edge))
+            .collect();
+
+        while let Some(key) = queue.pop() {
+            if !seen.insert(key.clone()) {
+                continue;
             }
+            queue.extend(
+                self.packages[&key]
+                    .dependencies
+                    .iter()
+                    .filter_map(|(name, edge)| self.locked_key(name, edge)),
+            );
         }
-        false
+
+        seen
+    }
+
+    /// Names of packages depending on `name`, with "root" for the project itself
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        let mut dependents: Vec<String> = Vec::new();
+        if self.root.contains_key(name) {
+            dependents.push("root".to_string());
+        }
+        for package in self.packages.values() {
+            if package.dependencies.contains_key(name) && !dependents.contains(&package.name) {
+                dependents.push(package.name.clone());
+            }
+        }
+        dependents
     }
 
     pub fn can_remove_package(&self, name: &str, required_by: &str) -> (bool, Vec<String>) {
-        if let Some(package) = self.packages.get(name) {
-            let remaining_deps: Vec<String> = package
-                .required_by
-                .iter()
-                .filter(|&dep| dep != required_by)
-                .cloned()
-                .collect();
+        let remaining_deps: Vec<String> = self
+            .dependents(name)
+            .into_iter()
+            .filter(|dependent| dependent != required_by)
+            .collect();
 
-            (remaining_deps.is_empty(), remaining_deps)
-        } else {
-            (true, Vec::new())
-        }
+        (remaining_deps.is_empty(), remaining_deps)
     }
 }
+
+/// SRI form of an npm hex sha1 shasum; values that already are SRI strings pass through
+pub fn sri_from_shasum(shasum: &str) -> String {
+    if shasum.is_empty() || shasum.contains('-') {
+        return shasum.to_string();
+    }
+
+    (0..shasum.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(shasum.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .map(|bytes| format!("sha1-{}", BASE64.encode(bytes)))
+        .unwrap_or_else(|| shasum.to_string())
+}
@@ -1,23 +1,45 @@
 use anyhow::{Result, anyhow};
 use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::ExitStatus;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::fs;
+use tokio::process::Command;
 
+use serde::Serialize;
 use serde_json::Value;
 use tokio::sync::{Mutex, Semaphore};
+use tracing::Instrument;
 
+use crate::catalog::Catalogs;
 use crate::cli_style::CliStyle;
-use crate::content_store::ContentStore;
+use crate::config::{ClayConfig, NodeLinker, PolicyConfig};
+use crate::content_store::{self, ContentStore};
+use crate::errors::{self, ErrorCode};
+use crate::exec;
+use crate::lifecycle::{self, BuildCache};
+use crate::links;
+use crate::lock;
+use crate::lock_cache;
+use crate::lock_import;
+use crate::long_path;
+use crate::node_runtime;
 use crate::npm_client::NpmClient;
 use crate::package_info::{
-    DependencyTree, DistInfo, LockFile, LockMode, NpmRegistryResponse, PackageInfo, PackageJson,
+    DependencyTree, DistInfo, LockFile, LockFormat, LockMode, LockedDependency,
+    NpmRegistryResponse, PackageInfo, PackageJson, sri_from_shasum,
 };
+use crate::patches;
+use crate::progress::{self, Event};
+use crate::shutdown::{CleanupGuard, ScratchDir};
+use crate::typings;
+use crate::version_range::VersionRange;
+use crate::virtual_store;
+use crate::workspace::LocalPackage;
 
 #[derive(Debug, Clone)]
 pub struct ResolvedPackage {
@@ -28,18 +50,518 @@ pub struct ResolvedPackage {
     pub is_dev: bool,
 }
 
+/// package.json fields that list dependencies
+const DEPENDENCY_FIELDS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+];
+
+/// Where `clay install <pkg>` records the packages it was asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaveTarget {
+    /// dependencies, or devDependencies for `--dev`
+    #[default]
+    Dependencies,
+    Peer,
+    Optional,
+    /// Leave package.json alone
+    None,
+}
+
+impl SaveTarget {
+    pub fn field(&self, is_dev: bool) -> Option<&'static str> {
+        match self {
+            SaveTarget::Dependencies if is_dev => Some("devDependencies"),
+            SaveTarget::Dependencies => Some("dependencies"),
+            SaveTarget::Peer => Some("peerDependencies"),
+            SaveTarget::Optional => Some("optionalDependencies"),
+            SaveTarget::None => None,
+        }
+    }
+}
+
+/// What `clay install --json` prints: how node_modules changed and how the install went
+#[derive(Debug, Default, Serialize)]
+pub struct InstallReport {
+    pub added: Vec<ReportedPackage>,
+    pub removed: Vec<ReportedPackage>,
+    pub changed: Vec<ChangedPackage>,
+    /// Milliseconds per phase (`resolve`, `fetch`, `link`, `scripts`) and in `total`
+    pub durations: BTreeMap<&'static str, u64>,
+    pub warnings: Vec<String>,
+    /// Advisory counts by severity for everything installed; None if the audit failed
+    pub audit: Option<BTreeMap<&'static str, usize>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportedPackage {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedPackage {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// What `clay install --timing` prints and saves: where an install spent its time
+#[derive(Debug, Serialize)]
+pub struct TimingReport {
+    /// Wall-clock milliseconds per phase, and in `total`
+    pub phases: BTreeMap<&'static str, u64>,
+    /// Milliseconds per step summed over every package. Packages are fetched concurrently,
+    /// so these add up to more than the wall-clock time.
+    pub steps: BTreeMap<&'static str, u64>,
+    pub metadata_requests: usize,
+    /// Every package that took time, slowest first
+    pub packages: Vec<PackageTiming>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageTiming {
+    pub name: String,
+    pub total: u64,
+    pub steps: BTreeMap<&'static str, u64>,
+}
+
+/// A dependency in `clay list --json`, nested like `npm ls --json`
+#[derive(Debug, Default, Serialize)]
+pub struct ListedDependency {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// The range asked for, when nothing satisfying it is locked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing: Option<String>,
+    /// Already listed higher up, so its dependencies aren't repeated
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deduped: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, ListedDependency>,
+}
+
+/// What `clay size` prints: the tree a package pulls in and what it costs to install
+#[derive(Debug, Serialize)]
+pub struct SizeReport {
+    pub name: String,
+    pub version: String,
+    /// The whole tree
+    pub total: SizeTotals,
+    /// Only the packages this project doesn't have installed yet
+    pub added: SizeTotals,
+    /// Every package of the tree, largest unpacked first
+    pub packages: Vec<PackageSize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SizeTotals {
+    pub packages: usize,
+    pub download: u64,
+    pub unpacked: u64,
+    /// Packages the registry gave no size for, left out of the sums
+    pub unknown: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageSize {
+    pub name: String,
+    pub version: String,
+    /// Tarball bytes
+    pub download: Option<u64>,
+    /// Bytes once extracted, as the registry publishes it
+    pub unpacked: Option<u64>,
+    pub installed: bool,
+}
+
+/// Packages listed in the `clay size` summary
+const SIZE_LARGEST: usize = 10;
+
+/// What `clay check --all` found comparing node_modules with the lockfile and the store
+#[derive(Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    pub packages: usize,
+    pub files: usize,
+    pub bin_links: usize,
+    /// Installed packages whose files weren't hashed: patched ones, and ones the store
+    /// has no per-file hashes for
+    pub unverified: Vec<String>,
+    pub problems: Vec<IntegrityProblem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegrityProblem {
+    /// `name@version`, or `.bin/<command>`
+    pub subject: String,
+    pub problem: String,
+}
+
+/// Why the resolver picked a version for a requested spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionChoice {
+    /// The spec is that version
+    Exact,
+    /// The spec is the `latest` dist-tag
+    DistTag,
+    /// The spec is a range (or another tag), which the resolver answers with `latest`
+    LatestForRange,
+}
+
+/// `clay explain`: everything that went into the version of one package
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub name: String,
+    pub requests: Vec<VersionRequest>,
+    /// None when the registry couldn't be reached
+    pub dist_tags: Option<BTreeMap<String, String>>,
+    /// Locked versions, each with the edges pointing at it as (dependent, range)
+    pub locked: BTreeMap<String, Vec<(String, String)>>,
+    /// Installed versions and where
+    pub installed: BTreeMap<String, Vec<PathBuf>>,
+    /// package.json overrides naming the package, as (where, value); clay doesn't apply them
+    pub overrides: Vec<(String, String)>,
+}
+
+/// One dependent asking for the package, and what the resolver makes of its range
+#[derive(Debug, Serialize)]
+pub struct VersionRequest {
+    /// `package.json (dependencies)` or the dependent's `name@version`
+    pub by: String,
+    pub range: String,
+    pub chosen: Option<String>,
+    pub choice: Option<VersionChoice>,
+    /// Published versions the range allows, newest first
+    pub candidates: Vec<String>,
+}
+
+/// Versions shown per request by `clay explain`
+const EXPLAIN_CANDIDATES: usize = 8;
+
+/// Packages listed in the `--timing` summary
+const TIMING_HOTSPOTS: usize = 10;
+
+/// Install phases in the order they run
+const PHASE_ORDER: [&str; 5] = ["resolve", "fetch", "link", "scripts", "total"];
+
+impl TimingReport {
+    pub fn print(&self) {
+        let format_ms = |ms: u64| CliStyle::format_duration(Duration::from_millis(ms));
+
+        println!("\n{}", CliStyle::section_header("Install timing"));
+        for phase in PHASE_ORDER {
+            if let Some(ms) = self.phases.get(phase) {
+                println!("  {:<10} {}", phase, format_ms(*ms));
+            }
+        }
+
+        println!(
+            "\n  {}",
+            CliStyle::dim_text("Summed over packages, overlapping in time:")
+        );
+        for (step, ms) in &self.steps {
+            let detail = if *step == "metadata" {
+                format!(" ({} requests)", self.metadata_requests)
+            } else {
+                String::new()
+            };
+            println!(
+                "  {:<10} {}{}",
+                step,
+                format_ms(*ms),
+                CliStyle::dim_text(&detail)
+            );
+        }
+
+        if self.packages.is_empty() {
+            return;
+        }
+        println!("\n{}", CliStyle::section_header("Slowest packages"));
+        let hotspots = &self.packages[..self.packages.len().min(TIMING_HOTSPOTS)];
+        let width = hotspots
+            .iter()
+            .map(|package| package.name.len())
+            .max()
+            .unwrap_or(0);
+        for package in hotspots {
+            let steps: Vec<String> = package
+                .steps
+                .iter()
+                .map(|(step, ms)| format!("{step} {}", format_ms(*ms)))
+                .collect();
+            println!(
+                "  {:<width$} {:>8}  {}",
+                package.name,
+                format_ms(package.total),
+                CliStyle::dim_text(&steps.join(", "))
+            );
+        }
+    }
+}
+
+impl SizeTotals {
+    fn add(&mut self, package: &PackageSize) {
+        self.packages += 1;
+        self.download += package.download.unwrap_or(0);
+        self.unpacked += package.unpacked.unwrap_or(0);
+        if package.download.is_none() || package.unpacked.is_none() {
+            self.unknown += 1;
+        }
+    }
+
+    /// `sign` goes before each figure, e.g. `+` for what an install adds
+    fn summary(&self, sign: &str) -> String {
+        let unknown = if self.unknown > 0 {
+            CliStyle::dim_text(&format!(" ({} without size info)", self.unknown))
+        } else {
+            String::new()
+        };
+        format!(
+            "{sign}{} package{}, {sign}{} download, {sign}{} unpacked{unknown}",
+            self.packages,
+            if self.packages == 1 { "" } else { "s" },
+            CliStyle::format_size(self.download),
+            CliStyle::format_size(self.unpacked)
+        )
+    }
+}
+
+impl SizeReport {
+    /// `in_project` adds what installing the package would add to the current project
+    pub fn print(&self, in_project: bool) {
+        println!(
+            "{} {}",
+            CliStyle::package_name(&self.name),
+            CliStyle::version(&self.version)
+        );
+        println!("  {}", self.total.summary(""));
+
+        println!("\n{}", CliStyle::section_header("Largest packages"));
+        let largest = &self.packages[..self.packages.len().min(SIZE_LARGEST)];
+        let width = largest
+            .iter()
+            .map(|package| package.name.len() + package.version.len() + 1)
+            .max()
+            .unwrap_or(0);
+        let format = |size: Option<u64>| size.map_or("?".to_string(), CliStyle::format_size);
+        for package in largest {
+            let installed = if package.installed {
+                CliStyle::dim_text("  installed")
+            } else {
+                String::new()
+            };
+            println!(
+                "  {:<width$} {:>10} {}{installed}",
+                format!("{}@{}", package.name, package.version),
+                format(package.unpacked),
+                CliStyle::dim_text(&format!("{} download", format(package.download)))
+            );
+        }
+
+        if in_project {
+            println!();
+            if self.added.packages == 0 {
+                println!(
+                    "{}",
+                    CliStyle::info("Everything it needs is already installed in this project")
+                );
+            } else {
+                println!(
+                    "{}",
+                    CliStyle::info(&format!(
+                        "Adding it to this project: {}",
+                        self.added.summary("+")
+                    ))
+                );
+            }
+        }
+    }
+}
+
+impl Explanation {
+    pub fn print(&self) {
+        println!("{}", CliStyle::section_header(&self.name));
+
+        println!("\n  {}", style("Requested by").bold());
+        let width = self
+            .requests
+            .iter()
+            .map(|request| request.by.len())
+            .max()
+            .unwrap_or(0);
+        for request in &self.requests {
+            let chosen = match (&request.chosen, request.choice) {
+                (Some(version), Some(choice)) => {
+                    let reason = match choice {
+                        VersionChoice::Exact => "exact version".to_string(),
+                        VersionChoice::DistTag => "latest dist-tag".to_string(),
+                        VersionChoice::LatestForRange => {
+                            "ranges resolve to the latest dist-tag".to_string()
+                        }
+                    };
+                    format!(
+                        "→ {} {}",
+                        CliStyle::version(version),
+                        CliStyle::dim_text(&format!("({reason})"))
+                    )
+                }
+                _ => CliStyle::dim_text("→ ?"),
+            };
+            println!(
+                "    {:<width$}  {}  {chosen}",
+                request.by,
+                style(&request.range).cyan()
+            );
+
+            if request.choice == Some(VersionChoice::LatestForRange)
+                && let (Some(chosen), Ok(range)) =
+                    (&request.chosen, VersionRange::parse(&request.range))
+                && semver::Version::parse(chosen).is_ok_and(|version| !range.matches(&version))
+            {
+                println!(
+                    "      {}",
+                    CliStyle::warning(&format!("{chosen} is outside {}", request.range))
+                );
+            }
+            if self.dist_tags.is_some() {
+                let shown = &request.candidates[..request.candidates.len().min(EXPLAIN_CANDIDATES)];
+                let more = request.candidates.len() - shown.len();
+                println!(
+                    "      {}",
+                    CliStyle::dim_text(&match (shown.is_empty(), more) {
+                        (true, _) => "candidates: none published".to_string(),
+                        (false, 0) => format!("candidates: {}", shown.join(", ")),
+                        (false, more) => {
+                            format!("candidates: {} and {more} older", shown.join(", "))
+                        }
+                    })
+                );
+            }
+        }
+
+        println!("\n  {}", style("Dist-tags").bold());
+        match &self.dist_tags {
+            Some(tags) if !tags.is_empty() => {
+                for (tag, version) in tags {
+                    println!("    {tag:<10} {}", CliStyle::version(version));
+                }
+            }
+            Some(_) => println!("    {}", CliStyle::dim_text("none")),
+            None => println!("    {}", CliStyle::dim_text("registry unavailable")),
+        }
+
+        println!("\n  {}", style("Lockfile").bold());
+        if self.locked.is_empty() {
+            println!("    {}", CliStyle::dim_text("not locked"));
+        }
+        for (version, edges) in &self.locked {
+            let edges: Vec<String> = edges
+                .iter()
+                .map(|(dependent, range)| format!("{dependent} {range}"))
+                .collect();
+            println!(
+                "    {} {}",
+                CliStyle::version(version),
+                CliStyle::dim_text(&format!("← {}", edges.join(", ")))
+            );
+        }
+
+        println!("\n  {}", style("Installed").bold());
+        if self.installed.is_empty() {
+            println!("    {}", CliStyle::dim_text("not installed"));
+        }
+        for (version, dirs) in &self.installed {
+            for dir in dirs {
+                println!(
+                    "    {} {}",
+                    CliStyle::version(version),
+                    CliStyle::dim_text(&dir.display().to_string())
+                );
+            }
+        }
+
+        if !self.overrides.is_empty() {
+            println!("\n  {}", style("Overrides").bold());
+            for (source, value) in &self.overrides {
+                println!("    {source}: {value}");
+            }
+            println!(
+                "    {}",
+                CliStyle::dim_text("clay doesn't apply overrides; these had no effect")
+            );
+        }
+    }
+}
+
+impl IntegrityReport {
+    fn problem(&mut self, subject: impl Into<String>, problem: impl Into<String>) {
+        self.problems.push(IntegrityProblem {
+            subject: subject.into(),
+            problem: problem.into(),
+        });
+    }
+
+    pub fn print(&self) {
+        for problem in &self.problems {
+            println!(
+                "  {} {} {}",
+                style("•").red(),
+                style(&problem.subject).white().bold(),
+                problem.problem
+            );
+        }
+        if !self.unverified.is_empty() {
+            println!(
+                "{}",
+                CliStyle::dim_text(&format!(
+                    "Files not checked (patched, or stored without file hashes): {}",
+                    self.unverified.join(", ")
+                ))
+            );
+        }
+        let summary = format!(
+            "{} packages, {} files and {} .bin links checked",
+            self.packages, self.files, self.bin_links
+        );
+        if self.problems.is_empty() {
+            println!("{}", CliStyle::success(&summary));
+        } else {
+            println!(
+                "{} {summary}; {} found",
+                style("⚠").yellow().bold(),
+                match self.problems.len() {
+                    1 => "1 problem".to_string(),
+                    n => format!("{n} problems"),
+                }
+            );
+        }
+    }
+}
+
 pub struct PackageResolver {
     npm_client: NpmClient,
     resolved_cache: HashMap<String, NpmRegistryResponse>,
     resolution_stack: HashSet<String>,
+    concurrency: usize,
+    /// Packages that may or may not be installed
+    policy: PolicyConfig,
+    /// Requested packages that couldn't be resolved, left for the caller to report
+    failures: Vec<(String, anyhow::Error)>,
 }
 
 impl PackageResolver {
-    fn new(npm_client: NpmClient) -> Self {
+    fn new(npm_client: NpmClient, concurrency: usize, policy: PolicyConfig) -> Self {
         Self {
             npm_client,
             resolved_cache: HashMap::new(),
             resolution_stack: HashSet::new(),
+            concurrency,
+            policy,
+            failures: Vec::new(),
         }
     }
 
@@ -53,17 +575,6 @@ impl PackageResolver {
             .await
     }
 
-    async fn resolve_package_with_spinner(
-        &mut self,
-        name: &str,
-        version_spec: &str,
-        is_dev: bool,
-        spinner: &indicatif::ProgressBar,
-    ) -> Result<ResolvedPackage> {
-        self.resolve_package_iterative(name, version_spec, is_dev, Some(spinner))
-            .await
-    }
-
     async fn resolve_package_iterative(
         &mut self,
         root_name: &str,
@@ -103,43 +614,46 @@ impl PackageResolver {
                 spinner.set_message(format!("Fetching {name}..."));
             }
 
-            // Fetch package info
-            if !self.resolved_cache.contains_key(&name) {
-                let response = self.npm_client.get_package_info(&name).await?;
-                self.resolved_cache.insert(name.clone(), response);
-            }
-            let registry_response = self.resolved_cache.get(&name).unwrap();
-
-            // Resolve version
-            let package_info = if version_spec == "latest" {
-                registry_response.get_latest_version()
-            } else if Self::is_exact_version(&version_spec) {
-                registry_response.get_version(&version_spec)
+            // Exact versions only need their own manifest unless the full packument is
+            // already at hand; fall back to the packument if the registry lacks the endpoint
+            let package_info = if Self::is_exact_version(&version_spec)
+                && !self.resolved_cache.contains_key(&name)
+                && let Ok(package_info) =
+                    self.npm_client.get_version_info(&name, &version_spec).await
+            {
+                package_info
             } else {
-                // For ranges, use latest for now
-                registry_response.get_latest_version()
-            }
-            .ok_or_else(|| {
-                anyhow!(
-                    "Version '{}' not found for package '{}'",
-                    version_spec,
-                    name
-                )
-            })?;
+                // Fetch package info
+                if !self.resolved_cache.contains_key(&name) {
+                    let response = self.npm_client.get_package_info(&name).await?;
+                    self.resolved_cache.insert(name.clone(), response);
+                }
+                let registry_response = self.resolved_cache.get(&name).unwrap();
+
+                Self::choose_version(registry_response, &version_spec)
+                    .map(|(package_info, _)| package_info)
+                    .ok_or_else(|| {
+                        errors::coded(
+                            ErrorCode::NotFound,
+                            format!("Version '{version_spec}' not found for package '{name}'"),
+                        )
+                    })?
+                    .clone()
+            };
 
-            let package_info = package_info.clone();
+            self.policy.check(&name, &package_info.version)?;
+            tracing::debug!("resolved {name}@{version_spec} to {}", package_info.version);
 
             // Update spinner for dependency processing if external spinner is provided
-            if let Some(spinner) = external_spinner {
-                if let Some(ref deps) = package_info.dependencies {
-                    if !deps.is_empty() {
-                        spinner.set_message(format!(
-                            "Processing {} dependencies for {}...",
-                            deps.len(),
-                            name
-                        ));
-                    }
-                }
+            if let Some(spinner) = external_spinner
+                && let Some(ref deps) = package_info.dependencies
+                && !deps.is_empty()
+            {
+                spinner.set_message(format!(
+                    "Processing {} dependencies for {}...",
+                    deps.len(),
+                    name
+                ));
             }
 
             // Add dependencies to work stack
@@ -219,10 +733,13 @@ impl PackageResolver {
                     dist: DistInfo {
                         tarball: String::new(),
                         shasum: String::new(),
+                        integrity: None,
+                        unpacked_size: None,
                     },
                     dependencies: None,
                     peer_dependencies: None,
                     optional_dependencies: None,
+                    registry: None,
                 },
                 dependencies: Vec::new(),
                 is_dev: false,
@@ -255,6 +772,25 @@ impl PackageResolver {
         Ok(pkg)
     }
 
+    /// The version the resolver installs for `spec`, and why: an exact version is taken as
+    /// is, while a dist-tag or range gets whatever `latest` points at
+    pub fn choose_version<'a>(
+        response: &'a NpmRegistryResponse,
+        spec: &str,
+    ) -> Option<(&'a PackageInfo, VersionChoice)> {
+        if spec == "latest" {
+            Some((response.get_latest_version()?, VersionChoice::DistTag))
+        } else if Self::is_exact_version(spec) {
+            Some((response.get_version(spec)?, VersionChoice::Exact))
+        } else {
+            // For ranges, use latest for now
+            Some((
+                response.get_latest_version()?,
+                VersionChoice::LatestForRange,
+            ))
+        }
+    }
+
     fn is_exact_version(version: &str) -> bool {
         if version.starts_with('^')
             || version.starts_with('~')
@@ -280,19 +816,6 @@ impl PackageResolver {
         }
     }
 
-    pub async fn resolve_multiple_packages(
-        &mut self,
-        packages: Vec<(String, String, bool)>, // name, version, is_dev
-    ) -> Result<Vec<ResolvedPackage>> {
-        if packages.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        let use_own_spinner = true;
-        self.resolve_multiple_packages_internal(packages, use_own_spinner, None)
-            .await
-    }
-
     pub async fn resolve_multiple_packages_with_spinner(
         &mut self,
         packages: Vec<(String, String, bool)>, // name, version, is_dev
@@ -302,6 +825,7 @@ impl PackageResolver {
             .await
     }
 
+    #[tracing::instrument(name = "resolve", skip_all, fields(packages = packages.len()))]
     async fn resolve_multiple_packages_internal(
         &mut self,
         packages: Vec<(String, String, bool)>, // name, version, is_dev
@@ -317,8 +841,9 @@ impl PackageResolver {
         // Don't create separate spinners when using external spinner
 
         // Create semaphore for concurrency control
-        let semaphore = Arc::new(Semaphore::new(50)); // Allow up to 50 concurrent resolutions
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
         let npm_client = self.npm_client.clone();
+        let concurrency = self.concurrency;
         let resolved_cache = Arc::new(Mutex::new(std::mem::take(&mut self.resolved_cache)));
 
         // Create futures for parallel resolution
@@ -328,12 +853,13 @@ impl PackageResolver {
             let semaphore = Arc::clone(&semaphore);
             let npm_client = npm_client.clone();
             let resolved_cache = Arc::clone(&resolved_cache);
+            let policy = self.policy.clone();
 
             let future = async move {
                 let _permit = semaphore.acquire().await.unwrap();
 
                 // Create a temporary resolver for this package
-                let mut temp_resolver = PackageResolver::new(npm_client);
+                let mut temp_resolver = PackageResolver::new(npm_client, concurrency, policy);
                 {
                     let cache = resolved_cache.lock().await;
                     temp_resolver.resolved_cache = cache.clone();
@@ -386,6 +912,7 @@ impl PackageResolver {
                 failed_packages.len()
             );
         }
+        self.failures.extend(failed_packages);
 
         Ok(resolved)
     }
@@ -425,6 +952,15 @@ struct ProgressTracker {
 
 impl ProgressTracker {
     fn new(total: u64) -> Self {
+        if !CliStyle::is_interactive() || progress::is_json() {
+            return Self {
+                progress_bar: CliStyle::create_progress_bar(total),
+                current: 0,
+                total,
+                start_time: Instant::now(),
+            };
+        }
+
         let pb = ProgressBar::new(total);
         pb.set_style(
             ProgressStyle::default_bar()
@@ -484,37 +1020,159 @@ pub struct PackageManager {
     semaphore: Arc<Semaphore>,
     file_mutex: Arc<Mutex<()>>,
     cache_dir: PathBuf,
-    use_toml_lock: bool,
+    /// Where downloaded tarballs wait to be stored or extracted
+    staging: ScratchDir,
+    lock_format: LockFormat,
     lock_mode: LockMode,
+    /// Lockfile changes made during an install or uninstall, written once when it finishes
+    lock_transaction: Mutex<Option<LockFile>>,
+    config: ClayConfig,
+    /// Print nothing to stdout; `clay install --json` reports through `install_report`
+    quiet: bool,
+    save_target: SaveTarget,
+    /// How long each install phase took, for the install report
+    phases: std::sync::Mutex<Vec<(&'static str, Duration)>>,
+    /// Problems an install worked around, for the install report
+    warnings: std::sync::Mutex<Vec<String>>,
+    build_cache: BuildCache,
+    /// How long each package spent in each install step, for `--timing`
+    package_timings: std::sync::Mutex<Vec<(String, &'static str, Duration)>>,
+    /// The monorepo's workspace packages by name, linked instead of downloaded
+    workspace_packages: HashMap<String, LocalPackage>,
+    /// The monorepo root's catalogs, which `catalog:` ranges resolve against
+    catalogs: Catalogs,
+    /// What the cached dependency tree is keyed by when the installed packages aren't
+    /// the ones package.json lists, as for hoisted workspace installs
+    tree_key: Option<String>,
+    /// Installing a workspace into the monorepo root's lockfile, where its edges are
+    /// the workspace entry's rather than the root's
+    shared_lock_file: bool,
 }
 
 impl PackageManager {
-    /// Create a new PackageManager with default settings
-    pub fn new() -> Self {
-        Self::with_toml_lock(true)
+    pub fn with_config(config: ClayConfig) -> Self {
+        let npm_client = NpmClient::with_config(&config);
+        Self::with_client(config, npm_client)
     }
 
-    pub fn with_toml_lock(use_toml: bool) -> Self {
+    /// Share an existing client (and its connection pool) instead of building a new one
+    pub fn with_client(config: ClayConfig, npm_client: NpmClient) -> Self {
         let cache_dir = Self::get_cache_dir();
-        let lock_file_path = if use_toml {
-            PathBuf::from("clay-lock.toml")
-        } else {
-            PathBuf::from("clay-lock.json")
-        };
+        let lock_format = config
+            .install
+            .lock_format
+            .unwrap_or_else(LockFormat::detect);
 
-        let lock_mode = Self::detect_lock_mode();
+        let lock_mode = config
+            .install
+            .lock_mode
+            .unwrap_or_else(Self::detect_lock_mode);
 
         Self {
-            npm_client: NpmClient::new(),
-            content_store: ContentStore::new(),
+            npm_client,
+            content_store: ContentStore::from_config(&config),
             node_modules_dir: PathBuf::from("node_modules"),
             package_json_path: PathBuf::from("package.json"),
-            lock_file_path,
-            semaphore: Arc::new(Semaphore::new(30)), // Limit concurrent downloads
+            lock_file_path: PathBuf::from(lock_format.file_name()),
+            semaphore: Arc::new(Semaphore::new(config.network.concurrency())),
             file_mutex: Arc::new(Mutex::new(())),
             cache_dir,
-            use_toml_lock: use_toml,
+            staging: ScratchDir::new("clay-staging"),
+            lock_format,
             lock_mode,
+            lock_transaction: Mutex::new(None),
+            config,
+            quiet: false,
+            save_target: SaveTarget::default(),
+            phases: std::sync::Mutex::new(Vec::new()),
+            warnings: std::sync::Mutex::new(Vec::new()),
+            build_cache: BuildCache::new(),
+            package_timings: std::sync::Mutex::new(Vec::new()),
+            workspace_packages: HashMap::new(),
+            catalogs: Catalogs::default(),
+            tree_key: None,
+            shared_lock_file: false,
+        }
+    }
+
+    /// Work on the project in `dir` instead of the current directory
+    pub fn with_project_dir(mut self, dir: &Path) -> Self {
+        self.node_modules_dir = dir.join("node_modules");
+        self.package_json_path = dir.join("package.json");
+        self.lock_file_path = dir.join(self.lock_format.file_name());
+        self
+    }
+
+    /// Lock into the lockfile of the monorepo rooted at `root`, as one of its workspaces
+    pub fn with_shared_lock_file(mut self, root: &Path) -> Self {
+        self.lock_file_path = root.join(self.lock_format.file_name());
+        self.shared_lock_file = true;
+        self
+    }
+
+    /// The directory holding package.json and node_modules
+    fn project_dir(&self) -> Result<PathBuf> {
+        match self.package_json_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => Ok(dir.to_path_buf()),
+            _ => Ok(std::env::current_dir()?),
+        }
+    }
+
+    /// Keep stdout free for a machine-readable report
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Install dependencies on these workspace packages as links to their directories
+    pub fn set_workspace_packages(&mut self, packages: HashMap<String, LocalPackage>) {
+        self.workspace_packages = packages;
+    }
+
+    /// Resolve `catalog:` ranges against these catalogs
+    pub fn set_catalogs(&mut self, catalogs: Catalogs) {
+        self.catalogs = catalogs;
+    }
+
+    /// Key the cached dependency tree of manifest installs by `key` instead of the
+    /// package.json fingerprint
+    pub fn set_tree_key(&mut self, key: String) {
+        self.tree_key = Some(key);
+    }
+
+    /// Which package.json field packages named on the command line are saved to
+    pub fn set_save_target(&mut self, save_target: SaveTarget) {
+        self.save_target = save_target;
+    }
+
+    /// A spinner on stderr, or a hidden one when quiet
+    fn spinner(&self, message: &str) -> ProgressBar {
+        if self.quiet {
+            ProgressBar::hidden()
+        } else {
+            CliStyle::create_spinner(message)
+        }
+    }
+
+    fn record_phase(&self, phase: &'static str, duration: Duration) {
+        if let Ok(mut phases) = self.phases.lock() {
+            phases.push((phase, duration));
+        }
+    }
+
+    fn record_package_step(&self, package: &str, step: &'static str, duration: Duration) {
+        if let Ok(mut timings) = self.package_timings.lock() {
+            timings.push((package.to_string(), step, duration));
+        }
+    }
+
+    /// Note a problem the install worked around, printing it unless quiet
+    fn warn(&self, message: String) {
+        tracing::warn!("{message}");
+        if !self.quiet {
+            eprintln!("{} {}", style("⚠").yellow(), message);
+        }
+        if let Ok(mut warnings) = self.warnings.lock() {
+            warnings.push(message);
         }
     }
 
@@ -533,9 +1191,9 @@ impl PackageManager {
             return LockMode::Explicit;
         }
 
-        // Check for existing lockfiles - use hybrid mode for backward compatibility
+        // Keep a lockfile the project already has up to date
         if PathBuf::from("clay-lock.toml").exists() || PathBuf::from("clay-lock.json").exists() {
-            return LockMode::Hybrid;
+            return LockMode::Explicit;
         }
 
         // Default to implicit (content-addressable) mode
@@ -573,14 +1231,12 @@ impl PackageManager {
 
         // Check if version matches (optional, for now just check existence)
         let package_json_path = package_dir.join("package.json");
-        if package_json_path.exists() {
-            if let Ok(content) = fs::read_to_string(&package_json_path).await {
-                if let Ok(package_json) = serde_json::from_str::<PackageJson>(&content) {
-                    if let Some(installed_version) = package_json.version {
-                        return Ok(installed_version == version);
-                    }
-                }
-            }
+        if package_json_path.exists()
+            && let Ok(content) = fs::read_to_string(&package_json_path).await
+            && let Ok(package_json) = serde_json::from_str::<PackageJson>(&content)
+            && let Some(installed_version) = package_json.version
+        {
+            return Ok(installed_version == version);
         }
 
         // Fallback: just check directory existence
@@ -592,14 +1248,11 @@ impl PackageManager {
         &self,
         is_dev_install: bool,
     ) -> Result<Option<DependencyTree>> {
-        // Only check cache for implicit/hybrid modes
-        if matches!(self.lock_mode, LockMode::Memory | LockMode::Explicit) {
+        if !self.lock_mode.caches_trees() {
             return Ok(None);
         }
 
-        let package_json = self.load_package_json().await?;
-        let dependency_fingerprint = package_json.calculate_dependency_fingerprint(is_dev_install);
-
+        let dependency_fingerprint = self.tree_fingerprint(is_dev_install).await?;
         if let Some(tree) = self
             .content_store
             .get_dependency_tree(&dependency_fingerprint)
@@ -612,14 +1265,30 @@ impl PackageManager {
         Ok(None)
     }
 
+    /// The key of the project's cached dependency tree
+    async fn tree_fingerprint(&self, include_dev: bool) -> Result<String> {
+        if let Some(key) = &self.tree_key {
+            return Ok(key.clone());
+        }
+        let package_json = self.load_package_json().await?;
+        let fingerprint = package_json.calculate_dependency_fingerprint(include_dev);
+        if self.catalogs.is_empty() {
+            return Ok(fingerprint);
+        }
+        // What `catalog:` ranges ask for lives in the root's package.json, not this one
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(fingerprint.as_bytes());
+        hasher.update(self.catalogs.fingerprint().as_bytes());
+        Ok(hasher.finalize().to_hex().to_string())
+    }
+
     /// Store dependency tree in content store using dependency fingerprint as key
     async fn store_dependency_tree(
         &self,
         tree: DependencyTree,
         dependency_fingerprint: &str,
     ) -> Result<()> {
-        // Only store for implicit/hybrid modes
-        if matches!(self.lock_mode, LockMode::Memory) {
+        if !self.lock_mode.caches_trees() {
             return Ok(());
         }
 
@@ -634,11 +1303,18 @@ impl PackageManager {
         Ok(())
     }
 
-    /// Create dependency tree from resolved packages
+    /// Create dependency tree from resolved packages, flattening transitive dependencies
+    /// the same way the node_modules layout does (first resolved version of a name wins)
     fn create_dependency_tree(&self, resolved_packages: &[ResolvedPackage]) -> DependencyTree {
         let mut tree = DependencyTree::new();
+        let mut stack: Vec<&ResolvedPackage> = resolved_packages.iter().rev().collect();
+
+        while let Some(package) = stack.pop() {
+            // Skip circular dependency stubs and names already recorded
+            if package.name == "circular" || tree.packages.contains_key(&package.name) {
+                continue;
+            }
 
-        for package in resolved_packages {
             let content_hash = format!("sha1:{}", package.info.dist.shasum);
             tree.add_package(
                 &package.name,
@@ -647,33 +1323,55 @@ impl PackageManager {
                 &package.info.dist.shasum,
                 package.info.dependencies.clone(),
             );
+
+            stack.extend(package.dependencies.iter().rev());
         }
 
         tree
     }
 
     /// Install packages from a cached dependency tree
+    #[tracing::instrument(name = "install cached tree", skip_all, fields(packages = tree.packages.len()))]
     async fn install_from_dependency_tree(
         &self,
         tree: &DependencyTree,
-        _is_dev: bool,
+        root_names: &[String],
     ) -> Result<()> {
+        let start_time = Instant::now();
         let packages_to_install: Vec<_> = tree.packages.iter().collect();
 
         if packages_to_install.is_empty() {
             return Ok(());
         }
 
-        let main_spinner = CliStyle::create_spinner("Installing from cached tree");
-
-        // Ensure node_modules directory exists
+        // Bail out before touching node_modules if the store can't satisfy the whole tree,
+        // first fetching damaged or missing entries again from where they came from
+        for (package_name, resolved_package) in &packages_to_install {
+            if !self
+                .content_store
+                .has_package(package_name, &resolved_package.version)
+                && !self
+                    .repair_stored_package(package_name, &resolved_package.version)
+                    .await
+            {
+                return Err(anyhow!(
+                    "Package {}@{} not found in content store",
+                    package_name,
+                    resolved_package.version
+                ));
+            }
+        }
+
+        let main_spinner = self.spinner("Installing from cached tree");
+
+        // Ensure node_modules directory exists
         self.ensure_node_modules_exists().await?;
 
         // Parallel package linking for speed
         use futures::stream::{FuturesUnordered, StreamExt};
-        
+
         let mut link_tasks = FuturesUnordered::new();
-        
+
         for (package_name, resolved_package) in &packages_to_install {
             if !self
                 .is_package_installed(package_name, &resolved_package.version)
@@ -683,10 +1381,14 @@ impl PackageManager {
                 let resolved_package = (*resolved_package).clone();
                 let content_store = &self.content_store;
                 let node_modules_dir = &self.node_modules_dir;
-                
+
                 let task = async move {
-                    // Try to link from content store first
+                    let started = Instant::now();
+                    // Replace any stale copy left at a different version
                     let target_path = node_modules_dir.join(&package_name);
+                    if target_path.exists() {
+                        fs::remove_dir_all(long_path::extend(&target_path)).await?;
+                    }
 
                     if !content_store
                         .link_package(&package_name, &resolved_package.version, &target_path)
@@ -699,9 +1401,9 @@ impl PackageManager {
                         ));
                     }
 
-                    Ok::<String, anyhow::Error>(package_name.clone())
+                    Ok::<_, anyhow::Error>((package_name, started.elapsed()))
                 };
-                
+
                 link_tasks.push(task);
             }
         }
@@ -710,7 +1412,13 @@ impl PackageManager {
         let mut linked_packages = Vec::new();
         while let Some(result) = link_tasks.next().await {
             match result {
-                Ok(package_name) => {
+                Ok((package_name, elapsed)) => {
+                    let version = &tree.packages[&package_name].version;
+                    progress::emit(Event::LinkDone {
+                        name: &package_name,
+                        version,
+                    });
+                    self.record_package_step(&format!("{package_name}@{version}"), "link", elapsed);
                     linked_packages.push(package_name);
                 }
                 Err(e) => {
@@ -720,28 +1428,66 @@ impl PackageManager {
             }
         }
 
+        self.content_store.save_access_times().await?;
+        self.record_project_references().await.ok();
+
         // Setup bin commands sequentially (faster than parallel for this)
         for package_name in &linked_packages {
             let target_path = self.node_modules_dir.join(package_name);
-            if let Err(_e) = self.setup_bin_commands(package_name, &target_path).await {
+            if let Err(_e) = self
+                .setup_bin_commands(
+                    &self.node_modules_dir.join(".bin"),
+                    package_name,
+                    &target_path,
+                )
+                .await
+            {
                 // Silent - don't clutter output
             }
         }
+        self.record_phase("link", start_time.elapsed());
 
+        if let Err(e) = self.apply_patches().await {
+            main_spinner.finish_and_clear();
+            return Err(e);
+        }
+        let linked: Vec<(String, String)> = linked_packages
+            .iter()
+            .map(|name| (name.clone(), tree.packages[name].version.clone()))
+            .collect();
+        if let Err(e) = self.build_packages(&linked, &main_spinner).await {
+            main_spinner.finish_and_clear();
+            return Err(e);
+        }
         main_spinner.finish_and_clear();
-        
+        progress::emit(Event::InstallDone {
+            packages: linked.len() as u64,
+            elapsed_ms: start_time.elapsed().as_millis() as u64,
+        });
+        if self.quiet {
+            return Ok(());
+        }
+
         // Print same format as regular installation
-        let package_names: Vec<String> = packages_to_install
+        let package_names: Vec<String> = root_names
             .iter()
-            .map(|(name, resolved)| format!("{}@{}", name, resolved.version))
+            .filter_map(|name| {
+                tree.packages
+                    .get(name)
+                    .map(|resolved| format!("{}@{}", name, resolved.version))
+            })
             .collect();
-        
+
         println!("clay install v0.1.1");
         println!();
         println!("installed {}", package_names.join(", "));
         println!();
-        println!("{} packages installed [0.1s]", packages_to_install.len());
-        
+        println!(
+            "{} packages installed [{}]",
+            packages_to_install.len(),
+            CliStyle::format_duration(start_time.elapsed())
+        );
+
         Ok(())
     }
 
@@ -773,7 +1519,7 @@ impl PackageManager {
             let bytes = fs::read(dest_path).await?;
             if !self
                 .npm_client
-                .verify_package_integrity(&bytes, &package_info.dist.shasum)?
+                .verify_package_integrity(&bytes, &package_info.dist)?
             {
                 // Cache is corrupted, remove it
                 fs::remove_file(&cache_path).await.ok();
@@ -793,18 +1539,30 @@ impl PackageManager {
     }
 
     /// Install multiple packages with unified progress
+    #[tracing::instrument(name = "install", skip_all)]
     pub async fn install_multiple_packages(
         &self,
         packages: Vec<(String, String)>,
         is_dev: bool,
         is_specific_install: bool,
     ) -> Result<()> {
+        // A lockfile that can't be read fails the install before anything changes
+        if self.lock_mode.writes_lock_file() {
+            self.load_lock_file().await?;
+        }
+
+        let requested: HashMap<String, String> = packages.iter().cloned().collect();
+        let packages = self.catalogs.resolve_all(packages)?;
+        let packages = self
+            .link_workspace_packages(packages, is_dev, is_specific_install)
+            .await?;
+
         // Early check: see if all packages are already installed
         let (already_installed, packages_to_check) =
             self.check_packages_already_installed(&packages).await?;
 
         // Show already installed packages only for specific installs
-        if is_specific_install {
+        if is_specific_install && !self.quiet {
             for package in &already_installed {
                 println!(
                     "{} {} already installed",
@@ -816,6 +1574,10 @@ impl PackageManager {
 
         // If all packages are already installed, skip resolution entirely
         if packages_to_check.is_empty() {
+            self.apply_patches().await?;
+            if self.quiet {
+                return Ok(());
+            }
             if is_specific_install {
                 println!(
                     "{}",
@@ -831,22 +1593,40 @@ impl PackageManager {
             return Ok(());
         }
 
-        // TODO: Re-enable cached installation after fixing archive extraction
-        // Check for cached dependency tree (content-addressable approach)
-        // if let Some(cached_tree) = self.check_cached_dependency_tree(is_dev).await? {
-        //     // We have a cached tree, try to install from it
-        //     match self.install_from_dependency_tree(&cached_tree, is_dev).await {
-        //         Ok(()) => {
-        //             // Cached installation successful - clean output already provided
-        //             return Ok(());
-        //         }
-        //         Err(_e) => {
-        //             // Cached installation failed, fall back to regular installation
-        //         }
-        //     }
-        // }
-
-        let mut resolver = PackageResolver::new(self.npm_client.clone());
+        // Manifest installs can skip resolution entirely when an identical manifest
+        // has been resolved before and every package is still in the content store
+        // (The cached tree is flat by name, so the isolated layout resolves again.)
+        if !is_specific_install
+            && self.config.install.node_linker == NodeLinker::Hoisted
+            && let Some(cached_tree) = self.check_cached_dependency_tree(is_dev).await?
+            // Resolve again if the policy changed since, so it reports what it blocks
+            && cached_tree.packages.iter().all(|(name, package)| {
+                self.config.policy.check(name, &package.version).is_ok()
+            })
+        {
+            let root_names: Vec<String> = packages.iter().map(|(name, _)| name.clone()).collect();
+            match self
+                .install_from_dependency_tree(&cached_tree, &root_names)
+                .await
+            {
+                Ok(()) => {
+                    // Cached installation successful - clean output already provided
+                    return Ok(());
+                }
+                Err(e) => {
+                    // Cached installation failed, fall back to regular installation
+                    self.warn(format!(
+                        "Cached dependency tree couldn't be installed ({e}); resolving again"
+                    ));
+                }
+            }
+        }
+
+        let mut resolver = PackageResolver::new(
+            self.npm_client.clone(),
+            self.config.network.resolve_concurrency(),
+            self.config.policy.clone(),
+        );
         let package_specs: Vec<(String, String, bool)> = packages_to_check
             .into_iter()
             .map(|(name, version)| (name, version, is_dev))
@@ -854,13 +1634,37 @@ impl PackageManager {
 
         // Single unified spinner for entire operation
         let start_time = std::time::Instant::now();
-        let main_spinner = CliStyle::create_spinner("clay install");
+        let main_spinner = self.spinner("clay install");
+        self.npm_client.attach_progress(&main_spinner);
 
         // Phase 1: Resolution
         main_spinner.set_message("resolving dependencies...");
+        progress::emit(Event::ResolveStart {
+            packages: package_specs.len() as u64,
+        });
         let resolved_packages = resolver
             .resolve_multiple_packages_with_spinner(package_specs, &main_spinner)
             .await?;
+        self.record_phase("resolve", start_time.elapsed());
+
+        // A package that can't be resolved fails the install unless it's optional
+        if !resolver.failures.is_empty() {
+            let names: Vec<&String> = resolver.failures.iter().map(|(name, _)| name).collect();
+            let optional = self.optional_roots(&names, is_specific_install).await;
+            for (name, e) in std::mem::take(&mut resolver.failures) {
+                if !optional.contains(&name) {
+                    main_spinner.finish_and_clear();
+                    self.npm_client.detach_progress();
+                    return Err(e.context(format!("Couldn't resolve {name}")));
+                }
+                self.warn(format!("{e:#}; skipping {name} as it's optional"));
+            }
+        }
+
+        progress::emit(Event::ResolveDone {
+            packages: PackageResolver::count_total_packages(&resolved_packages),
+            elapsed_ms: start_time.elapsed().as_millis() as u64,
+        });
 
         if resolved_packages.is_empty() {
             main_spinner.finish_with_message("No valid packages to install");
@@ -872,8 +1676,7 @@ impl PackageManager {
         let mut to_install = Vec::new();
 
         for resolved in &resolved_packages {
-            let package_dir = self.node_modules_dir.join(&resolved.name);
-            if package_dir.exists() {
+            if self.installed_at(&resolved.name, &resolved.version).await {
                 resolved_already_installed.push(resolved.name.clone());
             } else {
                 to_install.push(resolved);
@@ -881,7 +1684,7 @@ impl PackageManager {
         }
 
         // Show already installed dependencies only for specific installs
-        if is_specific_install {
+        if is_specific_install && !self.quiet {
             for package in &resolved_already_installed {
                 if !already_installed.contains(package) {
                     println!(
@@ -894,8 +1697,12 @@ impl PackageManager {
         }
 
         if to_install.is_empty() {
+            progress::emit(Event::InstallDone {
+                packages: 0,
+                elapsed_ms: start_time.elapsed().as_millis() as u64,
+            });
             main_spinner.finish_with_message("All packages already installed");
-            if !is_specific_install {
+            if !is_specific_install && !self.quiet {
                 self.show_installed_packages_summary().await?;
             }
             return Ok(());
@@ -912,20 +1719,28 @@ impl PackageManager {
         // Phase 3: Install with same spinner
         main_spinner.set_message("installing packages...");
 
-        for resolved_pkg in &to_install {
-            main_spinner.set_message(format!("installing {}...", resolved_pkg.name));
-            self.install_resolved_package_with_spinner(resolved_pkg, true, &main_spinner)
-                .await?;
-        }
+        self.begin_lock_transaction().await?;
+        let installed = self
+            .install_resolved_packages(
+                &to_install,
+                is_specific_install.then_some(&requested),
+                &main_spinner,
+            )
+            .await;
+        self.finish_lock_transaction(installed).await?;
+        self.content_store.save_access_times().await?;
+        self.record_project_references().await.ok();
 
         // Create Bun-style final summary
         let duration = start_time.elapsed();
+        // Skipped optional packages aren't listed
         let installed_packages: Vec<String> = to_install
             .iter()
+            .filter(|pkg| self.package_location(&pkg.name, &pkg.version).exists())
             .map(|pkg| format!("{}@{}", pkg.name, pkg.version))
             .collect();
 
-        let summary = if to_install.len() == 1 {
+        let summary = if installed_packages.len() == 1 {
             format!("installed {}", installed_packages[0])
         } else {
             format!("installed {}", installed_packages.join(", "))
@@ -938,16 +1753,31 @@ impl PackageManager {
         );
 
         main_spinner.finish_and_clear();
-
-        // Store dependency tree in content store (content-addressable approach)
-        let dependency_tree = self.create_dependency_tree(&resolved_packages);
-        let package_json = self.load_package_json().await?;
-        let dependency_fingerprint = package_json.calculate_dependency_fingerprint(is_dev);
-        if let Err(_e) = self
-            .store_dependency_tree(dependency_tree, &dependency_fingerprint)
-            .await
+        self.npm_client.detach_progress();
+        progress::emit(Event::InstallDone {
+            packages: total_packages,
+            elapsed_ms: duration.as_millis() as u64,
+        });
+
+        // Store dependency tree in content store (content-addressable approach).
+        // Only a full manifest install resolves the complete tree for the fingerprint, and
+        // a tree with skipped optional packages would have to resolve again anyway.
+        if !is_specific_install
+            && already_installed.is_empty()
+            && installed_packages.len() == to_install.len()
         {
-            // Silent - dependency tree storage failure doesn't affect functionality
+            let dependency_tree = self.create_dependency_tree(&resolved_packages);
+            let dependency_fingerprint = self.tree_fingerprint(is_dev).await?;
+            if let Err(_e) = self
+                .store_dependency_tree(dependency_tree, &dependency_fingerprint)
+                .await
+            {
+                // Silent - dependency tree storage failure doesn't affect functionality
+            }
+        }
+
+        if self.quiet {
+            return Ok(());
         }
 
         // Print final summary like Bun with proper spacing
@@ -960,606 +1790,565 @@ impl PackageManager {
         Ok(())
     }
 
-    /// Install a package and save it to node_modules
-    pub async fn install_package(&self, package_name: &str, version: &str) -> Result<()> {
-        self.install_multiple_packages(
-            vec![(package_name.to_string(), version.to_string())],
-            false,
-            true,
-        )
-        .await
-    }
-
-    /// Count total packages that will be installed (including dependencies)
-    async fn count_packages_to_install(&self, package_name: &str, version: &str) -> Result<u64> {
-        let mut count = 0;
-
-        // Check if main package needs installation
-        let package_dir = self.node_modules_dir.join(package_name);
-        if !package_dir.exists() {
-            count += 1;
-
-            // Fetch package info to check dependencies
-            let registry_response = self.npm_client.get_package_info(package_name).await?;
-            let package_info = if version == "latest" {
-                registry_response.get_latest_version()
-            } else {
-                registry_response.get_version(version)
-            };
-
-            if let Some(package_info) = package_info {
-                if let Some(ref dependencies) = package_info.dependencies {
-                    for dep_name in dependencies.keys() {
-                        let dep_package_dir = self.node_modules_dir.join(dep_name);
-                        if !dep_package_dir.exists() {
-                            count += 1;
+    /// Link the dependencies that are workspace packages to their directories, returning
+    /// the rest for the registry. `workspace:` ranges must be met by a workspace; other
+    /// ranges link one when its version is in range.
+    async fn link_workspace_packages(
+        &self,
+        packages: Vec<(String, String)>,
+        is_dev: bool,
+        save: bool,
+    ) -> Result<Vec<(String, String)>> {
+        let project_dir = std::path::absolute(self.project_dir()?)?;
+        let mut remaining = Vec::new();
+        for (name, range) in packages {
+            let local = self
+                .workspace_packages
+                .get(&name)
+                .filter(|local| local.dir != project_dir);
+            let Some(local) = local.filter(|local| local.satisfies(&range)) else {
+                if range.starts_with("workspace:") {
+                    return Err(match local {
+                        Some(local) => anyhow!(
+                            "{name}@{range} doesn't match the workspace's version {}",
+                            local.version
+                        ),
+                        None => {
+                            anyhow!("{name}@{range} asks for a workspace, and none is named {name}")
                         }
-                    }
+                    });
                 }
-            }
-        }
-
-        Ok(count)
-    }
-
-    /// Install a resolved package with its dependencies (using spinner)
-    async fn install_resolved_package_with_spinner(
-        &self,
-        resolved_pkg: &ResolvedPackage,
-        update_package_json: bool,
-        spinner: &indicatif::ProgressBar,
-    ) -> Result<()> {
-        // Check if already installed
-        let package_dir = self.node_modules_dir.join(&resolved_pkg.name);
-        if package_dir.exists() {
-            return Ok(());
-        }
+                remaining.push((name, range));
+                continue;
+            };
 
-        // Install dependencies first
-        for dep in &resolved_pkg.dependencies {
-            spinner.set_message(format!("Installing {}...", dep.name));
-            Box::pin(self.install_resolved_package_with_spinner(dep, false, spinner)).await?;
+            let link = self.node_modules_dir.join(&name);
+            virtual_store::link(&local.dir, &std::path::absolute(&link)?)?;
+            self.setup_bin_commands(&self.node_modules_dir.join(".bin"), &name, &link)
+                .await?;
+            progress::emit(Event::LinkDone {
+                name: &name,
+                version: &local.version,
+            });
+            if save && let Some(field) = self.save_target.field(is_dev) {
+                let saved = if range == "latest" {
+                    "workspace:^"
+                } else {
+                    &range
+                };
+                self.update_package_json(&name, saved, field).await?;
+            }
+            if !self.quiet {
+                println!(
+                    "{} {} {}",
+                    CliStyle::success("linked"),
+                    CliStyle::package_name(&name),
+                    CliStyle::dim_text(&format!("→ {}", local.dir.display()))
+                );
+            }
         }
-
-        // Install this package
-        spinner.set_message(format!("Installing {}...", resolved_pkg.name));
-        self.install_single_package_with_spinner(
-            &resolved_pkg.info,
-            update_package_json,
-            resolved_pkg.is_dev,
-            spinner,
-        )
-        .await?;
-
-        Ok(())
+        Ok(remaining)
     }
 
-    /// Install a resolved package with its dependencies (legacy progress bar)
-    async fn install_resolved_package(
-        &self,
-        resolved_pkg: &ResolvedPackage,
-        update_package_json: bool,
-        progress: &mut ProgressTracker,
-    ) -> Result<()> {
-        // Check if already installed
-        let package_dir = self.node_modules_dir.join(&resolved_pkg.name);
-        if package_dir.exists() {
-            progress.update(&format!("{} (cached)", resolved_pkg.name));
-            return Ok(());
-        }
-
-        // Install dependencies first
-        for dep in &resolved_pkg.dependencies {
-            Box::pin(self.install_resolved_package(dep, false, progress)).await?;
+    /// Give this project's node_modules/.bin the commands of `names`, installed in
+    /// another node_modules (the monorepo root's, for hoisted dependencies)
+    pub async fn link_bins_from(&self, node_modules: &Path, names: &[String]) -> Result<()> {
+        let bin_dir = self.node_modules_dir.join(".bin");
+        for name in names {
+            self.setup_bin_commands(&bin_dir, name, &node_modules.join(name))
+                .await?;
         }
-
-        // Install this package
-        self.install_single_package(
-            &resolved_pkg.info,
-            update_package_json,
-            resolved_pkg.is_dev,
-            progress,
-        )
-        .await?;
-
         Ok(())
     }
 
-    /// Install a single package without dependency resolution (using spinner)
-    async fn install_single_package_with_spinner(
+    /// The single install pipeline: every package of the resolved trees not yet in
+    /// node_modules is fetched (from the store, the tarball cache or the registry) and
+    /// linked concurrently, then bins, package.json and the lockfile are updated in
+    /// dependency order. `roots` are the packages the user asked for; `save` holds the
+    /// version specs they were asked for with, to record in package.json (None when
+    /// installing what package.json already lists).
+    #[tracing::instrument(name = "place packages", skip_all)]
+    async fn install_resolved_packages(
         &self,
-        package_info: &PackageInfo,
-        update_package_json: bool,
-        is_dev: bool,
+        roots: &[&ResolvedPackage],
+        save: Option<&HashMap<String, String>>,
         spinner: &indicatif::ProgressBar,
     ) -> Result<()> {
-        // Skip circular dependency stubs
-        if package_info.name == "circular" {
-            return Ok(());
+        if self.config.install.node_linker == NodeLinker::Isolated {
+            return self.install_isolated(roots, save, spinner).await;
         }
 
-        // Ensure node_modules directory exists
-        self.ensure_node_modules_exists().await?;
-
-        // Check if package is already installed
-        let package_dir = self.node_modules_dir.join(&package_info.name);
-        if package_dir.exists() {
-            return Ok(());
+        // node_modules is flat, so the first version of a name reached wins
+        fn collect<'a>(
+            package: &'a ResolvedPackage,
+            is_root: bool,
+            seen: &mut HashSet<String>,
+            out: &mut Vec<(&'a ResolvedPackage, bool)>,
+        ) {
+            if !seen.insert(package.name.clone()) {
+                return;
+            }
+            for dep in &package.dependencies {
+                collect(dep, false, seen, out);
+            }
+            out.push((package, is_root));
         }
 
-        // Download the package tarball
-        spinner.set_message(format!("Downloading {}...", package_info.name));
-        let tarball_path = self.download_package_tarball(package_info).await?;
-
-        // Check if tarball was actually created
-        if !tarball_path.exists() {
-            return Err(anyhow!(
-                "Failed to download tarball for {}",
-                package_info.name
-            ));
+        let mut seen = HashSet::new();
+        let mut packages = Vec::new();
+        for root in roots {
+            collect(root, true, &mut seen, &mut packages);
         }
-
-        // Store package in content store before extraction
-        spinner.set_message(format!("Storing {}...", package_info.name));
-        let tarball_data = fs::read(&tarball_path).await?;
-        if let Err(_e) = self.content_store.store_package(
-            &package_info.name,
-            &package_info.version,
-            &tarball_data,
-            &package_info.dist.shasum,
-        ).await {
-            // Silent - don't clutter final output
+        // Skip circular dependency stubs and what's already there. Dependencies keep the
+        // version that got there first; a requested package at another version is replaced.
+        let mut to_place = Vec::new();
+        for (package, is_root) in packages {
+            let package_dir = self.node_modules_dir.join(&package.name);
+            if package.info.name == "circular"
+                || package_dir.exists()
+                    && (!is_root || self.installed_at(&package.name, &package.version).await)
+            {
+                continue;
+            }
+            if package_dir.exists() {
+                fs::remove_dir_all(&package_dir).await?;
+            }
+            to_place.push((package, is_root));
         }
+        let packages = to_place;
 
-        // Extract the tarball to node_modules
-        spinner.set_message(format!("Extracting {}...", package_info.name));
-        self.extract_package(&tarball_path, &package_dir).await?;
+        self.ensure_node_modules_exists().await?;
+        let optional = self.optional_only(roots, save).await;
 
-        // Setup bin commands for this package
-        self.setup_bin_commands(&package_info.name, &package_dir)
+        let fetch_start = Instant::now();
+        let placed = futures::future::join_all(packages.iter().map(|(package, _)| async move {
+            let package_dir = self.node_modules_dir.join(&package.info.name);
+            self.place_package_with_retries(&package.info, &package_dir, |step| {
+                spinner.set_message(format!("{step} {}...", package.info.name))
+            })
+            .await
+        }))
+        .await;
+        self.record_phase("fetch", fetch_start.elapsed());
+        let placed = self.settle_placed(
+            packages.iter().map(|(package, _)| *package),
+            placed,
+            &optional,
+        )?;
+
+        let link_start = Instant::now();
+        for ((package, is_root), integrity) in packages.iter().zip(placed) {
+            let Some(integrity) = integrity else {
+                continue;
+            };
+            let package_info = &package.info;
+            let package_dir = self.node_modules_dir.join(&package_info.name);
+
+            // Setup bin commands for this package
+            self.setup_bin_commands(
+                &self.node_modules_dir.join(".bin"),
+                &package_info.name,
+                &package_dir,
+            )
             .await?;
 
-        // Clean up the tarball and temp directory
-        if tarball_path.exists() {
-            fs::remove_file(&tarball_path).await.ok();
-        }
-        if let Some(temp_dir) = tarball_path.parent() {
-            fs::remove_dir_all(temp_dir).await.ok();
-        }
+            if *is_root {
+                self.save_requested(package, save).await?;
+            }
 
-        // Update package.json only if this is the explicitly requested package
-        if update_package_json {
-            self.update_package_json(&package_info.name, &package_info.version, is_dev)
+            // Dependency edges are recorded on the dependent's own entry
+            let parent_name = if *is_root { "root" } else { &package_info.name };
+            self.update_lock_file_resolved(package_info, &integrity, parent_name)
                 .await?;
         }
 
-        // Update lock file
-        let parent_name = if update_package_json {
-            "root"
-        } else {
-            // For dependency packages, use the package name as parent
-            &package_info.name
-        };
-
-        self.update_lock_file(
-            &package_info.name,
-            &package_info.version,
-            &package_info.dist.tarball,
-            &package_info.dist.shasum,
-            package_info.dependencies.as_ref(),
-            parent_name,
-        )
-        .await?;
+        self.record_phase("link", link_start.elapsed());
+        self.apply_patches().await?;
 
-        Ok(())
+        let placed: Vec<(String, String)> = packages
+            .iter()
+            .filter(|(package, _)| self.node_modules_dir.join(&package.info.name).exists())
+            .map(|(package, _)| (package.info.name.clone(), package.info.version.clone()))
+            .collect();
+        self.build_packages(&placed, spinner).await
     }
 
-    /// Install a single package without dependency resolution (legacy progress bar)
-    async fn install_single_package(
+    /// Install into the isolated layout: each version once, under
+    /// node_modules/.clay/<name>@<version>/node_modules/<name>, with links to its own
+    /// dependencies beside it, and only `roots` linked into node_modules itself
+    async fn install_isolated(
         &self,
-        package_info: &PackageInfo,
-        update_package_json: bool,
-        is_dev: bool,
-        progress: &mut ProgressTracker,
+        roots: &[&ResolvedPackage],
+        save: Option<&HashMap<String, String>>,
+        spinner: &indicatif::ProgressBar,
     ) -> Result<()> {
-        // Skip circular dependency stubs
-        if package_info.name == "circular" {
-            return Ok(());
+        // Every version reached, dependencies first
+        fn collect<'a>(
+            package: &'a ResolvedPackage,
+            seen: &mut HashSet<(String, String)>,
+            out: &mut Vec<&'a ResolvedPackage>,
+        ) {
+            // Skip circular dependency stubs
+            if package.info.name == "circular"
+                || !seen.insert((package.name.clone(), package.version.clone()))
+            {
+                return;
+            }
+            for dep in &package.dependencies {
+                collect(dep, seen, out);
+            }
+            out.push(package);
         }
 
-        // Ensure node_modules directory exists
-        self.ensure_node_modules_exists().await?;
-
-        // Check if package is already installed
-        let package_dir = self.node_modules_dir.join(&package_info.name);
-        if package_dir.exists() {
-            return Ok(());
+        let mut seen = HashSet::new();
+        let mut packages = Vec::new();
+        for root in roots {
+            collect(root, &mut seen, &mut packages);
         }
-
-        // Download the package tarball
-        progress.update(&format!("Downloading {}", package_info.name));
-        let tarball_path = self.download_package_tarball(package_info).await?;
-
-        // Check if tarball was actually created
-        if !tarball_path.exists() {
-            return Err(anyhow!(
-                "Failed to download tarball for {}",
-                package_info.name
-            ));
+        // A dependency cut short as circular links to the version reached elsewhere
+        let mut versions: HashMap<&str, &str> = HashMap::new();
+        for package in &packages {
+            versions.entry(&package.name).or_insert(&package.version);
         }
 
-        // Store package in content store before extraction (silent)
-        let tarball_data = fs::read(&tarball_path).await?;
-        if let Err(_e) = self.content_store.store_package(
-            &package_info.name,
-            &package_info.version,
-            &tarball_data,
-            &package_info.dist.shasum,
-        ).await {
-            // Silent warning - don't clutter output
+        self.ensure_node_modules_exists().await?;
+        if !self.node_modules_dir.join(virtual_store::DIR).exists()
+            && !self.installed_snapshot().is_empty()
+        {
+            self.warn(
+                "node_modules has a hoisted layout; delete it and install again to drop \
+                 packages the project doesn't declare"
+                    .to_string(),
+            );
         }
 
-        // Extract the tarball to node_modules
-        progress.update(&format!("Extracting {}", package_info.name));
-        self.extract_package(&tarball_path, &package_dir).await?;
+        let to_place: Vec<&ResolvedPackage> = packages
+            .iter()
+            .copied()
+            .filter(|package| {
+                !self
+                    .package_location(&package.name, &package.version)
+                    .exists()
+            })
+            .collect();
 
-        // Setup bin commands for this package
-        self.setup_bin_commands(&package_info.name, &package_dir)
-            .await?;
+        let optional = self.optional_only(roots, save).await;
+        let fetch_start = Instant::now();
+        let placed = futures::future::join_all(to_place.iter().map(|package| async move {
+            let package_dir = self.package_location(&package.name, &package.version);
+            self.place_package_with_retries(&package.info, &package_dir, |step| {
+                spinner.set_message(format!("{step} {}...", package.info.name))
+            })
+            .await
+        }))
+        .await;
+        self.record_phase("fetch", fetch_start.elapsed());
+        let placed: Vec<(&ResolvedPackage, String)> = to_place
+            .iter()
+            .copied()
+            .zip(self.settle_placed(to_place.iter().copied(), placed, &optional)?)
+            .filter_map(|(package, integrity)| Some((package, integrity?)))
+            .collect();
+        let to_place: Vec<&ResolvedPackage> = placed.iter().map(|(package, _)| *package).collect();
 
-        // Clean up the tarball and temp directory
-        if tarball_path.exists() {
-            fs::remove_file(&tarball_path).await.ok();
-        }
-        if let Some(temp_dir) = tarball_path.parent() {
-            fs::remove_dir_all(temp_dir).await.ok();
+        let link_start = Instant::now();
+        for package in &to_place {
+            let entry_node_modules = virtual_store::entry_node_modules(
+                &self.node_modules_dir,
+                &package.name,
+                &package.version,
+            );
+
+            let declared = package.info.dependencies.iter().flatten();
+            for (dep_name, _) in declared {
+                let version = package
+                    .dependencies
+                    .iter()
+                    .find(|dep| &dep.name == dep_name)
+                    .map(|dep| dep.version.as_str())
+                    .or_else(|| versions.get(dep_name.as_str()).copied());
+                let Some(version) = version else {
+                    continue;
+                };
+                let dep_dir = self.package_location(dep_name, version);
+                // A skipped optional dependency is left out
+                if !dep_dir.exists() {
+                    continue;
+                }
+                virtual_store::link(&dep_dir, &entry_node_modules.join(dep_name))?;
+                // Install scripts find their dependencies' commands on PATH
+                self.setup_bin_commands(&entry_node_modules.join(".bin"), dep_name, &dep_dir)
+                    .await?;
+            }
         }
 
-        // Update package.json only if this is the explicitly requested package
-        if update_package_json {
-            self.update_package_json(&package_info.name, &package_info.version, is_dev)
+        for root in roots.iter().filter(|root| root.info.name != "circular") {
+            let location = self.package_location(&root.name, &root.version);
+            if !location.exists() {
+                continue;
+            }
+            let top_level = self.node_modules_dir.join(&root.name);
+            virtual_store::link(&location, &top_level)?;
+            self.setup_bin_commands(&self.node_modules_dir.join(".bin"), &root.name, &top_level)
                 .await?;
+            self.save_requested(root, save).await?;
         }
 
-        // Update lock file
-        let parent_name = if update_package_json {
-            "root"
-        } else {
-            // For dependency packages, use the package name as parent
-            &package_info.name
-        };
-
-        self.update_lock_file(
-            &package_info.name,
-            &package_info.version,
-            &package_info.dist.tarball,
-            &package_info.dist.shasum,
-            package_info.dependencies.as_ref(),
-            parent_name,
-        )
-        .await?;
-
-        // Update progress for main package
-        progress.update(&format!("Installed {}", package_info.name));
-
-        // Install dependencies in parallel if any
-        if let Some(ref dependencies) = package_info.dependencies {
-            self.install_dependencies_parallel(dependencies, &package_info.name, progress)
+        for (package, integrity) in &placed {
+            let is_root = roots
+                .iter()
+                .any(|root| root.name == package.name && root.version == package.version);
+            let parent_name = if is_root { "root" } else { &package.name };
+            self.update_lock_file_resolved(&package.info, integrity, parent_name)
                 .await?;
         }
+        self.record_phase("link", link_start.elapsed());
+        self.apply_patches().await?;
 
-        Ok(())
+        let placed: Vec<(String, String)> = to_place
+            .iter()
+            .map(|package| (package.name.clone(), package.version.clone()))
+            .collect();
+        self.build_packages(&placed, spinner).await
     }
 
-    /// Install dependencies in parallel
-    async fn install_dependencies_parallel(
+    /// Names in the trees of `roots` that only optional or peer dependencies lead to, so
+    /// failing to fetch one skips it rather than failing the install
+    async fn optional_only(
         &self,
-        dependencies: &std::collections::HashMap<String, String>,
-        parent_name: &str,
-        progress: &mut ProgressTracker,
-    ) -> Result<()> {
-        let mut tasks = Vec::new();
-
-        for (dep_name, dep_version) in dependencies {
-            // Check if dependency is already installed
-            let dep_package_dir = self.node_modules_dir.join(dep_name);
-            if dep_package_dir.exists() {
-                // Still add to lock file to track dependency relationship
-                self.update_lock_file(dep_name, dep_version, "", "", None, parent_name)
-                    .await?;
-                continue;
+        roots: &[&ResolvedPackage],
+        save: Option<&HashMap<String, String>>,
+    ) -> HashSet<String> {
+        fn reach(package: &ResolvedPackage, names: &mut HashSet<String>) {
+            if package.info.name == "circular" || !names.insert(package.name.clone()) {
+                return;
             }
+            for dep in &package.dependencies {
+                reach(dep, names);
+            }
+        }
 
-            // Clone data for the async task
-            let dep_name = dep_name.clone();
-            let dep_version = dep_version.clone();
-            let parent_name = parent_name.to_string();
-            let npm_client = self.npm_client.clone();
-            let node_modules_dir = self.node_modules_dir.clone();
-            let lock_file_path = self.lock_file_path.clone();
-            let semaphore = Arc::clone(&self.semaphore);
-            let file_mutex = Arc::clone(&self.file_mutex);
-
-            // Spawn async task for each dependency
-            let task = tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.unwrap();
+        let names: Vec<&String> = roots.iter().map(|root| &root.name).collect();
+        let optional_roots = self.optional_roots(&names, save.is_some()).await;
 
-                // Resolve version range
-                let registry_response = npm_client.get_package_info(&dep_name).await?;
-                let resolved_version = if dep_version == "latest" {
-                    registry_response
-                        .get_latest_version()
-                        .map(|p| p.version.clone())
-                } else {
-                    // Simple version resolution for ranges
-                    if Self::is_exact_version(&dep_version) {
-                        Some(dep_version.clone())
-                    } else {
-                        registry_response
-                            .get_latest_version()
-                            .map(|p| p.version.clone())
-                    }
-                };
+        let mut required = HashSet::new();
+        let mut all = HashSet::new();
+        for root in roots {
+            if !optional_roots.contains(&root.name) {
+                reach(root, &mut required);
+            }
+            reach(root, &mut all);
+        }
+        all.retain(|name| !required.contains(name));
+        all
+    }
 
-                let resolved_version = resolved_version
-                    .ok_or_else(|| anyhow::anyhow!("Could not resolve version for {}", dep_name))?;
-
-                let package_info = registry_response
-                    .get_version(&resolved_version)
-                    .or_else(|| registry_response.get_latest_version())
-                    .ok_or_else(|| anyhow::anyhow!("Package info not found for {}", dep_name))?;
-
-                // Download package with integrity verification
-                let tarball_path = {
-                    let tarball_filename =
-                        format!("{}-{}.tgz", package_info.name, package_info.version);
-
-                    // Create unique temp directory per package to avoid conflicts
-                    let temp_dir = PathBuf::from("temp").join(&dep_name);
-                    let tarball_path = temp_dir.join(&tarball_filename);
-
-                    // Ensure temp directory exists
-                    tokio::fs::create_dir_all(&temp_dir).await?;
-
-                    // Download and verify
-                    let response = npm_client
-                        .client
-                        .get(&package_info.dist.tarball)
-                        .send()
-                        .await?;
-                    if !response.status().is_success() {
-                        return Err(anyhow::anyhow!(
-                            "Failed to download package: HTTP {}",
-                            response.status()
-                        ));
-                    }
+    /// Which of the requested `names` are optional: all of them when saving to
+    /// optional or peer dependencies, otherwise those package.json lists only under
+    /// optionalDependencies
+    async fn optional_roots(&self, names: &[&String], save: bool) -> HashSet<String> {
+        if save {
+            return match self.save_target {
+                SaveTarget::Optional | SaveTarget::Peer => {
+                    names.iter().map(|name| name.to_string()).collect()
+                }
+                _ => HashSet::new(),
+            };
+        }
 
-                    let bytes = response.bytes().await?;
+        let package_json = self
+            .load_package_json()
+            .await
+            .unwrap_or_else(|_| PackageJson::new());
+        let listed = |deps: &Option<HashMap<String, String>>, name: &String| {
+            deps.as_ref().is_some_and(|deps| deps.contains_key(name))
+        };
+        names
+            .iter()
+            .filter(|name| {
+                listed(&package_json.optional_dependencies, name)
+                    && !listed(&package_json.dependencies, name)
+                    && !listed(&package_json.dev_dependencies, name)
+            })
+            .map(|name| name.to_string())
+            .collect()
+    }
 
-                    // Verify integrity
-                    if !npm_client.verify_package_integrity(&bytes, &package_info.dist.shasum)? {
-                        return Err(anyhow::anyhow!(
-                            "Package integrity verification failed for {}",
-                            package_info.name
-                        ));
-                    }
-
-                    // Write to file with proper error handling
-                    if let Some(parent) = tarball_path.parent() {
-                        tokio::fs::create_dir_all(parent).await?;
-                    }
-                    let mut file = tokio::fs::File::create(&tarball_path).await?;
-                    use tokio::io::AsyncWriteExt;
-                    file.write_all(&bytes).await?;
-                    file.sync_all().await?;
-
-                    tarball_path
-                };
-
-                // Extract package
-                let package_dir = node_modules_dir.join(&package_info.name);
-                tokio::fs::create_dir_all(&package_dir).await?;
-
-                // Check if tarball exists before extraction
-                if !tarball_path.exists() {
-                    return Err(anyhow::anyhow!("Tarball not found: {:?}", tarball_path));
-                }
-
-                let output = tokio::process::Command::new("tar")
-                    .args([
-                        "-xzf",
-                        tarball_path.to_str().unwrap(),
-                        "-C",
-                        package_dir.to_str().unwrap(),
-                        "--strip-components=1",
-                    ])
-                    .output()
-                    .await?;
-
-                if !output.status.success() {
-                    let error_message = String::from_utf8_lossy(&output.stderr);
-                    return Err(anyhow::anyhow!(
-                        "Failed to extract tarball for {}: {}",
+    /// `place_package`, tried again up to `network.fetch-retries` times with a growing
+    /// pause between attempts
+    async fn place_package_with_retries(
+        &self,
+        package_info: &PackageInfo,
+        package_dir: &Path,
+        on_step: impl Fn(&str),
+    ) -> Result<String> {
+        let retries = self.config.network.fetch_retries;
+        let mut attempt = 0;
+        loop {
+            match self
+                .place_package(package_info, package_dir, &on_step)
+                .await
+            {
+                Err(e) if attempt < retries => {
+                    tracing::info!(
+                        "{}@{} attempt {} failed, retrying: {e:#}",
                         package_info.name,
-                        error_message
-                    ));
-                }
-
-                // Clean up tarball and temp directory
-                if tarball_path.exists() {
-                    tokio::fs::remove_file(&tarball_path).await.ok();
-                }
-                if let Some(temp_dir) = tarball_path.parent() {
-                    tokio::fs::remove_dir_all(temp_dir).await.ok();
-                }
-
-                // Update lock file with mutex protection
-                {
-                    let _lock = file_mutex.lock().await;
-                    let mut lock_file = if lock_file_path.exists() {
-                        let content = tokio::fs::read_to_string(&lock_file_path).await?;
-                        if content.trim().is_empty() {
-                            LockFile::new()
-                        } else {
-                            serde_json::from_str::<LockFile>(&content)
-                                .unwrap_or_else(|_| LockFile::new())
-                        }
-                    } else {
-                        LockFile::new()
-                    };
-
-                    lock_file.add_package(
-                        &package_info.name,
-                        &package_info.version,
-                        &package_info.dist.tarball,
-                        &package_info.dist.shasum,
-                        package_info.dependencies.clone(),
-                        &parent_name,
+                        package_info.version,
+                        attempt + 1
                     );
-
-                    let content = serde_json::to_string_pretty(&lock_file)?;
-                    tokio::fs::write(&lock_file_path, content).await?;
+                    // Don't leave a half-linked package for the next attempt to trip over
+                    fs::remove_dir_all(package_dir).await.ok();
+                    on_step("Retrying");
+                    tokio::time::sleep(Duration::from_millis(500 << attempt)).await;
+                    attempt += 1;
                 }
-
-                Ok::<(String, Option<std::collections::HashMap<String, String>>), anyhow::Error>((
-                    dep_name,
-                    package_info.dependencies.clone(),
-                ))
-            });
-
-            tasks.push(task);
-        }
-
-        // Wait for all downloads to complete
-        let mut nested_dependencies = Vec::new();
-        for task in tasks {
-            match task.await? {
-                Ok((dep_name, deps)) => {
-                    progress.update(&format!("Installed {dep_name}"));
-                    if let Some(deps) = deps {
-                        nested_dependencies.push((dep_name, deps));
-                    }
+                Err(e) => {
+                    fs::remove_dir_all(package_dir).await.ok();
+                    let key = format!("{}@{}", package_info.name, package_info.version);
+                    return Err(match attempt {
+                        0 => e.context(format!("Couldn't fetch {key}")),
+                        _ => e.context(format!(
+                            "Couldn't fetch {key} after {} attempts",
+                            attempt + 1
+                        )),
+                    });
                 }
-                Err(e) => return Err(e),
+                Ok(integrity) => return Ok(integrity),
             }
         }
+    }
 
-        // Install nested dependencies (still parallel but after current level)
-        for (dep_name, deps) in nested_dependencies {
-            if !deps.is_empty() {
-                Box::pin(self.install_dependencies_parallel(&deps, &dep_name, progress)).await?;
+    /// The integrity of each placed package, or None for an optional one that failed and
+    /// is skipped with a warning. A required package that failed fails the install.
+    fn settle_placed<'a>(
+        &self,
+        packages: impl Iterator<Item = &'a ResolvedPackage>,
+        placed: Vec<Result<String>>,
+        optional: &HashSet<String>,
+    ) -> Result<Vec<Option<String>>> {
+        let mut settled = Vec::new();
+        let mut skipped = Vec::new();
+        let mut failure = None;
+        for (package, result) in packages.zip(placed) {
+            match result {
+                Ok(integrity) => settled.push(Some(integrity)),
+                Err(e) if optional.contains(&package.name) => {
+                    self.warn(format!("{e:#}; skipping it as it's optional"));
+                    skipped.push(format!("{}@{}", package.name, package.version));
+                    settled.push(None);
+                }
+                Err(e) => {
+                    failure.get_or_insert(e);
+                    settled.push(None);
+                }
             }
         }
 
-        Ok(())
-    }
-
-    /// Static helper for version checking
-    fn is_exact_version(version: &str) -> bool {
-        if version.starts_with('^')
-            || version.starts_with('~')
-            || version.starts_with('>')
-            || version.starts_with('<')
-            || version.starts_with('=')
-            || version == "*"
-        {
-            return false;
+        if !skipped.is_empty() && !self.quiet {
+            eprintln!(
+                "{} {} optional {} skipped: {}",
+                style("⚠").yellow(),
+                skipped.len(),
+                if skipped.len() == 1 {
+                    "package"
+                } else {
+                    "packages"
+                },
+                skipped.join(", ")
+            );
         }
-
-        let parts: Vec<&str> = version.split('.').collect();
-        if parts.len() >= 3 {
-            parts.iter().take(3).all(|part| {
-                part.split('-')
-                    .next()
-                    .unwrap_or("")
-                    .chars()
-                    .all(|c| c.is_ascii_digit())
-            })
-        } else {
-            false
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(settled),
         }
     }
 
-    /// Install all dependencies from package.json
-    pub async fn install_dependencies(&self) -> Result<()> {
-        if !self.package_json_path.exists() {
-            println!("{} No package.json found", style("•").yellow());
-            return Ok(());
-        }
-
-        let content = fs::read_to_string(&self.package_json_path).await?;
-        let package_json: PackageJson = if content.trim().is_empty() {
-            PackageJson::new()
-        } else {
-            serde_json::from_str(&content).unwrap_or_else(|_| PackageJson::new())
-        };
-
-        let mut total_packages = 0;
-        let mut has_deps = false;
-
-        // Count regular dependencies
-        if let Some(dependencies) = &package_json.dependencies {
-            if !dependencies.is_empty() {
-                has_deps = true;
-                for dep_name in dependencies.keys() {
-                    let dep_package_dir = self.node_modules_dir.join(dep_name);
-                    if !dep_package_dir.exists() {
-                        total_packages += 1;
-                    }
-                }
+    /// Where `name@version` is installed in the configured layout
+    fn package_location(&self, name: &str, version: &str) -> PathBuf {
+        match self.config.install.node_linker {
+            NodeLinker::Hoisted => self.node_modules_dir.join(name),
+            NodeLinker::Isolated => {
+                virtual_store::package_dir(&self.node_modules_dir, name, version)
             }
         }
+    }
 
-        // Count dev dependencies
-        if let Some(dev_dependencies) = &package_json.dev_dependencies {
-            if !dev_dependencies.is_empty() {
-                has_deps = true;
-                for dep_name in dev_dependencies.keys() {
-                    let dep_package_dir = self.node_modules_dir.join(dep_name);
-                    if !dep_package_dir.exists() {
-                        total_packages += 1;
-                    }
-                }
-            }
+    /// Record an explicitly requested package in package.json, with the spec it was asked
+    /// for with; nothing when installing what package.json already lists
+    async fn save_requested(
+        &self,
+        package: &ResolvedPackage,
+        save: Option<&HashMap<String, String>>,
+    ) -> Result<()> {
+        if let Some(requested) = save
+            && let Some(field) = self.save_target.field(package.is_dev)
+        {
+            let range = self.saved_range(
+                requested.get(&package.info.name).map(String::as_str),
+                &package.info.version,
+            );
+            self.update_package_json(&package.info.name, &range, field)
+                .await?;
         }
+        Ok(())
+    }
 
-        if !has_deps {
-            println!("{} No dependencies in package.json", style("•").yellow());
-            return Ok(());
-        }
+    /// Run the install scripts of newly linked packages, dependencies first, restoring
+    /// earlier builds from the build cache where it has them
+    #[tracing::instrument(name = "scripts", skip_all)]
+    async fn build_packages(
+        &self,
+        packages: &[(String, String)],
+        spinner: &ProgressBar,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let mut skipped = Vec::new();
 
-        if total_packages == 0 {
-            println!(
-                "{}",
-                CliStyle::success("All dependencies already installed")
-            );
-            return Ok(());
-        }
+        for (name, version) in packages {
+            let package_dir = self.package_location(name, version);
+            let scripts = lifecycle::install_scripts(&package_dir);
+            if scripts.is_empty() {
+                continue;
+            }
+            if self.config.install.ignore_scripts {
+                skipped.push(name.as_str());
+                continue;
+            }
 
-        // Create progress tracker
-        let mut progress = ProgressTracker::new(total_packages);
+            if self.build_cache.restore(name, version, &package_dir)? {
+                continue;
+            }
 
-        // Install regular dependencies
-        if let Some(dependencies) = package_json.dependencies {
-            self.install_dependencies_parallel(&dependencies, "root", &mut progress)
-                .await?;
+            spinner.set_message(format!("building {name}..."));
+            let started = Instant::now();
+            let before = BuildCache::snapshot(&package_dir);
+            for (event, command) in &scripts {
+                progress::emit(Event::ScriptRun {
+                    name,
+                    version,
+                    script: event,
+                });
+                lifecycle::run_install_script(&package_dir, name, version, event, command).await?;
+                progress::emit(Event::ScriptDone {
+                    name,
+                    version,
+                    script: event,
+                    code: Some(0),
+                });
+            }
+            self.record_package_step(&format!("{name}@{version}"), "scripts", started.elapsed());
+            if let Err(e) = self.build_cache.save(name, version, &package_dir, &before) {
+                self.warn(format!("Couldn't cache the build of {name}@{version}: {e}"));
+            }
         }
 
-        // Install dev dependencies
-        if let Some(dev_dependencies) = package_json.dev_dependencies {
-            self.install_dependencies_parallel(&dev_dependencies, "root", &mut progress)
-                .await?;
+        if !skipped.is_empty() {
+            self.warn(format!(
+                "Skipped install scripts of {} (ignore-scripts)",
+                skipped.join(", ")
+            ));
         }
-
-        progress.finish();
-
-        // Show summary
-        println!(
-            "\n{} Installed {} dependencies",
-            CliStyle::success(""),
-            style(total_packages).white().bold()
-        );
-
+        self.record_phase("scripts", start.elapsed());
         Ok(())
     }
 
@@ -1577,8 +2366,17 @@ impl PackageManager {
             return Ok(());
         }
 
+        // What's installed is the graph to count references in; a lockfile may be absent
+        // or behind node_modules
+        let package_json = self.load_package_json().await?;
+        let mut graph = lock_import::graph_from_node_modules(
+            &self.node_modules_dir,
+            &package_json,
+            &self.npm_client,
+        )?;
+
         // Check if other packages depend on this one
-        let (can_remove, dependents) = self.check_can_remove_package(package_name, "root").await?;
+        let (can_remove, dependents) = graph.can_remove_package(package_name, "root");
         if !can_remove {
             println!(
                 "{} Cannot remove {} - required by: {}",
@@ -1595,51 +2393,63 @@ impl PackageManager {
             .progress_bar
             .set_message(format!("{} {}", CliStyle::error(""), package_name));
 
-        // Cleanup bin commands before removing package
-        self.cleanup_bin_commands(package_name).await?;
-
-        // Remove package directory
-        fs::remove_dir_all(&package_dir).await?;
+        // Every installed package the project no longer reaches without this one goes,
+        // the package itself included
+        graph.root.remove(package_name);
+        let reachable = graph.reachable_keys();
+        let orphans: HashSet<String> = graph
+            .packages
+            .keys()
+            .filter(|key| !reachable.contains(*key))
+            .cloned()
+            .collect();
 
-        // Get package info to check dependencies before removing
-        let package_dependencies = self
-            .get_package_dependencies_from_lock(package_name)
-            .await?;
+        for key in &orphans {
+            let orphan = &graph.packages[key];
+            if self.get_package_version(&orphan.name).await.as_deref() == Some(&orphan.version) {
+                self.cleanup_bin_commands(&orphan.name).await?;
+                fs::remove_dir_all(long_path::extend(&self.node_modules_dir.join(&orphan.name)))
+                    .await?;
+            }
+            let entry =
+                virtual_store::entry_dir(&self.node_modules_dir, &orphan.name, &orphan.version);
+            if entry.exists() {
+                fs::remove_dir_all(long_path::extend(&entry)).await?;
+            }
+        }
 
         // Update package.json to remove dependency
         self.remove_from_package_json(package_name).await?;
 
-        // Update lock file and remove dependencies recursively
-        self.remove_from_lock_file(package_name, "root").await?;
-
-        // Remove dependencies if they're no longer needed
-        for dep_name in package_dependencies {
-            let (can_remove, _) = self
-                .check_can_remove_package(&dep_name, package_name)
-                .await?;
-            if can_remove {
-                // Cleanup bin commands for dependency
-                self.cleanup_bin_commands(&dep_name).await?;
-
-                // Remove dependency from filesystem
-                let dep_dir = self.node_modules_dir.join(&dep_name);
-                if dep_dir.exists() {
-                    fs::remove_dir_all(&dep_dir).await?;
-                }
-                // Remove from lock file
-                self.remove_from_lock_file(&dep_name, package_name).await?;
-            }
-        }
+        self.begin_lock_transaction().await?;
+        let removed = self
+            .edit_lock_file(|lock_file| {
+                lock_file.root.remove(package_name);
+                lock_file.packages.retain(|key, _| !orphans.contains(key));
+            })
+            .await;
+        self.finish_lock_transaction(removed).await?;
+        self.record_project_references().await.ok();
 
         // Update progress
         progress.update(&format!("Removed {package_name}"));
         progress.finish();
 
         // Show summary
+        let unused = orphans.len().saturating_sub(1);
         println!(
-            "\n{} Uninstalled {}",
+            "\n{} Uninstalled {}{}",
             CliStyle::success(""),
-            style(package_name).white().bold()
+            style(package_name).white().bold(),
+            if unused > 0 {
+                CliStyle::dim_text(&format!(
+                    " and {} unused dependenc{}",
+                    unused,
+                    if unused == 1 { "y" } else { "ies" }
+                ))
+            } else {
+                String::new()
+            }
         );
 
         Ok(())
@@ -1654,6 +2464,7 @@ impl PackageManager {
     }
 
     /// Download package tarball to a temporary location (with caching)
+    #[tracing::instrument(name = "download", skip_all)]
     async fn download_package_tarball(
         &self,
         package_info: &crate::package_info::PackageInfo,
@@ -1661,7 +2472,7 @@ impl PackageManager {
         let tarball_filename = format!("{}-{}.tgz", package_info.name, package_info.version);
 
         // Create unique temp directory to avoid conflicts
-        let temp_dir = PathBuf::from("temp").join(&package_info.name);
+        let temp_dir = self.staging_dir(package_info);
         let tarball_path = temp_dir.join(&tarball_filename);
 
         // Ensure temp directory exists
@@ -1679,9 +2490,14 @@ impl PackageManager {
             }
         }
 
-        // Download from registry
+        // Download from registry, bounded by the configured concurrency
+        let _permit = self.semaphore.acquire().await?;
         self.npm_client
-            .download_package(package_info, &tarball_path)
+            .download_package(
+                package_info,
+                &tarball_path,
+                &self.get_cache_path(package_info).with_extension("tgz.part"),
+            )
             .await?;
 
         // Save to cache for future use
@@ -1690,183 +2506,1085 @@ impl PackageManager {
         Ok(tarball_path)
     }
 
-    /// Extract package tarball to the specified directory
-    async fn extract_package(&self, tarball_path: &Path, dest_dir: &Path) -> Result<()> {
-        // Create the destination directory
-        fs::create_dir_all(dest_dir).await?;
+    /// Per-package staging directory for downloaded tarballs
+    fn staging_dir(&self, package_info: &PackageInfo) -> PathBuf {
+        self.staging
+            .path()
+            .join(format!("{}@{}", package_info.name, package_info.version))
+    }
 
-        // Use tar command to extract the tarball
-        let output = Command::new("tar")
-            .args([
-                "-xzf",
-                tarball_path.to_str().unwrap(),
-                "-C",
-                dest_dir.to_str().unwrap(),
-                "--strip-components=1",
-            ])
-            .output()?;
+    /// `name@version` of everything in node_modules, to diff an install against
+    pub fn installed_snapshot(&self) -> BTreeSet<String> {
+        lock_import::installed_versions(&self.node_modules_dir).unwrap_or_default()
+    }
 
-        if !output.status.success() {
-            let error_message = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow!("Failed to extract tarball: {}", error_message));
-        }
+    /// Resolve `name@version` and its dependencies without installing anything, and size
+    /// the tree, both whole and as it would grow this project
+    pub async fn size_report(&self, name: &str, version: &str) -> Result<SizeReport> {
+        use futures::stream::{self, StreamExt};
 
-        Ok(())
-    }
+        let mut resolver = PackageResolver::new(
+            self.npm_client.clone(),
+            self.config.network.resolve_concurrency(),
+            self.config.policy.clone(),
+        );
+        let spinner = self.spinner(&format!("resolving {name}@{version}..."));
+        let resolved = resolver
+            .resolve_multiple_packages_with_spinner(
+                vec![(name.to_string(), version.to_string(), false)],
+                &spinner,
+            )
+            .await;
+        if let Some((_, e)) = resolver.failures.pop() {
+            spinner.finish_and_clear();
+            return Err(e);
+        }
+        let resolved = resolved.inspect_err(|_| spinner.finish_and_clear())?;
+        let Some(root) = resolved.first() else {
+            spinner.finish_and_clear();
+            return Err(anyhow!("Couldn't resolve {name}@{version}"));
+        };
 
-    /// Update or create package.json with the new dependency
-    async fn update_package_json(
-        &self,
-        package_name: &str,
-        version: &str,
-        is_dev: bool,
-    ) -> Result<()> {
-        let _lock = self.file_mutex.lock().await;
-        let mut package_json = if self.package_json_path.exists() {
-            let content = fs::read_to_string(&self.package_json_path).await?;
-            if content.trim().is_empty() {
-                PackageJson::new()
-            } else {
-                serde_json::from_str::<PackageJson>(&content).unwrap_or_else(|_| PackageJson::new())
+        let mut tree = BTreeMap::new();
+        let mut pending: Vec<&ResolvedPackage> = vec![root];
+        while let Some(package) = pending.pop() {
+            if package.info.name == "circular" {
+                continue;
             }
-        } else {
-            PackageJson::new()
-        };
+            let key = format!("{}@{}", package.name, package.version);
+            if tree.insert(key, package).is_none() {
+                pending.extend(&package.dependencies);
+            }
+        }
 
-        // Add the dependency
-        if is_dev {
-            package_json.add_dev_dependency(package_name, version);
-        } else {
-            package_json.add_dependency(package_name, version);
+        // Registries publish the unpacked size but not the tarball's, so ask for that
+        spinner.set_message(format!("sizing {} packages...", tree.len()));
+        let installed = self.installed_snapshot();
+        let mut packages: Vec<PackageSize> = stream::iter(tree)
+            .map(|(key, package)| {
+                let installed = installed.contains(&key);
+                async move {
+                    PackageSize {
+                        name: package.name.clone(),
+                        version: package.version.clone(),
+                        download: self
+                            .npm_client
+                            .tarball_size(&package.info)
+                            .await
+                            .inspect_err(|e| tracing::debug!("no size for {key}: {e}"))
+                            .ok()
+                            .flatten(),
+                        unpacked: package.info.dist.unpacked_size,
+                        installed,
+                    }
+                }
+            })
+            .buffer_unordered(self.config.network.resolve_concurrency())
+            .collect()
+            .await;
+        spinner.finish_and_clear();
+
+        packages.sort_by(|a, b| {
+            b.unpacked
+                .cmp(&a.unpacked)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let mut total = SizeTotals::default();
+        let mut added = SizeTotals::default();
+        for package in &packages {
+            total.add(package);
+            if !package.installed {
+                added.add(package);
+            }
         }
+        Ok(SizeReport {
+            name: root.name.clone(),
+            version: root.version.clone(),
+            total,
+            added,
+            packages,
+        })
+    }
 
-        // Write back to package.json
-        let content = serde_json::to_string_pretty(&package_json)?;
-        fs::write(&self.package_json_path, content).await?;
+    /// How `name` got the version(s) it has: who asks for it with which range, what the
+    /// resolver picks for each range and from which candidates, and what the lockfile,
+    /// node_modules and package.json overrides say
+    pub async fn explain(&self, name: &str) -> Result<Explanation> {
+        let package_json: Value = serde_json::from_str(
+            &fs::read_to_string(&self.package_json_path)
+                .await
+                .map_err(|_| anyhow!("No package.json found"))?,
+        )?;
+        let lock_file = self.load_lock_file().await?;
+        let installed_dirs = lock_import::installed_dirs(&self.node_modules_dir)?;
 
-        Ok(())
-    }
+        let mut requests: Vec<(String, String)> = Vec::new();
+        for field in DEPENDENCY_FIELDS {
+            if let Some(range) = package_json[field][name].as_str() {
+                requests.push((format!("package.json ({field})"), range.to_string()));
+            }
+        }
+        for (key, dirs) in &installed_dirs {
+            let Some(dir) = dirs.first() else {
+                continue;
+            };
+            let Ok(content) = std::fs::read_to_string(dir.join("package.json")) else {
+                continue;
+            };
+            let Ok(dependent) = serde_json::from_str::<Value>(&content) else {
+                continue;
+            };
+            for field in ["dependencies", "optionalDependencies", "peerDependencies"] {
+                if let Some(range) = dependent[field][name].as_str() {
+                    requests.push((key.clone(), range.to_string()));
+                }
+            }
+        }
 
-    /// Load or create lock file
-    async fn load_lock_file(&self) -> Result<LockFile> {
-        let _lock = self.file_mutex.lock().await;
-        if self.lock_file_path.exists() {
-            let content = fs::read_to_string(&self.lock_file_path).await?;
-            if content.trim().is_empty() {
-                Ok(LockFile::new())
-            } else {
-                let lock_file: LockFile = if self.use_toml_lock {
-                    toml::from_str(&content).unwrap_or_else(|_| LockFile::new())
-                } else {
-                    serde_json::from_str(&content).unwrap_or_else(|_| LockFile::new())
-                };
-                Ok(lock_file)
+        let mut locked: BTreeMap<String, Vec<(String, String)>> = lock_file
+            .versions_of(name)
+            .map(|package| (package.version.clone(), Vec::new()))
+            .collect();
+        let edges = lock_file
+            .root
+            .iter()
+            .map(|(dependency, edge)| ("root".to_string(), dependency, edge))
+            .chain(lock_file.packages.iter().flat_map(|(key, package)| {
+                package
+                    .dependencies
+                    .iter()
+                    .map(move |(dependency, edge)| (key.clone(), dependency, edge))
+            }));
+        for (dependent, dependency, edge) in edges {
+            if dependency != name {
+                continue;
+            }
+            if let Some(version) = &edge.version {
+                locked
+                    .entry(version.clone())
+                    .or_default()
+                    .push((dependent.clone(), edge.requested_range.clone()));
+            }
+            // Dependents that aren't installed are only known from the lockfile
+            if dependent != "root" && !installed_dirs.contains_key(&dependent) {
+                requests.push((dependent, edge.requested_range.clone()));
             }
-        } else {
-            Ok(LockFile::new())
         }
-    }
+        requests.dedup();
 
-    /// Save lock file
-    async fn save_lock_file(&self, lock_file: &LockFile) -> Result<()> {
-        let _lock = self.file_mutex.lock().await;
-        let content = if self.use_toml_lock {
-            toml::to_string_pretty(lock_file)?
-        } else {
-            serde_json::to_string_pretty(lock_file)?
-        };
-        fs::write(&self.lock_file_path, content).await?;
+        let installed: BTreeMap<String, Vec<PathBuf>> = installed_dirs
+            .into_iter()
+            .filter_map(|(key, dirs)| {
+                let (installed_name, version) = key.rsplit_once('@')?;
+                (installed_name == name).then(|| (version.to_string(), dirs))
+            })
+            .collect();
+        let overrides = named_overrides(&package_json, name);
 
-        Ok(())
-    }
+        if requests.is_empty() && locked.is_empty() && installed.is_empty() {
+            return Err(anyhow!("Nothing in this project depends on {name}"));
+        }
 
-    /// Update lock file with new package
-    async fn update_lock_file(
-        &self,
-        name: &str,
-        version: &str,
-        resolved: &str,
-        integrity: &str,
-        dependencies: Option<&std::collections::HashMap<String, String>>,
-        required_by: &str,
-    ) -> Result<()> {
-        let mut lock_file = self.load_lock_file().await?;
-        lock_file.add_package(
-            name,
-            version,
-            resolved,
-            integrity,
-            dependencies.cloned(),
-            required_by,
-        );
-        self.save_lock_file(&lock_file).await?;
-        Ok(())
-    }
+        let response = self
+            .npm_client
+            .get_package_info(name)
+            .await
+            .inspect_err(|e| self.warn(format!("Couldn't fetch {name} from the registry: {e}")))
+            .ok();
+        let requests = requests
+            .into_iter()
+            .map(|(by, range)| {
+                let chosen = response
+                    .as_ref()
+                    .and_then(|response| PackageResolver::choose_version(response, &range));
+                let candidates = match (&response, VersionRange::parse(&range)) {
+                    (Some(response), Ok(parsed)) => {
+                        let mut versions: Vec<semver::Version> = response
+                            .versions
+                            .keys()
+                            .filter_map(|version| semver::Version::parse(version).ok())
+                            .filter(|version| parsed.matches(version))
+                            .collect();
+                        versions.sort_by(|a, b| b.cmp(a));
+                        versions.iter().map(ToString::to_string).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                VersionRequest {
+                    by,
+                    range,
+                    chosen: chosen.map(|(info, _)| info.version.clone()),
+                    choice: chosen.map(|(_, choice)| choice),
+                    candidates,
+                }
+            })
+            .collect();
 
-    /// Remove package from lock file
-    async fn remove_from_lock_file(&self, name: &str, required_by: &str) -> Result<()> {
-        let mut lock_file = self.load_lock_file().await?;
-        lock_file.remove_package(name, required_by);
-        self.save_lock_file(&lock_file).await?;
-        Ok(())
+        Ok(Explanation {
+            name: name.to_string(),
+            requests,
+            dist_tags: response.map(|response| response.dist_tags.into_iter().collect()),
+            locked,
+            installed,
+            overrides,
+        })
     }
 
-    /// Check if package can be removed
-    async fn check_can_remove_package(
+    /// Compare node_modules with the snapshot taken before the install, and collect the
+    /// phase timings and warnings the install recorded, plus any peer dependency conflicts
+    pub async fn install_report(
         &self,
-        name: &str,
-        required_by: &str,
-    ) -> Result<(bool, Vec<String>)> {
-        let lock_file = self.load_lock_file().await?;
-        Ok(lock_file.can_remove_package(name, required_by))
-    }
+        before: &BTreeSet<String>,
+        total: Duration,
+    ) -> InstallReport {
+        let after = self.installed_snapshot();
+        let split = |key: &String| {
+            let (name, version) = key.rsplit_once('@').unwrap_or((key, ""));
+            (name.to_string(), version.to_string())
+        };
+        let mut added: Vec<_> = after.difference(before).map(split).collect();
+        let mut removed: Vec<_> = before.difference(&after).map(split).collect();
+
+        // A name that lost one version and gained another was upgraded or downgraded
+        let mut changed = Vec::new();
+        removed.retain(|(name, from)| {
+            let Some(pos) = added.iter().position(|(added_name, _)| added_name == name) else {
+                return true;
+            };
+            let (_, to) = added.remove(pos);
+            changed.push(ChangedPackage {
+                name: name.clone(),
+                from: from.clone(),
+                to,
+            });
+            false
+        });
 
-    /// Get dependencies of a package from lock file
-    async fn get_package_dependencies_from_lock(&self, package_name: &str) -> Result<Vec<String>> {
-        let lock_file = self.load_lock_file().await?;
-        if let Some(package) = lock_file.packages.get(package_name) {
-            if let Some(ref deps) = package.dependencies {
-                return Ok(deps.keys().cloned().collect());
+        let mut durations: BTreeMap<&'static str, u64> = BTreeMap::new();
+        if let Ok(phases) = self.phases.lock() {
+            for (phase, duration) in phases.iter() {
+                *durations.entry(phase).or_default() += duration.as_millis() as u64;
             }
         }
-        Ok(Vec::new())
-    }
-
-    /// Remove a dependency from package.json
-    async fn remove_from_package_json(&self, package_name: &str) -> Result<()> {
-        let _lock = self.file_mutex.lock().await;
-        if !self.package_json_path.exists() {
+        durations.insert("total", total.as_millis() as u64);
+
+        let mut warnings = self
+            .warnings
+            .lock()
+            .map(|warnings| warnings.clone())
+            .unwrap_or_default();
+        for conflict in self
+            .check_peer_dependency_conflicts()
+            .await
+            .unwrap_or_default()
+        {
+            warnings.push(format!(
+                "{} requires peer {} {}, but {} is installed",
+                conflict.package,
+                conflict.peer_dependency,
+                conflict.required_version,
+                if conflict.installed_version == "missing" {
+                    "nothing"
+                } else {
+                    &conflict.installed_version
+                }
+            ));
+        }
+
+        let to_reported = |(name, version): (String, String)| ReportedPackage { name, version };
+        InstallReport {
+            added: added.into_iter().map(to_reported).collect(),
+            removed: removed.into_iter().map(to_reported).collect(),
+            changed,
+            durations,
+            warnings,
+            audit: None,
+        }
+    }
+
+    /// Phase wall-clock times, per-step totals and the slowest packages of this install,
+    /// including the time spent fetching registry metadata
+    pub fn timing_report(&self, total: Duration) -> TimingReport {
+        let as_ms = |duration: &Duration| duration.as_millis() as u64;
+
+        let mut phases: BTreeMap<&'static str, u64> = BTreeMap::new();
+        if let Ok(recorded) = self.phases.lock() {
+            for (phase, duration) in recorded.iter() {
+                *phases.entry(phase).or_default() += as_ms(duration);
+            }
+        }
+        phases.insert("total", as_ms(&total));
+
+        let mut steps: BTreeMap<&'static str, u64> = BTreeMap::new();
+        let mut by_package: BTreeMap<String, BTreeMap<&'static str, u64>> = BTreeMap::new();
+        if let Ok(recorded) = self.package_timings.lock() {
+            for (package, step, duration) in recorded.iter() {
+                *steps.entry(step).or_default() += as_ms(duration);
+                *by_package
+                    .entry(package.clone())
+                    .or_default()
+                    .entry(step)
+                    .or_default() += as_ms(duration);
+            }
+        }
+
+        // Metadata is fetched by name; charge it to the version that got installed
+        let metadata = self.npm_client.metadata_timings();
+        for (name, duration) in &metadata {
+            let key = by_package
+                .keys()
+                .find(|key| key.rsplit_once('@').is_some_and(|(bare, _)| bare == name))
+                .cloned()
+                .unwrap_or_else(|| name.clone());
+            *steps.entry("metadata").or_default() += as_ms(duration);
+            *by_package
+                .entry(key)
+                .or_default()
+                .entry("metadata")
+                .or_default() += as_ms(duration);
+        }
+
+        let mut packages: Vec<PackageTiming> = by_package
+            .into_iter()
+            .map(|(name, steps)| PackageTiming {
+                name,
+                total: steps.values().sum(),
+                steps,
+            })
+            .filter(|package| package.total > 0)
+            .collect();
+        packages.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+
+        TimingReport {
+            phases,
+            steps,
+            metadata_requests: metadata.len(),
+            packages,
+        }
+    }
+
+    /// Tell the content store which packages this project's node_modules uses, so store
+    /// cleanup keeps them
+    pub async fn record_project_references(&self) -> Result<()> {
+        if !self.node_modules_dir.is_dir() {
             return Ok(());
         }
 
-        let content = fs::read_to_string(&self.package_json_path).await?;
-        let mut package_json: PackageJson = if content.trim().is_empty() {
-            PackageJson::new()
+        let packages = lock_import::installed_versions(&self.node_modules_dir)?;
+        let tree_hash = if self.tree_key.is_some() || self.package_json_path.exists() {
+            Some(self.tree_fingerprint(true).await?)
         } else {
-            serde_json::from_str(&content).unwrap_or_else(|_| PackageJson::new())
+            None
+        };
+
+        self.content_store
+            .record_project(&self.project_dir()?, tree_hash, packages)
+            .await
+    }
+
+    /// Put a package into `package_dir` through the content store: link it when the same
+    /// tarball is already stored, otherwise download, store and link it. Returns the
+    /// integrity to lock.
+    #[tracing::instrument(name = "package", skip_all, fields(name = %package_info.name, version = %package_info.version))]
+    async fn place_package(
+        &self,
+        package_info: &PackageInfo,
+        package_dir: &Path,
+        on_step: impl Fn(&str),
+    ) -> Result<String> {
+        let already_stored = self
+            .content_store
+            .get_package_info(&package_info.name, &package_info.version)
+            .await
+            .is_some_and(|metadata| metadata.content_address.integrity == package_info.dist.shasum)
+            && self
+                .content_store
+                .has_package(&package_info.name, &package_info.version);
+        let key = format!("{}@{}", package_info.name, package_info.version);
+        if already_stored {
+            on_step("Linking");
+            let started = Instant::now();
+            let linked = self.link_from_store(package_info, package_dir).await;
+            self.record_package_step(&key, "link", started.elapsed());
+            if linked {
+                progress::emit(Event::LinkDone {
+                    name: &package_info.name,
+                    version: &package_info.version,
+                });
+                return Ok(package_info
+                    .dist
+                    .integrity
+                    .clone()
+                    .unwrap_or_else(|| sri_from_shasum(&package_info.dist.shasum)));
+            }
+        }
+
+        on_step("Downloading");
+        progress::emit(Event::DownloadStart {
+            name: &package_info.name,
+            version: &package_info.version,
+        });
+        let _staging_guard = CleanupGuard::new(self.staging_dir(package_info));
+        let started = Instant::now();
+        let tarball_path = self.download_package_tarball(package_info).await?;
+        self.record_package_step(&key, "download", started.elapsed());
+        progress::emit(Event::DownloadDone {
+            name: &package_info.name,
+            version: &package_info.version,
+        });
+
+        // Check if tarball was actually created
+        if !tarball_path.exists() {
+            return Err(anyhow!(
+                "Failed to download tarball for {}",
+                package_info.name
+            ));
+        }
+
+        on_step("Storing");
+        let started = Instant::now();
+        let tarball_data = fs::read(&tarball_path).await?;
+        let stored = self
+            .content_store
+            .store_package(
+                &package_info.name,
+                &package_info.version,
+                &tarball_data,
+                &package_info.dist.shasum,
+                &package_info.dist.tarball,
+            )
+            .await
+            .is_ok();
+
+        // Link the files from the store, extracting the tarball itself if that fails
+        on_step("Linking");
+        if !(stored && self.link_from_store(package_info, package_dir).await) {
+            self.extract_package(&tarball_path, package_dir).await?;
+        }
+        self.record_package_step(&key, "extract", started.elapsed());
+        progress::emit(Event::ExtractDone {
+            name: &package_info.name,
+            version: &package_info.version,
+        });
+
+        // Clean up the tarball and temp directory
+        if tarball_path.exists() {
+            fs::remove_file(&tarball_path).await.ok();
+        }
+        if let Some(temp_dir) = tarball_path.parent() {
+            fs::remove_dir_all(temp_dir).await.ok();
+        }
+
+        Ok(NpmClient::integrity_of(&tarball_data))
+    }
+
+    /// Download a stored package again from its recorded tarball URL and replace its store
+    /// entry; false if there's no URL or the download fails
+    async fn repair_stored_package(&self, name: &str, version: &str) -> bool {
+        let Some(metadata) = self.content_store.get_package_info(name, version).await else {
+            return false;
+        };
+        let Some(tarball) = metadata.resolved else {
+            return false;
+        };
+
+        let package_info = PackageInfo {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: None,
+            main: None,
+            bin: None,
+            dependencies: None,
+            peer_dependencies: None,
+            optional_dependencies: None,
+            dist: DistInfo {
+                tarball,
+                shasum: metadata.content_address.integrity,
+                integrity: None,
+                unpacked_size: None,
+            },
+            registry: None,
+        };
+
+        let _staging_guard = CleanupGuard::new(self.staging_dir(&package_info));
+        let Ok(tarball_path) = self.download_package_tarball(&package_info).await else {
+            return false;
+        };
+        let Ok(tarball_data) = fs::read(&tarball_path).await else {
+            return false;
         };
+        let repaired = self
+            .content_store
+            .store_package(
+                name,
+                version,
+                &tarball_data,
+                &package_info.dist.shasum,
+                &package_info.dist.tarball,
+            )
+            .await
+            .is_ok()
+            && self.content_store.has_package(name, version);
+        fs::remove_dir_all(self.staging_dir(&package_info))
+            .await
+            .ok();
+
+        if repaired {
+            println!(
+                "{}",
+                CliStyle::warning(&format!(
+                    "Fetched {name}@{version} again: its content store entry was damaged"
+                ))
+            );
+        }
+        repaired
+    }
+
+    /// Link a stored package into `dest_dir`; false if it couldn't be
+    #[tracing::instrument(name = "link", skip_all)]
+    async fn link_from_store(&self, package_info: &PackageInfo, dest_dir: &Path) -> bool {
+        self.content_store
+            .link_package(&package_info.name, &package_info.version, dest_dir)
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Extract package tarball to the specified directory
+    #[tracing::instrument(name = "extract", skip_all)]
+    async fn extract_package(&self, tarball_path: &Path, dest_dir: &Path) -> Result<()> {
+        // A half-extracted package would look installed on the next run
+        let _partial_guard = CleanupGuard::new(dest_dir);
+
+        // Create the destination directory
+        fs::create_dir_all(dest_dir).await?;
+
+        let tarball = fs::read(tarball_path).await?;
+        let dest_dir = dest_dir.to_path_buf();
+        content_store::run_blocking(move || ContentStore::unpack_tarball(&tarball, &dest_dir))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Update or create package.json with the new dependency
+    /// Add the DefinitelyTyped package for each of `names` that ships no types of its own
+    /// as a devDependency, at the major version of the installed package
+    pub async fn install_missing_types(&self, names: &[String]) -> Result<()> {
+        let package_json = self.load_package_json().await?;
+        let mut wanted = Vec::new();
+
+        for name in names {
+            let Some(types_name) = typings::types_package_name(name) else {
+                continue;
+            };
+            if package_json.requested_range(&types_name).is_some()
+                || typings::has_bundled_types(&self.node_modules_dir.join(name))
+            {
+                continue;
+            }
+            let Some(version) = self.get_package_version(name).await else {
+                continue;
+            };
+            // Most packages have no types package at all
+            let Ok(types) = self.npm_client.get_package_info(&types_name).await else {
+                continue;
+            };
+
+            match typings::matching_version(&types, &version) {
+                Some(types_version) => wanted.push((types_name, types_version)),
+                None if !self.quiet => println!(
+                    "{}",
+                    CliStyle::dim_text(&format!(
+                        "No {types_name} for {name}@{version}'s major version"
+                    ))
+                ),
+                None => {}
+            }
+        }
+
+        if wanted.is_empty() {
+            return Ok(());
+        }
+        // Exact versions, since the major has to match; saved with the usual prefix
+        self.install_multiple_packages(wanted.clone(), true, true)
+            .await?;
+        if self.save_target != SaveTarget::None {
+            for (types_name, version) in &wanted {
+                let range = self.saved_range(None, version);
+                self.update_package_json(types_name, &range, "devDependencies")
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the installed version of `name`, as published, into `edit_dir` (a temporary
+    /// directory by default) with any existing patch for it applied, and remember it for
+    /// `clay patch-commit`
+    pub async fn start_patch(&self, name: &str, edit_dir: Option<PathBuf>) -> Result<PathBuf> {
+        let version = self
+            .get_package_version(name)
+            .await
+            .ok_or_else(|| anyhow!("{name} is not installed"))?;
+        let key = format!("{name}@{version}");
+        let edit_dir = edit_dir.unwrap_or_else(|| {
+            std::env::temp_dir()
+                .join("clay-patch")
+                .join(key.replace('/', "+"))
+        });
+        if edit_dir
+            .read_dir()
+            .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            return Err(anyhow!(
+                "{} already exists; commit it with `clay patch-commit {}` or delete it",
+                edit_dir.display(),
+                edit_dir.display()
+            ));
+        }
+
+        self.published_copy(name, &version, &edit_dir).await?;
+        if let Some(patch_path) = self.patched_dependencies().await?.get(&key) {
+            let patch = fs::read_to_string(self.project_dir()?.join(patch_path)).await?;
+            patches::apply(&edit_dir, &patch)?;
+        }
+
+        let edit_dir = std::path::absolute(&edit_dir)?;
+        let mut state = self.patch_state().await;
+        state.insert(edit_dir.clone(), key);
+        fs::write(
+            self.patch_state_path(),
+            serde_json::to_string_pretty(&state)?,
+        )
+        .await?;
+        Ok(edit_dir)
+    }
+
+    /// Save the changes made in a `clay patch` directory as a patch file, list it under
+    /// patchedDependencies in package.json and apply it to node_modules. Returns the
+    /// patch file's path within the project.
+    pub async fn commit_patch(&self, edit_dir: &Path) -> Result<String> {
+        let edit_dir = std::path::absolute(edit_dir)?;
+        let mut state = self.patch_state().await;
+        let key = state.get(&edit_dir).cloned().ok_or_else(|| {
+            anyhow!(
+                "{} wasn't made by `clay patch` in this project",
+                edit_dir.display()
+            )
+        })?;
+        let (name, version) = key.rsplit_once('@').unwrap_or((&key, ""));
+
+        let original = ScratchDir::new("clay-patch-original");
+        self.published_copy(name, version, original.path()).await?;
+        let patch = patches::diff_dirs(original.path(), &edit_dir)?;
+        if patch.is_empty() {
+            return Err(anyhow!("No changes in {}", edit_dir.display()));
+        }
+
+        let patch_path = patches::patch_path(name, version);
+        let file = self.project_dir()?.join(&patch_path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&file, &patch).await?;
+
+        self.edit_package_json(|package_json| {
+            let patched = package_json
+                .entry("patchedDependencies")
+                .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            if let Value::Object(patched) = patched {
+                patched.insert(key.clone(), Value::String(patch_path.clone()));
+            }
+        })
+        .await?;
+
+        state.remove(&edit_dir);
+        fs::write(
+            self.patch_state_path(),
+            serde_json::to_string_pretty(&state)?,
+        )
+        .await?;
+        fs::remove_dir_all(&edit_dir).await.ok();
+
+        self.apply_patches().await?;
+        Ok(patch_path)
+    }
+
+    /// Apply the patches listed under patchedDependencies in package.json to the installed
+    /// packages they name. A package keeps a note of the patch it has, so unchanged patches
+    /// aren't applied twice and a changed one is applied to a fresh copy.
+    async fn apply_patches(&self) -> Result<()> {
+        let patched = self.patched_dependencies().await?;
+        if patched.is_empty() {
+            return Ok(());
+        }
+        let project_dir = self.project_dir()?;
+
+        for (key, patch_path) in &patched {
+            let (name, version) = key.rsplit_once('@').unwrap_or((key, ""));
+            let package_dir = self.package_location(name, version);
+            let installed = fs::read_to_string(package_dir.join("package.json"))
+                .await
+                .ok()
+                .and_then(|content| serde_json::from_str::<PackageJson>(&content).ok())
+                .and_then(|package_json| package_json.version);
+            match installed {
+                Some(installed) if installed == version => {}
+                Some(installed) => {
+                    self.warn(format!(
+                        "Patch {patch_path} is for {key}, but {name}@{installed} is installed"
+                    ));
+                    continue;
+                }
+                None => continue,
+            }
+
+            let patch = fs::read_to_string(project_dir.join(patch_path))
+                .await
+                .map_err(|e| anyhow!("Can't read patch {patch_path} for {key}: {e}"))?;
+            let fingerprint = patches::fingerprint(&patch);
+            let marker = package_dir.join(patches::APPLIED_MARKER);
+            match fs::read_to_string(&marker).await {
+                Ok(applied) if applied == fingerprint => continue,
+                Ok(_) => {
+                    // Patched with an older version of the patch: start from the original
+                    fs::remove_dir_all(long_path::extend(&package_dir)).await?;
+                    if !self
+                        .content_store
+                        .link_package(name, version, &package_dir)
+                        .await?
+                    {
+                        return Err(anyhow!("{key} is not in the content store; reinstall it"));
+                    }
+                }
+                Err(_) => {}
+            }
+
+            patches::apply(&package_dir, &patch)
+                .map_err(|e| anyhow!("Patch {patch_path} doesn't apply to {key}: {e}"))?;
+            fs::write(&marker, fingerprint).await?;
+            if !self.quiet {
+                println!(
+                    "{} Applied {}",
+                    CliStyle::info(""),
+                    style(patch_path).white()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// `name@version` to patch file, from package.json's patchedDependencies
+    async fn patched_dependencies(&self) -> Result<BTreeMap<String, String>> {
+        let Ok(content) = fs::read_to_string(&self.package_json_path).await else {
+            return Ok(BTreeMap::new());
+        };
+        let package_json: Value = serde_json::from_str(&content)?;
+        Ok(package_json
+            .get("patchedDependencies")
+            .and_then(Value::as_object)
+            .map(|patched| {
+                patched
+                    .iter()
+                    .filter_map(|(key, path)| Some((key.clone(), path.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
 
-        // Remove from dependencies
-        if let Some(ref mut deps) = package_json.dependencies {
-            deps.remove(package_name);
+    /// An editable copy of `name@version` as published, from the content store
+    async fn published_copy(&self, name: &str, version: &str, dest: &Path) -> Result<()> {
+        if (self.content_store.has_package(name, version)
+            || self.repair_stored_package(name, version).await)
+            && self.content_store.copy_package(name, version, dest).await?
+        {
+            return Ok(());
         }
+        Err(anyhow!(
+            "{name}@{version} is not in the content store; reinstall it first"
+        ))
+    }
+
+    /// Edit directories handed out by `clay patch`, with the package each is for
+    async fn patch_state(&self) -> BTreeMap<PathBuf, String> {
+        fs::read_to_string(self.patch_state_path())
+            .await
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn patch_state_path(&self) -> PathBuf {
+        self.node_modules_dir.join(".clay-patches.json")
+    }
+
+    /// What package.json records for a package the user added: the version or range they
+    /// asked for, as written, `catalog:` references included; for a tag or no version, the
+    /// resolved version prefixed per `save-exact` / `save-prefix`
+    pub fn saved_range(&self, requested: Option<&str>, version: &str) -> String {
+        if let Some(requested) = requested
+            && (VersionRange::parse(requested).is_ok() || Catalogs::is_reference(requested))
+        {
+            return requested.to_string();
+        }
+
+        if self.config.install.save_exact {
+            version.to_string()
+        } else {
+            let prefix = self.config.install.save_prefix.as_deref().unwrap_or("^");
+            format!("{prefix}{version}")
+        }
+    }
+
+    /// Record a dependency in `field` of package.json, taking it out of the other
+    /// dependency fields
+    pub async fn update_package_json(
+        &self,
+        package_name: &str,
+        range: &str,
+        field: &str,
+    ) -> Result<()> {
+        self.edit_package_json(|package_json| {
+            for other in DEPENDENCY_FIELDS {
+                if other != field
+                    && let Some(Value::Object(deps)) = package_json.get_mut(other)
+                {
+                    deps.shift_remove(package_name);
+                }
+            }
+
+            let deps = package_json
+                .entry(field)
+                .or_insert_with(|| Value::Object(Default::default()));
+            if !deps.is_object() {
+                *deps = Value::Object(Default::default());
+            }
+            if let Value::Object(deps) = deps {
+                deps.insert(package_name.to_string(), Value::String(range.to_string()));
+            }
+        })
+        .await
+    }
+
+    /// Rewrite the ranges of dependencies package.json already lists, each given as
+    /// (section, name, range), keeping them in their section
+    pub async fn set_dependency_ranges(&self, ranges: &[(&str, String, String)]) -> Result<()> {
+        self.edit_package_json(|package_json| {
+            for (field, name, range) in ranges {
+                if let Some(Value::Object(deps)) = package_json.get_mut(*field)
+                    && let Some(existing) = deps.get_mut(name)
+                {
+                    *existing = Value::String(range.clone());
+                }
+            }
+        })
+        .await
+    }
+
+    /// Change package.json as a JSON document, keeping fields clay doesn't model (scripts,
+    /// engines, ...) and their order
+    async fn edit_package_json(
+        &self,
+        edit: impl FnOnce(&mut serde_json::Map<String, Value>),
+    ) -> Result<()> {
+        let _lock = self.file_mutex.lock().await;
+        let content = fs::read_to_string(&self.package_json_path)
+            .await
+            .unwrap_or_default();
+        let mut package_json = if content.trim().is_empty() {
+            match serde_json::to_value(PackageJson::new())? {
+                Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            }
+        } else {
+            match serde_json::from_str(&content) {
+                Ok(Value::Object(map)) => map,
+                _ => return Err(anyhow!("package.json is not a JSON object")),
+            }
+        };
+
+        edit(&mut package_json);
 
-        // Write back to package.json
-        let content = serde_json::to_string_pretty(&package_json)?;
+        let mut content = serde_json::to_string_pretty(&package_json)?;
+        content.push('\n');
         fs::write(&self.package_json_path, content).await?;
+        Ok(())
+    }
+
+    /// Load or create lock file; during a transaction this is the in-memory copy
+    async fn load_lock_file(&self) -> Result<LockFile> {
+        if let Some(ref lock_file) = *self.lock_transaction.lock().await {
+            return Ok(lock_file.clone());
+        }
+
+        let _lock = self.file_mutex.lock().await;
+        if self.lock_file_path.exists() {
+            let content = fs::read_to_string(&self.lock_file_path).await?;
+            let parsed = if self.config.install.lock_cache {
+                lock_cache::parse(
+                    &content,
+                    self.lock_format == LockFormat::Json,
+                    &self.node_modules_dir,
+                )
+            } else {
+                LockFile::parse(&content, self.lock_format == LockFormat::Json)
+            };
+            // Failing keeps an install from replacing a lockfile it couldn't read
+            parsed.map_err(|e| lock::parse_error(&self.lock_file_path, &content, e))
+        } else {
+            Ok(LockFile::new())
+        }
+    }
+
+    /// Save lock file, if the lock mode persists one
+    #[tracing::instrument(name = "write lockfile", skip_all)]
+    async fn save_lock_file(&self, lock_file: &LockFile) -> Result<()> {
+        if !self.lock_mode.writes_lock_file() {
+            return Ok(());
+        }
+
+        let _lock = self.file_mutex.lock().await;
+        let content = if self.lock_format == LockFormat::Toml {
+            toml::to_string_pretty(lock_file)?
+        } else {
+            serde_json::to_string_pretty(lock_file)?
+        };
+
+        // Write to a sibling file and rename over the lockfile, so an interrupted
+        // write never leaves a truncated lockfile behind
+        let temp_path = self.lock_file_path.with_extension("tmp");
+        let _temp_guard = CleanupGuard::new(&temp_path);
+        fs::write(&temp_path, &content).await?;
+        fs::rename(&temp_path, &self.lock_file_path).await?;
+
+        if self.config.install.lock_cache {
+            lock_cache::store(&content, lock_file, &self.node_modules_dir);
+        }
 
         Ok(())
     }
 
-    /// List all installed packages with formatting
-    pub async fn list_installed_packages(&self) -> Result<()> {
-        if !self.node_modules_dir.exists() {
-            println!("{} No packages installed", style("•").yellow());
+    /// Start collecting lockfile changes in memory instead of rewriting the file per package
+    async fn begin_lock_transaction(&self) -> Result<()> {
+        if self.lock_mode.writes_lock_file() {
+            let lock_file = self.load_lock_file().await?;
+            *self.lock_transaction.lock().await = Some(lock_file);
+        }
+        Ok(())
+    }
+
+    /// End the transaction: write the lockfile once if the operation succeeded, and
+    /// discard the changes if it failed
+    async fn finish_lock_transaction<T>(&self, result: Result<T>) -> Result<T> {
+        let lock_file = self.lock_transaction.lock().await.take();
+        if result.is_ok()
+            && let Some(mut lock_file) = lock_file
+        {
+            lock_file.link();
+            self.save_lock_file(&lock_file).await?;
+        }
+        result
+    }
+
+    /// Apply a change to the lockfile: in memory during a transaction, otherwise on disk.
+    /// Edges are linked once, when the lockfile is written.
+    async fn edit_lock_file(&self, edit: impl FnOnce(&mut LockFile)) -> Result<()> {
+        // Concurrent edits queue up here; the span shows how long
+        let mut transaction = self
+            .lock_transaction
+            .lock()
+            .instrument(tracing::info_span!("lockfile wait"))
+            .await;
+        if let Some(ref mut lock_file) = *transaction {
+            edit(lock_file);
             return Ok(());
         }
+        drop(transaction);
 
-        let user_packages = self.get_user_installed_packages().await?;
-        let all_packages = self.get_installed_packages().await?;
+        let mut lock_file = self.load_lock_file().await?;
+        edit(&mut lock_file);
+        lock_file.link();
+        self.save_lock_file(&lock_file).await
+    }
+
+    /// Update lock file with a package resolved from the registry; `integrity` is that of
+    /// the downloaded tarball
+    #[tracing::instrument(name = "lock package", skip_all, fields(name = %package_info.name))]
+    async fn update_lock_file_resolved(
+        &self,
+        package_info: &PackageInfo,
+        integrity: &str,
+        required_by: &str,
+    ) -> Result<()> {
+        let package_json = self.load_package_json().await?;
+        self.edit_lock_file(|lock_file| {
+            lock_file.add_resolved_package(package_info, integrity);
+            // In a monorepo, what the root doesn't ask for itself was hoisted for
+            // workspaces, whose entries lock it
+            let requested_range = package_json.requested_range(&package_info.name);
+            let hoisted = requested_range.is_none() && !self.workspace_packages.is_empty();
+            if required_by == "root" && !self.shared_lock_file && !hoisted {
+                let requested_range = requested_range.unwrap_or(&package_info.version);
+                lock_file.add_root_dependency(&package_info.name, requested_range);
+            }
+        })
+        .await
+    }
+
+    /// Record the project's workspace packages, as `(path, package.json)` pairs
+    pub async fn lock_workspaces(&self, workspaces: &[(String, PackageJson)]) -> Result<()> {
+        self.edit_lock_file(|lock_file| {
+            lock_file.set_workspaces(
+                workspaces
+                    .iter()
+                    .map(|(path, package_json)| (path.as_str(), package_json)),
+            )
+        })
+        .await
+    }
+
+    /// Remove a dependency from package.json
+    async fn remove_from_package_json(&self, package_name: &str) -> Result<()> {
+        if !self.package_json_path.exists() {
+            return Ok(());
+        }
+
+        self.edit_package_json(|package_json| {
+            for field in DEPENDENCY_FIELDS {
+                if let Some(Value::Object(deps)) = package_json.get_mut(field) {
+                    deps.shift_remove(package_name);
+                }
+            }
+        })
+        .await
+    }
+
+    /// List all installed packages with formatting
+    pub async fn list_installed_packages(&self, json: bool) -> Result<()> {
+        let (user_packages, all_packages) = if self.node_modules_dir.exists() {
+            (
+                self.get_user_installed_packages().await?,
+                self.get_installed_packages().await?,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        if json {
+            let mut packages = Vec::new();
+            for package in &all_packages {
+                packages.push(serde_json::json!({
+                    "name": package,
+                    "version": self.get_package_version(package).await,
+                    "direct": user_packages.contains(package),
+                }));
+            }
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "packages": packages }))?
+            );
+            return Ok(());
+        }
 
         if all_packages.is_empty() {
             println!("{} No packages installed", style("•").yellow());
@@ -1897,32 +3615,200 @@ impl PackageManager {
             .filter(|pkg| !user_packages.contains(pkg))
             .collect();
 
-        if !dependencies.is_empty() {
-            println!("{}", CliStyle::dim_text("Dependencies:"));
-            for package in &dependencies {
-                let version = self
-                    .get_package_version(package)
-                    .await
-                    .unwrap_or_else(|| "unknown".to_string());
+        if !dependencies.is_empty() {
+            println!("{}", CliStyle::dim_text("Dependencies:"));
+            for package in &dependencies {
+                let version = self
+                    .get_package_version(package)
+                    .await
+                    .unwrap_or_else(|| "unknown".to_string());
+                println!(
+                    "  {} {} {}",
+                    style("•").dim(),
+                    style(package).dim(),
+                    style(&format!("v{version}")).dim()
+                );
+            }
+            println!();
+        }
+
+        println!(
+            "{} {} user packages, {} dependencies ({} total)",
+            CliStyle::success(""),
+            style(user_packages.len()).white().bold(),
+            style(dependencies.len()).dim(),
+            style(all_packages.len()).white()
+        );
+
+        Ok(())
+    }
+
+    /// Print the dependency tree, npm ls style, from the lockfile or from node_modules
+    /// when there is none. `depth` 0 shows only the project's own dependencies; packages
+    /// already shown higher up are marked deduped rather than expanded again.
+    pub async fn list_dependency_tree(
+        &self,
+        depth: Option<usize>,
+        prod: bool,
+        dev: bool,
+        json: bool,
+    ) -> Result<()> {
+        let package_json = self.load_package_json().await?;
+        let lock_file = if self.lock_file_path.exists() {
+            self.load_lock_file().await?
+        } else if self.node_modules_dir.exists() {
+            lock_import::graph_from_node_modules(
+                &self.node_modules_dir,
+                &package_json,
+                &self.npm_client,
+            )
+            .unwrap_or_else(|_| LockFile::new())
+        } else {
+            LockFile::new()
+        };
+
+        let dev_names: HashSet<&String> = package_json
+            .dev_dependencies
+            .iter()
+            .flat_map(|deps| deps.keys())
+            .collect();
+        let roots: Vec<(&String, &LockedDependency)> = lock_file
+            .root
+            .iter()
+            .filter(|(name, _)| {
+                let is_dev = dev_names.contains(name);
+                !(prod && is_dev || dev && !is_dev)
+            })
+            .collect();
+
+        // The project's own dependencies are expanded at the top, and deduped below
+        let mut shown: HashSet<String> = roots
+            .iter()
+            .filter_map(|(name, edge)| lock_file.locked_key(name, edge))
+            .collect();
+
+        if json {
+            let tree = serde_json::json!({
+                "name": package_json.name,
+                "version": package_json.version,
+                "path": std::env::current_dir().unwrap_or_default(),
+                "dependencies": Self::json_tree_level(&lock_file, &roots, 0, depth, &mut shown),
+            });
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+            return Ok(());
+        }
+
+        if roots.is_empty() {
+            println!("{} No packages installed", style("•").yellow());
+            return Ok(());
+        }
+
+        let project = match (&package_json.name, &package_json.version) {
+            (Some(name), Some(version)) => format!("{name}@{version}"),
+            (Some(name), None) => name.clone(),
+            _ => "project".to_string(),
+        };
+        let cwd = std::env::current_dir().unwrap_or_default();
+        println!(
+            "{} {}",
+            style(project).white().bold(),
+            CliStyle::dim_text(&cwd.display().to_string())
+        );
+        Self::print_tree_level(&lock_file, &roots, "", 0, depth, &mut shown);
+        Ok(())
+    }
+
+    fn print_tree_level(
+        lock_file: &LockFile,
+        edges: &[(&String, &LockedDependency)],
+        prefix: &str,
+        level: usize,
+        depth: Option<usize>,
+        shown: &mut HashSet<String>,
+    ) {
+        for (i, (name, edge)) in edges.iter().enumerate() {
+            let last = i + 1 == edges.len();
+            let branch = if last { "└── " } else { "├── " };
+
+            let Some(key) = lock_file.locked_key(name, edge) else {
                 println!(
-                    "  {} {} {}",
-                    style("•").dim(),
-                    style(package).dim(),
-                    style(&format!("v{version}")).dim()
+                    "{}{}{} {}",
+                    CliStyle::dim_text(prefix),
+                    CliStyle::dim_text(branch),
+                    style(format!("{}@{}", name, edge.requested_range)).red(),
+                    style("missing").red().dim()
+                );
+                continue;
+            };
+
+            let label = if level == 0 {
+                style(&key).white().bold().to_string()
+            } else {
+                style(&key).white().to_string()
+            };
+            let package = &lock_file.packages[&key];
+            if level > 0 && !shown.insert(key.clone()) {
+                println!(
+                    "{}{}{} {}",
+                    CliStyle::dim_text(prefix),
+                    CliStyle::dim_text(branch),
+                    label,
+                    CliStyle::dim_text("deduped")
                 );
+                continue;
+            }
+            println!(
+                "{}{}{}",
+                CliStyle::dim_text(prefix),
+                CliStyle::dim_text(branch),
+                label
+            );
+
+            if depth.is_none_or(|depth| level < depth) {
+                let children: Vec<_> = package.dependencies.iter().collect();
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                Self::print_tree_level(lock_file, &children, &prefix, level + 1, depth, shown);
             }
-            println!();
         }
+    }
 
-        println!(
-            "{} {} user packages, {} dependencies ({} total)",
-            CliStyle::success(""),
-            style(user_packages.len()).white().bold(),
-            style(dependencies.len()).dim(),
-            style(all_packages.len()).white()
-        );
+    /// `print_tree_level`, as the nested objects of `clay list --json`
+    fn json_tree_level(
+        lock_file: &LockFile,
+        edges: &[(&String, &LockedDependency)],
+        level: usize,
+        depth: Option<usize>,
+        shown: &mut HashSet<String>,
+    ) -> BTreeMap<String, ListedDependency> {
+        let mut listed = BTreeMap::new();
+        for (name, edge) in edges {
+            let Some(key) = lock_file.locked_key(name, edge) else {
+                listed.insert(
+                    name.to_string(),
+                    ListedDependency {
+                        missing: Some(edge.requested_range.clone()),
+                        ..Default::default()
+                    },
+                );
+                continue;
+            };
 
-        Ok(())
+            let package = &lock_file.packages[&key];
+            let mut dependency = ListedDependency {
+                version: Some(package.version.clone()),
+                resolved: Some(package.resolved.clone()).filter(|resolved| !resolved.is_empty()),
+                ..Default::default()
+            };
+            if level > 0 && !shown.insert(key.clone()) {
+                dependency.deduped = true;
+            } else if depth.is_none_or(|depth| level < depth) {
+                let children: Vec<_> = package.dependencies.iter().collect();
+                dependency.dependencies =
+                    Self::json_tree_level(lock_file, &children, level + 1, depth, shown);
+            }
+            listed.insert(name.to_string(), dependency);
+        }
+        listed
     }
 
     async fn show_installed_packages_summary(&self) -> Result<()> {
@@ -1978,25 +3864,25 @@ impl PackageManager {
         // Read package.json to get user-installed packages
         if self.package_json_path.exists() {
             let content = fs::read_to_string(&self.package_json_path).await?;
-            if !content.trim().is_empty() {
-                if let Ok(package_json) = serde_json::from_str::<PackageJson>(&content) {
-                    // Add regular dependencies
-                    if let Some(dependencies) = &package_json.dependencies {
-                        for name in dependencies.keys() {
-                            let package_dir = self.node_modules_dir.join(name);
-                            if package_dir.exists() {
-                                user_packages.push(name.clone());
-                            }
+            if !content.trim().is_empty()
+                && let Ok(package_json) = serde_json::from_str::<PackageJson>(&content)
+            {
+                // Add regular dependencies
+                if let Some(dependencies) = &package_json.dependencies {
+                    for name in dependencies.keys() {
+                        let package_dir = self.node_modules_dir.join(name);
+                        if package_dir.exists() {
+                            user_packages.push(name.clone());
                         }
                     }
+                }
 
-                    // Add dev dependencies
-                    if let Some(dev_dependencies) = &package_json.dev_dependencies {
-                        for name in dev_dependencies.keys() {
-                            let package_dir = self.node_modules_dir.join(name);
-                            if package_dir.exists() {
-                                user_packages.push(name.clone());
-                            }
+                // Add dev dependencies
+                if let Some(dev_dependencies) = &package_json.dev_dependencies {
+                    for name in dev_dependencies.keys() {
+                        let package_dir = self.node_modules_dir.join(name);
+                        if package_dir.exists() {
+                            user_packages.push(name.clone());
                         }
                     }
                 }
@@ -2016,7 +3902,15 @@ impl PackageManager {
 
         for (name, version) in package_specs {
             let package_dir = self.node_modules_dir.join(name);
-            if package_dir.exists() {
+            // A range the installed copy doesn't satisfy asks for a different version
+            let outdated = match VersionRange::parse(version) {
+                Ok(range) => !self
+                    .get_package_version(name)
+                    .await
+                    .is_some_and(|installed| range.matches_str(&installed)),
+                Err(_) => false,
+            };
+            if package_dir.exists() && !outdated {
                 already_installed.push(name.clone());
             } else {
                 to_install.push((name.clone(), version.clone()));
@@ -2031,12 +3925,12 @@ impl PackageManager {
 
         let mut entries = fs::read_dir(&self.node_modules_dir).await?;
         while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                if let Some(name) = entry.file_name().to_str() {
-                    // Skip hidden directories and .bin
-                    if !name.starts_with('.') {
-                        packages.push(name.to_string());
-                    }
+            if entry.file_type().await?.is_dir()
+                && let Some(name) = entry.file_name().to_str()
+            {
+                // Skip hidden directories and .bin
+                if !name.starts_with('.') {
+                    packages.push(name.to_string());
                 }
             }
         }
@@ -2045,6 +3939,11 @@ impl PackageManager {
         Ok(packages)
     }
 
+    /// Whether node_modules holds `name` at exactly `version`
+    async fn installed_at(&self, name: &str, version: &str) -> bool {
+        self.get_package_version(name).await.as_deref() == Some(version)
+    }
+
     /// Get version of an installed package
     async fn get_package_version(&self, package_name: &str) -> Option<String> {
         let package_json_path = self
@@ -2052,63 +3951,30 @@ impl PackageManager {
             .join(package_name)
             .join("package.json");
 
-        if let Ok(content) = fs::read_to_string(&package_json_path).await {
-            if let Ok(package_json) = serde_json::from_str::<PackageJson>(&content) {
-                return package_json.version;
-            }
+        if let Ok(content) = fs::read_to_string(&package_json_path).await
+            && let Ok(package_json) = serde_json::from_str::<PackageJson>(&content)
+        {
+            return package_json.version;
         }
 
         None
     }
 
-    /// Resolve version range to actual version by fetching from registry
-    async fn resolve_version_range(
-        &self,
-        package_name: &str,
-        version_range: &str,
-    ) -> Result<String> {
-        // For now, we'll use a simple approach:
-        // - If it's already a specific version (x.y.z), use it as-is
-        // - If it's a range (^x.y.z, ~x.y.z, *, etc.), fetch latest
-        if Self::is_exact_version(version_range) {
-            return Ok(version_range.to_string());
-        }
-
-        // For version ranges, fetch the latest version
-        let registry_response = self.npm_client.get_package_info(package_name).await?;
-
-        if let Some(package_info) = registry_response.get_latest_version() {
-            Ok(package_info.version.clone())
-        } else {
-            Err(anyhow!(
-                "Could not resolve version range '{}' for package '{}'",
-                version_range,
-                package_name
-            ))
-        }
-    }
-
     /// Show cache information
-    pub async fn cache_info(&self) -> Result<()> {
+    pub async fn cache_info(&self, json: bool) -> Result<()> {
         use console::style;
 
-        self.ensure_cache_dir_exists().await?;
-
-        let mut total_size = 0u64;
-        let mut package_count = 0u32;
-
-        if self.cache_dir.exists() {
-            let mut entries = fs::read_dir(&self.cache_dir).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                if let Ok(metadata) = entry.metadata().await {
-                    if metadata.is_file()
-                        && entry.path().extension().is_some_and(|ext| ext == "tgz")
-                    {
-                        total_size += metadata.len();
-                        package_count += 1;
-                    }
-                }
-            }
+        let (package_count, total_size) = self.cache_stats().await?;
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({
+                    "directory": self.cache_dir,
+                    "packages": package_count,
+                    "size": total_size,
+                }))?
+            );
+            return Ok(());
         }
 
         println!("{}", CliStyle::section_header("Cache Information"));
@@ -2125,6 +3991,29 @@ impl PackageManager {
         Ok(())
     }
 
+    /// Number and total size of the tarballs in the cache
+    pub async fn cache_stats(&self) -> Result<(u32, u64)> {
+        self.ensure_cache_dir_exists().await?;
+
+        let mut total_size = 0u64;
+        let mut package_count = 0u32;
+
+        if self.cache_dir.exists() {
+            let mut entries = fs::read_dir(&self.cache_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if let Ok(metadata) = entry.metadata().await
+                    && metadata.is_file()
+                    && entry.path().extension().is_some_and(|ext| ext == "tgz")
+                {
+                    total_size += metadata.len();
+                    package_count += 1;
+                }
+            }
+        }
+
+        Ok((package_count, total_size))
+    }
+
     /// Clear all cached packages
     pub async fn cache_clear(&self) -> Result<()> {
         use console::style;
@@ -2199,24 +4088,7 @@ impl PackageManager {
             serde_json::from_str(&content).unwrap_or_else(|_| PackageJson::new())
         };
 
-        let mut package_specs = Vec::new();
-
-        // Add regular dependencies
-        if let Some(dependencies) = &package_json.dependencies {
-            for (name, version_spec) in dependencies {
-                package_specs.push((name.clone(), version_spec.clone()));
-            }
-        }
-
-        // Add dev dependencies if requested
-        if include_dev {
-            if let Some(dev_dependencies) = &package_json.dev_dependencies {
-                for (name, version_spec) in dev_dependencies {
-                    package_specs.push((name.clone(), version_spec.clone()));
-                }
-            }
-        }
-
+        let package_specs = package_json.dependency_specs(include_dev);
         if package_specs.is_empty() {
             println!(
                 "{} No dependencies found in package.json",
@@ -2227,7 +4099,13 @@ impl PackageManager {
         Ok(package_specs)
     }
 
-    async fn setup_bin_commands(&self, package_name: &str, package_dir: &Path) -> Result<()> {
+    /// Link the commands the package in `package_dir` provides into `bin_dir`
+    async fn setup_bin_commands(
+        &self,
+        bin_dir: &Path,
+        package_name: &str,
+        package_dir: &Path,
+    ) -> Result<()> {
         // Read the package's package.json to get bin information
         let package_json_path = package_dir.join("package.json");
         if !package_json_path.exists() {
@@ -2245,36 +4123,29 @@ impl PackageManager {
         };
 
         if let Some(bin) = package_json.get("bin") {
-            let bin_dir = self.node_modules_dir.join(".bin");
-            if let Err(e) = fs::create_dir_all(&bin_dir).await {
-                eprintln!(
-                    "{} Failed to create .bin directory: {}",
-                    style("⚠").yellow(),
-                    e
-                );
+            if let Err(e) = fs::create_dir_all(bin_dir).await {
+                self.warn(format!("Failed to create .bin directory: {e}"));
                 return Ok(());
             }
 
             match bin {
                 // Handle string format: "bin": "path/to/executable"
                 Value::String(bin_path) => {
-                    let executable_name = package_name;
+                    // Scoped packages get a command named without the scope
+                    let executable_name = package_name.rsplit('/').next().unwrap_or(package_name);
                     if let Err(e) = self
                         .create_bin_link(
                             executable_name,
                             package_name,
                             bin_path,
-                            &bin_dir,
+                            bin_dir,
                             package_dir,
                         )
                         .await
                     {
-                        eprintln!(
-                            "{} Failed to create bin command {}: {}",
-                            style("⚠").yellow(),
-                            style(executable_name).white(),
-                            e
-                        );
+                        self.warn(format!(
+                            "Failed to create bin command {executable_name}: {e}"
+                        ));
                     }
                 }
                 // Handle object format: "bin": { "command": "path/to/executable" }
@@ -2286,18 +4157,17 @@ impl PackageManager {
                                     command_name,
                                     package_name,
                                     path_str,
-                                    &bin_dir,
+                                    bin_dir,
                                     package_dir,
                                 )
                                 .await
                             {
-                                println!(
-                                    "{} Failed to create bin command {}: {}",
-                                    style("⚠").yellow(),
-                                    style(command_name).white(),
-                                    e
-                                );
-                            } else {
+                                self.warn(format!(
+                                    "Failed to create bin command {command_name}: {e}"
+                                ));
+                            } else if !self.quiet
+                                && bin_dir.parent() == Some(&self.node_modules_dir)
+                            {
                                 println!(
                                     "{} Added bin command: {}",
                                     CliStyle::info(""),
@@ -2323,43 +4193,13 @@ impl PackageManager {
         package_dir: &Path,
     ) -> Result<()> {
         let source_path = package_dir.join(bin_path);
-        let link_path = bin_dir.join(command_name);
-
-        // Remove existing link if it exists
-        if link_path.exists() {
-            fs::remove_file(&link_path).await.ok();
-        }
-
-        #[cfg(unix)]
-        {
-            // On Unix systems, create a symlink and make source executable
-            use std::os::unix::fs as unix_fs;
-            use std::os::unix::fs::PermissionsExt;
-
-            // Make the source file executable if it isn't already
-            if source_path.exists() {
-                if let Ok(metadata) = fs::metadata(&source_path).await {
-                    let mut perms = metadata.permissions();
-                    perms.set_mode(perms.mode() | 0o755);
-                    let _ = fs::set_permissions(&source_path, perms).await;
-                }
-            }
-
-            unix_fs::symlink(&source_path, &link_path)?;
-        }
-
-        #[cfg(windows)]
-        {
-            // On Windows, create a batch file that calls the executable
-            let batch_content = format!(
-                "@echo off\nnode \"{}\" %*",
-                source_path.to_string_lossy().replace('/', "\\")
-            );
-            let batch_path = bin_dir.join(format!("{}.cmd", command_name));
-            fs::write(&batch_path, batch_content).await?;
-        }
-
-        Ok(())
+        // Link relative to .bin so the link survives the project being moved
+        let node_modules = bin_dir.parent().unwrap_or(&self.node_modules_dir);
+        let target = match package_dir.strip_prefix(node_modules) {
+            Ok(relative) => Path::new("..").join(relative).join(bin_path),
+            Err(_) => source_path.clone(),
+        };
+        links::link_bin(bin_dir, command_name, &source_path, &target)
     }
 
     async fn cleanup_bin_commands(&self, package_name: &str) -> Result<()> {
@@ -2374,49 +4214,20 @@ impl PackageManager {
 
         if package_json_path.exists() {
             let content = fs::read_to_string(&package_json_path).await?;
-            if let Ok(package_json) = serde_json::from_str::<Value>(&content) {
-                if let Some(bin) = package_json.get("bin") {
-                    match bin {
-                        Value::String(_) => {
-                            let link_path = bin_dir.join(package_name);
-                            if link_path.exists() {
-                                fs::remove_file(&link_path).await.ok();
-                                println!(
-                                    "{} Removed bin command: {}",
-                                    CliStyle::dim_text(""),
-                                    style(package_name).dim()
-                                );
-                            }
-                            #[cfg(windows)]
-                            {
-                                let batch_path = bin_dir.join(format!("{}.cmd", package_name));
-                                if batch_path.exists() {
-                                    fs::remove_file(&batch_path).await.ok();
-                                }
-                            }
-                        }
-                        Value::Object(bin_map) => {
-                            for command_name in bin_map.keys() {
-                                let link_path = bin_dir.join(command_name);
-                                if link_path.exists() {
-                                    fs::remove_file(&link_path).await.ok();
-                                    println!(
-                                        "{} Removed bin command: {}",
-                                        CliStyle::dim_text(""),
-                                        style(command_name).dim()
-                                    );
-                                }
-                                #[cfg(windows)]
-                                {
-                                    let batch_path = bin_dir.join(format!("{}.cmd", command_name));
-                                    if batch_path.exists() {
-                                        fs::remove_file(&batch_path).await.ok();
-                                    }
-                                }
-                            }
+            if let Ok(package_json) = serde_json::from_str::<Value>(&content)
+                && let Some(bin) = package_json.get("bin")
+            {
+                match bin {
+                    Value::String(_) => {
+                        let command_name = package_name.rsplit('/').next().unwrap_or(package_name);
+                        self.remove_bin_command(&bin_dir, command_name);
+                    }
+                    Value::Object(bin_map) => {
+                        for command_name in bin_map.keys() {
+                            self.remove_bin_command(&bin_dir, command_name);
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
             }
         }
@@ -2424,12 +4235,20 @@ impl PackageManager {
         Ok(())
     }
 
-    /// Run a script from package.json
-    pub async fn run_script(&self, script_name: &str) -> Result<()> {
-        // Check if package.json exists
+    fn remove_bin_command(&self, bin_dir: &Path, command_name: &str) {
+        if links::unlink_bin(bin_dir, command_name) {
+            println!(
+                "{} Removed bin command: {}",
+                CliStyle::dim_text(""),
+                style(command_name).dim()
+            );
+        }
+    }
+
+    /// Run a script from package.json, returning how it exited for clay to exit the same way
+    pub async fn run_script(&self, script_name: &str) -> Result<ExitStatus> {
         if !self.package_json_path.exists() {
-            println!("{}", CliStyle::error("No package.json found"));
-            return Ok(());
+            return Err(anyhow!("No package.json found"));
         }
 
         // Read package.json
@@ -2439,10 +4258,7 @@ impl PackageManager {
         // Get scripts section
         let scripts = match package_json.get("scripts") {
             Some(Value::Object(scripts)) => scripts,
-            _ => {
-                println!("{}", CliStyle::error("No scripts found in package.json"));
-                return Ok(());
-            }
+            _ => return Err(anyhow!("No scripts found in package.json")),
         };
 
         // Find the requested script
@@ -2469,7 +4285,7 @@ impl PackageManager {
                         }
                     }
                 }
-                return Ok(());
+                return Err(anyhow!("Script '{script_name}' not found"));
             }
         };
 
@@ -2497,10 +4313,10 @@ impl PackageManager {
             if let Ok(mut entries) = fs::read_dir(&bin_dir).await {
                 let mut bin_commands = Vec::new();
                 while let Some(entry) = entries.next_entry().await.unwrap_or(None) {
-                    if let Ok(name) = entry.file_name().into_string() {
-                        if !name.ends_with(".cmd") {
-                            bin_commands.push(name);
-                        }
+                    if let Ok(name) = entry.file_name().into_string()
+                        && !name.ends_with(".cmd")
+                    {
+                        bin_commands.push(name);
                     }
                 }
                 if !bin_commands.is_empty() {
@@ -2525,38 +4341,54 @@ impl PackageManager {
             cmd
         };
 
-        // Add node_modules/.bin to PATH
+        // Add node_modules/.bin, then the Node the project pins, to PATH
+        let mut path_dirs = Vec::new();
         if bin_dir.exists() {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let path_separator = if cfg!(target_os = "windows") {
-                ";"
-            } else {
-                ":"
-            };
-            let new_path = if current_path.is_empty() {
-                bin_dir.to_string_lossy().to_string()
-            } else {
-                format!(
-                    "{}{}{}",
-                    bin_dir.to_string_lossy(),
-                    path_separator,
-                    current_path
-                )
-            };
-            cmd.env("PATH", new_path);
             println!(
                 "{} Added {} to PATH",
                 CliStyle::dim_text(""),
                 bin_dir.to_string_lossy()
             );
+            path_dirs.push(bin_dir.clone());
+        }
+        if let Some(node_bin) = node_runtime::runtime_bin_dir(&self.project_dir()?) {
+            println!(
+                "{} Using Node from {}",
+                CliStyle::dim_text(""),
+                node_bin.display()
+            );
+            path_dirs.push(node_bin);
+        }
+        if !path_dirs.is_empty() {
+            cmd.env("PATH", exec::path_with(&path_dirs)?);
         }
 
         // Set working directory to project root
-        cmd.current_dir(self.package_json_path.parent().unwrap_or(Path::new(".")));
+        cmd.current_dir(
+            self.package_json_path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        );
 
         // Execute the command
         println!("{}", CliStyle::info("Executing command..."));
-        let status = cmd.status()?;
+        let name = package_json["name"].as_str().unwrap_or_default();
+        let version = package_json["version"].as_str().unwrap_or_default();
+        progress::emit(Event::ScriptRun {
+            name,
+            version,
+            script: script_name,
+        });
+        let status = exec::status_forwarding_signals(&mut cmd)
+            .await
+            .map_err(|e| anyhow!("Couldn't run the {script_name} script: {e}"))?;
+        progress::emit(Event::ScriptDone {
+            name,
+            version,
+            script: script_name,
+            code: status.code(),
+        });
 
         if status.success() {
             println!(
@@ -2565,15 +4397,15 @@ impl PackageManager {
                 style(script_name).white()
             );
         } else {
-            println!(
-                "\n{} Script '{}' failed with exit code: {}",
-                CliStyle::error(""),
-                style(script_name).white(),
-                status.code().unwrap_or(-1)
+            eprintln!(
+                "\n{}",
+                CliStyle::error(&format!(
+                    "Script '{script_name}' failed with exit code {}",
+                    exec::exit_code(&status)
+                ))
             );
         }
-
-        Ok(())
+        Ok(status)
     }
 
     /// List all available scripts from package.json
@@ -2636,12 +4468,14 @@ impl PackageManager {
                 return Ok(());
             }
 
-            println!(
-                "{} Found {} peer dependencies for {}",
-                CliStyle::info(""),
-                style(peer_deps.len()).yellow(),
-                style(&package_info.name).white().bold()
-            );
+            if !self.quiet {
+                println!(
+                    "{} Found {} peer dependencies for {}",
+                    CliStyle::info(""),
+                    style(peer_deps.len()).yellow(),
+                    style(&package_info.name).white().bold()
+                );
+            }
 
             let mut missing_peers = Vec::new();
             let mut optional_peers = Vec::new();
@@ -2666,19 +4500,27 @@ impl PackageManager {
             }
 
             // Install missing peer dependencies
-            if !missing_peers.is_empty() {
+            if !missing_peers.is_empty() && !self.quiet {
                 println!(
                     "{} Installing {} required peer dependencies...",
                     CliStyle::cyan_text(""),
                     style(missing_peers.len()).yellow()
                 );
-
-                self.install_multiple_packages(missing_peers, false, false)
-                    .await?;
+            }
+            // A peer that can't be installed is the dependent's problem, not the install's
+            if !missing_peers.is_empty()
+                && let Err(e) = self
+                    .install_multiple_packages(missing_peers, false, false)
+                    .await
+            {
+                self.warn(format!(
+                    "Peer dependencies of {} skipped: {e:#}",
+                    package_info.name
+                ));
             }
 
             // Optionally install optional peer dependencies
-            if !optional_peers.is_empty() {
+            if !optional_peers.is_empty() && !self.quiet {
                 println!(
                     "{} {} optional peer dependencies available:",
                     CliStyle::info(""),
@@ -2699,8 +4541,16 @@ impl PackageManager {
                     "{} Installing optional peer dependencies...",
                     CliStyle::cyan_text("")
                 );
-                self.install_multiple_packages(optional_peers, false, false)
-                    .await?;
+            }
+            if !optional_peers.is_empty()
+                && let Err(e) = self
+                    .install_multiple_packages(optional_peers, false, false)
+                    .await
+            {
+                self.warn(format!(
+                    "Optional peer dependencies of {} skipped: {e:#}",
+                    package_info.name
+                ));
             }
         }
 
@@ -2718,38 +4568,35 @@ impl PackageManager {
                 .join(package_name)
                 .join("package.json");
 
-            if let Ok(content) = fs::read_to_string(&package_json_path).await {
-                if let Ok(package_json) = serde_json::from_str::<PackageJson>(&content) {
-                    if let Some(ref peer_deps) = package_json.peer_dependencies {
-                        for (peer_name, peer_version_spec) in peer_deps {
-                            let peer_package_dir = self.node_modules_dir.join(peer_name);
-
-                            if peer_package_dir.exists() {
-                                // Check version compatibility
-                                let installed_version = self
-                                    .get_package_version(peer_name)
-                                    .await
-                                    .unwrap_or_else(|| "unknown".to_string());
-
-                                if !self
-                                    .is_version_compatible(&installed_version, peer_version_spec)
-                                {
-                                    conflicts.push(PeerConflict {
-                                        package: package_name.clone(),
-                                        peer_dependency: peer_name.clone(),
-                                        required_version: peer_version_spec.clone(),
-                                        installed_version: installed_version.clone(),
-                                    });
-                                }
-                            } else {
-                                conflicts.push(PeerConflict {
-                                    package: package_name.clone(),
-                                    peer_dependency: peer_name.clone(),
-                                    required_version: peer_version_spec.clone(),
-                                    installed_version: "missing".to_string(),
-                                });
-                            }
+            if let Ok(content) = fs::read_to_string(&package_json_path).await
+                && let Ok(package_json) = serde_json::from_str::<PackageJson>(&content)
+                && let Some(ref peer_deps) = package_json.peer_dependencies
+            {
+                for (peer_name, peer_version_spec) in peer_deps {
+                    let peer_package_dir = self.node_modules_dir.join(peer_name);
+
+                    if peer_package_dir.exists() {
+                        // Check version compatibility
+                        let installed_version = self
+                            .get_package_version(peer_name)
+                            .await
+                            .unwrap_or_else(|| "unknown".to_string());
+
+                        if !self.is_version_compatible(&installed_version, peer_version_spec) {
+                            conflicts.push(PeerConflict {
+                                package: package_name.clone(),
+                                peer_dependency: peer_name.clone(),
+                                required_version: peer_version_spec.clone(),
+                                installed_version: installed_version.clone(),
+                            });
                         }
+                    } else {
+                        conflicts.push(PeerConflict {
+                            package: package_name.clone(),
+                            peer_dependency: peer_name.clone(),
+                            required_version: peer_version_spec.clone(),
+                            installed_version: "missing".to_string(),
+                        });
                     }
                 }
             }
@@ -2784,8 +4631,125 @@ impl PackageManager {
     }
 
     /// Report peer dependency conflicts
-    pub async fn report_peer_conflicts(&self) -> Result<()> {
+    /// Check node_modules against the lockfile (or, without one, against itself): every
+    /// locked package is installed at its locked version, its files still hash as the
+    /// content store recorded them, and every .bin link points at something
+    pub async fn verify_installed(&self) -> Result<IntegrityReport> {
+        use futures::stream::{self, StreamExt};
+
+        let mut report = IntegrityReport::default();
+        let lock_file = self.load_lock_file().await?;
+        let installed = lock_import::installed_dirs(&self.node_modules_dir)?;
+        let keys: Vec<&String> = if lock_file.packages.is_empty() {
+            installed.keys().collect()
+        } else {
+            lock_file.packages.keys().collect()
+        };
+        let patched = self.patched_dependencies().await.unwrap_or_default();
+
+        let mut to_hash = Vec::new();
+        for key in keys {
+            let (name, version) = key.rsplit_once('@').unwrap_or((key, ""));
+            report.packages += 1;
+            let Some(dirs) = installed.get(key) else {
+                let others: Vec<&str> = installed
+                    .keys()
+                    .filter_map(|other| other.rsplit_once('@'))
+                    .filter(|(other, _)| *other == name)
+                    .map(|(_, version)| version)
+                    .collect();
+                report.problem(
+                    key.as_str(),
+                    if others.is_empty() {
+                        "is locked but not installed".to_string()
+                    } else {
+                        format!("is locked but node_modules has {}", others.join(", "))
+                    },
+                );
+                continue;
+            };
+            if patched.contains_key(key.as_str()) {
+                report.unverified.push(key.clone());
+                continue;
+            }
+            match self.content_store.get_package_info(name, version).await {
+                Some(metadata) if !metadata.file_hashes.is_empty() => {
+                    // A store link and the store entry it points at are one directory
+                    let mut dirs: Vec<PathBuf> = dirs
+                        .iter()
+                        .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+                        .collect();
+                    dirs.sort();
+                    dirs.dedup();
+                    for dir in dirs {
+                        to_hash.push((key.clone(), dir, metadata.file_hashes.clone()));
+                    }
+                }
+                _ => report.unverified.push(key.clone()),
+            }
+        }
+
+        let hashed: Vec<(String, usize, Vec<String>)> = stream::iter(to_hash)
+            .map(|(key, dir, files)| {
+                content_store::run_blocking(move || {
+                    let mut damaged = Vec::new();
+                    for (path, file) in &files {
+                        match std::fs::read(dir.join(path)) {
+                            Err(_) => damaged.push(format!("{path} is missing")),
+                            Ok(contents)
+                                if contents.len() as u64 != file.size
+                                    || ContentStore::content_hash(&contents) != file.hash =>
+                            {
+                                damaged.push(format!("{path} was modified"))
+                            }
+                            Ok(_) => {}
+                        }
+                    }
+                    Ok((key, files.len(), damaged))
+                })
+            })
+            .buffer_unordered(self.config.network.concurrency())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+        for (key, files, damaged) in hashed {
+            report.files += files;
+            for problem in damaged {
+                report.problem(key.as_str(), problem);
+            }
+        }
+
+        if let Ok(entries) = std::fs::read_dir(self.node_modules_dir.join(".bin")) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_symlink() {
+                    continue;
+                }
+                report.bin_links += 1;
+                if !path.exists() {
+                    let target = std::fs::read_link(&path).unwrap_or_default();
+                    report.problem(
+                        format!(".bin/{}", entry.file_name().to_string_lossy()),
+                        format!("points at {}, which doesn't exist", target.display()),
+                    );
+                }
+            }
+        }
+
+        report.problems.sort_by(|a, b| a.subject.cmp(&b.subject));
+        Ok(report)
+    }
+
+    pub async fn report_peer_conflicts(&self, json: bool) -> Result<()> {
         let conflicts = self.check_peer_dependency_conflicts().await?;
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "conflicts": conflicts }))?
+            );
+            return Ok(());
+        }
 
         if conflicts.is_empty() {
             println!(
@@ -2826,7 +4790,7 @@ impl PackageManager {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct PeerConflict {
     pub package: String,
     pub peer_dependency: String,
@@ -2834,8 +4798,127 @@ pub struct PeerConflict {
     pub installed_version: String,
 }
 
-impl Default for PackageManager {
-    fn default() -> Self {
-        Self::new()
+/// Entries of package.json's `overrides` (npm, nested), `resolutions` (yarn) and
+/// `pnpm.overrides` that target `name`, as (where, value)
+fn named_overrides(package_json: &Value, name: &str) -> Vec<(String, String)> {
+    fn collect(entries: &Value, source: String, name: &str, found: &mut Vec<(String, String)>) {
+        let Some(entries) = entries.as_object() else {
+            return;
+        };
+        for (selector, value) in entries {
+            let source = format!("{source} > {selector}");
+            if override_target(selector) == name {
+                let value = match value {
+                    Value::String(version) => version.clone(),
+                    // npm's nested form sets the package itself under "."
+                    other => other["."].as_str().unwrap_or("(nested)").to_string(),
+                };
+                found.push((source.clone(), value));
+            }
+            if value.is_object() {
+                collect(value, source, name, found);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(
+        &package_json["overrides"],
+        "overrides".to_string(),
+        name,
+        &mut found,
+    );
+    collect(
+        &package_json["resolutions"],
+        "resolutions".to_string(),
+        name,
+        &mut found,
+    );
+    collect(
+        &package_json["pnpm"]["overrides"],
+        "pnpm.overrides".to_string(),
+        name,
+        &mut found,
+    );
+    found
+}
+
+/// The package an override selector sets: `b` for `a>b`, `**/b`, `a/b` and `b@^1`
+fn override_target(selector: &str) -> &str {
+    let last = selector.rsplit('>').next().unwrap_or(selector);
+    // A yarn path's last package, which is two segments when scoped
+    let segments: Vec<&str> = last.split('/').collect();
+    let start = match segments.len() {
+        0 | 1 => 0,
+        n if segments[n - 2].starts_with('@') => {
+            last.len() - segments[n - 2].len() - segments[n - 1].len() - 1
+        }
+        n => last.len() - segments[n - 1].len(),
+    };
+    let package = &last[start..];
+    match package.rfind('@') {
+        Some(at) if at > 0 => &package[..at],
+        _ => package,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project in a fresh temporary directory whose clay-lock.toml is `lock`
+    fn project_with_lock(name: &str, lock: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("clay-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("package.json"),
+            r#"{"name":"app","version":"1.0.0","dependencies":{"left-pad":"^1.3.0"}}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("clay-lock.toml"), lock).unwrap();
+        dir
+    }
+
+    fn explicit_lock_manager(dir: &Path) -> PackageManager {
+        let mut config = ClayConfig::default();
+        config.install.lock_mode = Some(LockMode::Explicit);
+        config.install.lock_format = Some(LockFormat::Toml);
+        PackageManager::with_config(config).with_project_dir(dir)
+    }
+
+    async fn install(dir: &Path) -> anyhow::Error {
+        let specs = vec![("left-pad".to_string(), "^1.3.0".to_string())];
+        explicit_lock_manager(dir)
+            .install_multiple_packages(specs, false, false)
+            .await
+            .expect_err("install should refuse the lockfile")
+    }
+
+    #[tokio::test]
+    async fn conflicted_lock_fails_install() {
+        let dir = project_with_lock(
+            "conflicted-lock",
+            "version = \"2\"\n<<<<<<< ours\n[root]\n=======\n[packages]\n>>>>>>> theirs\n",
+        );
+        let error = install(&dir).await;
+        assert_eq!(ErrorCode::of(&error), Some(ErrorCode::LockConflict));
+        assert!(error.to_string().contains("clay lock merge"));
+        assert!(!dir.join("node_modules").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_lock_fails_install() {
+        let dir = project_with_lock("corrupt-lock", "not a [[ lockfile");
+        let error = install(&dir).await;
+        assert_eq!(ErrorCode::of(&error), None);
+        assert!(error.to_string().starts_with("Failed to parse"));
+        assert!(!dir.join("node_modules").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("clay-lock.toml")).unwrap(),
+            "not a [[ lockfile"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,141 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Where `clay patch-commit` writes patches, relative to the project
+pub const PATCHES_DIR: &str = "patches";
+
+/// File in a patched package naming the patch it has, so installs apply each patch once
+pub const APPLIED_MARKER: &str = ".clay-patch";
+
+/// `patches/<name>@<version>.patch`, with a scope's `/` written as `__`
+pub fn patch_path(name: &str, version: &str) -> String {
+    format!("{PATCHES_DIR}/{}@{version}.patch", name.replace('/', "__"))
+}
+
+/// Short hash of a patch's text, kept in the APPLIED_MARKER of packages it was applied to
+pub fn fingerprint(patch: &str) -> String {
+    blake3::hash(patch.as_bytes()).to_hex()[..16].to_string()
+}
+
+/// A git-style patch turning the package in `original` into the one in `modified`.
+/// Nested node_modules are left out, and only text files can be patched.
+pub fn diff_dirs(original: &Path, modified: &Path) -> Result<String> {
+    let original_files = package_files(original);
+    let modified_files = package_files(modified);
+    let paths: BTreeSet<&String> = original_files.keys().chain(modified_files.keys()).collect();
+
+    let mut patch = String::new();
+    for path in paths {
+        let read = |files: &BTreeMap<String, PathBuf>| -> Result<Option<String>> {
+            let Some(file) = files.get(path) else {
+                return Ok(None);
+            };
+            let bytes = std::fs::read(file)?;
+            String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|_| anyhow!("{path} is a binary file; only text files can be patched"))
+        };
+        let before = read(&original_files)?;
+        let after = read(&modified_files)?;
+        if before == after {
+            continue;
+        }
+
+        let mut options = diffy::DiffOptions::new();
+        options
+            .set_original_filename(match before {
+                Some(_) => format!("a/{path}"),
+                None => "/dev/null".to_string(),
+            })
+            .set_modified_filename(match after {
+                Some(_) => format!("b/{path}"),
+                None => "/dev/null".to_string(),
+            });
+        let file_patch = options.create_patch(
+            before.as_deref().unwrap_or(""),
+            after.as_deref().unwrap_or(""),
+        );
+        patch.push_str(&format!("diff --git a/{path} b/{path}\n{file_patch}"));
+    }
+    Ok(patch)
+}
+
+/// Apply a patch made by `diff_dirs` to the package in `package_dir`. Files are replaced
+/// rather than written through, since they may be hardlinks into the content store.
+pub fn apply(package_dir: &Path, patch: &str) -> Result<()> {
+    for section in file_sections(patch) {
+        let file_patch = diffy::Patch::from_str(&section)?;
+        let original = file_patch.original();
+        let modified = file_patch.modified();
+        let Some(path) = modified
+            .filter(|name| *name != "/dev/null")
+            .and_then(|name| name.strip_prefix("b/"))
+            .or_else(|| original.and_then(|name| name.strip_prefix("a/")))
+        else {
+            return Err(anyhow!("A file in the patch has no name"));
+        };
+        let target = package_dir.join(path);
+
+        if modified == Some("/dev/null") {
+            std::fs::remove_file(&target).ok();
+            continue;
+        }
+
+        let base = if original == Some("/dev/null") {
+            String::new()
+        } else {
+            std::fs::read_to_string(&target)
+                .map_err(|e| anyhow!("Can't read {path} to patch it: {e}"))?
+        };
+        let patched = diffy::apply(&base, &file_patch)
+            .map_err(|e| anyhow!("{path} doesn't match the patch: {e}"))?;
+
+        let permissions = std::fs::metadata(&target).ok().map(|m| m.permissions());
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::remove_file(&target).ok();
+        std::fs::write(&target, patched)?;
+        if let Some(permissions) = permissions {
+            std::fs::set_permissions(&target, permissions)?;
+        }
+    }
+    Ok(())
+}
+
+/// The patch split at each `diff --git` line, one section per file
+fn file_sections(patch: &str) -> Vec<String> {
+    let mut sections: Vec<String> = Vec::new();
+    for line in patch.split_inclusive('\n') {
+        match sections.last_mut() {
+            Some(section) if !line.starts_with("diff --git ") => section.push_str(line),
+            _ => sections.push(line.to_string()),
+        }
+    }
+    sections
+}
+
+/// Regular files of a package by `/`-separated relative path
+fn package_files(dir: &Path) -> BTreeMap<String, PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name();
+            entry.depth() == 0
+                || (name != "node_modules" && name != ".git" && name != APPLIED_MARKER)
+        })
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(dir).ok()?;
+            let key = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((key, entry.path().to_path_buf()))
+        })
+        .collect()
+}
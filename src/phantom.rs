@@ -0,0 +1,309 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::LazyLock;
+use walkdir::WalkDir;
+
+use crate::cli_style::CliStyle;
+use crate::package_manager::PackageManager;
+
+/// package.json sections that count as declaring a dependency
+const DECLARING_FIELDS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+    "peerDependencies",
+];
+
+/// Extensions of the files whose imports are checked
+const SOURCE_EXTENSIONS: [&str; 8] = ["js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx"];
+
+/// Directories that hold installed, generated or bundled code rather than the project's own
+const SKIPPED_DIRS: [&str; 6] = ["node_modules", "dist", "build", "out", "coverage", "vendor"];
+
+/// Larger files are almost always bundles or generated code
+const MAX_SOURCE_SIZE: u64 = 1024 * 1024;
+
+/// Modules Node provides itself, importable without the `node:` prefix
+const NODE_BUILTINS: [&str; 42] = [
+    "assert",
+    "async_hooks",
+    "buffer",
+    "child_process",
+    "cluster",
+    "console",
+    "constants",
+    "crypto",
+    "dgram",
+    "diagnostics_channel",
+    "dns",
+    "domain",
+    "events",
+    "fs",
+    "http",
+    "http2",
+    "https",
+    "inspector",
+    "module",
+    "net",
+    "os",
+    "path",
+    "perf_hooks",
+    "process",
+    "punycode",
+    "querystring",
+    "readline",
+    "repl",
+    "stream",
+    "string_decoder",
+    "sys",
+    "timers",
+    "tls",
+    "trace_events",
+    "tty",
+    "url",
+    "util",
+    "v8",
+    "vm",
+    "wasi",
+    "worker_threads",
+    "zlib",
+];
+
+/// `import ... from 'x'`, `import 'x'` and `export ... from 'x'`; the first group is set
+/// for type-only imports, which don't exist at runtime
+static STATIC_IMPORT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?:^|[^.\w$])(?:import|export)\s+(type\s+)?(?:[\w$*{}\s,]+?\s*from\s*)?['"]([^'"\n]+)['"]"#,
+    )
+    .unwrap()
+});
+
+/// `require('x')`, `require.resolve('x')` and `import('x')`
+static DYNAMIC_IMPORT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:^|[^.\w$])(?:require(?:\.resolve)?|import)\s*\(\s*['"]([^'"\n]+)['"]\s*\)"#)
+        .unwrap()
+});
+
+static BLOCK_COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)/\*.*?\*/").unwrap());
+
+/// A package the project's code imports without package.json declaring it
+#[derive(Debug, Serialize)]
+pub struct MissingDependency {
+    pub name: String,
+    /// Files importing it, relative to the project
+    pub files: Vec<String>,
+    /// Version node_modules has, typically hoisted there by another dependency
+    pub installed: Option<String>,
+}
+
+impl MissingDependency {
+    /// Only imported from tests, so it belongs in devDependencies
+    fn dev_only(&self) -> bool {
+        self.files.iter().all(|file| is_test_file(file))
+    }
+}
+
+/// The packages imported by the code of the project in `project_dir` but declared in none
+/// of its dependency sections, by name. Nested directories with their own package.json are
+/// separate packages and aren't looked into.
+pub fn find_missing(project_dir: &Path) -> Result<Vec<MissingDependency>> {
+    let content = std::fs::read_to_string(project_dir.join("package.json"))
+        .map_err(|_| anyhow!("No package.json found"))?;
+    let package_json: Value = serde_json::from_str(&content)?;
+    let mut declared: HashSet<&str> = DECLARING_FIELDS
+        .iter()
+        .filter_map(|field| package_json.get(field)?.as_object())
+        .flat_map(|deps| deps.keys().map(String::as_str))
+        .collect();
+    if let Some(name) = package_json["name"].as_str() {
+        declared.insert(name);
+    }
+
+    let mut imported: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let sources = WalkDir::new(project_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || !(entry.file_name().to_string_lossy().starts_with('.')
+                    || SKIPPED_DIRS.iter().any(|dir| entry.file_name() == *dir)
+                    || entry.path().join("package.json").is_file())
+        })
+        .flatten()
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| SOURCE_EXTENSIONS.iter().any(|source| ext == *source))
+                && !entry.file_name().to_string_lossy().ends_with(".d.ts")
+                && entry
+                    .metadata()
+                    .is_ok_and(|metadata| metadata.len() <= MAX_SOURCE_SIZE)
+        });
+    for entry in sources {
+        let Ok(source) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let relative = entry
+            .path()
+            .strip_prefix(project_dir)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        for specifier in imports(&source) {
+            let Some(name) = package_name(&specifier) else {
+                continue;
+            };
+            if declared.contains(name) {
+                continue;
+            }
+            let files = imported.entry(name.to_string()).or_default();
+            if !files.contains(&relative) {
+                files.push(relative.clone());
+            }
+        }
+    }
+
+    let node_modules = project_dir.join("node_modules");
+    Ok(imported
+        .into_iter()
+        .map(|(name, files)| MissingDependency {
+            installed: installed_version(&node_modules.join(&name)),
+            name,
+            files,
+        })
+        .collect())
+}
+
+/// Module specifiers `source` imports at runtime
+fn imports(source: &str) -> Vec<String> {
+    let source = BLOCK_COMMENT.replace_all(source, "");
+    let code: String = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut specifiers: Vec<String> = STATIC_IMPORT
+        .captures_iter(&code)
+        .filter(|captures| captures.get(1).is_none())
+        .map(|captures| captures[2].to_string())
+        .collect();
+    specifiers.extend(
+        DYNAMIC_IMPORT
+            .captures_iter(&code)
+            .map(|captures| captures[1].to_string()),
+    );
+    specifiers
+}
+
+/// The package a bare specifier names (`lodash` for `lodash/fp`, `@scope/pkg` for
+/// `@scope/pkg/sub`); None for relative paths, builtins, protocols and path aliases
+fn package_name(specifier: &str) -> Option<&str> {
+    if specifier.starts_with(['.', '/', '#', '~', '$'])
+        || specifier.starts_with("@/")
+        || specifier.contains(':')
+    {
+        return None;
+    }
+    let name = match specifier.strip_prefix('@') {
+        Some(scoped) => {
+            let (scope, rest) = scoped.split_once('/')?;
+            let package = rest.split('/').next().filter(|name| !name.is_empty())?;
+            &specifier[..1 + scope.len() + 1 + package.len()]
+        }
+        None => specifier.split('/').next()?,
+    };
+    if NODE_BUILTINS.contains(&name) {
+        return None;
+    }
+    Some(name)
+}
+
+fn is_test_file(file: &str) -> bool {
+    let mut segments = file.split('/');
+    let file_name = segments.next_back().unwrap_or(file);
+    segments.any(|dir| matches!(dir, "test" | "tests" | "__tests__" | "spec" | "e2e"))
+        || file_name.contains(".test.")
+        || file_name.contains(".spec.")
+}
+
+fn installed_version(package_dir: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(package_dir.join("package.json")).ok()?;
+    let package_json: Value = serde_json::from_str(&content).ok()?;
+    Some(package_json["version"].as_str()?.to_string())
+}
+
+pub fn print(missing: &[MissingDependency]) {
+    if missing.is_empty() {
+        println!(
+            "{}",
+            CliStyle::success("Every imported package is declared in package.json")
+        );
+        return;
+    }
+
+    for dependency in missing {
+        let installed = match &dependency.installed {
+            Some(version) => format!("{} installed", CliStyle::version(version)),
+            None => "not installed".to_string(),
+        };
+        println!(
+            "  {} {}",
+            CliStyle::package_name(&dependency.name),
+            CliStyle::dim_text(&format!("({installed})"))
+        );
+        for file in &dependency.files {
+            println!("    {}", CliStyle::dim_text(file));
+        }
+    }
+}
+
+/// Declare each of `missing` in package.json: at the installed version where there is one,
+/// in devDependencies when only tests import it. The ones not installed are installed.
+pub async fn fix(package_manager: &PackageManager, missing: &[MissingDependency]) -> Result<()> {
+    let mut to_install = Vec::new();
+    for dependency in missing {
+        let field = if dependency.dev_only() {
+            "devDependencies"
+        } else {
+            "dependencies"
+        };
+        match &dependency.installed {
+            Some(version) => {
+                let range = package_manager.saved_range(None, version);
+                package_manager
+                    .update_package_json(&dependency.name, &range, field)
+                    .await?;
+                println!(
+                    "{} {}@{} to {field}",
+                    CliStyle::success("Added"),
+                    dependency.name,
+                    range
+                );
+            }
+            None => to_install.push(dependency),
+        }
+    }
+
+    // Installing saves into the one section the manager is set to, so go section by section
+    for dev in [false, true] {
+        let specs: Vec<(String, String)> = to_install
+            .iter()
+            .filter(|dependency| dependency.dev_only() == dev)
+            .map(|dependency| (dependency.name.clone(), "latest".to_string()))
+            .collect();
+        if !specs.is_empty() {
+            package_manager
+                .install_multiple_packages(specs, dev, true)
+                .await?;
+        }
+    }
+    Ok(())
+}
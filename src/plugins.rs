@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use crate::config::ClayConfig;
+use crate::content_store::ContentStore;
+use crate::exec;
+
+/// `clay <name>` runs an executable called clay-<name> from PATH, as git runs git-<name>
+const PREFIX: &str = "clay-";
+
+/// The executable `clay <name>` would run, the first one found on PATH
+pub fn find(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{PREFIX}{name}");
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        executable_names(&file_name)
+            .into_iter()
+            .map(|candidate| dir.join(candidate))
+            .find(|candidate| is_executable(candidate))
+    })
+}
+
+/// Run the plugin at `program` with `args`, telling it about clay and the project through
+/// environment variables, and wait for it with signals passed on
+pub async fn run(config: &ClayConfig, program: &Path, args: &[OsString]) -> Result<ExitStatus> {
+    let cwd = std::env::current_dir()?;
+    let mut cmd = tokio::process::Command::new(program);
+    cmd.args(args)
+        .env("CLAY_BIN", std::env::current_exe()?)
+        .env("CLAY_VERSION", env!("CARGO_PKG_VERSION"))
+        .env("CLAY_PROJECT_ROOT", exec::project_root(&cwd))
+        .env("CLAY_STORE_DIR", ContentStore::from_config(config).path())
+        .env("CLAY_CONFIG", serde_json::to_string(config)?);
+    exec::status_forwarding_signals(&mut cmd)
+        .await
+        .with_context(|| format!("Couldn't run {}", program.display()))
+}
+
+/// File names an executable called `name` may have on this platform
+fn executable_names(name: &str) -> Vec<String> {
+    if cfg!(windows) {
+        let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.CMD;.BAT".into());
+        extensions
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(|extension| format!("{name}{}", extension.to_lowercase()))
+            .collect()
+    } else {
+        vec![name.to_string()]
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use std::path::Path;
+use tracing::{Level, Subscriber};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard, TraceStyle};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+/// A layer recording the spans of this run as a Chrome trace in `path`, viewable in
+/// chrome://tracing or https://ui.perfetto.dev. Spans are drawn per async task rather
+/// than per thread, so concurrent downloads show up side by side. The trace is written
+/// when the returned guard is dropped.
+pub fn layer<S>(path: &Path) -> Result<(impl Layer<S>, FlushGuard)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Fail here on an unwritable path rather than in the layer's writer thread
+    std::fs::File::create(path)?;
+
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .file(path)
+        .trace_style(TraceStyle::Async)
+        .include_args(true)
+        .build();
+    // Only clay's own spans; the HTTP stack's per-request events would bury them
+    let layer = layer.with_filter(Targets::new().with_target("clay", Level::TRACE));
+    Ok((layer, guard))
+}
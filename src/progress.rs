@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Shortest gap between two download_progress events for the same download
+pub const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How progress is shown (`--progress`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Spinners and bars on terminals, plain status lines elsewhere
+    #[default]
+    Auto,
+    /// One JSON event per line on stderr, for IDEs and wrappers drawing their own progress
+    Json,
+}
+
+/// Something an install got done or started, as `--progress=json` reports it
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Resolution of the requested packages began
+    ResolveStart {
+        packages: u64,
+    },
+    /// Every package to install is known
+    ResolveDone {
+        packages: u64,
+        elapsed_ms: u64,
+    },
+    DownloadStart {
+        name: &'a str,
+        version: &'a str,
+    },
+    DownloadProgress {
+        name: &'a str,
+        version: &'a str,
+        bytes: u64,
+        /// None when the registry doesn't say how large the tarball is
+        total: Option<u64>,
+    },
+    DownloadDone {
+        name: &'a str,
+        version: &'a str,
+    },
+    /// A downloaded package is unpacked into node_modules
+    ExtractDone {
+        name: &'a str,
+        version: &'a str,
+    },
+    /// A package already in the content store is linked into node_modules
+    LinkDone {
+        name: &'a str,
+        version: &'a str,
+    },
+    ScriptRun {
+        name: &'a str,
+        version: &'a str,
+        script: &'a str,
+    },
+    ScriptDone {
+        name: &'a str,
+        version: &'a str,
+        script: &'a str,
+        code: Option<i32>,
+    },
+    InstallDone {
+        packages: u64,
+        elapsed_ms: u64,
+    },
+}
+
+pub fn set_format(format: ProgressFormat) {
+    JSON.store(format == ProgressFormat::Json, Ordering::Relaxed);
+}
+
+/// Whether progress goes out as JSON events, in place of spinners and bars
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Print `event` as a line of JSON on stderr with the time it happened, under
+/// `--progress=json`
+pub fn emit(event: Event) {
+    if !is_json() {
+        return;
+    }
+    let Ok(serde_json::Value::Object(mut line)) = serde_json::to_value(&event) else {
+        return;
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    line.insert("time".to_string(), time.into());
+    eprintln!("{}", serde_json::Value::Object(line));
+}
@@ -0,0 +1,369 @@
+use anyhow::{Result, anyhow};
+use console::style;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::cli_style::CliStyle;
+use crate::npm_client::NpmClient;
+use crate::npmrc::Npmrc;
+use crate::package_info::PackageJson;
+use crate::version_range::VersionRange;
+
+/// Commands that read or change package metadata on the registry rather than the local project
+pub struct RegistryManager {
+    npm_client: NpmClient,
+}
+
+impl RegistryManager {
+    pub fn new(npm_client: NpmClient) -> Self {
+        Self { npm_client }
+    }
+
+    /// Split `name@version`, keeping the leading `@` of scoped names
+    fn split_spec(spec: &str) -> (&str, Option<&str>) {
+        match spec.rfind('@') {
+            Some(at_pos) if at_pos > 0 => (&spec[..at_pos], Some(&spec[at_pos + 1..])),
+            _ => (spec, None),
+        }
+    }
+
+    /// Name from ./package.json, used when no package is given
+    async fn current_package_name() -> Result<String> {
+        let path = Path::new("package.json");
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|_| anyhow!("No package given and no package.json in this directory"))?;
+        let package_json: PackageJson = serde_json::from_str(&content)?;
+        package_json
+            .name
+            .ok_or_else(|| anyhow!("package.json has no name field"))
+    }
+
+    pub async fn dist_tag_ls(&self, package: Option<&str>, json: bool) -> Result<()> {
+        let package_name = match package {
+            Some(spec) => Self::split_spec(spec).0.to_string(),
+            None => Self::current_package_name().await?,
+        };
+
+        let tags = self.npm_client.get_dist_tags(&package_name).await?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&tags)?);
+            return Ok(());
+        }
+        if tags.is_empty() {
+            println!(
+                "{} {} has no dist-tags",
+                style("•").yellow(),
+                style(&package_name).white().bold()
+            );
+            return Ok(());
+        }
+
+        for (tag, version) in &tags {
+            println!("{}: {}", style(tag).cyan(), style(version).green());
+        }
+
+        Ok(())
+    }
+
+    pub async fn dist_tag_add(&self, spec: &str, tag: &str) -> Result<()> {
+        let (package_name, version) = Self::split_spec(spec);
+        let version = version
+            .filter(|version| !version.is_empty())
+            .ok_or_else(|| anyhow!("Expected <package>@<version>, got '{spec}'"))?;
+
+        // A tag that parses as a version would be ambiguous in `install pkg@tag`
+        if tag
+            .trim_start_matches('v')
+            .starts_with(|c: char| c.is_ascii_digit())
+        {
+            return Err(anyhow!(
+                "Tag '{tag}' looks like a version; tags must not be semver"
+            ));
+        }
+
+        let package_info = self.npm_client.get_package_info(package_name).await?;
+        if !package_info.versions.contains_key(version) {
+            return Err(anyhow!("{package_name}@{version} is not published"));
+        }
+
+        self.npm_client
+            .set_dist_tag(package_name, tag, version)
+            .await?;
+
+        println!(
+            "{} {} {}@{}",
+            CliStyle::success(""),
+            style(format!("+{tag}:")).cyan(),
+            style(package_name).white().bold(),
+            style(version).green()
+        );
+        Ok(())
+    }
+
+    pub async fn dist_tag_rm(&self, package: &str, tag: &str) -> Result<()> {
+        let package_name = Self::split_spec(package).0;
+        if tag == "latest" {
+            return Err(anyhow!("The latest tag cannot be removed"));
+        }
+
+        let tags = self.npm_client.get_dist_tags(package_name).await?;
+        let Some(version) = tags.get(tag) else {
+            return Err(anyhow!("{package_name} has no dist-tag '{tag}'"));
+        };
+
+        self.npm_client.remove_dist_tag(package_name, tag).await?;
+
+        println!(
+            "{} {} {}@{}",
+            CliStyle::success(""),
+            style(format!("-{tag}:")).cyan(),
+            style(package_name).white().bold(),
+            style(version).green()
+        );
+        Ok(())
+    }
+
+    /// Set (or with an empty message, clear) the deprecation message of every version
+    /// matching the range in `spec`
+    pub async fn deprecate(&self, spec: &str, message: &str) -> Result<()> {
+        let (package_name, range) = Self::split_spec(spec);
+        let range = VersionRange::parse(range.unwrap_or("*"))?;
+
+        let mut packument = self
+            .npm_client
+            .get_packument_for_write(package_name)
+            .await?;
+        let versions = packument
+            .get_mut("versions")
+            .and_then(|versions| versions.as_object_mut())
+            .ok_or_else(|| anyhow!("Registry returned no versions for {package_name}"))?;
+
+        let mut changed = Vec::new();
+        for (version, manifest) in versions.iter_mut() {
+            if !range.matches_str(version) {
+                continue;
+            }
+            if let Some(manifest) = manifest.as_object_mut() {
+                manifest.insert(
+                    "deprecated".to_string(),
+                    serde_json::Value::String(message.to_string()),
+                );
+                changed.push(version.clone());
+            }
+        }
+
+        if changed.is_empty() {
+            return Err(anyhow!(
+                "No published versions of {package_name} match '{spec}'"
+            ));
+        }
+
+        self.npm_client
+            .put_packument(package_name, &packument)
+            .await?;
+
+        let action = if message.is_empty() {
+            "Undeprecated"
+        } else {
+            "Deprecated"
+        };
+        println!(
+            "{} {} {} version(s) of {}",
+            CliStyle::success(""),
+            action,
+            style(changed.len()).white().bold(),
+            style(package_name).white().bold()
+        );
+        for version in &changed {
+            println!("  {} {}", style("•").dim(), style(version).green());
+        }
+
+        Ok(())
+    }
+
+    /// Print registry metadata for a package, as `clay info` does for uncached packages
+    pub async fn show_info(&self, package_name: &str, json: bool) -> Result<()> {
+        let document = self.npm_client.get_package_document(package_name).await?;
+        let latest = document
+            .dist_tags
+            .get("latest")
+            .and_then(|version| document.versions.get(version));
+
+        if json {
+            let mut versions: Vec<&String> = document.versions.keys().collect();
+            versions.sort_by_key(|version| semver::Version::parse(version).ok());
+            let info = serde_json::json!({
+                "name": document.name,
+                "version": latest.map(|latest| &latest.version),
+                "description": document.description,
+                "license": Self::license_name(document.license.as_ref()),
+                "homepage": document.homepage,
+                "deprecated": latest.and_then(|latest| latest.deprecated.as_ref()),
+                "dependencies": latest.and_then(|latest| latest.dependencies.as_ref()),
+                "dist_tags": document.dist_tags.iter().collect::<BTreeMap<_, _>>(),
+                "versions": versions,
+                "time": document.time.iter().collect::<BTreeMap<_, _>>(),
+                "maintainers": document
+                    .maintainers
+                    .iter()
+                    .map(|maintainer| serde_json::json!({
+                        "name": maintainer.name,
+                        "email": maintainer.email,
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+
+        println!(
+            "{}@{} {} {}",
+            style(&document.name).white().bold(),
+            style(latest.map_or("unknown", |latest| latest.version.as_str())).green(),
+            CliStyle::dim_text("|"),
+            CliStyle::dim_text(&format!(
+                "{} | deps: {} | versions: {}",
+                Self::license_name(document.license.as_ref()),
+                latest
+                    .and_then(|latest| latest.dependencies.as_ref())
+                    .map_or(0, |deps| deps.len()),
+                document.versions.len()
+            ))
+        );
+
+        if let Some(ref description) = document.description {
+            println!("{description}");
+        }
+        if let Some(ref homepage) = document.homepage {
+            println!("{}", CliStyle::cyan_text(homepage));
+        }
+        if let Some(deprecated) = latest.and_then(|latest| latest.deprecated.as_ref()) {
+            println!(
+                "{}",
+                CliStyle::warning(&format!("Deprecated: {deprecated}"))
+            );
+        }
+
+        if !document.dist_tags.is_empty() {
+            let mut tags: Vec<_> = document.dist_tags.iter().collect();
+            tags.sort();
+            println!("\n{}", CliStyle::section_header("dist-tags:"));
+            for (tag, version) in tags {
+                println!("  {}: {}", style(tag).cyan(), style(version).green());
+            }
+        }
+
+        // Most recently published versions first; `time` also holds created/modified
+        let mut published: Vec<(&String, &String)> = document
+            .time
+            .iter()
+            .filter(|(version, _)| document.versions.contains_key(*version))
+            .collect();
+        published.sort_by(|a, b| b.1.cmp(a.1));
+        if !published.is_empty() {
+            println!("\n{}", CliStyle::section_header("Recent versions:"));
+            for (version, time) in published.iter().take(5) {
+                println!(
+                    "  {} {}",
+                    style(version).green(),
+                    CliStyle::dim_text(time.split('T').next().unwrap_or(time))
+                );
+            }
+        }
+
+        if !document.maintainers.is_empty() {
+            println!("\n{}", CliStyle::section_header("Maintainers:"));
+            for maintainer in &document.maintainers {
+                match maintainer.email {
+                    Some(ref email) => println!(
+                        "  {} {}",
+                        maintainer.name,
+                        CliStyle::dim_text(&format!("<{email}>"))
+                    ),
+                    None => println!("  {}", maintainer.name),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `license` is usually a string, but old packages use `{ "type": ... }`
+    fn license_name(license: Option<&serde_json::Value>) -> String {
+        match license {
+            Some(serde_json::Value::String(license)) => license.clone(),
+            Some(license) => license
+                .get("type")
+                .and_then(|license_type| license_type.as_str())
+                .unwrap_or("unknown license")
+                .to_string(),
+            None => "no license".to_string(),
+        }
+    }
+
+    /// Prompt for credentials, log in to the registry and save the token to ~/.npmrc
+    pub async fn login(&self, registry: Option<&str>, scope: Option<&str>) -> Result<()> {
+        let registry = registry
+            .unwrap_or(self.npm_client.default_registry())
+            .trim_end_matches('/')
+            .to_string();
+        let scope = scope.map(|scope| {
+            if scope.starts_with('@') {
+                scope.to_string()
+            } else {
+                format!("@{scope}")
+            }
+        });
+
+        if !CliStyle::is_interactive() {
+            return Err(anyhow!(
+                "clay login asks for credentials and needs a terminal; in CI, put an \
+                 `_authToken` for {registry} in .npmrc instead"
+            ));
+        }
+
+        println!(
+            "{} Logging in to {}",
+            CliStyle::info(""),
+            style(&registry).cyan()
+        );
+        let username = Self::prompt("Username: ")?;
+        let password = rpassword::prompt_password("Password: ")?;
+        let email = Self::prompt("Email: ")?;
+        if username.is_empty() || password.is_empty() {
+            return Err(anyhow!("Username and password are required"));
+        }
+
+        let token = self
+            .npm_client
+            .login(&registry, &username, &password, &email)
+            .await?;
+
+        let path = Npmrc::save_auth_token(&registry, &token)?;
+        if let Some(ref scope) = scope {
+            Npmrc::save_user_setting(&format!("{scope}:registry"), &registry)?;
+        }
+
+        println!(
+            "{} Logged in as {}{} (token saved to {})",
+            CliStyle::success(""),
+            style(&username).white().bold(),
+            scope
+                .map(|scope| format!(" for {scope}"))
+                .unwrap_or_default(),
+            CliStyle::dim_text(&path.display().to_string())
+        );
+        Ok(())
+    }
+
+    fn prompt(label: &str) -> Result<String> {
+        print!("{label}");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim().to_string())
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::{Result, anyhow};
+use console::{Key, Term, style};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::npm_client::NpmClient;
+use crate::package_info::{PackageDocument, SearchResult};
+use crate::package_manager::PackageManager;
+
+/// Results asked of the registry per search
+const SEARCH_SIZE: usize = 20;
+
+/// Results shown at once by `clay search -i`
+const VISIBLE_RESULTS: usize = 10;
+
+/// Pause in typing after which `clay search -i` searches
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// `clay search <query>`: the best matches, one per line
+pub async fn search(npm_client: &NpmClient, query: &str, json: bool) -> Result<()> {
+    let results = npm_client.search(query, SEARCH_SIZE).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results.objects)?);
+        return Ok(());
+    }
+    if results.objects.is_empty() {
+        println!("{} No packages match '{query}'", style("•").yellow());
+        return Ok(());
+    }
+
+    let width = Term::stdout().size().1 as usize;
+    let name_width = results
+        .objects
+        .iter()
+        .map(|result| result.package.name.len())
+        .max()
+        .unwrap_or(0);
+    for result in &results.objects {
+        println!(
+            "{}",
+            console::truncate_str(&result_line(result, name_width), width, "…")
+        );
+    }
+    Ok(())
+}
+
+/// `name  version  weekly downloads  description`
+fn result_line(result: &SearchResult, name_width: usize) -> String {
+    let package = &result.package;
+    format!(
+        "{} {} {} {}",
+        style(format!("{:<name_width$}", package.name))
+            .white()
+            .bold(),
+        CliStyle::version(&format!("{:<10}", package.version)),
+        CliStyle::cyan_text(&format!(
+            "{:>9}",
+            result
+                .downloads
+                .as_ref()
+                .map_or(String::new(), |downloads| format!(
+                    "{}/wk",
+                    short_count(downloads.weekly)
+                ))
+        )),
+        CliStyle::dim_text(package.description.as_deref().unwrap_or(""))
+    )
+}
+
+/// `35.2M` for 35,212,345
+fn short_count(count: u64) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", count as f64 / 1e3),
+        _ => format!("{:.1}M", count as f64 / 1e6),
+    }
+}
+
+/// What `clay search -i` shows, redrawn after every key
+#[derive(Default)]
+struct SearchScreen {
+    query: String,
+    /// The query `results` are for
+    searched: String,
+    results: Vec<SearchResult>,
+    cursor: usize,
+    searching: bool,
+    error: Option<String>,
+    /// Full documents of highlighted results, None where the fetch failed
+    documents: HashMap<String, Option<PackageDocument>>,
+}
+
+impl SearchScreen {
+    fn selected(&self) -> Option<&SearchResult> {
+        self.results.get(self.cursor)
+    }
+
+    fn render(&self, width: usize) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{} {} {}{}",
+                style("?").cyan().bold(),
+                style("Search:").bold(),
+                self.query,
+                style("▏").cyan()
+            ),
+            CliStyle::dim_text(if self.searching {
+                "  searching..."
+            } else {
+                "  type to search, ↑/↓ move, enter install, esc quit"
+            }),
+        ];
+
+        if let Some(error) = &self.error {
+            lines.push(CliStyle::error(error));
+        } else if self.results.is_empty() && !self.searched.is_empty() && !self.searching {
+            lines.push(CliStyle::dim_text(&format!(
+                "  No packages match '{}'",
+                self.searched
+            )));
+        }
+
+        let start = self.cursor.saturating_sub(VISIBLE_RESULTS - 1);
+        let visible = self
+            .results
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(VISIBLE_RESULTS);
+        let name_width = self
+            .results
+            .iter()
+            .map(|result| result.package.name.len())
+            .max()
+            .unwrap_or(0);
+        for (i, result) in visible {
+            let marker = if i == self.cursor {
+                style("❯").cyan().to_string()
+            } else {
+                " ".to_string()
+            };
+            lines.push(format!("{marker} {}", result_line(result, name_width)));
+        }
+
+        if let Some(result) = self.selected() {
+            lines.push(String::new());
+            lines.extend(self.details(result));
+        }
+
+        lines
+            .iter()
+            .map(|line| console::truncate_str(line, width, "…").to_string())
+            .collect()
+    }
+
+    /// Description, downloads, dist-tags and recent versions of the highlighted package
+    fn details(&self, result: &SearchResult) -> Vec<String> {
+        let package = &result.package;
+        let mut summary = vec![format!(
+            "{} {}",
+            CliStyle::package_name(&package.name),
+            CliStyle::version(&package.version)
+        )];
+        if let Some(downloads) = &result.downloads {
+            summary.push(format!("{} downloads/wk", short_count(downloads.weekly)));
+        }
+        let document = self.documents.get(&package.name).and_then(Option::as_ref);
+        if let Some(document) = document {
+            let count = document.versions.len();
+            summary.push(format!(
+                "{count} version{}",
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+
+        let mut lines = vec![format!("  {}", summary.join(&CliStyle::dim_text(" · ")))];
+        if let Some(description) = &package.description {
+            lines.push(format!("  {description}"));
+        }
+        let Some(document) = document else {
+            return lines;
+        };
+
+        let mut tags: Vec<_> = document.dist_tags.iter().collect();
+        tags.sort();
+        if !tags.is_empty() {
+            let tags: Vec<String> = tags
+                .into_iter()
+                .map(|(tag, version)| {
+                    format!("{} {}", style(tag).cyan(), CliStyle::version(version))
+                })
+                .collect();
+            lines.push(format!("  {}", tags.join("  ")));
+        }
+        // Most recently published first; `time` also holds created/modified
+        let mut published: Vec<(&String, &String)> = document
+            .time
+            .iter()
+            .filter(|(version, _)| document.versions.contains_key(*version))
+            .collect();
+        published.sort_by(|a, b| b.1.cmp(a.1));
+        if !published.is_empty() {
+            let recent: Vec<&str> = published
+                .iter()
+                .take(5)
+                .map(|(version, _)| version.as_str())
+                .collect();
+            lines.push(CliStyle::dim_text(&format!(
+                "  recent: {}",
+                recent.join(", ")
+            )));
+        }
+        lines
+    }
+}
+
+/// `clay search -i`: search as the user types, and install the package they pick with enter
+pub async fn interactive(config: ClayConfig, npm_client: NpmClient, query: String) -> Result<()> {
+    if !CliStyle::is_interactive() {
+        return Err(anyhow!(
+            "search -i needs a terminal; run clay search <query> instead"
+        ));
+    }
+
+    // Reading a key blocks, so keys arrive from a thread that stops at the last one
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let term = Term::stderr();
+        while let Ok(key) = term.read_key() {
+            let last = matches!(key, Key::Enter | Key::Escape | Key::CtrlC);
+            if keys_tx.send(key).is_err() || last {
+                break;
+            }
+        }
+    });
+
+    let term = Term::stderr();
+    let mut screen = SearchScreen {
+        query,
+        ..SearchScreen::default()
+    };
+    let mut search_at = (!screen.query.is_empty()).then(Instant::now);
+    let mut drawn = 0;
+    let chosen = loop {
+        term.clear_last_lines(drawn)?;
+        let lines = screen.render(term.size().1 as usize);
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        drawn = lines.len();
+
+        let key = tokio::select! {
+            key = keys.recv() => key,
+            _ = tokio::time::sleep_until(search_at.unwrap_or_else(Instant::now)),
+                if search_at.is_some() =>
+            {
+                search_at = None;
+                run_search(&npm_client, &mut screen, &term, &mut drawn).await?;
+                continue;
+            }
+        };
+
+        match key {
+            Some(Key::Char(c)) if !c.is_control() => {
+                screen.query.push(c);
+                search_at = Some(Instant::now() + SEARCH_DEBOUNCE);
+            }
+            Some(Key::Backspace) => {
+                screen.query.pop();
+                search_at = Some(Instant::now() + SEARCH_DEBOUNCE);
+            }
+            Some(Key::ArrowUp) if screen.cursor > 0 => {
+                screen.cursor -= 1;
+                fetch_document(&npm_client, &mut screen).await;
+            }
+            Some(Key::ArrowDown) if screen.cursor + 1 < screen.results.len() => {
+                screen.cursor += 1;
+                fetch_document(&npm_client, &mut screen).await;
+            }
+            Some(Key::Enter) => break screen.selected().map(|result| result.package.name.clone()),
+            Some(Key::Escape | Key::CtrlC) | None => break None,
+            Some(_) => {}
+        }
+    };
+    term.clear_last_lines(drawn)?;
+
+    let Some(name) = chosen else {
+        return Ok(());
+    };
+    let package_manager = PackageManager::with_client(config, npm_client);
+    package_manager.initialize().await?;
+    package_manager
+        .install_multiple_packages(vec![(name, "latest".to_string())], false, true)
+        .await
+}
+
+/// Search for the screen's query, showing "searching..." meanwhile
+async fn run_search(
+    npm_client: &NpmClient,
+    screen: &mut SearchScreen,
+    term: &Term,
+    drawn: &mut usize,
+) -> Result<()> {
+    if screen.query.trim().is_empty() {
+        screen.results.clear();
+        screen.searched.clear();
+        screen.error = None;
+        return Ok(());
+    }
+
+    screen.searching = true;
+    term.clear_last_lines(*drawn)?;
+    let lines = screen.render(term.size().1 as usize);
+    for line in &lines {
+        term.write_line(line)?;
+    }
+    *drawn = lines.len();
+
+    let query = screen.query.trim().to_string();
+    let results = npm_client.search(&query, SEARCH_SIZE).await;
+    screen.searching = false;
+    screen.cursor = 0;
+    match results {
+        Ok(results) => {
+            screen.results = results.objects;
+            screen.error = None;
+        }
+        Err(e) => {
+            screen.results.clear();
+            screen.error = Some(format!("{e}"));
+        }
+    }
+    screen.searched = query;
+    fetch_document(npm_client, screen).await;
+    Ok(())
+}
+
+/// Load the full document of the highlighted package, once, for its versions and tags
+async fn fetch_document(npm_client: &NpmClient, screen: &mut SearchScreen) {
+    let Some(name) = screen.selected().map(|result| result.package.name.clone()) else {
+        return;
+    };
+    if let Entry::Vacant(entry) = screen.documents.entry(name) {
+        let document = npm_client.get_package_document(entry.key()).await.ok();
+        entry.insert(document);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::cli_style::CliStyle;
+use crate::errors;
+use crate::npm_client::NpmClient;
+
+/// GitHub Releases API for clay; CLAY_RELEASES_URL points upgrades at a mirror instead
+const RELEASES_URL: &str = "https://api.github.com/repos/lassejlv/clay/releases";
+
+/// Release files listing the sha256 of each binary, tried in order
+const CHECKSUM_ASSETS: &[&str] = &["SHA256SUMS", "checksums.txt"];
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// The release's version, without the tag's `v`
+    pub fn version(&self) -> Result<semver::Version> {
+        semver::Version::parse(self.tag_name.trim_start_matches('v'))
+            .with_context(|| format!("Release tag {} isn't a version", self.tag_name))
+    }
+
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Name of the release binary for this platform, e.g. `clay-linux-x86_64` or
+/// `clay-windows-x86_64.exe`
+pub fn asset_name() -> String {
+    format!(
+        "clay-{}-{}{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// The newest published release
+pub async fn latest_release(npm_client: &NpmClient) -> Result<Release> {
+    let base = std::env::var("CLAY_RELEASES_URL").unwrap_or_else(|_| RELEASES_URL.to_string());
+    let url = format!("{}/latest", base.trim_end_matches('/'));
+    let response = npm_client
+        .client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(errors::http(
+            status,
+            format!("Couldn't look up the latest release at {url}: HTTP {status}"),
+        ));
+    }
+    Ok(response.json().await?)
+}
+
+/// Download this platform's binary from `release`, check it against the release's
+/// checksums, and swap it in for the running executable. Returns the path replaced.
+pub async fn install(npm_client: &NpmClient, release: &Release) -> Result<PathBuf> {
+    let name = asset_name();
+    let asset = release
+        .asset(&name)
+        .ok_or_else(|| anyhow!("Release {} has no {name} binary", release.tag_name))?;
+    let expected = expected_checksum(npm_client, release, &name).await?;
+
+    let spinner = CliStyle::create_spinner(&format!("Downloading {name}..."));
+    let bytes = download(npm_client, &asset.browser_download_url).await;
+    spinner.finish_and_clear();
+    let bytes = bytes?;
+
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if actual != expected {
+        return Err(errors::coded(
+            errors::ErrorCode::Integrity,
+            format!(
+                "{name} doesn't match its published checksum (expected {expected}, got {actual})"
+            ),
+        ));
+    }
+
+    let current = std::env::current_exe()?
+        .canonicalize()
+        .context("Couldn't locate the running clay executable")?;
+    replace_executable(&current, &bytes)?;
+    Ok(current)
+}
+
+/// The sha256 the release publishes for `name`, from `<name>.sha256` or a checksums file
+async fn expected_checksum(
+    npm_client: &NpmClient,
+    release: &Release,
+    name: &str,
+) -> Result<String> {
+    let own = format!("{name}.sha256");
+    let Some(asset) = std::iter::once(own.as_str())
+        .chain(CHECKSUM_ASSETS.iter().copied())
+        .find_map(|file| release.asset(file))
+    else {
+        return Err(anyhow!(
+            "Release {} publishes no checksums; refusing to install an unverified binary",
+            release.tag_name
+        ));
+    };
+
+    let listing = String::from_utf8(download(npm_client, &asset.browser_download_url).await?)
+        .with_context(|| format!("{} isn't text", asset.name))?;
+    // `<hex>  <file>` per line (sha256sum's format), or just the hex for a single file
+    listing
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let hash = fields.next()?;
+            match fields.next() {
+                Some(file) if file.trim_start_matches('*') != name => None,
+                _ => Some(hash.to_lowercase()),
+            }
+        })
+        .next()
+        .ok_or_else(|| anyhow!("{} has no checksum for {name}", asset.name))
+}
+
+async fn download(npm_client: &NpmClient, url: &str) -> Result<Vec<u8>> {
+    let response = npm_client.client.get(url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(errors::http(
+            status,
+            format!("Couldn't download {url}: HTTP {status}"),
+        ));
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Write the new binary next to `current` and rename it over the original, so the
+/// executable is never half-written. The new binary must run `--version` first.
+fn replace_executable(current: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = current
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", current.display()))?;
+    let staged = dir.join(format!(
+        ".clay-upgrade-{}{}",
+        std::process::id(),
+        std::env::consts::EXE_SUFFIX
+    ));
+    std::fs::write(&staged, bytes).with_context(|| {
+        format!(
+            "Can't write to {}; try again with more permissions",
+            dir.display()
+        )
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    let runs = std::process::Command::new(&staged)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !runs {
+        std::fs::remove_file(&staged).ok();
+        return Err(anyhow!("The downloaded binary doesn't run on this machine"));
+    }
+
+    // Windows won't overwrite a running executable, but will rename it out of the way
+    #[cfg(windows)]
+    {
+        let old = current.with_extension("old.exe");
+        std::fs::remove_file(&old).ok();
+        std::fs::rename(current, &old)?;
+    }
+    std::fs::rename(&staged, current).inspect_err(|_| {
+        std::fs::remove_file(&staged).ok();
+    })?;
+    Ok(())
+}
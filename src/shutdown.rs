@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Paths that must not outlive an interrupted run (staging dirs, partial extractions, locks)
+static CLEANUP_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The child process signals are passed on to while a command waits on it, and whether it
+/// leads its own process group
+static FORWARD_TO: Mutex<Option<(u32, bool)>> = Mutex::new(None);
+
+/// Watch loops still running, whether they have been told to stop, and the wakeups for
+/// both changes
+static WATCHERS: AtomicUsize = AtomicUsize::new(0);
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static STOP: Notify = Notify::const_new();
+static STOPPED: Notify = Notify::const_new();
+
+/// How long an interrupted run waits for its watch loops to stop
+pub const WATCHER_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+        }
+    }
+
+    /// Conventional shell exit code for a process killed by this signal
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+
+    #[cfg(unix)]
+    fn number(&self) -> i32 {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+        }
+    }
+}
+
+/// Wait until the process receives SIGINT (Ctrl+C) or SIGTERM
+pub async fn wait_for_signal() -> Signal {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => Signal::Interrupt,
+                    _ = terminate.recv() => Signal::Terminate,
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                Signal::Interrupt
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        Signal::Interrupt
+    }
+}
+
+/// Sends the signals this process receives on to a child process, which then decides how
+/// the run ends, until dropped
+pub struct ForwardSignals;
+
+impl ForwardSignals {
+    /// `own_group`: the child leads a process group of its own, and the whole group gets
+    /// the signals
+    pub fn new(pid: u32, own_group: bool) -> Self {
+        if let Ok(mut forward_to) = FORWARD_TO.lock() {
+            *forward_to = Some((pid, own_group));
+        }
+        Self
+    }
+}
+
+impl Drop for ForwardSignals {
+    fn drop(&mut self) {
+        if let Ok(mut forward_to) = FORWARD_TO.lock() {
+            *forward_to = None;
+        }
+    }
+}
+
+/// Pass `signal` on to the child being waited on; false when there is none and the run
+/// itself should stop
+pub fn forward(signal: Signal) -> bool {
+    let Some((pid, own_group)) = FORWARD_TO.lock().ok().and_then(|forward_to| *forward_to) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        // Sharing our process group, the child got Ctrl+C from the terminal already
+        if signal == Signal::Interrupt && !own_group {
+            return true;
+        }
+        let target = if own_group { -(pid as i32) } else { pid as i32 };
+        // SAFETY: kill only sends a signal; a process that is already gone makes it fail
+        unsafe {
+            libc::kill(target, signal.number());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (pid, own_group, signal);
+    true
+}
+
+/// A long-running watch loop (dev server, `bundle --watch`) that a signal stops between
+/// iterations rather than in the middle of a rebuild; registered until dropped
+pub struct Watcher;
+
+impl Watcher {
+    pub fn new() -> Self {
+        WATCHERS.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+
+    /// Wait `interval` before the next iteration; false once the run is stopping
+    pub async fn tick(&self, interval: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => !STOPPING.load(Ordering::SeqCst),
+            _ = self.stopping() => false,
+        }
+    }
+
+    /// Resolves once the run is stopping
+    pub async fn stopping(&self) {
+        let stop = STOP.notified();
+        if !STOPPING.load(Ordering::SeqCst) {
+            stop.await;
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if WATCHERS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            STOPPED.notify_waiters();
+        }
+    }
+}
+
+/// Tell every watch loop to stop; false when none is running
+pub fn stop_watchers() -> bool {
+    STOPPING.store(true, Ordering::SeqCst);
+    STOP.notify_waiters();
+    WATCHERS.load(Ordering::SeqCst) > 0
+}
+
+/// Wait until every watch loop has stopped
+pub async fn watchers_stopped() {
+    loop {
+        let stopped = STOPPED.notified();
+        if WATCHERS.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        stopped.await;
+    }
+}
+
+/// Registers a path for removal if the process is interrupted; unregisters on drop
+pub struct CleanupGuard {
+    path: PathBuf,
+}
+
+impl CleanupGuard {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if let Ok(mut paths) = CLEANUP_PATHS.lock() {
+            paths.push(path.clone());
+        }
+        Self { path }
+    }
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        unregister(&self.path);
+    }
+}
+
+/// A directory under the OS temp dir that belongs to this process alone. Removed when
+/// dropped (including while unwinding from a panic) and when the run is interrupted.
+pub struct ScratchDir {
+    path: PathBuf,
+    _guard: CleanupGuard,
+}
+
+impl ScratchDir {
+    /// Reserve a fresh path; the directory itself is created by whoever first writes into it
+    pub fn new(prefix: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            prefix,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self {
+            _guard: CleanupGuard::new(&path),
+            path,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+fn unregister(path: &Path) {
+    if let Ok(mut paths) = CLEANUP_PATHS.lock()
+        && let Some(pos) = paths.iter().rposition(|p| p == path)
+    {
+        paths.remove(pos);
+    }
+}
+
+/// Remove every registered path, newest first. Called after a signal aborts the command.
+pub fn run_cleanup() {
+    let paths = match CLEANUP_PATHS.lock() {
+        Ok(mut paths) => std::mem::take(&mut *paths),
+        Err(_) => return,
+    };
+
+    for path in paths.iter().rev() {
+        if path.is_dir() {
+            std::fs::remove_dir_all(path).ok();
+        } else if path.exists() {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::Value;
+use std::path::Path;
+
+use crate::package_info::NpmRegistryResponse;
+
+/// Whether the package in `package_dir` ships its own TypeScript declarations: a
+/// `types`/`typings` field, `types` conditions in `exports`, or a `.d.ts` beside its
+/// entry point
+pub fn has_bundled_types(package_dir: &Path) -> bool {
+    let Some(package_json) = std::fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+    else {
+        return false;
+    };
+
+    if package_json.get("types").is_some() || package_json.get("typings").is_some() {
+        return true;
+    }
+    if package_json
+        .get("exports")
+        .is_some_and(|exports| exports.to_string().contains("\"types\""))
+    {
+        return true;
+    }
+
+    let main = package_json
+        .get("main")
+        .and_then(Value::as_str)
+        .unwrap_or("index.js");
+    let main = main.strip_prefix("./").unwrap_or(main);
+    let stem = main
+        .strip_suffix(".js")
+        .or_else(|| main.strip_suffix(".cjs"))
+        .or_else(|| main.strip_suffix(".mjs"))
+        .unwrap_or(main);
+    package_dir.join(format!("{stem}.d.ts")).is_file()
+        || package_dir.join(stem).join("index.d.ts").is_file()
+}
+
+/// The DefinitelyTyped package for `name`: `@types/name`, or `@types/scope__name` for
+/// `@scope/name`. None for packages that are themselves types.
+pub fn types_package_name(name: &str) -> Option<String> {
+    if name.starts_with("@types/") {
+        return None;
+    }
+    Some(match name.strip_prefix('@') {
+        Some(scoped) => format!("@types/{}", scoped.replacen('/', "__", 1)),
+        None => format!("@types/{name}"),
+    })
+}
+
+/// Newest version of a types package written for the same major version as `version`;
+/// DefinitelyTyped numbers its packages after the library they describe
+pub fn matching_version(types: &NpmRegistryResponse, version: &str) -> Option<String> {
+    let major = semver::Version::parse(version).ok()?.major;
+    types
+        .versions
+        .keys()
+        .filter_map(|candidate| semver::Version::parse(candidate).ok())
+        .filter(|candidate| candidate.major == major && candidate.pre.is_empty())
+        .max()
+        .map(|candidate| candidate.to_string())
+}
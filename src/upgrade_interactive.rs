@@ -0,0 +1,322 @@
+use anyhow::{Result, anyhow};
+use console::{Key, Term, style};
+use futures::stream::{self, StreamExt};
+use semver::Version;
+use serde_json::Value;
+use std::path::Path;
+
+use crate::cli_style::CliStyle;
+use crate::config::ClayConfig;
+use crate::npm_client::NpmClient;
+use crate::package_manager::{PackageManager, SaveTarget};
+use crate::version_bump;
+use crate::version_range::VersionRange;
+
+/// package.json sections upgrades are offered for; peer ranges are the consumers' business
+const UPGRADE_FIELDS: [&str; 3] = ["dependencies", "devDependencies", "optionalDependencies"];
+
+/// A dependency the registry has something newer of
+pub struct Outdated {
+    pub name: String,
+    /// package.json section it's listed in
+    pub field: &'static str,
+    pub range: String,
+    /// Version in node_modules, if installed
+    pub current: Option<Version>,
+    /// Newest version the range allows
+    pub wanted: Option<Version>,
+    pub latest: Version,
+}
+
+impl Outdated {
+    /// Versions it can be bumped to, oldest first: the wanted one, then latest
+    fn targets(&self) -> Vec<&Version> {
+        let mut targets = Vec::new();
+        for version in [self.wanted.as_ref(), Some(&self.latest)]
+            .into_iter()
+            .flatten()
+        {
+            if self
+                .current
+                .as_ref()
+                .is_none_or(|current| version > current)
+                && !targets.contains(&version)
+            {
+                targets.push(version);
+            }
+        }
+        targets
+    }
+}
+
+/// The dependencies in `project_dir`'s package.json with a newer version than installed,
+/// by name. Ranges that aren't semver (git, file:, workspace:) are skipped.
+pub async fn outdated(
+    npm_client: &NpmClient,
+    project_dir: &Path,
+    concurrency: usize,
+) -> Result<Vec<Outdated>> {
+    let path = project_dir.join("package.json");
+    let content = std::fs::read_to_string(&path).map_err(|_| anyhow!("No package.json found"))?;
+    let package_json: Value = serde_json::from_str(&content)?;
+
+    let mut dependencies = Vec::new();
+    for field in UPGRADE_FIELDS {
+        let Some(Value::Object(deps)) = package_json.get(field) else {
+            continue;
+        };
+        for (name, range) in deps {
+            if let Some(range) = range.as_str()
+                && VersionRange::parse(range).is_ok()
+            {
+                dependencies.push((name.clone(), field, range.to_string()));
+            }
+        }
+    }
+
+    let node_modules = project_dir.join("node_modules");
+    let mut outdated: Vec<Outdated> = stream::iter(dependencies)
+        .map(|(name, field, range)| {
+            let node_modules = &node_modules;
+            async move {
+                let info = match npm_client.get_package_info(&name).await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        eprintln!(
+                            "{}",
+                            CliStyle::warning(&format!("Couldn't check {name}: {e}"))
+                        );
+                        return None;
+                    }
+                };
+                let latest = Version::parse(&info.get_latest_version()?.version).ok()?;
+                let wanted = info
+                    .max_satisfying(&range)
+                    .and_then(|wanted| Version::parse(&wanted.version).ok());
+                let current = installed_version(&node_modules.join(&name));
+                let outdated = Outdated {
+                    name,
+                    field,
+                    range,
+                    current,
+                    wanted,
+                    latest,
+                };
+                (!outdated.targets().is_empty()).then_some(outdated)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(|outdated| async { outdated })
+        .collect()
+        .await;
+    outdated.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(outdated)
+}
+
+fn installed_version(package_dir: &Path) -> Option<Version> {
+    let content = std::fs::read_to_string(package_dir.join("package.json")).ok()?;
+    let package_json: Value = serde_json::from_str(&content).ok()?;
+    Version::parse(package_json["version"].as_str()?).ok()
+}
+
+/// Let the user pick which of `outdated` to upgrade and to which of its targets. Returns
+/// the picks as (index into `outdated`, version), or None if they backed out.
+pub fn select(outdated: &[Outdated]) -> Result<Option<Vec<(usize, Version)>>> {
+    let term = Term::stderr();
+    let mut cursor = 0;
+    // Per package: the target shown as picked (latest by default) and whether it's chosen
+    let mut levels: Vec<usize> = outdated
+        .iter()
+        .map(|package| package.targets().len() - 1)
+        .collect();
+    let mut chosen = vec![false; outdated.len()];
+
+    let mut drawn = 0;
+    loop {
+        term.clear_last_lines(drawn)?;
+        let lines = render(outdated, cursor, &levels, &chosen);
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        drawn = lines.len();
+
+        match term.read_key()? {
+            Key::ArrowUp | Key::Char('k') => {
+                cursor = cursor.checked_sub(1).unwrap_or(outdated.len() - 1)
+            }
+            Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1) % outdated.len(),
+            Key::Char(' ') => chosen[cursor] = !chosen[cursor],
+            Key::ArrowLeft | Key::Char('h') => {
+                levels[cursor] = levels[cursor].saturating_sub(1);
+                chosen[cursor] = true;
+            }
+            Key::ArrowRight | Key::Char('l') => {
+                levels[cursor] = (levels[cursor] + 1).min(outdated[cursor].targets().len() - 1);
+                chosen[cursor] = true;
+            }
+            Key::Char('a') => {
+                let all = !chosen.iter().all(|chosen| *chosen);
+                chosen.fill(all);
+            }
+            Key::Enter => break,
+            Key::Escape | Key::Char('q') | Key::CtrlC => {
+                term.clear_last_lines(drawn)?;
+                return Ok(None);
+            }
+            _ => {}
+        }
+    }
+    term.clear_last_lines(drawn)?;
+
+    Ok(Some(
+        outdated
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| chosen[*i])
+            .map(|(i, package)| (i, package.targets()[levels[i]].clone()))
+            .collect(),
+    ))
+}
+
+fn render(outdated: &[Outdated], cursor: usize, levels: &[usize], chosen: &[bool]) -> Vec<String> {
+    let name_width = outdated
+        .iter()
+        .map(|package| package.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Package".len());
+    let version_width = outdated
+        .iter()
+        .flat_map(|package| [&package.current, &package.wanted])
+        .flatten()
+        .chain(outdated.iter().map(|package| &package.latest))
+        .map(|version| version.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("Current".len());
+
+    let mut lines = vec![
+        format!(
+            "{} {}",
+            style("?").cyan().bold(),
+            style("Choose which packages to upgrade").bold()
+        ),
+        CliStyle::dim_text(
+            "  ↑/↓ move, space select, ←/→ wanted or latest, a all, enter upgrade, esc cancel",
+        ),
+        CliStyle::dim_text(&format!(
+            "      {:<name_width$}  {:<version_width$}  {:<version_width$}  {:<version_width$}",
+            "Package", "Current", "Wanted", "Latest"
+        )),
+    ];
+    for (i, package) in outdated.iter().enumerate() {
+        let picked = package.targets()[levels[i]];
+        let column = |version: Option<&Version>| {
+            let text = format!(
+                "{:<version_width$}",
+                version.map_or("-".to_string(), Version::to_string)
+            );
+            match version {
+                Some(version) if version == picked && chosen[i] => {
+                    style(text).green().bold().to_string()
+                }
+                Some(version) if version == picked => style(text).underlined().to_string(),
+                _ => CliStyle::dim_text(&text),
+            }
+        };
+        let wanted = package
+            .wanted
+            .as_ref()
+            .filter(|wanted| package.targets().contains(wanted));
+        lines.push(format!(
+            "{} {} {:<name_width$}  {:<version_width$}  {}  {}",
+            if i == cursor {
+                style("❯").cyan().to_string()
+            } else {
+                " ".to_string()
+            },
+            if chosen[i] {
+                style("◉").green().to_string()
+            } else {
+                "◯".to_string()
+            },
+            package.name,
+            package
+                .current
+                .as_ref()
+                .map_or("-".to_string(), Version::to_string),
+            if wanted.is_some() {
+                column(wanted)
+            } else {
+                CliStyle::dim_text(&format!(
+                    "{:<version_width$}",
+                    package
+                        .wanted
+                        .as_ref()
+                        .map_or("-".to_string(), Version::to_string)
+                ))
+            },
+            column(Some(&package.latest)),
+        ));
+    }
+    lines
+}
+
+/// `clay upgrade-interactive`: list outdated dependencies, let the user pick upgrades, then
+/// rewrite their package.json ranges and install them together
+pub async fn upgrade_interactive(config: ClayConfig, npm_client: NpmClient) -> Result<()> {
+    if !CliStyle::is_interactive() {
+        return Err(anyhow!(
+            "upgrade-interactive needs a terminal; run clay install <package>@<version> instead"
+        ));
+    }
+
+    let project_dir = std::env::current_dir()?;
+    let spinner = CliStyle::create_spinner("Checking for newer versions...");
+    let outdated = outdated(
+        &npm_client,
+        &project_dir,
+        config.network.resolve_concurrency(),
+    )
+    .await;
+    spinner.finish_and_clear();
+    let outdated = outdated?;
+    if outdated.is_empty() {
+        println!("{}", CliStyle::success("All dependencies are up to date"));
+        return Ok(());
+    }
+
+    let Some(picks) = select(&outdated)? else {
+        return Ok(());
+    };
+    if picks.is_empty() {
+        println!("{}", CliStyle::info("Nothing selected"));
+        return Ok(());
+    }
+
+    let mut package_manager = PackageManager::with_client(config, npm_client);
+    let mut ranges = Vec::new();
+    let mut specs = Vec::new();
+    for (i, version) in picks {
+        let package = &outdated[i];
+        // Keep the range's operator (^1.2.0 becomes ^2.0.0), else save it as install would
+        let range = version_bump::bump_range(&package.range, &version)
+            .unwrap_or_else(|| package_manager.saved_range(None, &version.to_string()));
+        println!(
+            "  {} {} → {}",
+            CliStyle::package_name(&package.name),
+            CliStyle::dim_text(&package.range),
+            CliStyle::version(&range)
+        );
+        ranges.push((package.field, package.name.clone(), range.clone()));
+        specs.push((package.name.clone(), range));
+    }
+
+    package_manager.initialize().await?;
+    package_manager.set_dependency_ranges(&ranges).await?;
+    // package.json already says what was picked, in the section each dependency is in
+    package_manager.set_save_target(SaveTarget::None);
+    package_manager
+        .install_multiple_packages(specs, false, true)
+        .await
+}
@@ -0,0 +1,350 @@
+use anyhow::{Context, Result, anyhow};
+use semver::{Prerelease, Version};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::ClayConfig;
+use crate::lifecycle;
+use crate::workspace::{WorkspaceManager, WorkspacePackage};
+
+/// package.json sections a workspace can depend on another one from
+const DEPENDENCY_FIELDS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+    "peerDependencies",
+];
+
+/// How `clay version` changes a package's version
+#[derive(Debug, Clone)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    Exact(Version),
+}
+
+impl std::str::FromStr for Bump {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "major" => Bump::Major,
+            "minor" => Bump::Minor,
+            "patch" => Bump::Patch,
+            exact => {
+                Bump::Exact(Version::parse(exact.trim_start_matches('v')).map_err(|_| {
+                    anyhow!("'{exact}' is neither major, minor, patch nor a version")
+                })?)
+            }
+        })
+    }
+}
+
+impl Bump {
+    /// The version after `current`. As with npm, bumping a prerelease to the release it
+    /// leads up to just drops the prerelease (1.2.0-rc.1 minor is 1.2.0).
+    pub fn apply(&self, current: &Version) -> Version {
+        let pre = !current.pre.is_empty();
+        let (major, minor, patch) = (current.major, current.minor, current.patch);
+        let mut next = match self {
+            Bump::Exact(version) => return version.clone(),
+            Bump::Major if pre && minor == 0 && patch == 0 => Version::new(major, 0, 0),
+            Bump::Major => Version::new(major + 1, 0, 0),
+            Bump::Minor if pre && patch == 0 => Version::new(major, minor, 0),
+            Bump::Minor => Version::new(major, minor + 1, 0),
+            Bump::Patch if pre => Version::new(major, minor, patch),
+            Bump::Patch => Version::new(major, minor, patch + 1),
+        };
+        next.pre = Prerelease::EMPTY;
+        next
+    }
+}
+
+pub struct VersionOptions {
+    /// Commit package.json and tag the commit `v<version>` when in a git repository
+    pub git: bool,
+    /// Commit message; `%s` is replaced with the new version
+    pub message: String,
+    /// Run the preversion, version and postversion scripts
+    pub scripts: bool,
+}
+
+/// Bump the package in `project_dir`, point other workspaces' dependencies on it at the
+/// new version, then commit and tag. Returns the new version.
+pub async fn bump(
+    config: &ClayConfig,
+    project_dir: &Path,
+    bump: &Bump,
+    options: &VersionOptions,
+) -> Result<Version> {
+    let package_json_path = project_dir.join("package.json");
+    let package_json = read_json(&package_json_path)?;
+    let name = package_json["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("package.json has no name"))?
+        .to_string();
+    let current = package_json["version"]
+        .as_str()
+        .ok_or_else(|| anyhow!("package.json has no version"))?;
+    let current =
+        Version::parse(current).with_context(|| format!("'{current}' isn't a valid version"))?;
+    let next = bump.apply(&current);
+    if next == current {
+        return Err(anyhow!("{name} is already at {current}"));
+    }
+
+    let git = options.git && in_git_repository(project_dir);
+    if git {
+        ensure_clean(project_dir)?;
+    }
+
+    if options.scripts {
+        lifecycle::run_project_script(project_dir, "preversion").await?;
+    }
+    edit_json(&package_json_path, |package_json| {
+        package_json["version"] = Value::String(next.to_string());
+        true
+    })?;
+    let mut changed = vec![package_json_path.clone()];
+    changed.extend(update_dependents(config, project_dir, &name, &next).await?);
+    if options.scripts {
+        lifecycle::run_project_script(project_dir, "version").await?;
+    }
+
+    if git {
+        let message = options.message.replace("%s", &next.to_string());
+        let mut add = vec!["add".into(), "--".into()];
+        add.extend(changed.iter().map(|path| path.as_os_str().to_os_string()));
+        git_run(project_dir, &add)?;
+        git_run(project_dir, &["commit", "-m", &message])?;
+        git_run(
+            project_dir,
+            &["tag", "-a", &format!("v{next}"), "-m", &message],
+        )?;
+    }
+
+    if options.scripts {
+        lifecycle::run_project_script(project_dir, "postversion").await?;
+    }
+    Ok(next)
+}
+
+/// `clay workspace version`: bump each of `workspaces`, point the monorepo's dependencies
+/// on them at their new versions, then make one commit for the release. It's tagged
+/// `v<version>` when several workspaces all land on the same version, otherwise with a
+/// `<name>@<version>` tag each. Returns the new version of each workspace.
+pub async fn bump_workspaces(
+    config: &ClayConfig,
+    root: &Path,
+    workspaces: &[WorkspacePackage],
+    bump: &Bump,
+    options: &VersionOptions,
+) -> Result<Vec<(String, Version)>> {
+    let mut planned = Vec::new();
+    for workspace in workspaces {
+        let package_json = read_json(&workspace.package_json)?;
+        let current = package_json["version"]
+            .as_str()
+            .ok_or_else(|| anyhow!("{} has no version", workspace.name))?;
+        let current = Version::parse(current)
+            .with_context(|| format!("{}'s '{current}' isn't a valid version", workspace.name))?;
+        let next = bump.apply(&current);
+        if next == current {
+            return Err(anyhow!("{} is already at {current}", workspace.name));
+        }
+        planned.push((workspace, next));
+    }
+
+    let git = options.git && in_git_repository(root);
+    if git {
+        ensure_clean(root)?;
+    }
+
+    if options.scripts {
+        for (workspace, _) in &planned {
+            lifecycle::run_project_script(Path::new(&workspace.path), "preversion").await?;
+        }
+    }
+    let mut changed = Vec::new();
+    for (workspace, next) in &planned {
+        edit_json(&workspace.package_json, |package_json| {
+            package_json["version"] = Value::String(next.to_string());
+            true
+        })?;
+        changed.push(workspace.package_json.clone());
+    }
+    for (workspace, next) in &planned {
+        let dir = Path::new(&workspace.path);
+        for manifest in update_dependents(config, dir, &workspace.name, next).await? {
+            if !changed.contains(&manifest) {
+                changed.push(manifest);
+            }
+        }
+    }
+    if options.scripts {
+        for (workspace, _) in &planned {
+            lifecycle::run_project_script(Path::new(&workspace.path), "version").await?;
+        }
+    }
+
+    let bumped: Vec<(String, Version)> = planned
+        .iter()
+        .map(|(workspace, next)| (workspace.name.clone(), next.clone()))
+        .collect();
+    if git {
+        let released: Vec<String> = bumped
+            .iter()
+            .map(|(name, version)| format!("{name}@{version}"))
+            .collect();
+        let message = options.message.replace("%s", &released.join(", "));
+        let mut add = vec!["add".into(), "--".into()];
+        add.extend(changed.iter().map(|path| path.as_os_str().to_os_string()));
+        git_run(root, &add)?;
+        git_run(root, &["commit", "-m", &message])?;
+        let tags = match bumped.first() {
+            Some((_, version))
+                if bumped.len() > 1 && bumped.iter().all(|(_, other)| other == version) =>
+            {
+                vec![format!("v{version}")]
+            }
+            _ => released,
+        };
+        for tag in tags {
+            git_run(root, &["tag", "-a", &tag, "-m", &message])?;
+        }
+    }
+
+    if options.scripts {
+        for (workspace, _) in &planned {
+            lifecycle::run_project_script(Path::new(&workspace.path), "postversion").await?;
+        }
+    }
+    Ok(bumped)
+}
+
+/// Rewrite the ranges other packages of the monorepo declare for `name` to match
+/// `version`; returns the package.json files changed
+async fn update_dependents(
+    config: &ClayConfig,
+    project_dir: &Path,
+    name: &str,
+    version: &Version,
+) -> Result<Vec<PathBuf>> {
+    let Some(root) = WorkspaceManager::find_root(project_dir) else {
+        return Ok(Vec::new());
+    };
+    let mut manifests = vec![root.join("package.json")];
+    manifests.extend(
+        WorkspaceManager::with_root(&root, config.clone())
+            .discover_workspaces()
+            .await?
+            .into_iter()
+            .map(|workspace| workspace.package_json),
+    );
+
+    let own = std::path::absolute(project_dir.join("package.json"))?;
+    let mut changed = Vec::new();
+    for manifest in manifests {
+        if std::path::absolute(&manifest)? == own {
+            continue;
+        }
+        let edited = edit_json(&manifest, |package_json| {
+            let mut edited = false;
+            for field in DEPENDENCY_FIELDS {
+                let Some(range) = package_json
+                    .get_mut(field)
+                    .and_then(|deps| deps.get_mut(name))
+                else {
+                    continue;
+                };
+                if let Some(updated) = range.as_str().and_then(|range| bump_range(range, version)) {
+                    *range = Value::String(updated);
+                    edited = true;
+                }
+            }
+            edited
+        })?;
+        if edited {
+            changed.push(manifest);
+        }
+    }
+    Ok(changed)
+}
+
+/// `range` pointing at `version` instead, keeping its operator and any `workspace:`
+/// protocol. Ranges that aren't a single version (`*`, `workspace:^`, `>=1 <2`) already
+/// follow the package and are left alone.
+pub fn bump_range(range: &str, version: &Version) -> Option<String> {
+    let (protocol, spec) = match range.strip_prefix("workspace:") {
+        Some(spec) => ("workspace:", spec),
+        None => ("", range),
+    };
+    let operator_len = spec.len() - spec.trim_start_matches(['^', '~', '=']).len();
+    let (operator, pinned) = spec.split_at(operator_len);
+    Version::parse(pinned).ok()?;
+    Some(format!("{protocol}{operator}{version}"))
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid JSON in {}", path.display()))
+}
+
+/// Apply `edit` to a JSON file, writing it back when `edit` returns true
+fn edit_json(path: &Path, edit: impl FnOnce(&mut Value) -> bool) -> Result<bool> {
+    let mut value = read_json(path)?;
+    if !edit(&mut value) {
+        return Ok(false);
+    }
+    let mut content = serde_json::to_string_pretty(&value)?;
+    content.push('\n');
+    std::fs::write(path, content)?;
+    Ok(true)
+}
+
+fn in_git_repository(dir: &Path) -> bool {
+    Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .current_dir(dir)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Refuse to bump over uncommitted changes to tracked files, which the release commit
+/// would otherwise leave out or sweep in
+fn ensure_clean(dir: &Path) -> Result<()> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+    let changes = String::from_utf8_lossy(&output.stdout);
+    if !changes.trim().is_empty() {
+        return Err(anyhow!(
+            "Git working directory not clean; commit or stash first (or pass --no-git-tag-version):\n{}",
+            changes.trim_end()
+        ));
+    }
+    Ok(())
+}
+
+fn git_run<S: AsRef<std::ffi::OsStr>>(dir: &Path, args: &[S]) -> Result<()> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first()
+                .map(|arg| arg.as_ref().to_string_lossy())
+                .unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
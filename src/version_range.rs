@@ -0,0 +1,98 @@
+use anyhow::{Result, anyhow};
+use semver::{Version, VersionReq};
+
+/// An npm-style version range: `||` alternatives of space-separated comparators,
+/// hyphen ranges, x-ranges and bare versions, evaluated with the semver crate
+#[derive(Debug, Clone)]
+pub struct VersionRange {
+    alternatives: Vec<VersionReq>,
+}
+
+impl VersionRange {
+    pub fn parse(range: &str) -> Result<Self> {
+        let alternatives = range
+            .split("||")
+            .map(|alternative| {
+                let translated = Self::translate(alternative.trim());
+                VersionReq::parse(&translated)
+                    .map_err(|e| anyhow!("Invalid version range '{range}': {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { alternatives })
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|req| req.matches(version))
+    }
+
+    /// Like `matches`, treating unparseable versions as non-matching
+    pub fn matches_str(&self, version: &str) -> bool {
+        Version::parse(version.trim_start_matches('v'))
+            .map(|version| self.matches(&version))
+            .unwrap_or(false)
+    }
+
+    /// Rewrite one npm alternative into the comma-separated syntax the semver crate expects
+    fn translate(alternative: &str) -> String {
+        if alternative.is_empty() || alternative == "*" || alternative == "x" {
+            return "*".to_string();
+        }
+
+        if let Some((low, high)) = alternative.split_once(" - ") {
+            return format!(
+                ">={}, <={}",
+                low.trim().trim_start_matches('v'),
+                high.trim().trim_start_matches('v')
+            );
+        }
+
+        // Join operators written apart from their version (`>= 1.0.0`)
+        let mut comparators: Vec<String> = Vec::new();
+        let mut pending_operator = String::new();
+        for token in alternative.split_whitespace() {
+            if token.chars().all(|c| "<>=~^".contains(c)) {
+                pending_operator.push_str(token);
+                continue;
+            }
+            comparators.push(Self::translate_comparator(&format!(
+                "{pending_operator}{token}"
+            )));
+            pending_operator.clear();
+        }
+
+        comparators.join(", ")
+    }
+
+    /// Bare versions are exact in npm but caret in the semver crate; bare partial
+    /// versions (`1.2`) mean `1.2.x`
+    fn translate_comparator(comparator: &str) -> String {
+        let operator_len = comparator
+            .find(|c: char| !"<>=~^".contains(c))
+            .unwrap_or(comparator.len());
+        let (operator, version) = comparator.split_at(operator_len);
+        let version = version.trim_start_matches('v');
+
+        if !operator.is_empty() {
+            return format!("{operator}{version}");
+        }
+
+        let is_wildcard = version
+            .split('.')
+            .any(|part| part == "x" || part == "X" || part == "*");
+        let core_parts = version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .count();
+
+        if is_wildcard {
+            version.to_string()
+        } else if core_parts >= 3 {
+            format!("={version}")
+        } else {
+            format!("~{version}")
+        }
+    }
+}
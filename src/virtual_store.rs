@@ -0,0 +1,69 @@
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+use crate::links;
+use crate::long_path;
+
+/// Directory under node_modules holding the packages of the isolated layout
+pub const DIR: &str = ".clay";
+
+/// Name of the virtual store entry for `name@version`; a scope's `/` becomes `+`
+pub fn entry_name(name: &str, version: &str) -> String {
+    format!("{}@{version}", name.replace('/', "+"))
+}
+
+/// Where `name@version` lives: `.clay/<entry>/node_modules/<name>`, so the links to its
+/// dependencies beside it are all Node's resolution can find
+pub fn package_dir(node_modules: &Path, name: &str, version: &str) -> PathBuf {
+    entry_node_modules(node_modules, name, version).join(name)
+}
+
+/// The node_modules directory holding `name@version` and its dependencies
+pub fn entry_node_modules(node_modules: &Path, name: &str, version: &str) -> PathBuf {
+    entry_dir(node_modules, name, version).join("node_modules")
+}
+
+/// `.clay/<entry>`, everything installed for `name@version`
+pub fn entry_dir(node_modules: &Path, name: &str, version: &str) -> PathBuf {
+    node_modules.join(DIR).join(entry_name(name, version))
+}
+
+/// Point `link` at `target` with a relative symlink, replacing whatever is there unless
+/// it already points the same way
+pub fn link(target: &Path, link: &Path) -> Result<()> {
+    let parent = link.parent().unwrap_or(Path::new(""));
+    let relative = relative_path(parent, target);
+
+    if let Ok(metadata) = link.symlink_metadata() {
+        if metadata.is_symlink()
+            && std::fs::read_link(link).is_ok_and(|existing| existing == relative)
+        {
+            return Ok(());
+        }
+        if metadata.is_dir() {
+            std::fs::remove_dir_all(long_path::extend(link))?;
+        } else if std::fs::remove_file(link).is_err() {
+            // Directory symlinks and junctions on Windows
+            std::fs::remove_dir(link)?;
+        }
+    }
+
+    std::fs::create_dir_all(parent)?;
+    links::symlink(&relative, link)
+}
+
+/// `to` as seen from `from_dir`, both given relative to the same directory
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
@@ -1,17 +1,78 @@
 use anyhow::{Result, anyhow};
-use console::style;
-use futures::future::join_all;
+use console::{Color, style};
+use futures::stream::{FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
+use walkdir::WalkDir;
 
+use crate::catalog::Catalogs;
 use crate::cli_style::CliStyle;
-use crate::package_manager::PackageManager;
+use crate::config::ClayConfig;
+use crate::errors::{self, ErrorCode};
+use crate::package_info::PackageJson;
+use crate::package_manager::{PackageManager, PackageResolver};
+use crate::version_range::VersionRange;
+use crate::workspace_filter::{self, WorkspaceGraph};
+use crate::workspace_graph::{Graph, GraphNode};
+
+/// package.json sections that make one workspace depend on another
+const DEPENDENCY_FIELDS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+    "peerDependencies",
+];
+
+/// Directories never searched for workspaces
+const IGNORED_DIRS: [&str; 2] = ["node_modules", ".git"];
+
+/// Colors of the workspace names prefixing parallel script output, in turn
+const PREFIX_COLORS: [Color; 5] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Blue,
+    Color::Green,
+];
+
+/// How a parallel `workspace run` shows its scripts' output (`--output-style`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputStyle {
+    /// Lines as they come, each prefixed with its workspace's name
+    #[default]
+    Stream,
+    /// Each workspace's lines together, once its script finishes
+    Group,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WorkspaceConfig {
-    pub workspaces: Vec<String>,
+/// How many scripts a parallel `workspace run` runs at once, and how it shows them
+#[derive(Debug, Clone, Copy)]
+pub struct Parallelism {
+    pub concurrency: usize,
+    pub output_style: OutputStyle,
+}
+
+/// A workspace package as the others see it: installed by linking its directory
+#[derive(Debug, Clone)]
+pub struct LocalPackage {
+    pub dir: PathBuf,
+    pub version: String,
+}
+
+impl LocalPackage {
+    /// Whether linking this package meets a dependency on it with `range`: `workspace:*`,
+    /// `workspace:^` and `workspace:~` always do, other ranges when the version is in them
+    pub fn satisfies(&self, range: &str) -> bool {
+        let range = range.strip_prefix("workspace:").unwrap_or(range);
+        matches!(range, "*" | "^" | "~" | "latest")
+            || VersionRange::parse(range).is_ok_and(|range| range.matches_str(&self.version))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,19 +85,164 @@ pub struct WorkspacePackage {
 pub struct WorkspaceManager {
     root_path: PathBuf,
     workspace_config_path: PathBuf,
+    config: ClayConfig,
 }
 
 impl WorkspaceManager {
-    pub fn new() -> Self {
+    pub fn new(config: ClayConfig) -> Self {
         Self {
             root_path: PathBuf::from("."),
             workspace_config_path: PathBuf::from("package.json"),
+            config,
         }
     }
 
-    pub async fn list_workspaces(&self) -> Result<()> {
+    /// Workspaces of the monorepo rooted at `root`
+    pub fn with_root(root: &Path, config: ClayConfig) -> Self {
+        Self {
+            root_path: root.to_path_buf(),
+            workspace_config_path: root.join("package.json"),
+            config,
+        }
+    }
+
+    /// The nearest directory from `dir` up whose package.json declares workspaces
+    pub fn find_root(dir: &Path) -> Option<PathBuf> {
+        let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+        dir.ancestors()
+            .find(|dir| {
+                std::fs::read_to_string(dir.join("package.json"))
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+                    .is_some_and(|package_json| package_json.get("workspaces").is_some())
+            })
+            .map(Path::to_path_buf)
+    }
+
+    /// Every workspace package by name, for installs to link rather than download
+    pub async fn local_packages(&self) -> Result<HashMap<String, LocalPackage>> {
+        let mut packages = HashMap::new();
+        for workspace in self.discover_workspaces().await? {
+            let package_json = self.read_workspace_package_json(&workspace.path).await?;
+            let version = package_json
+                .get("version")
+                .and_then(|v| v.as_str())
+                .unwrap_or("0.0.0")
+                .to_string();
+            let dir = std::path::absolute(&workspace.path)?;
+            packages.insert(workspace.name, LocalPackage { dir, version });
+        }
+        Ok(packages)
+    }
+
+    /// The `catalog` and `catalogs` of the root package.json
+    pub fn catalogs(&self) -> Result<Catalogs> {
+        Catalogs::read(&self.root_path)
+    }
+
+    /// Which workspaces each workspace depends on, from their package.json files
+    pub async fn dependency_graph(
+        &self,
+        workspaces: &[WorkspacePackage],
+    ) -> Result<WorkspaceGraph> {
+        let names: BTreeSet<&str> = workspaces.iter().map(|w| w.name.as_str()).collect();
+        let mut graph = WorkspaceGraph::new();
+        for workspace in workspaces {
+            let package_json = self.read_workspace_package_json(&workspace.path).await?;
+            let dependencies = DEPENDENCY_FIELDS
+                .iter()
+                .filter_map(|field| package_json.get(field).and_then(|deps| deps.as_object()))
+                .flat_map(|deps| deps.keys())
+                .filter(|name| names.contains(name.as_str()) && **name != workspace.name)
+                .cloned()
+                .collect();
+            graph.insert(workspace.name.clone(), dependencies);
+        }
+        Ok(graph)
+    }
+
+    /// `clay workspace graph`: which workspaces depend on which, as a tree, DOT or JSON,
+    /// with cycles and the workspaces depending on `dependents_of` highlighted
+    pub async fn print_graph(
+        &self,
+        dot: bool,
+        json: bool,
+        dependents_of: Option<&str>,
+    ) -> Result<()> {
+        let workspaces = self.discover_workspaces().await?;
+        let edges = self.dependency_graph(&workspaces).await?;
+        let mut nodes = Vec::new();
+        for workspace in workspaces {
+            let package_json = self.read_workspace_package_json(&workspace.path).await?;
+            let version = package_json
+                .get("version")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+            nodes.push(GraphNode {
+                name: workspace.name,
+                version,
+                path: workspace.path,
+            });
+        }
+
+        let graph = Graph::new(nodes, edges, dependents_of)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&graph.to_json())?);
+        } else if dot {
+            print!("{}", graph.to_dot());
+        } else {
+            graph.print_tree();
+        }
+        Ok(())
+    }
+
+    /// The workspaces `--filter` selectors pick, narrowed with `since` to the ones changed
+    /// since that git ref and their dependents; all of them without either
+    pub async fn select_workspaces(
+        &self,
+        filters: &[String],
+        since: Option<&str>,
+    ) -> Result<Vec<WorkspacePackage>> {
+        let workspaces = self.discover_workspaces().await?;
+        if filters.is_empty() && since.is_none() {
+            return Ok(workspaces);
+        }
+        let graph = self.dependency_graph(&workspaces).await?;
+        let mut selected: BTreeSet<String> =
+            workspace_filter::select(&workspaces, &graph, filters)?
+                .into_iter()
+                .map(|workspace| workspace.name.clone())
+                .collect();
+        if let Some(since) = since {
+            let changed = workspace_filter::changed_since(since, &workspaces, &graph)?;
+            selected.retain(|name| changed.contains(name));
+        }
+        Ok(workspaces
+            .into_iter()
+            .filter(|workspace| selected.contains(&workspace.name))
+            .collect())
+    }
+
+    pub async fn list_workspaces(&self, json: bool) -> Result<()> {
         let workspaces = self.discover_workspaces().await?;
 
+        if json {
+            let mut listed = Vec::new();
+            for workspace in &workspaces {
+                let package_info = self.read_workspace_package_json(&workspace.path).await?;
+                listed.push(serde_json::json!({
+                    "name": workspace.name,
+                    "version": package_info.get("version"),
+                    "path": workspace.path,
+                }));
+            }
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "workspaces": listed }))?
+            );
+            return Ok(());
+        }
+
         if workspaces.is_empty() {
             println!("{} No workspaces configured", style("•").yellow());
             return Ok(());
@@ -124,16 +330,12 @@ impl WorkspaceManager {
     pub async fn run_script(
         &self,
         script: &str,
-        workspace_filter: Option<&str>,
-        parallel: bool,
+        filters: &[String],
+        since: Option<&str>,
+        parallel: Option<Parallelism>,
     ) -> Result<()> {
-        let workspaces = self.discover_workspaces().await?;
-
-        let target_workspaces: Vec<&WorkspacePackage> = if let Some(filter) = workspace_filter {
-            workspaces.iter().filter(|w| w.name == filter).collect()
-        } else {
-            workspaces.iter().collect()
-        };
+        let workspaces = self.select_workspaces(filters, since).await?;
+        let target_workspaces: Vec<&WorkspacePackage> = workspaces.iter().collect();
 
         if target_workspaces.is_empty() {
             println!("{} No workspaces found", style("•").yellow());
@@ -150,60 +352,84 @@ impl WorkspaceManager {
             } else {
                 "s"
             },
-            if parallel { " (parallel)" } else { "" }
+            match parallel {
+                Some(parallel) => format!(" (parallel, {} at a time)", parallel.concurrency),
+                None => String::new(),
+            }
         );
 
-        if parallel {
-            let tasks: Vec<_> = target_workspaces
+        // A workspace's script runs after those of the selected workspaces it depends on
+        let graph = self.dependency_graph(&workspaces).await?;
+        let dependencies = dependency_indices(&target_workspaces, &graph);
+
+        if let Some(parallel) = parallel {
+            let width = target_workspaces
                 .iter()
-                .map(|workspace| {
-                    let workspace_name = workspace.name.clone();
-                    let workspace_path = workspace.path.clone();
-                    let script = script.to_string();
-
-                    async move {
-                        println!(
-                            "{} [{}] Starting script...",
-                            style("→").cyan(),
-                            style(&workspace_name).white().bold()
-                        );
-
-                        let result = self
-                            .execute_script_in_workspace(&script, &workspace_path)
-                            .await;
-
-                        match result {
-                            Ok(success) => {
-                                if success {
-                                    println!(
-                                        "{} [{}] Script completed successfully",
-                                        CliStyle::success(""),
-                                        style(&workspace_name).white().bold()
-                                    );
-                                } else {
-                                    println!(
-                                        "{} [{}] Script failed",
-                                        CliStyle::error(""),
-                                        style(&workspace_name).white().bold()
-                                    );
-                                }
-                                success
-                            }
-                            Err(e) => {
+                .map(|workspace| workspace.name.len())
+                .max()
+                .unwrap_or(0);
+            let run = |i: usize| {
+                let workspace: &WorkspacePackage = target_workspaces[i];
+                let workspace_name = workspace.name.clone();
+                let workspace_path = workspace.path.clone();
+                let script = script.to_string();
+                let prefix = style(format!("{workspace_name:<width$} |"))
+                    .fg(PREFIX_COLORS[i % PREFIX_COLORS.len()])
+                    .to_string();
+
+                async move {
+                    println!(
+                        "{} [{}] Starting script...",
+                        style("→").cyan(),
+                        style(&workspace_name).white().bold()
+                    );
+
+                    let result = self
+                        .execute_script_prefixed(
+                            &script,
+                            &workspace_path,
+                            &prefix,
+                            parallel.output_style,
+                        )
+                        .await;
+
+                    match result {
+                        Ok(success) => {
+                            if success {
                                 println!(
-                                    "{} [{}] Script error: {}",
+                                    "{} [{}] Script completed successfully",
+                                    CliStyle::success(""),
+                                    style(&workspace_name).white().bold()
+                                );
+                            } else {
+                                println!(
+                                    "{} [{}] Script failed",
                                     CliStyle::error(""),
-                                    style(&workspace_name).white().bold(),
-                                    e
+                                    style(&workspace_name).white().bold()
                                 );
-                                false
                             }
+                            success
+                        }
+                        Err(e) => {
+                            println!(
+                                "{} [{}] Script error: {}",
+                                CliStyle::error(""),
+                                style(&workspace_name).white().bold(),
+                                e
+                            );
+                            false
                         }
                     }
-                })
-                .collect();
+                }
+            };
 
-            let results = join_all(tasks).await;
+            let results = run_in_dependency_order(
+                &target_workspaces,
+                dependencies,
+                parallel.concurrency,
+                run,
+            )
+            .await;
             let successful = results.iter().filter(|&&success| success).count();
             let failed = results.len() - successful;
 
@@ -214,6 +440,7 @@ impl WorkspaceManager {
                     style(successful).green(),
                     style(failed).red()
                 );
+                return Err(script_failures(script, failed));
             } else {
                 println!(
                     "\n{} All {} scripts completed successfully",
@@ -222,47 +449,59 @@ impl WorkspaceManager {
                 );
             }
         } else {
-            for workspace in target_workspaces {
-                println!(
-                    "{} [{}] Running script...",
-                    style("→").cyan(),
-                    style(&workspace.name).white().bold()
-                );
-
-                match self
-                    .execute_script_in_workspace(script, &workspace.path)
-                    .await
-                {
-                    Ok(true) => {
-                        println!(
-                            "{} [{}] Script completed successfully",
-                            CliStyle::success(""),
-                            style(&workspace.name).white().bold()
-                        );
-                    }
-                    Ok(false) => {
-                        println!(
-                            "{} [{}] Script failed",
-                            CliStyle::error(""),
-                            style(&workspace.name).white().bold()
-                        );
-                    }
-                    Err(e) => {
-                        println!(
-                            "{} [{}] Script error: {}",
-                            CliStyle::error(""),
-                            style(&workspace.name).white().bold(),
-                            e
-                        );
+            let run = |i: usize| {
+                let workspace: &WorkspacePackage = target_workspaces[i];
+                async move {
+                    println!(
+                        "{} [{}] Running script...",
+                        style("→").cyan(),
+                        style(&workspace.name).white().bold()
+                    );
+
+                    match self
+                        .execute_script_in_workspace(script, &workspace.path)
+                        .await
+                    {
+                        Ok(true) => {
+                            println!(
+                                "{} [{}] Script completed successfully",
+                                CliStyle::success(""),
+                                style(&workspace.name).white().bold()
+                            );
+                            true
+                        }
+                        Ok(false) => {
+                            println!(
+                                "{} [{}] Script failed",
+                                CliStyle::error(""),
+                                style(&workspace.name).white().bold()
+                            );
+                            false
+                        }
+                        Err(e) => {
+                            println!(
+                                "{} [{}] Script error: {}",
+                                CliStyle::error(""),
+                                style(&workspace.name).white().bold(),
+                                e
+                            );
+                            false
+                        }
                     }
                 }
+            };
+
+            let results = run_in_dependency_order(&target_workspaces, dependencies, 1, run).await;
+            let failed = results.iter().filter(|&&success| !success).count();
+            if failed > 0 {
+                return Err(script_failures(script, failed));
             }
         }
 
         Ok(())
     }
 
-    async fn discover_workspaces(&self) -> Result<Vec<WorkspacePackage>> {
+    pub async fn discover_workspaces(&self) -> Result<Vec<WorkspacePackage>> {
         let mut workspaces = Vec::new();
 
         // Check if we have a workspace configuration
@@ -297,18 +536,15 @@ impl WorkspaceManager {
                 _ => return Ok(workspaces),
             };
 
-            for pattern in patterns {
-                let workspace_paths = self.resolve_workspace_pattern(&pattern).await?;
-                for path in workspace_paths {
-                    if let Ok(package_info) = self.read_workspace_package_json(&path).await {
-                        if let Some(name) = package_info.get("name").and_then(|n| n.as_str()) {
-                            workspaces.push(WorkspacePackage {
-                                name: name.to_string(),
-                                path: path.clone(),
-                                package_json: PathBuf::from(&path).join("package.json"),
-                            });
-                        }
-                    }
+            for path in self.resolve_workspace_patterns(&patterns)? {
+                if let Ok(package_info) = self.read_workspace_package_json(&path).await
+                    && let Some(name) = package_info.get("name").and_then(|n| n.as_str())
+                {
+                    workspaces.push(WorkspacePackage {
+                        name: name.to_string(),
+                        path: path.clone(),
+                        package_json: PathBuf::from(&path).join("package.json"),
+                    });
                 }
             }
         }
@@ -316,37 +552,78 @@ impl WorkspaceManager {
         Ok(workspaces)
     }
 
-    async fn resolve_workspace_pattern(&self, pattern: &str) -> Result<Vec<String>> {
-        let mut paths = Vec::new();
-
-        if pattern.contains('*') {
-            // Handle glob patterns
-
-            let mut entries = fs::read_dir(".").await?;
-            while let Some(entry) = entries.next_entry().await? {
-                if entry.file_type().await?.is_dir() {
-                    let dir_name = entry.file_name();
-                    let dir_str = dir_name.to_string_lossy();
-
-                    if let Some(base_pattern) = pattern.strip_suffix("/*") {
-                        if dir_str.starts_with(base_pattern) {
-                            let package_json_path = entry.path().join("package.json");
-                            if package_json_path.exists() {
-                                paths.push(entry.path().to_string_lossy().to_string());
-                            }
-                        }
-                    }
+    /// Directories holding a package.json that the `workspaces` patterns match, sorted.
+    /// Patterns are globs relative to the root (`packages/*`, `apps/**`); ones starting
+    /// with `!` exclude what they match.
+    fn resolve_workspace_patterns(&self, patterns: &[String]) -> Result<Vec<String>> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        let mut paths = BTreeSet::new();
+        for pattern in patterns {
+            let (excluded, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+            let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+            if !excluded && !pattern.contains(['*', '?', '[']) {
+                // A plain path, which may also point outside the root (`../shared`)
+                let path = self.root_path.join(pattern);
+                if path.join("package.json").exists() {
+                    paths.insert(path.to_string_lossy().to_string());
                 }
+                continue;
             }
-        } else {
-            // Direct path
-            let path = PathBuf::from(pattern);
-            if path.exists() && path.join("package.json").exists() {
-                paths.push(pattern.to_string());
+            let glob = glob::Pattern::new(pattern)
+                .map_err(|e| anyhow!("Invalid workspace pattern '{pattern}': {e}"))?;
+            if excluded {
+                exclude.push(glob);
+            } else {
+                include.push(glob);
+            }
+        }
+
+        // Without `**` nothing deeper than the pattern's own depth can match
+        let max_depth = include
+            .iter()
+            .map(|pattern| {
+                if pattern.as_str().contains("**") {
+                    usize::MAX
+                } else {
+                    pattern.as_str().split('/').count()
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let walker = WalkDir::new(&self.root_path)
+            .min_depth(1)
+            .max_depth(max_depth)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.file_type().is_dir()
+                    && !IGNORED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref())
+            });
+        for entry in walker.flatten() {
+            let Ok(relative) = entry.path().strip_prefix(&self.root_path) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if include
+                .iter()
+                .any(|pattern| pattern.matches_with(&relative, options))
+                && !exclude
+                    .iter()
+                    .any(|pattern| pattern.matches_with(&relative, options))
+                && entry.path().join("package.json").exists()
+            {
+                paths.insert(entry.path().to_string_lossy().to_string());
             }
         }
 
-        Ok(paths)
+        Ok(paths.into_iter().collect())
     }
 
     async fn read_workspace_package_json(&self, workspace_path: &str) -> Result<serde_json::Value> {
@@ -406,6 +683,57 @@ impl WorkspaceManager {
         script: &str,
         workspace_path: &str,
     ) -> Result<bool> {
+        let mut cmd = self.script_command(script, workspace_path).await?;
+        let status = cmd.status().await?;
+        Ok(status.success())
+    }
+
+    /// Run `script` in a workspace with each line it prints prefixed by `prefix`, as it
+    /// comes or, grouped, all at once when the script finishes
+    async fn execute_script_prefixed(
+        &self,
+        script: &str,
+        workspace_path: &str,
+        prefix: &str,
+        output_style: OutputStyle,
+    ) -> Result<bool> {
+        let mut child = self
+            .script_command(script, workspace_path)
+            .await?
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let grouped = std::sync::Mutex::new(Vec::new());
+        let grouped = (output_style == OutputStyle::Group).then_some(&grouped);
+        let (stdout, stderr, status) = tokio::join!(
+            forward_lines(stdout, prefix, false, grouped),
+            forward_lines(stderr, prefix, true, grouped),
+            child.wait()
+        );
+        stdout?;
+        stderr?;
+
+        if let Some(grouped) = grouped {
+            let lines = std::mem::take(&mut *grouped.lock().unwrap());
+            let mut out = std::io::stdout().lock();
+            let mut err = std::io::stderr().lock();
+            for (is_err, line) in lines {
+                if is_err {
+                    writeln!(err, "{prefix} {line}")?;
+                } else {
+                    writeln!(out, "{prefix} {line}")?;
+                }
+            }
+        }
+        Ok(status?.success())
+    }
+
+    /// The shell command running `script` from a workspace's package.json, with the
+    /// root's and the workspace's node_modules/.bin on PATH
+    async fn script_command(&self, script: &str, workspace_path: &str) -> Result<Command> {
         let package_json_path = PathBuf::from(workspace_path).join("package.json");
 
         if !package_json_path.exists() {
@@ -482,19 +810,78 @@ impl WorkspaceManager {
             cmd.env("PATH", new_path);
         }
 
-        let status = cmd.status().await?;
-        Ok(status.success())
+        Ok(cmd)
     }
 
-    pub async fn install_workspace_dependencies(&self) -> Result<()> {
+    /// `clay install <packages> --workspace <name>`: save `specs` to the named
+    /// workspace's package.json `field`, then install it with the rest of the monorepo
+    pub async fn add_dependencies(
+        &self,
+        workspace: &str,
+        specs: Vec<(String, String)>,
+        field: &str,
+    ) -> Result<()> {
         let workspaces = self.discover_workspaces().await?;
+        let Some(target) = workspaces.iter().find(|w| w.name == workspace) else {
+            let names: Vec<&str> = workspaces.iter().map(|w| w.name.as_str()).collect();
+            return Err(anyhow!(
+                "No workspace named '{workspace}' (workspaces: {})",
+                names.join(", ")
+            ));
+        };
 
-        if workspaces.is_empty() {
+        let package_manager = PackageManager::with_config(self.config.clone())
+            .with_project_dir(Path::new(&target.path));
+        let local_packages = self.local_packages().await?;
+        let catalogs = self.catalogs()?;
+        for (name, spec) in &specs {
+            let range = match local_packages.get(name) {
+                Some(local) if local.satisfies(spec) && spec == "latest" => "workspace:^".into(),
+                Some(local) if local.satisfies(spec) => spec.clone(),
+                _ => {
+                    let wanted = catalogs.resolve(name, spec)?;
+                    let response = package_manager.npm_client.get_package_info(name).await?;
+                    let (info, _) = PackageResolver::choose_version(&response, &wanted)
+                        .ok_or_else(|| anyhow!("No version of {name} matches {spec}"))?;
+                    package_manager.saved_range(Some(spec), &info.version)
+                }
+            };
+            package_manager
+                .update_package_json(name, &range, field)
+                .await?;
+            println!(
+                "{} {} {} {}",
+                CliStyle::success("Added"),
+                CliStyle::package_name(name),
+                CliStyle::version(&range),
+                CliStyle::dim_text(&format!("to {workspace}'s {field}"))
+            );
+        }
+
+        self.install_workspace_dependencies(&[workspace.to_string()])
+            .await
+    }
+
+    /// Install the monorepo: dependencies (dev ones included) of the root and of the
+    /// workspaces `filters` select, all without filters, resolve together into the root
+    /// node_modules. A workspace asking for another range of a package than the root or
+    /// an earlier workspace gets its own copy, and dependencies on other workspaces are
+    /// linked.
+    pub async fn install_workspace_dependencies(&self, filters: &[String]) -> Result<()> {
+        let workspaces = self.discover_workspaces().await?;
+        let selected: BTreeSet<String> = self
+            .select_workspaces(filters, None)
+            .await?
+            .into_iter()
+            .map(|workspace| workspace.name)
+            .collect();
+
+        if selected.is_empty() {
             println!("{} No workspaces found", style("•").yellow());
             return Ok(());
         }
 
-        let workspace_count = workspaces.len();
+        let workspace_count = selected.len();
         let workspace_word = if workspace_count == 1 {
             "workspace"
         } else {
@@ -504,34 +891,350 @@ impl WorkspaceManager {
             "Installing dependencies for {workspace_count} {workspace_word}..."
         ));
 
-        // Install root dependencies first
-        let package_manager = PackageManager::new();
-        let root_deps = package_manager.get_package_json_dependencies(false).await?;
-        if !root_deps.is_empty() {
-            install_spinner.set_message("Installing root dependencies...");
+        let mut workspace_manifests = Vec::new();
+        for workspace in &workspaces {
+            workspace_manifests.push((
+                workspace.path.clone(),
+                read_manifest(&workspace.package_json).await?,
+            ));
+        }
+
+        // The root's own dependencies are hoisted first, then each workspace's in order,
+        // compared by the ranges `catalog:` references stand for
+        let local_packages = self.local_packages().await?;
+        let catalogs = self.catalogs()?;
+        let mut hoisted: Vec<(String, String)> = Vec::new();
+        if self.workspace_config_path.exists() {
+            let specs = read_manifest(&self.workspace_config_path)
+                .await?
+                .dependency_specs(true);
+            hoisted = catalogs
+                .resolve_all(specs)?
+                .into_iter()
+                .filter(|(name, _)| !local_packages.contains_key(name))
+                .collect();
+        }
+        let mut own: Vec<Vec<(String, String)>> = Vec::new();
+        let mut hoisted_names: Vec<Vec<String>> = Vec::new();
+        for (workspace, (_, package_json)) in workspaces.iter().zip(&workspace_manifests) {
+            let mut installs_own = Vec::new();
+            let mut uses_hoisted = Vec::new();
+            if selected.contains(&workspace.name) {
+                for (name, range) in catalogs.resolve_all(package_json.dependency_specs(true))? {
+                    if local_packages.contains_key(&name) || range.starts_with("workspace:") {
+                        installs_own.push((name, range));
+                        continue;
+                    }
+                    match hoisted.iter().find(|(hoisted, _)| *hoisted == name) {
+                        Some((_, hoisted_range)) if *hoisted_range != range => {
+                            installs_own.push((name, range));
+                        }
+                        Some(_) => uses_hoisted.push(name),
+                        None => {
+                            hoisted.push((name.clone(), range));
+                            uses_hoisted.push(name);
+                        }
+                    }
+                }
+            }
+            own.push(installs_own);
+            hoisted_names.push(uses_hoisted);
+        }
+
+        let mut package_manager =
+            PackageManager::with_config(self.config.clone()).with_project_dir(&self.root_path);
+        package_manager.set_quiet(true);
+        package_manager.set_workspace_packages(local_packages.clone());
+        let locked: Vec<(String, PackageJson)> = workspace_manifests
+            .into_iter()
+            .map(|(path, package_json)| {
+                let relative = Path::new(&path)
+                    .strip_prefix(&self.root_path)
+                    .map(|relative| relative.to_string_lossy().to_string())
+                    .unwrap_or(path);
+                (relative, package_json)
+            })
+            .collect();
+        if !hoisted.is_empty() {
+            install_spinner.set_message("Installing shared dependencies...");
+            package_manager.set_tree_key(specs_key(&hoisted));
             package_manager
-                .install_multiple_packages(root_deps, false, false)
+                .install_multiple_packages(hoisted.clone(), false, false)
                 .await?;
         }
 
-        // Install workspace dependencies
-        for workspace in workspaces {
+        // Then what each workspace needs in its own node_modules, and links to the
+        // commands of what it uses from the root
+        let root_node_modules = std::path::absolute(self.root_path.join("node_modules"))?;
+        for ((workspace, specs), names) in workspaces.iter().zip(own).zip(hoisted_names) {
+            if specs.is_empty() && names.is_empty() {
+                continue;
+            }
             install_spinner
                 .set_message(format!("Installing dependencies for {}...", workspace.name));
-
-            // Note: We would need to modify PackageManager to work with different working directories
-            // For now, we'll use a simple approach - this is a placeholder for future implementation
+            let mut workspace_install = PackageManager::with_config(self.config.clone())
+                .with_project_dir(Path::new(&workspace.path))
+                .with_shared_lock_file(&self.root_path);
+            workspace_install.set_quiet(true);
+            workspace_install.set_workspace_packages(local_packages.clone());
+            if !specs.is_empty() {
+                workspace_install.set_tree_key(specs_key(&specs));
+                workspace_install
+                    .install_multiple_packages(specs, false, false)
+                    .await?;
+            }
+            workspace_install
+                .link_bins_from(&root_node_modules, &names)
+                .await?;
         }
 
+        // Lock the workspace packages themselves last, so their edges find the versions
+        // installed for them and the lockfile covers the whole monorepo
+        package_manager.lock_workspaces(&locked).await?;
+
         install_spinner.finish_with_message(format!(
-            "Installed dependencies for {workspace_count} {workspace_word}"
+            "Installed dependencies for {workspace_count} {workspace_word}, {} shared in the root node_modules",
+            hoisted.len()
         ));
         Ok(())
     }
 }
 
-impl Default for WorkspaceManager {
-    fn default() -> Self {
-        Self::new()
+/// For each workspace, the indices of the others in `workspaces` it depends on
+fn dependency_indices(
+    workspaces: &[&WorkspacePackage],
+    graph: &WorkspaceGraph,
+) -> Vec<BTreeSet<usize>> {
+    let index: HashMap<&str, usize> = workspaces
+        .iter()
+        .enumerate()
+        .map(|(i, workspace)| (workspace.name.as_str(), i))
+        .collect();
+    workspaces
+        .iter()
+        .map(|workspace| {
+            graph
+                .get(&workspace.name)
+                .into_iter()
+                .flatten()
+                .filter_map(|name| index.get(name.as_str()).copied())
+                .collect()
+        })
+        .collect()
+}
+
+/// Run `run(i)` for every workspace, at most `concurrency` at a time, each once the
+/// workspaces it depends on have finished. Dependents of a failed workspace are skipped
+/// and count as failed; workspaces in a cycle start when nothing else can. Whether each
+/// succeeded, in the order of `workspaces`.
+async fn run_in_dependency_order<F, Fut>(
+    workspaces: &[&WorkspacePackage],
+    dependencies: Vec<BTreeSet<usize>>,
+    concurrency: usize,
+    run: F,
+) -> Vec<bool>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut results = vec![false; workspaces.len()];
+    let mut unfinished = dependencies.clone();
+    let mut failed: BTreeSet<usize> = BTreeSet::new();
+    let mut waiting: Vec<usize> = (0..workspaces.len()).collect();
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        while running.len() < concurrency.max(1) && !waiting.is_empty() {
+            let ready = waiting.iter().position(|&i| unfinished[i].is_empty());
+            let Some(position) = ready.or_else(|| running.is_empty().then_some(0)) else {
+                break;
+            };
+            let i = waiting.remove(position);
+
+            if let Some(&dependency) = dependencies[i].intersection(&failed).next() {
+                println!(
+                    "{} [{}] Skipped: {} failed",
+                    CliStyle::error(""),
+                    style(&workspaces[i].name).white().bold(),
+                    workspaces[dependency].name
+                );
+                failed.insert(i);
+                for unfinished in &mut unfinished {
+                    unfinished.remove(&i);
+                }
+                continue;
+            }
+            let task = run(i);
+            running.push(async move { (i, task.await) });
+        }
+
+        let Some((i, success)) = running.next().await else {
+            break;
+        };
+        results[i] = success;
+        if !success {
+            failed.insert(i);
+        }
+        for unfinished in &mut unfinished {
+            unfinished.remove(&i);
+        }
+    }
+
+    results
+}
+
+/// Print each line `reader` gives after `prefix`, to stderr when `is_err`, or keep it in
+/// `grouped` to print later
+async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    prefix: &str,
+    is_err: bool,
+    grouped: Option<&std::sync::Mutex<Vec<(bool, String)>>>,
+) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    while reader.read_until(b'\n', &mut buf).await? > 0 {
+        let line = String::from_utf8_lossy(&buf)
+            .trim_end_matches(['\n', '\r'])
+            .to_string();
+        match grouped {
+            Some(grouped) => grouped.lock().unwrap().push((is_err, line)),
+            None if is_err => eprintln!("{prefix} {line}"),
+            None => println!("{prefix} {line}"),
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+async fn read_manifest(path: &Path) -> Result<PackageJson> {
+    let content = fs::read_to_string(path).await?;
+    serde_json::from_str(&content).map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Key of the cached dependency tree for installing exactly `specs`
+fn specs_key(specs: &[(String, String)]) -> String {
+    let mut sorted: Vec<&(String, String)> = specs.iter().collect();
+    sorted.sort();
+    let mut hasher = blake3::Hasher::new();
+    for (name, range) in sorted {
+        hasher.update(format!("dep:{name}:{range}\n").as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// ESCRIPTFAIL for a `workspace run` where `failed` workspaces' scripts didn't succeed
+fn script_failures(script: &str, failed: usize) -> anyhow::Error {
+    errors::coded(
+        ErrorCode::ScriptFailed,
+        format!("'{script}' failed in {failed} workspace(s)"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn workspaces(names: &[&str]) -> Vec<WorkspacePackage> {
+        names
+            .iter()
+            .map(|name| WorkspacePackage {
+                name: name.to_string(),
+                path: format!("packages/{name}"),
+                package_json: PathBuf::from(format!("packages/{name}/package.json")),
+            })
+            .collect()
+    }
+
+    fn graph(edges: &[(&str, &[&str])]) -> WorkspaceGraph {
+        edges
+            .iter()
+            .map(|(name, dependencies)| {
+                let dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+                (name.to_string(), dependencies)
+            })
+            .collect()
+    }
+
+    /// Run every workspace, recording when each starts and finishes; `fail` ones fail
+    async fn schedule(
+        names: &[&str],
+        edges: &[(&str, &[&str])],
+        concurrency: usize,
+        fail: &[&str],
+    ) -> (Vec<bool>, Vec<String>) {
+        let workspaces = workspaces(names);
+        let targets: Vec<&WorkspacePackage> = workspaces.iter().collect();
+        let dependencies = dependency_indices(&targets, &graph(edges));
+        let events = Mutex::new(Vec::new());
+        let results = run_in_dependency_order(&targets, dependencies, concurrency, |i| {
+            let name = names[i];
+            let events = &events;
+            async move {
+                events.lock().unwrap().push(format!("start {name}"));
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                events.lock().unwrap().push(format!("end {name}"));
+                !fail.contains(&name)
+            }
+        })
+        .await;
+        (results, events.into_inner().unwrap())
+    }
+
+    fn position(events: &[String], event: &str) -> usize {
+        events.iter().position(|e| e == event).unwrap()
+    }
+
+    #[tokio::test]
+    async fn dependencies_finish_before_dependents_start() {
+        let (results, events) = schedule(
+            &["app", "ui", "utils"],
+            &[("app", &["ui", "utils"]), ("ui", &["utils"])],
+            4,
+            &[],
+        )
+        .await;
+
+        assert_eq!(results, [true, true, true]);
+        assert!(position(&events, "end utils") < position(&events, "start ui"));
+        assert!(position(&events, "end ui") < position(&events, "start app"));
+    }
+
+    #[tokio::test]
+    async fn independent_workspaces_run_within_the_limit() {
+        let (_, events) = schedule(&["a", "b", "c"], &[], 2, &[]).await;
+        assert_eq!(&events[..2], ["start a", "start b"]);
+        assert!(
+            position(&events, "start c")
+                > position(&events, "end a").min(position(&events, "end b"))
+        );
+
+        let (_, events) = schedule(&["a", "b", "c"], &[], 3, &[]).await;
+        assert_eq!(&events[..3], ["start a", "start b", "start c"]);
+    }
+
+    #[tokio::test]
+    async fn dependents_of_a_failure_are_skipped() {
+        let (results, events) =
+            schedule(&["app", "lib", "docs"], &[("app", &["lib"])], 1, &["lib"]).await;
+
+        assert_eq!(results, [false, false, true]);
+        assert!(!events.contains(&"start app".to_string()));
+    }
+
+    #[tokio::test]
+    async fn cycles_still_run() {
+        let (results, events) = schedule(
+            &["a", "b", "c"],
+            &[("a", &["b"]), ("b", &["a"]), ("c", &["a"])],
+            2,
+            &[],
+        )
+        .await;
+
+        assert_eq!(results, [true, true, true]);
+        assert!(position(&events, "end a") < position(&events, "start c"));
     }
 }
@@ -0,0 +1,219 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::workspace::WorkspacePackage;
+
+/// Each workspace's name mapped to the names of the workspaces it depends on
+pub type WorkspaceGraph = BTreeMap<String, BTreeSet<String>>;
+
+/// One `--filter` selector, pnpm style: a name glob (`pkg-*`, `@scope/*`) or a path glob
+/// from the current directory (`./apps/web`, `./apps/*`). `pkg...` adds the workspaces
+/// that depend on it, `...pkg` the ones it depends on, and `!` leaves the selection out.
+#[derive(Debug)]
+struct Filter {
+    exclude: bool,
+    with_dependents: bool,
+    with_dependencies: bool,
+    target: Target,
+}
+
+#[derive(Debug)]
+enum Target {
+    Name(glob::Pattern),
+    Path(glob::Pattern),
+}
+
+impl Filter {
+    fn parse(selector: &str, cwd: &Path) -> Result<Self> {
+        let (exclude, selector) = match selector.strip_prefix('!') {
+            Some(selector) => (true, selector),
+            None => (false, selector),
+        };
+        let (with_dependencies, selector) = match selector.strip_prefix("...") {
+            Some(selector) => (true, selector),
+            None => (false, selector),
+        };
+        let (with_dependents, selector) = match selector.strip_suffix("...") {
+            Some(selector) => (true, selector),
+            None => (false, selector),
+        };
+        if selector.is_empty() {
+            return Err(anyhow!("Empty --filter selector"));
+        }
+
+        let invalid = |e: glob::PatternError| anyhow!("Invalid --filter '{selector}': {e}");
+        let target = if selector.starts_with('.') {
+            let dir = normalize(&cwd.join(selector.trim_end_matches('/')));
+            let pattern = glob::Pattern::new(&dir.to_string_lossy()).map_err(invalid)?;
+            Target::Path(pattern)
+        } else {
+            Target::Name(glob::Pattern::new(selector).map_err(invalid)?)
+        };
+        Ok(Self {
+            exclude,
+            with_dependents,
+            with_dependencies,
+            target,
+        })
+    }
+
+    fn matches(&self, workspace: &WorkspacePackage, cwd: &Path) -> bool {
+        match &self.target {
+            Target::Name(pattern) => pattern.matches(&workspace.name),
+            Target::Path(pattern) => {
+                let options = glob::MatchOptions {
+                    require_literal_separator: true,
+                    ..Default::default()
+                };
+                let dir = normalize(&cwd.join(&workspace.path));
+                pattern.matches_with(&dir.to_string_lossy(), options)
+            }
+        }
+    }
+}
+
+/// The workspaces `selectors` pick, in discovery order. With no selector, or only
+/// excluding ones, everything not excluded is picked.
+pub fn select<'a>(
+    workspaces: &'a [WorkspacePackage],
+    graph: &WorkspaceGraph,
+    selectors: &[String],
+) -> Result<Vec<&'a WorkspacePackage>> {
+    let cwd = std::env::current_dir()?;
+    let filters = selectors
+        .iter()
+        .map(|selector| Filter::parse(selector, &cwd))
+        .collect::<Result<Vec<_>>>()?;
+
+    let dependents = dependents(graph);
+    let mut included: Option<BTreeSet<String>> = None;
+    let mut excluded = BTreeSet::new();
+    for filter in &filters {
+        let mut selected: BTreeSet<String> = workspaces
+            .iter()
+            .filter(|workspace| filter.matches(workspace, &cwd))
+            .map(|workspace| workspace.name.clone())
+            .collect();
+        if filter.with_dependencies {
+            selected.extend(reachable(&selected, graph));
+        }
+        if filter.with_dependents {
+            selected.extend(reachable(&selected, &dependents));
+        }
+        if filter.exclude {
+            excluded.extend(selected);
+        } else {
+            included.get_or_insert_default().extend(selected);
+        }
+    }
+
+    Ok(workspaces
+        .iter()
+        .filter(|workspace| {
+            included
+                .as_ref()
+                .is_none_or(|included| included.contains(&workspace.name))
+                && !excluded.contains(&workspace.name)
+        })
+        .collect())
+}
+
+/// Workspaces with files changed since `git_ref` (committed, staged, unstaged or
+/// untracked), and the workspaces that depend on them
+pub fn changed_since(
+    git_ref: &str,
+    workspaces: &[WorkspacePackage],
+    graph: &WorkspaceGraph,
+) -> Result<BTreeSet<String>> {
+    let toplevel = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let mut files = git(&["diff", "--name-only", git_ref, "--"])?;
+    files.push_str(&git(&[
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+        "--full-name",
+    ])?);
+
+    let dirs: Vec<(PathBuf, &str)> = workspaces
+        .iter()
+        .filter_map(|workspace| {
+            let dir = std::fs::canonicalize(&workspace.path).ok()?;
+            Some((dir, workspace.name.as_str()))
+        })
+        .collect();
+    let mut changed = BTreeSet::new();
+    for file in files.lines().filter(|line| !line.is_empty()) {
+        let path = toplevel.join(file);
+        // Nested workspaces own their files, not the workspace around them
+        let owner = dirs
+            .iter()
+            .filter(|(dir, _)| path.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count());
+        if let Some((_, name)) = owner {
+            changed.insert(name.to_string());
+        }
+    }
+
+    let with_dependents = reachable(&changed, &dependents(graph));
+    changed.extend(with_dependents);
+    Ok(changed)
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Couldn't run git: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `graph` reversed: each workspace mapped to the workspaces depending on it
+pub fn dependents(graph: &WorkspaceGraph) -> WorkspaceGraph {
+    let mut dependents = WorkspaceGraph::new();
+    for (name, dependencies) in graph {
+        for dependency in dependencies {
+            dependents
+                .entry(dependency.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+    }
+    dependents
+}
+
+/// Every workspace reachable from `from` along `edges`, not counting `from` itself
+pub fn reachable(from: &BTreeSet<String>, edges: &WorkspaceGraph) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    let mut pending: Vec<&String> = from.iter().collect();
+    while let Some(name) = pending.pop() {
+        for next in edges.get(name).into_iter().flatten() {
+            if !from.contains(next) && found.insert(next.clone()) {
+                pending.push(next);
+            }
+        }
+    }
+    found
+}
+
+/// `path` with `.` and `..` components resolved without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,262 @@
+use anyhow::{Result, anyhow};
+use console::style;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::cli_style::CliStyle;
+use crate::workspace_filter::{self, WorkspaceGraph};
+
+/// A workspace as `clay workspace graph` shows it
+pub struct GraphNode {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: String,
+}
+
+/// The workspaces of a monorepo and which depend on which, with the cycles among them
+/// and, when asked about one workspace, every workspace depending on it
+pub struct Graph {
+    nodes: Vec<GraphNode>,
+    edges: WorkspaceGraph,
+    dependents: WorkspaceGraph,
+    cycles: Vec<BTreeSet<String>>,
+    focus: Option<(String, BTreeSet<String>)>,
+}
+
+impl Graph {
+    /// `nodes` in discovery order with their `edges`; `dependents_of` must name one of them
+    pub fn new(
+        nodes: Vec<GraphNode>,
+        edges: WorkspaceGraph,
+        dependents_of: Option<&str>,
+    ) -> Result<Self> {
+        let dependents = workspace_filter::dependents(&edges);
+        let focus = match dependents_of {
+            Some(name) if !nodes.iter().any(|node| node.name == name) => {
+                return Err(anyhow!("No workspace named '{name}'"));
+            }
+            Some(name) => {
+                let from = BTreeSet::from([name.to_string()]);
+                Some((
+                    name.to_string(),
+                    workspace_filter::reachable(&from, &dependents),
+                ))
+            }
+            None => None,
+        };
+        let cycles = cycles(&edges);
+        Ok(Self {
+            nodes,
+            edges,
+            dependents,
+            cycles,
+            focus,
+        })
+    }
+
+    fn dependencies(&self, name: &str) -> Vec<&String> {
+        self.edges.get(name).into_iter().flatten().collect()
+    }
+
+    fn in_cycle(&self, from: &str, to: &str) -> bool {
+        self.cycles
+            .iter()
+            .any(|cycle| cycle.contains(from) && cycle.contains(to))
+    }
+
+    fn is_focus(&self, name: &str) -> bool {
+        self.focus.as_ref().is_some_and(|(focus, _)| focus == name)
+    }
+
+    fn is_dependent(&self, name: &str) -> bool {
+        self.focus
+            .as_ref()
+            .is_some_and(|(_, dependents)| dependents.contains(name))
+    }
+
+    /// Each workspace nothing depends on, with the workspaces it depends on below it;
+    /// then the cycles, and who depends on the workspace asked about
+    pub fn print_tree(&self) {
+        if self.nodes.is_empty() {
+            println!("{} No workspaces configured", style("•").yellow());
+            return;
+        }
+
+        // Workspaces only reachable through a cycle get a tree of their own
+        let mut shown = BTreeSet::new();
+        let mut path = Vec::new();
+        let roots: Vec<&GraphNode> = self
+            .nodes
+            .iter()
+            .filter(|node| !self.dependents.contains_key(&node.name))
+            .collect();
+        for root in roots.iter().copied().chain(&self.nodes) {
+            if shown.contains(&root.name) {
+                continue;
+            }
+            shown.insert(root.name.clone());
+            println!("{}", style(self.label(&root.name)).bold());
+            path.push(root.name.clone());
+            self.print_level(&root.name, "", &mut path, &mut shown);
+            path.pop();
+        }
+
+        for cycle in &self.cycles {
+            let names: Vec<&str> = cycle.iter().map(String::as_str).collect();
+            println!(
+                "\n{} Dependency cycle between {}",
+                CliStyle::error(""),
+                style(names.join(", ")).red()
+            );
+        }
+        if let Some((name, dependents)) = &self.focus {
+            let names: Vec<&str> = dependents.iter().map(String::as_str).collect();
+            let summary = match names.len() {
+                0 => format!("No workspaces depend on {name}"),
+                1 => format!("1 workspace depends on {name}: {}", names[0]),
+                n => format!("{n} workspaces depend on {name}: {}", names.join(", ")),
+            };
+            println!("\n{}", CliStyle::info(&summary));
+        }
+    }
+
+    fn print_level(
+        &self,
+        name: &str,
+        prefix: &str,
+        path: &mut Vec<String>,
+        shown: &mut BTreeSet<String>,
+    ) {
+        let dependencies = self.dependencies(name);
+        for (i, dependency) in dependencies.iter().enumerate() {
+            let last = i + 1 == dependencies.len();
+            let branch = if last { "└── " } else { "├── " };
+            let line = format!(
+                "{}{}",
+                CliStyle::dim_text(prefix),
+                CliStyle::dim_text(branch)
+            );
+            let label = self.label(dependency);
+
+            if path.contains(dependency) {
+                println!("{line}{label} {}", style("cycle").red());
+                continue;
+            }
+            if !shown.insert(dependency.to_string()) && !self.dependencies(dependency).is_empty() {
+                println!("{line}{label} {}", CliStyle::dim_text("deduped"));
+                continue;
+            }
+            println!("{line}{label}");
+
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            path.push(dependency.to_string());
+            self.print_level(dependency, &prefix, path, shown);
+            path.pop();
+        }
+    }
+
+    /// `name@version`, in cyan for the workspace asked about and yellow for those
+    /// depending on it
+    fn label(&self, name: &str) -> String {
+        let version = self
+            .nodes
+            .iter()
+            .find(|node| node.name == name)
+            .and_then(|node| node.version.as_deref());
+        let label = match version {
+            Some(version) => format!("{name}@{version}"),
+            None => name.to_string(),
+        };
+        if self.is_focus(name) {
+            style(label).cyan().to_string()
+        } else if self.is_dependent(name) {
+            style(label).yellow().to_string()
+        } else {
+            label
+        }
+    }
+
+    /// Graphviz source: an edge from each workspace to each it depends on, red along
+    /// cycles, with the workspace asked about and its dependents filled
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph workspaces {\n");
+        for node in &self.nodes {
+            let label = match &node.version {
+                Some(version) => format!("{}@{version}", node.name),
+                None => node.name.clone(),
+            };
+            let fill = if self.is_focus(&node.name) {
+                ", style=filled, fillcolor=lightblue"
+            } else if self.is_dependent(&node.name) {
+                ", style=filled, fillcolor=lightyellow"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("  {:?} [label={label:?}{fill}];\n", node.name));
+        }
+        for (name, dependencies) in &self.edges {
+            for dependency in dependencies {
+                let color = if self.in_cycle(name, dependency) {
+                    " [color=red]"
+                } else {
+                    ""
+                };
+                dot.push_str(&format!("  {name:?} -> {dependency:?}{color};\n"));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let workspaces: Vec<serde_json::Value> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                json!({
+                    "name": node.name,
+                    "version": node.version,
+                    "path": node.path,
+                    "dependencies": self.dependencies(&node.name),
+                    "dependents": self.dependents.get(&node.name).into_iter().flatten().collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let mut report = json!({
+            "workspaces": workspaces,
+            "cycles": self.cycles,
+        });
+        if let Some((name, dependents)) = &self.focus {
+            report["dependents_of"] = json!({ "name": name, "workspaces": dependents });
+        }
+        report
+    }
+}
+
+/// Groups of workspaces that depend on each other, directly or through the others
+fn cycles(edges: &WorkspaceGraph) -> Vec<BTreeSet<String>> {
+    let reached: BTreeMap<&String, BTreeSet<String>> = edges
+        .keys()
+        .map(|name| {
+            let from = BTreeSet::from([name.clone()]);
+            (name, workspace_filter::reachable(&from, edges))
+        })
+        .collect();
+
+    let mut cycles: Vec<BTreeSet<String>> = Vec::new();
+    for (name, reachable) in &reached {
+        if cycles.iter().any(|cycle| cycle.contains(*name)) {
+            continue;
+        }
+        let mut cycle: BTreeSet<String> = reachable
+            .iter()
+            .filter(|other| reached.get(other).is_some_and(|back| back.contains(*name)))
+            .cloned()
+            .collect();
+        if !cycle.is_empty() {
+            cycle.insert((*name).clone());
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}